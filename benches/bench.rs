@@ -4,7 +4,8 @@ extern crate rand;
 extern crate test;
 
 use kdtree::distance::squared_euclidean;
-use kdtree::KdTree;
+use kdtree::tree::{BuildOrder, Builder};
+use kdtree::{KdTree, OwnedKdTree};
 use test::Bencher;
 
 fn rand_data() -> ([f64; 3], f64) {
@@ -40,3 +41,71 @@ fn bench_nearest_from_kdtree_with_1k_3d_points(b: &mut Bencher) {
     }
     b.iter(|| kdtree.nearest(&point.0, 8, &squared_euclidean).unwrap());
 }
+
+fn owned_tree_with_2m_3d_points(order: BuildOrder) -> OwnedKdTree<f64, f64, 3> {
+    let points = (0..2_000_000).map(|_| rand_data()).collect();
+    Builder::new()
+        .capacity(16)
+        .order(order)
+        .from_points(points)
+        .unwrap()
+}
+
+fn bench_nearest_from_owned_kdtree_with_2m_3d_points(b: &mut Bencher, order: BuildOrder) {
+    let kdtree = owned_tree_with_2m_3d_points(order);
+    let queries: Vec<_> = (0..1024).map(|_| rand_data().0).collect();
+    let mut i = 0;
+    b.iter(|| {
+        i = (i + 1) % queries.len();
+        kdtree
+            .nearest(&queries[i], 8, &|a, b| squared_euclidean(a, b))
+            .unwrap()
+            .len()
+    });
+}
+
+#[bench]
+fn bench_nearest_from_unsorted_owned_kdtree_with_2m_3d_points(b: &mut Bencher) {
+    bench_nearest_from_owned_kdtree_with_2m_3d_points(b, BuildOrder::Unsorted);
+}
+
+#[bench]
+fn bench_nearest_from_morton_owned_kdtree_with_2m_3d_points(b: &mut Bencher) {
+    bench_nearest_from_owned_kdtree_with_2m_3d_points(b, BuildOrder::Morton);
+}
+
+#[bench]
+fn bench_iter_from_owned_kdtree_with_2m_3d_points(b: &mut Bencher) {
+    let kdtree = owned_tree_with_2m_3d_points(BuildOrder::Unsorted);
+    b.iter(|| kdtree.iter().count());
+}
+
+/// Compare with the plain iteration above, which the Morton order costs a sort of every leaf
+#[bench]
+fn bench_iter_morton_from_owned_kdtree_with_2m_3d_points(b: &mut Bencher) {
+    let kdtree = owned_tree_with_2m_3d_points(BuildOrder::Unsorted);
+    b.iter(|| kdtree.iter_morton().count());
+}
+
+fn points_5m_3d() -> Vec<([f64; 3], f64)> {
+    (0..5_000_000).map(|_| rand_data()).collect()
+}
+
+#[bench]
+fn bench_build_owned_kdtree_with_5m_3d_points(b: &mut Bencher) {
+    let points = points_5m_3d();
+    b.iter(|| OwnedKdTree::from_points(points.clone(), 16).unwrap().size());
+}
+
+/// Compare with the sequential build above, run with `RAYON_NUM_THREADS` set to vary the number
+/// of cores
+#[cfg(feature = "rayon")]
+#[bench]
+fn bench_par_build_owned_kdtree_with_5m_3d_points(b: &mut Bencher) {
+    let points = points_5m_3d();
+    b.iter(|| {
+        OwnedKdTree::par_from_points(points.clone(), 16)
+            .unwrap()
+            .size()
+    });
+}
@@ -0,0 +1,142 @@
+//! The build and query times of the configurations of `OwnedKdTree`, one knob at a time; see
+//! the `bench_support` module for how to run it and read the results.
+
+#[macro_use]
+extern crate criterion;
+extern crate kdtree;
+extern crate num_traits;
+
+use std::time::Duration;
+
+use criterion::{BatchSize, Criterion};
+use kdtree::bench_support::{items, points, Distribution};
+use kdtree::distance::squared_euclidean;
+use kdtree::tree::{Builder, SplitStrategy, DEFAULT_LEAF_CAPACITY};
+use kdtree::OwnedKdTree;
+use num_traits::Float;
+
+const ITEMS: usize = 50_000;
+const QUERIES: usize = 256;
+const NEIGHBOURS: usize = 8;
+
+const DISTRIBUTIONS: [Distribution; 3] = [
+    Distribution::Uniform,
+    Distribution::Clustered {
+        clusters: 20,
+        spread: 0.02,
+    },
+    Distribution::Manifold,
+];
+
+/// One cell of the matrix
+#[derive(Clone, Copy)]
+struct Config {
+    distribution: Distribution,
+    capacity: usize,
+    strategy: SplitStrategy,
+    frozen: bool,
+}
+
+impl Config {
+    fn name<A, const D: usize>(&self, what: &str) -> String {
+        format!(
+            "{}/{}/d{}/f{}/cap{}/{}/{}",
+            what,
+            self.distribution.name(),
+            D,
+            std::mem::size_of::<A>() * 8,
+            self.capacity,
+            match self.strategy {
+                SplitStrategy::Widest => "widest",
+                SplitStrategy::MaxVariance => "maxvariance",
+            },
+            if self.frozen { "frozen" } else { "boxed" },
+        )
+    }
+}
+
+fn run<A: Float, const D: usize>(c: &mut Criterion, config: Config) {
+    let data = items::<A, D>(config.distribution, ITEMS, 1);
+    let builder = Builder::new()
+        .capacity(config.capacity)
+        .split_strategy(config.strategy);
+    let make = |data| builder.clone().from_points(data).unwrap();
+    c.bench_function(&config.name::<A, D>("build"), |b| {
+        if config.frozen {
+            b.iter_batched(|| data.clone(), |d| make(d).freeze(), BatchSize::LargeInput)
+        } else {
+            b.iter_batched(|| data.clone(), make, BatchSize::LargeInput)
+        }
+    });
+
+    let queries = points::<A, D>(config.distribution, QUERIES, 2);
+    let distance = |a: &[A; D], b: &[A; D]| squared_euclidean(a, b);
+    let mut i = 0;
+    let mut next = || {
+        i = (i + 1) % queries.len();
+        &queries[i]
+    };
+    c.bench_function(&config.name::<A, D>("nearest"), |b| {
+        if config.frozen {
+            let tree = make(data.clone()).freeze();
+            b.iter(|| tree.nearest(next(), NEIGHBOURS, &distance).unwrap().len())
+        } else {
+            let tree: OwnedKdTree<A, usize, D> = make(data.clone());
+            b.iter(|| tree.nearest(next(), NEIGHBOURS, &distance).unwrap().len())
+        }
+    });
+}
+
+/// The baseline of every distribution and its variations in one knob, for `f64` coordinates
+/// and then the baseline again for `f32`
+fn run_dimensions<const D: usize>(c: &mut Criterion) {
+    for &distribution in &DISTRIBUTIONS {
+        let baseline = Config {
+            distribution,
+            capacity: DEFAULT_LEAF_CAPACITY,
+            strategy: SplitStrategy::Widest,
+            frozen: false,
+        };
+        let variations = [
+            baseline,
+            Config {
+                capacity: DEFAULT_LEAF_CAPACITY / 2,
+                ..baseline
+            },
+            Config {
+                capacity: DEFAULT_LEAF_CAPACITY * 2,
+                ..baseline
+            },
+            Config {
+                strategy: SplitStrategy::MaxVariance,
+                ..baseline
+            },
+            Config {
+                frozen: true,
+                ..baseline
+            },
+        ];
+        for &config in &variations {
+            run::<f64, D>(c, config);
+        }
+        run::<f32, D>(c, baseline);
+    }
+}
+
+fn matrix(c: &mut Criterion) {
+    run_dimensions::<2>(c);
+    run_dimensions::<3>(c);
+    run_dimensions::<8>(c);
+    run_dimensions::<32>(c);
+}
+
+criterion_group! {
+    name = benches;
+    // few short samples, so that the 144 benchmarks of the matrix take minutes rather than hours
+    config = Criterion::default()
+        .sample_size(10)
+        .warm_up_time(Duration::from_millis(500))
+        .measurement_time(Duration::from_secs(1));
+    targets = matrix
+}
+criterion_main!(benches);
@@ -0,0 +1,17 @@
+//! Applies the ops decoded from the input to an `OwnedKdTree` and to a brute force model,
+//! checking that they agree and that the tree keeps its invariants after every op. See
+//! `kdtree::test_support::decode_ops` for the format.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|bytes: &[u8]| {
+    // the first byte picks the number of dimensions, the rest go to the harness
+    if let Some((&dimensions, bytes)) = bytes.split_first() {
+        match dimensions % 3 {
+            0 => kdtree::test_support::fuzz::<1>(bytes),
+            1 => kdtree::test_support::fuzz::<2>(bytes),
+            _ => kdtree::test_support::fuzz::<3>(bytes),
+        }
+    }
+});
@@ -0,0 +1,386 @@
+//! An [`OwnedKdTree`] which caches an aggregate of the payloads of every subtree, such as the sum
+//! of their masses, so that region statistics only have to visit the leaves on the boundary of
+//! the region.
+
+use std::collections::BinaryHeap;
+
+use num_traits::{Float, One, Zero};
+
+use crate::error::ErrorKind;
+use crate::geometry::distance_to_space_const;
+use crate::heap_element::Neighbour;
+use crate::priority::{MinByDistance, Priority};
+use crate::tree::{NodeRef, OwnedKdTree};
+
+/// An [`OwnedKdTree`] together with a monoid over its payloads. `map` turns a payload into an
+/// aggregate and `combine` merges two aggregates; it must be associative and commutative, as
+/// payloads are combined in tree order.
+pub struct Aggregated<A, T: PartialEq, M, const D: usize> {
+    tree: OwnedKdTree<A, T, D>,
+    map: fn(&T) -> M,
+    combine: fn(&M, &M) -> M,
+    root: Node<M>,
+}
+
+/// The cached aggregate of a node, `None` for empty nodes, mirroring the structure of the tree
+struct Node<M> {
+    value: Option<M>,
+    children: Option<Box<[Node<M>; 2]>>,
+}
+
+impl<A: Float + Zero + One, T: PartialEq, M: Clone, const D: usize> Aggregated<A, T, M, D> {
+    pub fn with_capacity(capacity: usize, map: fn(&T) -> M, combine: fn(&M, &M) -> M) -> Self {
+        Aggregated {
+            tree: OwnedKdTree::with_capacity(capacity),
+            map,
+            combine,
+            root: Node {
+                value: None,
+                children: None,
+            },
+        }
+    }
+
+    pub fn from_points(
+        items: Vec<([A; D], T)>,
+        capacity: usize,
+        map: fn(&T) -> M,
+        combine: fn(&M, &M) -> M,
+    ) -> Result<Self, ErrorKind> {
+        let tree = OwnedKdTree::from_points(items, capacity)?;
+        let mut aggregated = Self::with_capacity(capacity, map, combine);
+        aggregated.root = aggregated.build(tree.root());
+        aggregated.tree = tree;
+        Ok(aggregated)
+    }
+
+    /// The underlying tree, for all other queries
+    pub fn tree(&self) -> &OwnedKdTree<A, T, D> {
+        &self.tree
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    /// The aggregate of all payloads, `None` if the tree is empty
+    pub fn aggregate(&self) -> Option<M> {
+        self.root.value.clone()
+    }
+
+    /// The aggregate of the payloads whose points lie within the box `[min, max]`, bounds
+    /// included, or `None` if there are none. Subtrees entirely inside the box contribute their
+    /// cached aggregate.
+    pub fn aggregate_within_box(&self, min: &[A; D], max: &[A; D]) -> Result<Option<M>, ErrorKind> {
+        let () = self.tree.check_point(min)?;
+        let () = self.tree.check_point(max)?;
+        Ok(self.within_box(self.tree.root(), &self.root, min, max))
+    }
+
+    /// The `num` nearest items to `point` for which `keep` holds, skipping every subtree whose
+    /// aggregate fails `descend`. `descend` must be conservative: it may only reject a subtree
+    /// if `keep` fails for all of its payloads.
+    pub fn nearest_where<F, P, K>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &F,
+        descend: P,
+        keep: K,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+        P: Fn(&M) -> bool,
+        K: Fn(&T) -> bool,
+    {
+        let () = self.tree.check_point(point)?;
+        let num = std::cmp::min(num, self.size());
+        if num == 0 {
+            return Ok(vec![]);
+        }
+        let mut pending = BinaryHeap::new();
+        let mut evaluated = BinaryHeap::<Neighbour<A, &T, D>>::new();
+        pending.push(MinByDistance::new(
+            A::zero(),
+            (self.tree.root(), &self.root),
+        ));
+        while let Some(MinByDistance(Priority {
+            distance: node_dist,
+            element: (node, cached),
+        })) = pending.pop()
+        {
+            let worst = match evaluated.peek() {
+                Some(worst) if evaluated.len() == num => worst.distance,
+                _ => A::infinity(),
+            };
+            if node_dist > worst {
+                break;
+            }
+            match cached.value {
+                Some(ref value) if descend(value) => {}
+                _ => continue,
+            }
+            match (node.left(), node.right(), &cached.children) {
+                (Some(left), Some(right), Some(children)) => {
+                    for (child, cached) in [(left, &children[0]), (right, &children[1])] {
+                        if let Some((lo, hi)) = child.bounds() {
+                            let child_dist = distance_to_space_const(point, lo, hi, distance);
+                            if child_dist <= worst {
+                                pending.push(MinByDistance::new(child_dist, (child, cached)));
+                            }
+                        }
+                    }
+                }
+                _ => {
+                    for (p, d) in node.iter().filter(|(_, d)| keep(d)) {
+                        let element = Neighbour {
+                            distance: distance(point, p),
+                            point: p,
+                            element: d,
+                        };
+                        if element.distance.is_nan() {
+                            continue;
+                        }
+                        if evaluated.len() < num {
+                            evaluated.push(element);
+                        } else if element < *evaluated.peek().unwrap() {
+                            evaluated.pop();
+                            evaluated.push(element);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(evaluated
+            .into_sorted_vec()
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// The items within `radius` of `point` for which `keep` holds, sorted by distance,
+    /// skipping every subtree whose aggregate fails `descend`. As for
+    /// [`nearest_where`](#method.nearest_where), `descend` may only reject a subtree if `keep`
+    /// fails for all of its payloads.
+    pub fn within_where<F, P, K>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        distance: &F,
+        descend: P,
+        keep: K,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+        P: Fn(&M) -> bool,
+        K: Fn(&T) -> bool,
+    {
+        let () = self.tree.check_point(point)?;
+        let mut within = vec![];
+        let mut pending = vec![(self.tree.root(), &self.root)];
+        while let Some((node, cached)) = pending.pop() {
+            match (node.bounds(), &cached.value) {
+                (Some((lo, hi)), Some(value)) if descend(value) => {
+                    if distance_to_space_const(point, lo, hi, distance) > radius {
+                        continue;
+                    }
+                }
+                _ => continue,
+            }
+            match (node.left(), node.right(), &cached.children) {
+                (Some(left), Some(right), Some(children)) => {
+                    pending.push((right, &children[1]));
+                    pending.push((left, &children[0]));
+                }
+                _ => {
+                    for (p, d) in node.iter().filter(|(_, d)| keep(d)) {
+                        let dist = distance(point, p);
+                        if dist <= radius {
+                            within.push((dist, d));
+                        }
+                    }
+                }
+            }
+        }
+        within.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(within)
+    }
+
+    pub fn add(&mut self, point: [A; D], data: T) -> Result<(), ErrorKind> {
+        let () = self.tree.add(point, data)?;
+        self.refresh(&point);
+        Ok(())
+    }
+
+    /// Removes all items at `point` equal to `data`, returning how many were removed
+    pub fn remove(&mut self, point: &[A; D], data: &T) -> Result<usize, ErrorKind> {
+        self.remove_matching(point, |d| d == data)
+    }
+
+    /// Removes all items at `point` whose payloads `matches` accepts, returning how many were
+    /// removed
+    pub fn remove_matching<P>(&mut self, point: &[A; D], matches: P) -> Result<usize, ErrorKind>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        let removed = self.tree.remove_matching(point, matches)?;
+        if removed > 0 {
+            self.refresh(point);
+        }
+        Ok(removed)
+    }
+
+    fn merge(&self, a: Option<M>, b: Option<M>) -> Option<M> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some((self.combine)(&a, &b)),
+            (a, None) => a,
+            (None, b) => b,
+        }
+    }
+
+    fn build(&self, node: NodeRef<'_, A, T, D>) -> Node<M> {
+        match (node.left(), node.right()) {
+            (Some(left), Some(right)) => {
+                let children = Box::new([self.build(left), self.build(right)]);
+                let value = self.merge(children[0].value.clone(), children[1].value.clone());
+                Node {
+                    value,
+                    children: Some(children),
+                }
+            }
+            _ => Node {
+                value: node
+                    .iter()
+                    .fold(None, |acc, (_, d)| self.merge(acc, Some((self.map)(d)))),
+                children: None,
+            },
+        }
+    }
+
+    /// Recomputes the aggregates on the path to `point` after it was added or removed. Subtrees
+    /// on that path which were split or collapsed since are rebuilt.
+    fn refresh(&mut self, point: &[A; D]) {
+        let mut root = std::mem::replace(
+            &mut self.root,
+            Node {
+                value: None,
+                children: None,
+            },
+        );
+        self.refresh_in(self.tree.root(), &mut root, point);
+        self.root = root;
+    }
+
+    fn refresh_in(&self, node: NodeRef<'_, A, T, D>, cached: &mut Node<M>, point: &[A; D]) {
+        let (dim, split) = match (
+            node.split_dimension(),
+            node.split_value(),
+            &mut cached.children,
+        ) {
+            (Some(dim), Some(split), Some(_)) => (dim, split),
+            _ => {
+                *cached = self.build(node);
+                return;
+            }
+        };
+        let children = cached.children.as_mut().unwrap();
+        if point[dim] < split {
+            self.refresh_in(node.left().unwrap(), &mut children[0], point);
+        } else {
+            self.refresh_in(node.right().unwrap(), &mut children[1], point);
+        }
+        cached.value = self.merge(children[0].value.clone(), children[1].value.clone());
+    }
+
+    fn within_box(
+        &self,
+        node: NodeRef<'_, A, T, D>,
+        cached: &Node<M>,
+        min: &[A; D],
+        max: &[A; D],
+    ) -> Option<M> {
+        let (lo, hi) = node.bounds()?;
+        if (0..D).any(|i| hi[i] < min[i] || lo[i] > max[i]) {
+            return None;
+        }
+        if (0..D).all(|i| min[i] <= lo[i] && hi[i] <= max[i]) {
+            return cached.value.clone();
+        }
+        match (node.left(), node.right(), &cached.children) {
+            (Some(left), Some(right), Some(children)) => {
+                let a = self.within_box(left, &children[0], min, max);
+                let b = self.within_box(right, &children[1], min, max);
+                self.merge(a, b)
+            }
+            _ => node
+                .iter()
+                .filter(|(p, _)| (0..D).all(|i| min[i] <= p[i] && p[i] <= max[i]))
+                .fold(None, |acc, (_, d)| self.merge(acc, Some((self.map)(d)))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Aggregated;
+
+    fn mass(m: &u64) -> u64 {
+        *m
+    }
+
+    fn sum(a: &u64, b: &u64) -> u64 {
+        a + b
+    }
+
+    fn brute_force(items: &[([f64; 2], u64)], min: &[f64; 2], max: &[f64; 2]) -> Option<u64> {
+        items
+            .iter()
+            .filter(|(p, _)| (0..2).all(|i| min[i] <= p[i] && p[i] <= max[i]))
+            .map(|&(_, m)| m)
+            .reduce(|a, b| a + b)
+    }
+
+    fn check(tree: &Aggregated<f64, u64, u64, 2>, items: &[([f64; 2], u64)]) {
+        assert_eq!(
+            tree.aggregate(),
+            items.iter().map(|&(_, m)| m).reduce(|a, b| a + b)
+        );
+        for i in 0..20 {
+            let x = (i * 37 % 100) as f64;
+            let y = (i * 53 % 100) as f64;
+            let (min, max) = ([x - 20.0, y - 15.0], [x + 10.0, y + 25.0]);
+            assert_eq!(
+                tree.aggregate_within_box(&min, &max).unwrap(),
+                brute_force(items, &min, &max)
+            );
+        }
+    }
+
+    #[test]
+    fn it_keeps_aggregates_through_splits_and_removals() {
+        let mut items: Vec<([f64; 2], u64)> = (0..2000u64)
+            .map(|i| ([(i * 7919 % 100) as f64, (i * 104729 % 97) as f64], i))
+            .collect();
+        let mut tree = Aggregated::with_capacity(8, mass, sum);
+        for &(p, m) in &items {
+            tree.add(p, m).unwrap();
+        }
+        assert!(tree.tree().stats().stems > 100);
+        check(&tree, &items);
+
+        let built = Aggregated::from_points(items.clone(), 8, mass, sum).unwrap();
+        check(&built, &items);
+
+        for &(p, m) in items.iter().filter(|(_, m)| m % 3 != 0) {
+            assert_eq!(tree.remove(&p, &m).unwrap(), 1);
+        }
+        items.retain(|(_, m)| m % 3 == 0);
+        check(&tree, &items);
+
+        for &(p, m) in &items {
+            tree.remove(&p, &m).unwrap();
+        }
+        assert_eq!(tree.aggregate(), None);
+        assert_eq!(tree.aggregate_within_box(&[0.0; 2], &[100.0; 2]), Ok(None));
+    }
+}
@@ -0,0 +1,155 @@
+//! Building an [`OwnedKdTree`] from Apache Arrow arrays, enabled by the `arrow` feature.
+
+use arrow_array::types::{ArrowPrimitiveType, Float32Type, Float64Type};
+use arrow_array::{Array, FixedSizeListArray, PrimitiveArray, UInt64Array};
+use num_traits::{Float, One, ToPrimitive, Zero};
+
+use crate::error::ErrorKind;
+use crate::tree::OwnedKdTree;
+
+impl<A: Float + Zero + One, const D: usize> OwnedKdTree<A, u64, D> {
+    /// Build a tree from a column of `f32` or `f64` lists of length `D` and a column of
+    /// payloads. Rows where either column, or any coordinate, is null are skipped; their number
+    /// is returned with the tree.
+    pub fn from_arrow(
+        points: &FixedSizeListArray,
+        data: &UInt64Array,
+        capacity: usize,
+    ) -> Result<(Self, usize), ErrorKind> {
+        if points.len() != data.len() {
+            return Err(ErrorKind::LengthMismatch);
+        }
+        if points.value_length() as usize != D {
+            return Err(ErrorKind::DimensionMismatch {
+                expected: D,
+                got: points.value_length() as usize,
+            });
+        }
+        let values = points.values().as_any();
+        let (items, skipped) = if let Some(values) = values.downcast_ref() {
+            rows::<A, Float64Type, D>(points, values, data)
+        } else if let Some(values) = values.downcast_ref() {
+            rows::<A, Float32Type, D>(points, values, data)
+        } else {
+            return Err(ErrorKind::UnsupportedType);
+        };
+        Ok((OwnedKdTree::from_points(items, capacity)?, skipped))
+    }
+}
+
+/// The non-null rows of `points` and `data`, and the number of rows skipped
+fn rows<A: Float, P: ArrowPrimitiveType, const D: usize>(
+    points: &FixedSizeListArray,
+    values: &PrimitiveArray<P>,
+    data: &UInt64Array,
+) -> (Vec<([A; D], u64)>, usize)
+where
+    P::Native: ToPrimitive,
+{
+    let mut items = Vec::with_capacity(points.len());
+    let mut skipped = 0;
+    'rows: for row in 0..points.len() {
+        if points.is_null(row) || data.is_null(row) {
+            skipped += 1;
+            continue;
+        }
+        let offset = points.value_offset(row) as usize;
+        let mut point = [A::nan(); D];
+        for (dim, x) in point.iter_mut().enumerate() {
+            if values.is_null(offset + dim) {
+                skipped += 1;
+                continue 'rows;
+            }
+            *x = A::from(values.value(offset + dim)).unwrap_or_else(A::nan);
+        }
+        items.push((point, data.value(row)));
+    }
+    (items, skipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::distance::squared_euclidean;
+    use crate::error::ErrorKind;
+    use crate::tree::OwnedKdTree;
+    use arrow_array::builder::{FixedSizeListBuilder, Float32Builder, Float64Builder};
+    use arrow_array::{FixedSizeListArray, UInt64Array};
+
+    /// A column of `f64` lists from rows of optional points
+    fn points_column<const D: usize>(rows: &[Option<[f64; D]>]) -> FixedSizeListArray {
+        let mut builder = FixedSizeListBuilder::new(Float64Builder::new(), D as i32);
+        for row in rows {
+            match row {
+                Some(point) => builder.values().append_slice(point),
+                None => builder.values().append_nulls(D),
+            }
+            builder.append(row.is_some());
+        }
+        builder.finish()
+    }
+
+    #[test]
+    fn it_builds_from_arrow_arrays() {
+        let rows: Vec<Option<[f64; 2]>> = (0..100)
+            .map(|i| {
+                if i % 10 == 3 {
+                    None
+                } else {
+                    Some([i as f64, (i % 7) as f64])
+                }
+            })
+            .collect();
+        let points = points_column(&rows);
+        let data: UInt64Array = (0..100u64)
+            .map(|i| if i % 25 == 0 { None } else { Some(i) })
+            .collect();
+
+        let (tree, skipped) = OwnedKdTree::<f64, u64, 2>::from_arrow(&points, &data, 8).unwrap();
+        assert_eq!(skipped, 14);
+        assert_eq!(tree.size(), 86);
+        let dist = |a: &[f64; 2], b: &[f64; 2]| squared_euclidean(a, b);
+        assert_eq!(
+            tree.nearest(&[42.0, 0.0], 1, &dist).unwrap(),
+            vec![(0.0, &42)]
+        );
+    }
+
+    #[test]
+    fn it_reads_f32_coordinates() {
+        let mut builder = FixedSizeListBuilder::new(Float32Builder::new(), 3);
+        for i in 0..10 {
+            builder.values().append_slice(&[i as f32, 0.5, -1.0]);
+            builder.append(true);
+        }
+        builder.values().append_slice(&[1.0, f32::NAN, 0.0]);
+        builder.append(true);
+        let points = builder.finish();
+
+        let data: UInt64Array = (0..10u64).map(Some).collect();
+        let (tree, skipped) =
+            OwnedKdTree::<f64, u64, 3>::from_arrow(&points.slice(0, 10), &data, 4).unwrap();
+        assert_eq!((tree.size(), skipped), (10, 0));
+
+        let data: UInt64Array = (0..11u64).map(Some).collect();
+        let result = OwnedKdTree::<f64, u64, 3>::from_arrow(&points, &data, 4);
+        assert_eq!(result.unwrap_err(), ErrorKind::NonFiniteCoordinate);
+    }
+
+    #[test]
+    fn it_rejects_mismatched_columns() {
+        let points = points_column(&[Some([0.0, 1.0, 2.0]); 4]);
+        let data: UInt64Array = (0..4u64).map(Some).collect();
+        let result = OwnedKdTree::<f64, u64, 2>::from_arrow(&points, &data, 8);
+        assert_eq!(
+            result.unwrap_err(),
+            ErrorKind::DimensionMismatch {
+                expected: 2,
+                got: 3
+            }
+        );
+
+        let data: UInt64Array = (0..3u64).map(Some).collect();
+        let result = OwnedKdTree::<f64, u64, 3>::from_arrow(&points, &data, 8);
+        assert_eq!(result.unwrap_err(), ErrorKind::LengthMismatch);
+    }
+}
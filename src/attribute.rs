@@ -0,0 +1,217 @@
+//! A tree whose subtrees cache the range of a numeric attribute of their payloads, such as a
+//! timestamp or a price, so that queries restricted to an attribute range skip the subtrees
+//! holding no payload in it.
+
+use num_traits::{Float, One, Zero};
+
+use crate::aggregate::Aggregated;
+use crate::error::ErrorKind;
+use crate::tree::OwnedKdTree;
+
+/// An [`OwnedKdTree`] storing every item with its attribute, `attribute_of(payload)`. Every
+/// subtree caches the smallest and largest attribute below it; the caches follow the tree
+/// through adds, removes and splits.
+pub struct AttributeIndexed<A, T: PartialEq, B: PartialEq, const D: usize> {
+    tree: Aggregated<A, (B, T), (B, B), D>,
+    attribute_of: fn(&T) -> B,
+}
+
+fn range<B: Copy, T>(entry: &(B, T)) -> (B, B) {
+    (entry.0, entry.0)
+}
+
+fn hull<B: PartialOrd + Copy>(a: &(B, B), b: &(B, B)) -> (B, B) {
+    let min = if b.0 < a.0 { b.0 } else { a.0 };
+    let max = if b.1 > a.1 { b.1 } else { a.1 };
+    (min, max)
+}
+
+impl<A, T, B, const D: usize> AttributeIndexed<A, T, B, D>
+where
+    A: Float + Zero + One,
+    T: PartialEq,
+    B: PartialOrd + Copy,
+{
+    pub fn with_capacity(capacity: usize, attribute_of: fn(&T) -> B) -> Self {
+        AttributeIndexed {
+            tree: Aggregated::with_capacity(capacity, range, hull),
+            attribute_of,
+        }
+    }
+
+    pub fn from_points(
+        items: Vec<([A; D], T)>,
+        capacity: usize,
+        attribute_of: fn(&T) -> B,
+    ) -> Result<Self, ErrorKind> {
+        let items = items
+            .into_iter()
+            .map(|(point, data)| (point, (attribute_of(&data), data)))
+            .collect();
+        let tree = Aggregated::from_points(items, capacity, range, hull)?;
+        Ok(AttributeIndexed { tree, attribute_of })
+    }
+
+    /// The underlying tree, storing `(attribute, data)` pairs
+    pub fn tree(&self) -> &OwnedKdTree<A, (B, T), D> {
+        self.tree.tree()
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    /// The smallest and largest attribute of all payloads, `None` if the tree is empty
+    pub fn attribute_range(&self) -> Option<(B, B)> {
+        self.tree.aggregate()
+    }
+
+    pub fn add(&mut self, point: [A; D], data: T) -> Result<(), ErrorKind> {
+        self.tree.add(point, ((self.attribute_of)(&data), data))
+    }
+
+    /// Removes all items at `point` equal to `data`, returning how many were removed
+    pub fn remove(&mut self, point: &[A; D], data: &T) -> Result<usize, ErrorKind> {
+        self.tree.remove_matching(point, |(_, d)| d == data)
+    }
+
+    /// The items within `radius` of `point` whose attribute is in `[min, max]`, bounds
+    /// included, sorted by distance. Subtrees farther than `radius` or whose attribute range
+    /// does not meet `[min, max]` are skipped without looking at their payloads.
+    pub fn within_filtered_by_attr<F>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        (min, max): (B, B),
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let within = self.tree.within_where(
+            point,
+            radius,
+            distance,
+            |&(lo, hi)| lo <= max && min <= hi,
+            |&(attribute, _)| min <= attribute && attribute <= max,
+        )?;
+        Ok(within.into_iter().map(|(d, (_, data))| (d, data)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::{Rng, SeedableRng, StdRng};
+    use super::AttributeIndexed;
+    use crate::distance::squared_euclidean;
+
+    fn dist(a: &[f64; 2], b: &[f64; 2]) -> f64 {
+        squared_euclidean(a, b)
+    }
+
+    fn timestamp(item: &(u32, u64)) -> u64 {
+        item.1
+    }
+
+    fn brute_force<'a>(
+        items: &'a [([f64; 2], (u32, u64))],
+        point: &[f64; 2],
+        radius: f64,
+        (min, max): (u64, u64),
+    ) -> Vec<(f64, &'a (u32, u64))> {
+        let mut within: Vec<(f64, &(u32, u64))> = items
+            .iter()
+            .filter(|(_, d)| min <= d.1 && d.1 <= max)
+            .map(|(p, d)| (dist(point, p), d))
+            .filter(|&(d, _)| d <= radius)
+            .collect();
+        within.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        within
+    }
+
+    fn check(tree: &AttributeIndexed<f64, (u32, u64), u64, 2>, items: &[([f64; 2], (u32, u64))]) {
+        let mut rng: StdRng = SeedableRng::from_seed(&[19, 5, 2][..]);
+        let ranges = [
+            (0, 10_000),
+            (4000, 4100),
+            (9990, 10_000),
+            (0, 0),
+            (7000, 6000),
+        ];
+        for range in ranges.iter().copied() {
+            for _ in 0..20 {
+                let point = [rng.gen_range(0.0, 100.0), rng.gen_range(0.0, 100.0)];
+                let radius = rng.gen_range(1.0, 2000.0);
+                let mut found = tree
+                    .within_filtered_by_attr(&point, radius, range, &dist)
+                    .unwrap();
+                found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                assert_eq!(found, brute_force(items, &point, radius, range));
+            }
+        }
+    }
+
+    #[test]
+    fn it_filters_by_attribute_like_a_brute_force_search() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[19, 5, 1][..]);
+        // timestamps grow as the items drift across space, as for a moving sensor
+        let mut items: Vec<([f64; 2], (u32, u64))> = (0..3000u32)
+            .map(|i| {
+                let x = i as f64 / 30.0 + rng.gen_range(-5.0, 5.0);
+                let point = [x.clamp(0.0, 100.0), rng.gen_range(0.0, 100.0)];
+                (point, (i, (i as u64 * 10_000 / 3000)))
+            })
+            .collect();
+        let mut tree = AttributeIndexed::with_capacity(8, timestamp);
+        for &(point, data) in &items {
+            tree.add(point, data).unwrap();
+        }
+        assert_eq!(tree.attribute_range(), Some((0, 9996)));
+        check(&tree, &items);
+
+        let built = AttributeIndexed::from_points(items.clone(), 8, timestamp).unwrap();
+        check(&built, &items);
+
+        for &(point, data) in items.iter().filter(|(_, d)| d.0 % 4 != 0 || d.1 > 9000) {
+            assert_eq!(tree.remove(&point, &data).unwrap(), 1);
+        }
+        items.retain(|(_, d)| d.0 % 4 == 0 && d.1 <= 9000);
+        assert_eq!(tree.size(), items.len());
+        assert_eq!(tree.attribute_range(), Some((0, 9000)));
+        check(&tree, &items);
+        assert!(tree
+            .within_filtered_by_attr(&[f64::NAN, 0.0], 1.0, (0, 1), &dist)
+            .is_err());
+    }
+
+    #[test]
+    fn it_skips_subtrees_outside_the_attribute_range() {
+        let items: Vec<([f64; 2], (u32, u64))> = (0..4000u32)
+            .map(|i| ([(i % 100) as f64, (i / 100) as f64], (i, i as u64)))
+            .collect();
+        let tree = AttributeIndexed::from_points(items, 8, timestamp).unwrap();
+        let measured = std::cell::Cell::new(0);
+        let counting = |a: &[f64; 2], b: &[f64; 2]| {
+            measured.set(measured.get() + 1);
+            dist(a, b)
+        };
+        // the timestamps of a tight range are all in one row of the grid
+        let found = tree
+            .within_filtered_by_attr(&[50.0, 20.0], 1e6, (2010, 2030), &counting)
+            .unwrap();
+        assert_eq!(found.len(), 21);
+        let tight = measured.replace(0);
+        let found = tree
+            .within_filtered_by_attr(&[50.0, 20.0], 1e6, (0, 4000), &counting)
+            .unwrap();
+        assert_eq!(found.len(), 4000);
+        assert!(
+            tight * 20 < measured.get(),
+            "{} of {}",
+            tight,
+            measured.get()
+        );
+    }
+}
@@ -0,0 +1,165 @@
+//! Seeded datasets for comparing build and query configurations, used by `benches/matrix.rs`
+//! and reusable from tests.
+//!
+//! ## Running
+//!
+//! `cargo bench` runs the whole matrix with [criterion](https://docs.rs/criterion) on a stable
+//! toolchain. Benchmarks whose name matches the arguments after `--` are the only ones run, so
+//! `cargo bench --bench matrix -- nearest/clustered/d8` times the 8 dimensional clustered
+//! queries alone.
+//!
+//! ## Reading the results
+//!
+//! Every configuration changes one knob of a baseline of `f64` coordinates, leaves of
+//! [`DEFAULT_LEAF_CAPACITY`](crate::tree::DEFAULT_LEAF_CAPACITY), the
+//! [`Widest`](crate::tree::SplitStrategy::Widest) split and a boxed tree, and its name
+//! lists the value of every knob:
+//!
+//! ```text
+//! nearest/uniform/d3/f64/cap16/widest/boxed
+//!                         time:   [2.4731 µs 2.5182 µs 2.5950 µs]
+//! ```
+//!
+//! The middle number is criterion's estimate of the time of one iteration and the outer ones
+//! bound its confidence interval. Compare lines which differ in a single knob; a difference
+//! within the intervals is noise. On a second run criterion also reports the change from the
+//! previous one, and `target/criterion` holds the full reports. The datasets only depend on
+//! their seed, so the numbers of two commits measured on one machine are comparable.
+
+use num_traits::Float;
+
+/// A splitmix64 generator, so that datasets depend on the seed alone
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Normally distributed with mean 0 and standard deviation 1
+    pub fn gaussian(&mut self) -> f64 {
+        let u = 1.0 - self.next_f64();
+        let v = self.next_f64();
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+    }
+}
+
+/// How the points of a dataset are spread, roughly over the unit cube
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Distribution {
+    /// Uniform over the unit cube
+    Uniform,
+    /// Gaussian clusters of standard deviation `spread` around `clusters` uniform centres
+    Clustered { clusters: usize, spread: f64 },
+    /// On a smooth surface of two dimensions folded through every dimension, the way real
+    /// data of many dimensions often has few degrees of freedom
+    Manifold,
+}
+
+impl Distribution {
+    /// The short name used in benchmark names
+    pub fn name(&self) -> &'static str {
+        match self {
+            Distribution::Uniform => "uniform",
+            Distribution::Clustered { .. } => "clustered",
+            Distribution::Manifold => "manifold",
+        }
+    }
+}
+
+/// `n` points spread as `distribution` says, the same for the same `seed`
+pub fn points<A: Float, const D: usize>(
+    distribution: Distribution,
+    n: usize,
+    seed: u64,
+) -> Vec<[A; D]> {
+    let mut rng = Rng::new(seed);
+    let centres: Vec<[f64; D]> = match distribution {
+        Distribution::Clustered { clusters, .. } => (0..clusters.max(1))
+            .map(|_| std::array::from_fn(|_| rng.next_f64()))
+            .collect(),
+        _ => vec![],
+    };
+    (0..n)
+        .map(|_| {
+            let point: [f64; D] = match distribution {
+                Distribution::Uniform => std::array::from_fn(|_| rng.next_f64()),
+                Distribution::Clustered { spread, .. } => {
+                    let centre = centres[rng.next_u64() as usize % centres.len()];
+                    std::array::from_fn(|d| centre[d] + spread * rng.gaussian())
+                }
+                Distribution::Manifold => {
+                    let (u, v) = (rng.next_f64(), rng.next_f64());
+                    std::array::from_fn(|d| {
+                        let phase = u * (d + 1) as f64 + v * (d % 3) as f64;
+                        0.5 + 0.5 * (2.0 * std::f64::consts::PI * phase).sin()
+                    })
+                }
+            };
+            point.map(|x| A::from(x).unwrap_or_else(A::zero))
+        })
+        .collect()
+}
+
+/// [`points`] paired with their positions as payloads
+pub fn items<A: Float, const D: usize>(
+    distribution: Distribution,
+    n: usize,
+    seed: u64,
+) -> Vec<([A; D], usize)> {
+    points(distribution, n, seed).into_iter().zip(0..).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{items, points, Distribution};
+
+    #[test]
+    fn it_generates_the_same_points_for_the_same_seed() {
+        let clustered = Distribution::Clustered {
+            clusters: 5,
+            spread: 0.01,
+        };
+        for &distribution in &[Distribution::Uniform, clustered, Distribution::Manifold] {
+            let a: Vec<[f64; 8]> = points(distribution, 1000, 7);
+            assert_eq!(a, points(distribution, 1000, 7));
+            assert_ne!(a, points(distribution, 1000, 8));
+            let narrow: Vec<[f32; 8]> = points(distribution, 1000, 7);
+            assert!(a.iter().zip(&narrow).all(|(a, b)| a[3] as f32 == b[3]));
+        }
+        let uniform: Vec<[f64; 3]> = points(Distribution::Uniform, 1000, 1);
+        assert!(uniform.iter().flatten().all(|&x| (0.0..1.0).contains(&x)));
+        let manifold: Vec<[f64; 32]> = points(Distribution::Manifold, 1000, 1);
+        assert!(manifold.iter().flatten().all(|&x| (0.0..=1.0).contains(&x)));
+
+        // every point lies near one of the 5 centres
+        let clustered: Vec<[f64; 2]> = points(clustered, 1000, 1);
+        let mut cells: Vec<(i64, i64)> = clustered
+            .iter()
+            .map(|p| ((p[0] * 10.0).round() as i64, (p[1] * 10.0).round() as i64))
+            .collect();
+        cells.sort_unstable();
+        cells.dedup();
+        assert!(cells.len() <= 20, "{:?}", cells);
+
+        let ids: Vec<usize> = items::<f64, 2>(Distribution::Uniform, 10, 1)
+            .into_iter()
+            .map(|(_, i)| i)
+            .collect();
+        assert!(ids.into_iter().eq(0..10));
+    }
+}
@@ -0,0 +1,234 @@
+//! A tree holding at most a fixed number of items, for caching the spatial objects relevant to
+//! a moving point of interest.
+//!
+//! Inserting into a full [`BoundedKdTree`] evicts one item, chosen by its [`Eviction`] policy:
+//! the item farthest from the focus point passed along with the insertion, found with
+//! [`OwnedKdTree::iter_farthest`] by squared euclidean distance, or the oldest insertion.
+//!
+//! ```rust
+//! use kdtree::bounded::{BoundedKdTree, Eviction};
+//!
+//! let mut cache = BoundedKdTree::new(2, Eviction::Farthest);
+//! let focus = [0.0, 0.0];
+//! assert_eq!(cache.insert([1.0, 0.0], "near", &focus).unwrap(), None);
+//! assert_eq!(cache.insert([9.0, 0.0], "far", &focus).unwrap(), None);
+//! let evicted = cache.insert([2.0, 0.0], "nearer", &focus).unwrap();
+//! assert_eq!(evicted, Some(([9.0, 0.0], "far")));
+//! assert_eq!(cache.size(), 2);
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+
+use num_traits::{Float, One, Zero};
+
+use crate::distance::squared_euclidean;
+use crate::error::ErrorKind;
+use crate::tree::{OwnedKdTree, DEFAULT_LEAF_CAPACITY};
+
+/// Which item a full [`BoundedKdTree`] evicts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Eviction {
+    /// The item farthest from the focus point of the insertion
+    Farthest,
+    /// The item inserted first
+    Oldest,
+}
+
+/// A tree of at most `bound` items, see the [module documentation](index.html)
+pub struct BoundedKdTree<A, T, const D: usize> {
+    /// Ids of the items, indexing `items`
+    tree: OwnedKdTree<A, u64, D>,
+    items: HashMap<u64, ([A; D], T)>,
+    /// Ids in insertion order, kept for `Eviction::Oldest`
+    order: VecDeque<u64>,
+    next_id: u64,
+    bound: usize,
+    eviction: Eviction,
+}
+
+impl<A: Float + Zero + One, T, const D: usize> BoundedKdTree<A, T, D> {
+    /// Create an empty tree of at most `bound` items with leaves of [`DEFAULT_LEAF_CAPACITY`]
+    pub fn new(bound: usize, eviction: Eviction) -> Self {
+        BoundedKdTree {
+            tree: OwnedKdTree::with_capacity(DEFAULT_LEAF_CAPACITY),
+            items: HashMap::new(),
+            order: VecDeque::new(),
+            next_id: 0,
+            bound,
+            eviction,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    /// The largest number of items the tree holds
+    pub fn bound(&self) -> usize {
+        self.bound
+    }
+
+    pub fn eviction(&self) -> Eviction {
+        self.eviction
+    }
+
+    /// Add `data` at `point`, then evict and return an item if there are more than
+    /// [`bound`](#method.bound), which may be the item just added. `focus` is the point of
+    /// interest from which `Eviction::Farthest` evicts the farthest item, and is ignored by
+    /// `Eviction::Oldest`. Nothing is added if `point` or `focus` has a non finite coordinate,
+    /// which returns `ErrorKind::NonFiniteCoordinate`.
+    pub fn insert(
+        &mut self,
+        point: [A; D],
+        data: T,
+        focus: &[A; D],
+    ) -> Result<Option<([A; D], T)>, ErrorKind> {
+        let () = self.tree.check_point(focus)?;
+        let id = self.next_id;
+        self.tree.add(point, id)?;
+        self.next_id += 1;
+        self.items.insert(id, (point, data));
+        if self.eviction == Eviction::Oldest {
+            self.order.push_back(id);
+        }
+        if self.tree.size() <= self.bound {
+            return Ok(None);
+        }
+        let victim = match self.eviction {
+            Eviction::Farthest => {
+                let distance = |a: &[A; D], b: &[A; D]| squared_euclidean(a, b);
+                let mut farthest = self.tree.iter_farthest(focus, &distance)?;
+                *farthest.next().unwrap().1
+            }
+            Eviction::Oldest => self.order.pop_front().unwrap(),
+        };
+        let (point, data) = self.items.remove(&victim).unwrap();
+        self.tree.remove(&point, &victim)?;
+        Ok(Some((point, data)))
+    }
+
+    /// The `num` items nearest to `point` with their distances, nearest first
+    pub fn nearest<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let found = self.tree.nearest(point, num, distance)?;
+        Ok(found
+            .into_iter()
+            .map(|(d, id)| (d, &self.items[id].1))
+            .collect())
+    }
+
+    /// The items within `radius` of `point` with their distances, nearest first
+    pub fn within<F>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let found = self.tree.within(point, radius, distance)?;
+        Ok(found
+            .into_iter()
+            .map(|(d, id)| (d, &self.items[id].1))
+            .collect())
+    }
+
+    /// Iterate over the items in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&[A; D], &T)> {
+        self.items.values().map(|(point, data)| (point, data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::{Rng, SeedableRng, StdRng};
+    use super::{BoundedKdTree, Eviction};
+    use crate::distance::squared_euclidean;
+    use crate::error::ErrorKind;
+
+    fn dist(a: &[f64; 2], b: &[f64; 2]) -> f64 {
+        squared_euclidean(a, b)
+    }
+
+    #[test]
+    fn it_evicts_the_farthest_item_from_the_focus() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[4, 1, 1][..]);
+        let mut cache = BoundedKdTree::new(50, Eviction::Farthest);
+        let mut model: Vec<([f64; 2], usize)> = vec![];
+        for i in 0..1000 {
+            // the focus wanders, so items are evicted from all sides
+            let focus = [(i as f64 / 50.0).cos() * 5.0, (i as f64 / 70.0).sin() * 5.0];
+            let point = [rng.gen_range(-10.0, 10.0), rng.gen_range(-10.0, 10.0)];
+            let evicted = cache.insert(point, i, &focus).unwrap();
+            model.push((point, i));
+            if model.len() > 50 {
+                let victim = (0..model.len())
+                    .max_by(|&a, &b| {
+                        let a = dist(&model[a].0, &focus);
+                        let b = dist(&model[b].0, &focus);
+                        a.partial_cmp(&b).unwrap()
+                    })
+                    .unwrap();
+                assert_eq!(evicted, Some(model.swap_remove(victim)));
+            } else {
+                assert_eq!(evicted, None);
+            }
+            assert!(cache.size() <= 50);
+            assert_eq!(cache.size(), model.len());
+        }
+        let mut held: Vec<usize> = cache.iter().map(|(_, &i)| i).collect();
+        let mut expected: Vec<usize> = model.iter().map(|&(_, i)| i).collect();
+        held.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(held, expected);
+        let nearest = cache.nearest(&model[0].0, 1, &dist).unwrap();
+        assert_eq!(nearest, vec![(0.0, &model[0].1)]);
+    }
+
+    #[test]
+    fn it_evicts_the_oldest_item() {
+        let mut cache = BoundedKdTree::new(3, Eviction::Oldest);
+        let focus = [0.0, 0.0];
+        for i in 0..3 {
+            assert_eq!(cache.insert([i as f64, 0.0], i, &focus), Ok(None));
+        }
+        for i in 3..20 {
+            let evicted = cache.insert([i as f64, 0.0], i, &focus).unwrap();
+            assert_eq!(evicted, Some(([(i - 3) as f64, 0.0], i - 3)));
+            assert_eq!(cache.size(), 3);
+        }
+        let within: Vec<i32> = cache
+            .within(&[0.0, 0.0], 1000.0, &dist)
+            .unwrap()
+            .into_iter()
+            .map(|(_, &i)| i)
+            .collect();
+        assert_eq!(within, vec![17, 18, 19]);
+
+        assert_eq!(
+            cache.insert([f64::NAN, 0.0], 100, &focus),
+            Err(ErrorKind::NonFiniteCoordinate)
+        );
+        assert_eq!(
+            cache.insert([1.0, 0.0], 100, &[f64::INFINITY, 0.0]),
+            Err(ErrorKind::NonFiniteCoordinate)
+        );
+        assert_eq!(cache.size(), 3);
+        assert_eq!(cache.insert([1.0, 0.0], 20, &focus).unwrap().unwrap().1, 17);
+
+        let mut nothing = BoundedKdTree::new(0, Eviction::Farthest);
+        let evicted = nothing.insert([1.0, 2.0], "a", &focus).unwrap();
+        assert_eq!(evicted, Some(([1.0, 2.0], "a")));
+        assert_eq!(nothing.size(), 0);
+    }
+}
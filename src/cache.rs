@@ -0,0 +1,288 @@
+//! Memoized nearest neighbour queries for callers which repeat queries at nearly the same points,
+//! such as agents clustered in space asking for their neighbours every tick.
+//!
+//! [`CachedQueries`] quantizes every query to a cubic cell of a given size. The first query in a
+//! cell collects every item which could be among the `num` nearest of any point in the cell, and
+//! later queries in the cell only rank those candidates. The results are exact: identical to
+//! [`OwnedKdTree::nearest`] with [`squared_euclidean`], except for the order of items stored at
+//! the same point, which is unspecified in both.
+//!
+//! ```rust
+//! use kdtree::cache::CachedQueries;
+//! use kdtree::distance::squared_euclidean;
+//! use kdtree::OwnedKdTree;
+//!
+//! let mut tree = OwnedKdTree::new();
+//! for i in 0..100 {
+//!     tree.add([i as f64, 0.0], i).unwrap();
+//! }
+//! let mut cached = CachedQueries::new(&tree, 1.0, 64).unwrap();
+//! let first = cached.nearest(&[10.2, 0.4], 2).unwrap();
+//! let second = cached.nearest(&[10.7, 0.1], 2).unwrap();
+//! let distance = |a: &[f64; 2], b: &[f64; 2]| squared_euclidean(a, b);
+//! assert_eq!(second, tree.nearest(&[10.7, 0.1], 2, &distance).unwrap());
+//! assert_eq!((cached.hits(), cached.misses()), (1, 1));
+//! assert_eq!(first.iter().map(|&(_, &i)| i).collect::<Vec<_>>(), vec![10, 11]);
+//! ```
+
+use std::collections::VecDeque;
+
+use num_traits::{Float, One, Zero};
+
+use crate::distance::squared_euclidean;
+use crate::error::ErrorKind;
+use crate::geometry::distance_to_space_const;
+use crate::heap_element::Neighbour;
+use crate::tree::OwnedKdTree;
+
+/// The items which can be among the `num` nearest of any point in a cell
+struct Entry<'t, A, T, const D: usize> {
+    cell: [i64; D],
+    num: usize,
+    candidates: Vec<(&'t [A; D], &'t T)>,
+}
+
+/// Caches [`nearest`](#method.nearest) queries by squared Euclidean distance per query cell and
+/// number of neighbours, keeping the `capacity` most recently used cells. The tree cannot change
+/// while it is borrowed, so cached entries are never stale; to query a modified tree, build a new
+/// cache. [`invalidate_all`](#method.invalidate_all) drops the entries, for instance once the
+/// queries move to other parts of the space.
+///
+/// Each entry holds every item within the distance of the `num`th nearest item to the cell's
+/// centre plus the cell's diagonal, so cells much larger than the spacing of the items make
+/// entries large and the first query of each cell slow.
+pub struct CachedQueries<'t, A, T: PartialEq, const D: usize> {
+    tree: &'t OwnedKdTree<A, T, D>,
+    cell_size: A,
+    capacity: usize,
+    entries: VecDeque<Entry<'t, A, T, D>>,
+    hits: usize,
+    misses: usize,
+}
+
+impl<'t, A: Float + Zero + One, T: PartialEq, const D: usize> CachedQueries<'t, A, T, D> {
+    /// Caches queries to `tree` in cells of side `cell_size`, for at most `capacity` cells at a
+    /// time. A `cell_size` which is not positive and finite returns `ErrorKind::InvalidRadius`.
+    pub fn new(
+        tree: &'t OwnedKdTree<A, T, D>,
+        cell_size: A,
+        capacity: usize,
+    ) -> Result<Self, ErrorKind> {
+        if !cell_size.is_finite() || cell_size <= A::zero() {
+            return Err(ErrorKind::InvalidRadius);
+        }
+        Ok(CachedQueries {
+            tree,
+            cell_size,
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+            hits: 0,
+            misses: 0,
+        })
+    }
+
+    /// The `num` items nearest to `point` by squared Euclidean distance, as returned by
+    /// [`OwnedKdTree::nearest`]
+    pub fn nearest(&mut self, point: &[A; D], num: usize) -> Result<Vec<(A, &'t T)>, ErrorKind> {
+        let () = self.tree.check_point(point)?;
+        let cell = match self.cell_of(point) {
+            Some(cell) if self.capacity > 0 => cell,
+            _ => return self.tree.nearest(point, num, &squared),
+        };
+        let found = self
+            .entries
+            .iter()
+            .position(|e| e.num == num && e.cell == cell);
+        let entry = match found {
+            Some(i) => {
+                self.hits += 1;
+                let entry = self.entries.remove(i).unwrap();
+                self.entries.push_back(entry);
+                self.entries.back().unwrap()
+            }
+            None => {
+                self.misses += 1;
+                let candidates = self.candidates(&cell, num)?;
+                if self.entries.len() == self.capacity {
+                    self.entries.pop_front();
+                }
+                self.entries.push_back(Entry {
+                    cell,
+                    num,
+                    candidates,
+                });
+                self.entries.back().unwrap()
+            }
+        };
+        let mut ranked: Vec<Neighbour<A, &T, D>> = entry
+            .candidates
+            .iter()
+            .map(|&(p, element)| Neighbour {
+                distance: squared_euclidean(point, p),
+                point: p,
+                element,
+            })
+            .filter(|n| !n.distance.is_nan())
+            .collect();
+        ranked.sort_unstable();
+        ranked.truncate(num);
+        Ok(ranked.into_iter().map(Into::into).collect())
+    }
+
+    /// Drops every cached cell
+    pub fn invalidate_all(&mut self) {
+        self.entries.clear();
+    }
+
+    /// The number of cached cells
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no cell is cached
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// How many queries were answered from a cached cell
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// How many queries had to collect the candidates of their cell
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    fn cell_of(&self, point: &[A; D]) -> Option<[i64; D]> {
+        let mut cell = [0; D];
+        for (c, &x) in cell.iter_mut().zip(point) {
+            *c = (x / self.cell_size).floor().to_i64()?;
+        }
+        Some(cell)
+    }
+
+    /// Every item within the distance of the `num`th nearest item to the centre of `cell` plus
+    /// the cell's diagonal. For a query `q` in the cell with centre `c` and half diagonal `h`,
+    /// the `num`th nearest item to `q` is at most `d(q, c) + d_num(c) <= h + d_num(c)` away, so
+    /// each of the `num` nearest items to `q` is within `2h + d_num(c)` of `c`.
+    fn candidates(
+        &self,
+        cell: &[i64; D],
+        num: usize,
+    ) -> Result<Vec<(&'t [A; D], &'t T)>, ErrorKind> {
+        let two = A::one() + A::one();
+        let mut centre = [A::zero(); D];
+        for (c, &i) in centre.iter_mut().zip(cell) {
+            *c = (A::from(i).unwrap() + A::one() / two) * self.cell_size;
+        }
+        let tree = self.tree;
+        let nearest = tree.nearest(&centre, num, &squared)?;
+        let radius = match nearest.last() {
+            Some(&(d, _)) if nearest.len() == num => {
+                let diagonal = self.cell_size * A::from(D).unwrap().sqrt();
+                // the slack covers rounding in the distances and the quantization
+                let slack = A::one() + A::epsilon() * A::from(16).unwrap();
+                let radius = (d.sqrt() + diagonal) * slack;
+                radius * radius
+            }
+            _ => A::infinity(),
+        };
+        let mut candidates = vec![];
+        let _ = tree.traverse(
+            |min, max| distance_to_space_const(&centre, min, max, &squared) <= radius,
+            |p, element| {
+                if squared_euclidean(&centre, p) <= radius {
+                    candidates.push((p, element));
+                }
+                std::ops::ControlFlow::Continue(())
+            },
+        );
+        Ok(candidates)
+    }
+}
+
+fn squared<A: Float, const D: usize>(a: &[A; D], b: &[A; D]) -> A {
+    squared_euclidean(a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use super::{squared, CachedQueries};
+    use crate::error::ErrorKind;
+    use crate::tree::OwnedKdTree;
+
+    #[test]
+    fn it_returns_cached_results_identical_to_the_tree() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[1, 4, 2][..]);
+        let mut tree = OwnedKdTree::with_capacity(8);
+        for i in 0..3000 {
+            let point = [rng.gen_range(-10.0, 10.0), rng.gen_range(-10.0, 10.0)];
+            tree.add(point, i).unwrap();
+        }
+        let mut cached = CachedQueries::new(&tree, 0.5, 16).unwrap();
+        // agents wandering around a few spots, so most queries land in a cached cell
+        let spots = [[0.1, 0.2], [-7.3, 4.4], [9.9, -9.9]];
+        for i in 0..600 {
+            let spot = spots[i % spots.len()];
+            let query = [
+                spot[0] + rng.gen_range(-0.4, 0.4),
+                spot[1] + rng.gen_range(-0.4, 0.4),
+            ];
+            let num = [1, 5, 20][i % 3];
+            let expected = tree.nearest(&query, num, &squared).unwrap();
+            assert_eq!(cached.nearest(&query, num).unwrap(), expected);
+        }
+        assert_eq!(cached.hits() + cached.misses(), 600);
+        assert!(cached.hits() > 500);
+        assert!(cached.len() <= 16);
+
+        let everything = cached.nearest(&[0.0, 0.0], 5000).unwrap();
+        assert_eq!(everything.len(), 3000);
+        assert_eq!(
+            cached.nearest(&[f64::NAN, 0.0], 1),
+            Err(ErrorKind::NonFiniteCoordinate)
+        );
+        assert!(CachedQueries::new(&tree, 0.0, 4).is_err());
+        assert!(CachedQueries::new(&tree, f64::INFINITY, 4).is_err());
+    }
+
+    #[test]
+    fn it_evicts_and_invalidates_cells() {
+        let mut tree = OwnedKdTree::with_capacity(2);
+        for i in 0..50 {
+            tree.add([i as f64], i).unwrap();
+        }
+        let mut cached = CachedQueries::new(&tree, 1.0, 2).unwrap();
+        cached.nearest(&[1.5], 3).unwrap();
+        cached.nearest(&[2.5], 3).unwrap();
+        cached.nearest(&[1.2], 3).unwrap();
+        assert_eq!((cached.hits(), cached.misses()), (1, 2));
+        // evicts the cell of 2.5, used least recently
+        cached.nearest(&[30.5], 3).unwrap();
+        cached.nearest(&[1.7], 3).unwrap();
+        cached.nearest(&[2.1], 3).unwrap();
+        assert_eq!((cached.hits(), cached.misses()), (2, 4));
+        // separate entries for different numbers of neighbours
+        let expected = tree.nearest(&[2.1], 1, &squared).unwrap();
+        assert_eq!(cached.nearest(&[2.1], 1).unwrap(), expected);
+        assert_eq!(cached.misses(), 5);
+
+        cached.invalidate_all();
+        assert!(cached.is_empty());
+        assert_eq!(
+            cached.nearest(&[30.5], 2).unwrap(),
+            vec![(0.25, &30), (0.25, &31)]
+        );
+        assert_eq!((cached.hits(), cached.misses()), (2, 6));
+
+        let mut uncached = CachedQueries::new(&tree, 1.0, 0).unwrap();
+        let expected = tree.nearest(&[4.4], 1, &squared).unwrap();
+        assert_eq!(uncached.nearest(&[4.4], 1).unwrap(), expected);
+        assert!(uncached.is_empty());
+    }
+}
@@ -0,0 +1,249 @@
+//! Space filling curves used to order points so that items close on the curve are also close in
+//! space.
+
+use num_traits::Float;
+
+/// Quantize `value` to an integer in `0..2^bits` relative to the range `[min, max]`, with
+/// `bits` of at least 64 using the whole range of `u64`
+fn quantize<A: Float>(value: A, min: A, max: A, bits: u32) -> u64 {
+    let cells = u64::MAX.checked_shr(64 - bits.min(64)).unwrap_or(0) as f64;
+    let extent = (max - min).to_f64().unwrap_or(0.0);
+    if extent <= 0.0 || !extent.is_finite() {
+        return 0;
+    }
+    let offset = (value - min).to_f64().unwrap_or(0.0) / extent;
+    (offset.clamp(0.0, 1.0) * cells).round() as u64
+}
+
+/// Returns the Morton (Z-order) index of `point` within the box `[min, max]`, quantizing every
+/// coordinate to `bits` bits and interleaving them with the first dimension as the least
+/// significant bit of each group of `D` bits. `bits` above 64 count as 64. If `bits * D` is
+/// more than 64, only the 64 most significant bits of the index are kept, which orders the
+/// points along a coarser curve.
+///
+/// # Examples
+///
+/// ```rust
+/// use kdtree::curve::morton_index;
+///
+/// let (min, max) = ([0.0, 0.0], [1.0, 1.0]);
+/// assert_eq!(morton_index(&[0.0, 0.0], &min, &max, 1), 0);
+/// assert_eq!(morton_index(&[1.0, 0.0], &min, &max, 1), 1);
+/// assert_eq!(morton_index(&[0.0, 1.0], &min, &max, 1), 2);
+/// assert_eq!(morton_index(&[1.0, 1.0], &min, &max, 1), 3);
+/// ```
+pub fn morton_index<A: Float, const D: usize>(
+    point: &[A; D],
+    min: &[A; D],
+    max: &[A; D],
+    bits: u32,
+) -> u64 {
+    let bits = bits.min(64);
+    let mut x = [0u64; D];
+    for dim in 0..D {
+        x[dim] = quantize(point[dim], min[dim], max[dim], bits);
+    }
+    let mut index = 0;
+    let mut taken = 0;
+    for bit in (0..bits).rev() {
+        for v in x.iter().rev() {
+            if taken == 64 {
+                return index;
+            }
+            index = (index << 1) | ((v >> bit) & 1);
+            taken += 1;
+        }
+    }
+    index
+}
+
+/// Returns the Hilbert index of `point` within the box `[min, max]`, quantizing every coordinate
+/// to `bits` bits as [`morton_index`] does. Unlike the Morton curve, the Hilbert curve never
+/// jumps: points of a grid of `2^bits` cells per side which are next to each other on the
+/// curve are also next to each other in space. The index is computed by Skilling's transform of
+/// the quantized coordinates, with the first dimension as the most significant bit of each
+/// group of `D` bits. `bits` above 64 count as 64. If `bits * D` is more than 64, only the 64
+/// most significant bits of the index are kept, which orders the points along a coarser curve.
+///
+/// # Examples
+///
+/// ```rust
+/// use kdtree::curve::hilbert_index;
+///
+/// let (min, max) = ([0.0, 0.0], [1.0, 1.0]);
+/// assert_eq!(hilbert_index(&[0.0, 0.0], &min, &max, 1), 0);
+/// assert_eq!(hilbert_index(&[0.0, 1.0], &min, &max, 1), 1);
+/// assert_eq!(hilbert_index(&[1.0, 1.0], &min, &max, 1), 2);
+/// assert_eq!(hilbert_index(&[1.0, 0.0], &min, &max, 1), 3);
+/// ```
+pub fn hilbert_index<A: Float, const D: usize>(
+    point: &[A; D],
+    min: &[A; D],
+    max: &[A; D],
+    bits: u32,
+) -> u64 {
+    let bits = bits.min(64);
+    if bits == 0 {
+        return 0;
+    }
+    let mut x = [0u64; D];
+    for dim in 0..D {
+        x[dim] = quantize(point[dim], min[dim], max[dim], bits);
+    }
+    // Skilling, "Programming the Hilbert curve", AIP Conference Proceedings 707 (2004)
+    let high = 1u64 << (bits - 1);
+    let mut q = high;
+    while q > 1 {
+        let p = q - 1;
+        for dim in 0..D {
+            if x[dim] & q != 0 {
+                x[0] ^= p;
+            } else {
+                let t = (x[0] ^ x[dim]) & p;
+                x[0] ^= t;
+                x[dim] ^= t;
+            }
+        }
+        q >>= 1;
+    }
+    for dim in 1..D {
+        x[dim] ^= x[dim - 1];
+    }
+    let mut t = 0;
+    let mut q = high;
+    while q > 1 {
+        if x[D - 1] & q != 0 {
+            t ^= q - 1;
+        }
+        q >>= 1;
+    }
+    for v in x.iter_mut() {
+        *v ^= t;
+    }
+    let mut index = 0;
+    let mut taken = 0;
+    for bit in (0..bits).rev() {
+        for v in x.iter() {
+            if taken == 64 {
+                return index;
+            }
+            index = (index << 1) | ((v >> bit) & 1);
+            taken += 1;
+        }
+    }
+    index
+}
+
+/// The number of bits per dimension that fit into a 64 bit curve index
+pub(crate) fn bits_per_dimension(dimensions: usize) -> u32 {
+    (64 / dimensions.max(1)).clamp(1, 32) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hilbert_index, morton_index};
+
+    #[test]
+    fn it_interleaves_bits() {
+        let (min, max) = ([0.0, 0.0], [3.0, 3.0]);
+        assert_eq!(morton_index(&[3.0, 0.0], &min, &max, 2), 0b0101);
+        assert_eq!(morton_index(&[0.0, 3.0], &min, &max, 2), 0b1010);
+        assert_eq!(morton_index(&[2.0, 1.0], &min, &max, 2), 0b0110);
+    }
+
+    #[test]
+    fn it_keeps_the_most_significant_bits() {
+        let (min, max) = ([0.0; 2], [1.0; 2]);
+        let near = morton_index(&[0.1, 0.1], &min, &max, 40);
+        let far = morton_index(&[0.9, 0.9], &min, &max, 40);
+        assert!(near < far);
+        assert_eq!(morton_index(&[1.0, 1.0], &min, &max, 40), u64::MAX);
+        assert_eq!(morton_index(&[0.5, 0.0], &min, &max, 40), 1 << 62);
+    }
+
+    #[test]
+    fn it_takes_up_to_64_bits() {
+        let (min, max) = ([0.0], [1.0]);
+        assert_eq!(morton_index(&[0.5], &min, &max, 63), 1 << 62);
+        for &bits in &[64, 65, 200] {
+            assert_eq!(morton_index(&[0.0], &min, &max, bits), 0);
+            assert_eq!(morton_index(&[0.5], &min, &max, bits), 1 << 63);
+        }
+        assert_eq!(morton_index(&[1.0], &min, &max, 64), u64::MAX);
+    }
+
+    #[test]
+    fn it_handles_flat_boxes() {
+        let (min, max) = ([1.0, 0.0], [1.0, 1.0]);
+        assert_eq!(morton_index(&[1.0, 1.0], &min, &max, 4), 0b1010_1010);
+    }
+
+    #[test]
+    fn it_numbers_grid_cells_along_a_continuous_curve() {
+        let (min, max) = ([0.0; 2], [15.0; 2]);
+        let mut cells = vec![None; 256];
+        for x in 0..16i32 {
+            for y in 0..16i32 {
+                let i = hilbert_index(&[x as f64, y as f64], &min, &max, 4) as usize;
+                assert!(cells[i].is_none());
+                cells[i] = Some((x, y));
+            }
+        }
+        let cells: Vec<(i32, i32)> = cells.into_iter().map(Option::unwrap).collect();
+        for pair in cells.windows(2) {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            assert_eq!((x1 - x0).abs() + (y1 - y0).abs(), 1);
+        }
+
+        let (min, max) = ([0.0; 3], [7.0; 3]);
+        let mut cells = vec![None; 512];
+        for i in 0..512 {
+            let point = [(i % 8) as f64, (i / 8 % 8) as f64, (i / 64) as f64];
+            let index = hilbert_index(&point, &min, &max, 3) as usize;
+            assert!(cells[index].is_none());
+            cells[index] = Some(point);
+        }
+        let cells: Vec<[f64; 3]> = cells.into_iter().map(Option::unwrap).collect();
+        for pair in cells.windows(2) {
+            let steps: f64 = (0..3).map(|d| (pair[1][d] - pair[0][d]).abs()).sum();
+            assert_eq!(steps, 1.0);
+        }
+    }
+
+    #[test]
+    fn it_numbers_along_the_hilbert_curve_with_up_to_64_bits() {
+        let (min, max) = ([0.0], [1.0]);
+        for &bits in &[64, 65, 200] {
+            assert_eq!(hilbert_index(&[0.0], &min, &max, bits), 0);
+            assert_eq!(hilbert_index(&[0.5], &min, &max, bits), 1 << 63);
+            assert_eq!(hilbert_index(&[1.0], &min, &max, bits), u64::MAX);
+        }
+
+        let (min, max) = ([0.0; 2], [1.0; 2]);
+        for &bits in &[32, 40, 64] {
+            let corners = [[0.0, 0.0], [0.0, 1.0], [1.0, 1.0], [1.0, 0.0]];
+            let indices: Vec<u64> = corners
+                .iter()
+                .map(|p| hilbert_index(p, &min, &max, bits))
+                .collect();
+            assert!(indices.windows(2).all(|w| w[0] < w[1]), "{:?}", indices);
+        }
+    }
+
+    #[test]
+    fn it_jumps_less_than_the_morton_curve() {
+        let (min, max) = ([0.0; 2], [63.0; 2]);
+        let jumps = |index: fn(&[f64; 2], &[f64; 2], &[f64; 2], u32) -> u64| -> f64 {
+            let mut points: Vec<[f64; 2]> = (0..4096)
+                .map(|i| [(i % 64) as f64, (i / 64) as f64])
+                .collect();
+            points.sort_by_key(|p| index(p, &min, &max, 6));
+            let steps = points
+                .windows(2)
+                .map(|w| (w[1][0] - w[0][0]).abs() + (w[1][1] - w[0][1]).abs());
+            steps.sum()
+        };
+        assert_eq!(jumps(hilbert_index), 4095.0);
+        assert!(jumps(morton_index) > 1.5 * 4095.0);
+    }
+}
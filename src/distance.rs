@@ -1,9 +1,18 @@
 //! Defines different distance metrics, in simplest case it defines the
 //! euclidean distance which is no more than the square root of the sum of the
 //! squares of the distances in each dimension.
+//!
+//! The metrics with their bounds on the distance to a box are in [`metric`](crate::metric) and
+//! the box distances themselves in [`geometry`](crate::geometry), and both are re-exported here.
 
 use num_traits::Float;
 
+pub use crate::geometry::{
+    distance_between_far_corners_const, distance_between_spaces_const,
+    distance_to_far_corner_const, distance_to_space, distance_to_space_const,
+};
+pub use crate::metric::{Chebyshev, Manhattan, Metric, QuadraticForm, Scaled, SquaredEuclidean};
+
 /// Returns the squared euclidean distance between two points. When you only
 /// need to compare distances, rather than having the exact distance between
 /// the points, this metric is benefitial because it avoids the expensive square
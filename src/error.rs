@@ -0,0 +1,126 @@
+//! The errors of [`OwnedKdTree`](crate::tree::OwnedKdTree) and of the structures built on it.
+//! The original [`KdTree`](crate::KdTree) keeps its own [`ErrorKind`](crate::kdtree::ErrorKind).
+//!
+//! ```rust
+//! use kdtree::error::ErrorKind;
+//! use kdtree::tree::OwnedKdTree;
+//!
+//! let mut tree: OwnedKdTree<f64, usize, 2> = OwnedKdTree::new();
+//! assert_eq!(tree.add([f64::NAN, 0.0], 0), Err(ErrorKind::NonFiniteCoordinate));
+//! ```
+
+#[derive(Debug, PartialEq)]
+pub enum ErrorKind {
+    NonFiniteCoordinate,
+    ZeroCapacity,
+    Cancelled,
+    ZeroVector,
+    InvalidRadius,
+    LengthMismatch,
+    /// A point or a stored tree has `got` dimensions where `expected` are needed
+    DimensionMismatch {
+        expected: usize,
+        got: usize,
+    },
+    UnsupportedType,
+    InvalidFormat,
+    FormatVersionMismatch,
+    InvalidTrajectory,
+    /// The tree already holds its [`max_items`](crate::tree::Builder::max_items)
+    Full,
+    /// The matrix of a [`QuadraticForm`](crate::metric::QuadraticForm) is not positive
+    /// definite
+    NotPositiveDefinite,
+    /// Coordinate `dim` of a point is too large for the coordinate type it is converted to
+    CastOverflow {
+        dim: usize,
+    },
+    /// The lower bound of a box is above its upper bound in some dimension
+    InvalidBounds,
+    /// Coordinate `dim` of a point lies outside the bounds the tree was created with, see
+    /// [`OutOfBounds::Reject`](crate::tree::OutOfBounds::Reject)
+    OutOfBounds {
+        dim: usize,
+    },
+    /// An item a search found is not in the leaf its point belongs in, which only a tree whose
+    /// invariants were broken reports
+    ItemNotFound,
+    /// The angle of a cone is negative or NaN
+    InvalidAngle,
+}
+
+impl std::error::Error for ErrorKind {}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let reason = match *self {
+            ErrorKind::NonFiniteCoordinate => "non-finite coordinate",
+            ErrorKind::ZeroCapacity => "zero capacity",
+            ErrorKind::Cancelled => "cancelled",
+            ErrorKind::ZeroVector => "zero vector",
+            ErrorKind::InvalidRadius => "invalid radius",
+            ErrorKind::LengthMismatch => "length mismatch",
+            ErrorKind::DimensionMismatch { expected, got } => {
+                return write!(
+                    f,
+                    "KdTree error: dimension mismatch, expected {} but got {}",
+                    expected, got
+                )
+            }
+            ErrorKind::UnsupportedType => "unsupported type",
+            ErrorKind::InvalidFormat => "invalid format",
+            ErrorKind::FormatVersionMismatch => "format version mismatch",
+            ErrorKind::InvalidTrajectory => "invalid trajectory",
+            ErrorKind::Full => "full",
+            ErrorKind::NotPositiveDefinite => "not positive definite",
+            ErrorKind::CastOverflow { dim } => {
+                return write!(f, "KdTree error: coordinate {} overflows the cast", dim)
+            }
+            ErrorKind::InvalidBounds => "invalid bounds",
+            ErrorKind::OutOfBounds { dim } => {
+                return write!(f, "KdTree error: coordinate {} is out of bounds", dim)
+            }
+            ErrorKind::ItemNotFound => "item not found",
+            ErrorKind::InvalidAngle => "invalid angle",
+        };
+        write!(f, "KdTree error: {}", reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ErrorKind;
+    use crate::tree::OwnedKdTree;
+
+    fn add_twice(tree: &mut OwnedKdTree<f64, usize, 2>) -> Result<(), Box<dyn std::error::Error>> {
+        tree.add([0.0, 0.0], 0)?;
+        tree.add([0.0, f64::INFINITY], 1)?;
+        Ok(())
+    }
+
+    #[test]
+    fn it_describes_the_errors() {
+        assert_eq!(
+            ErrorKind::InvalidRadius.to_string(),
+            "KdTree error: invalid radius"
+        );
+        assert_eq!(
+            ErrorKind::DimensionMismatch {
+                expected: 3,
+                got: 2
+            }
+            .to_string(),
+            "KdTree error: dimension mismatch, expected 3 but got 2"
+        );
+        assert_eq!(
+            ErrorKind::OutOfBounds { dim: 1 }.to_string(),
+            "KdTree error: coordinate 1 is out of bounds"
+        );
+
+        let mut tree = OwnedKdTree::new();
+        let error = add_twice(&mut tree).unwrap_err();
+        assert_eq!(error.to_string(), "KdTree error: non-finite coordinate");
+        assert!(error.source().is_none());
+        assert_eq!(tree.size(), 1);
+    }
+}
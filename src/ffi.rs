@@ -0,0 +1,378 @@
+//! A C interface to an [`OwnedKdTree`] of `double` coordinates and `uint64_t` payloads, enabled
+//! by the `ffi` feature. A shared library exporting it can be built with
+//! `cargo rustc --release --features ffi --crate-type cdylib`.
+//!
+//! Trees are opaque handles created by [`kdtree_create`] with a number of dimensions between 1
+//! and [`KDTREE_MAX_DIMENSIONS`], and freed by [`kdtree_destroy`]. Points are pointers to as many
+//! `double`s as the tree has dimensions, and distances are squared euclidean distances.
+//!
+//! No panic crosses the boundary: a panic inside a call is caught and reported as
+//! [`KDTREE_PANIC`], after which the tree may only be destroyed. Functions returning a status
+//! return one of the `KDTREE_*` codes. Functions returning a count return [`KDTREE_ERROR`] on
+//! failure, and [`kdtree_last_error`] tells why.
+
+use std::convert::TryInto;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::distance::squared_euclidean;
+use crate::error::ErrorKind;
+use crate::tree::OwnedKdTree;
+
+pub const KDTREE_OK: i32 = 0;
+pub const KDTREE_NULL_POINTER: i32 = 1;
+pub const KDTREE_NON_FINITE_COORDINATE: i32 = 2;
+pub const KDTREE_INVALID_RADIUS: i32 = 3;
+pub const KDTREE_PANIC: i32 = 4;
+pub const KDTREE_FAILED: i32 = 5;
+
+/// Returned instead of a count when a call fails
+pub const KDTREE_ERROR: usize = usize::MAX;
+
+/// The largest number of dimensions of a tree created by [`kdtree_create`]
+pub const KDTREE_MAX_DIMENSIONS: usize = 4;
+
+enum Tree {
+    D1(OwnedKdTree<f64, u64, 1>),
+    D2(OwnedKdTree<f64, u64, 2>),
+    D3(OwnedKdTree<f64, u64, 3>),
+    D4(OwnedKdTree<f64, u64, 4>),
+}
+
+/// Evaluates `$body` with `$tree` bound to the tree of whichever dimension `$handle` holds
+macro_rules! with_tree {
+    ($handle:expr, $tree:ident => $body:expr) => {
+        match $handle {
+            Tree::D1($tree) => $body,
+            Tree::D2($tree) => $body,
+            Tree::D3($tree) => $body,
+            Tree::D4($tree) => $body,
+        }
+    };
+}
+
+/// A tree behind a C handle
+pub struct KdTreeHandle {
+    tree: Tree,
+    last_error: i32,
+}
+
+fn code(error: ErrorKind) -> i32 {
+    match error {
+        ErrorKind::NonFiniteCoordinate => KDTREE_NON_FINITE_COORDINATE,
+        ErrorKind::InvalidRadius => KDTREE_INVALID_RADIUS,
+        _ => KDTREE_FAILED,
+    }
+}
+
+/// Runs `f` on the tree of `handle`, catching panics and recording the outcome as the last error
+unsafe fn call<R, F>(handle: *mut KdTreeHandle, f: F) -> Result<R, i32>
+where
+    F: FnOnce(&mut Tree) -> Result<R, i32>,
+{
+    let handle = match handle.as_mut() {
+        Some(handle) => handle,
+        None => return Err(KDTREE_NULL_POINTER),
+    };
+    let tree = &mut handle.tree;
+    let result = panic::catch_unwind(AssertUnwindSafe(|| f(tree))).unwrap_or(Err(KDTREE_PANIC));
+    handle.last_error = match result {
+        Ok(_) => KDTREE_OK,
+        Err(code) => code,
+    };
+    result
+}
+
+unsafe fn read_point<const D: usize>(point: *const f64) -> Result<[f64; D], i32> {
+    if point.is_null() {
+        return Err(KDTREE_NULL_POINTER);
+    }
+    Ok(std::slice::from_raw_parts(point, D).try_into().unwrap())
+}
+
+/// Writes `found` to the output arrays, which hold at least `capacity` entries
+unsafe fn write_out(
+    found: &[(f64, &u64)],
+    capacity: usize,
+    out_distances: *mut f64,
+    out_data: *mut u64,
+) -> Result<(), i32> {
+    let written = std::cmp::min(found.len(), capacity);
+    if written > 0 && (out_distances.is_null() || out_data.is_null()) {
+        return Err(KDTREE_NULL_POINTER);
+    }
+    for (i, &(distance, &data)) in found[..written].iter().enumerate() {
+        *out_distances.add(i) = distance;
+        *out_data.add(i) = data;
+    }
+    Ok(())
+}
+
+fn distance<const D: usize>(a: &[f64; D], b: &[f64; D]) -> f64 {
+    squared_euclidean(a, b)
+}
+
+/// Creates an empty tree of points with `dimensions` coordinates and leaves holding up to
+/// `capacity` items. Returns null if `dimensions` is not between 1 and
+/// [`KDTREE_MAX_DIMENSIONS`], or `capacity` is zero.
+#[no_mangle]
+pub extern "C" fn kdtree_create(dimensions: usize, capacity: usize) -> *mut KdTreeHandle {
+    if capacity == 0 {
+        return std::ptr::null_mut();
+    }
+    let tree = match dimensions {
+        1 => Tree::D1(OwnedKdTree::with_capacity(capacity)),
+        2 => Tree::D2(OwnedKdTree::with_capacity(capacity)),
+        3 => Tree::D3(OwnedKdTree::with_capacity(capacity)),
+        4 => Tree::D4(OwnedKdTree::with_capacity(capacity)),
+        _ => return std::ptr::null_mut(),
+    };
+    Box::into_raw(Box::new(KdTreeHandle {
+        tree,
+        last_error: KDTREE_OK,
+    }))
+}
+
+/// Frees a tree created by [`kdtree_create`]. Null handles are ignored.
+///
+/// # Safety
+///
+/// `handle` must be null or a handle returned by [`kdtree_create`] which was not destroyed yet.
+#[no_mangle]
+pub unsafe extern "C" fn kdtree_destroy(handle: *mut KdTreeHandle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// The error code of the last call on `handle`, [`KDTREE_OK`] if it succeeded
+///
+/// # Safety
+///
+/// `handle` must be null or a live handle returned by [`kdtree_create`].
+#[no_mangle]
+pub unsafe extern "C" fn kdtree_last_error(handle: *const KdTreeHandle) -> i32 {
+    handle
+        .as_ref()
+        .map_or(KDTREE_NULL_POINTER, |handle| handle.last_error)
+}
+
+/// The number of items in the tree, or [`KDTREE_ERROR`] for a null handle
+///
+/// # Safety
+///
+/// `handle` must be null or a live handle returned by [`kdtree_create`].
+#[no_mangle]
+pub unsafe extern "C" fn kdtree_size(handle: *mut KdTreeHandle) -> usize {
+    call(handle, |tree| Ok(with_tree!(tree, tree => tree.size()))).unwrap_or(KDTREE_ERROR)
+}
+
+/// Adds `data` at `point`, returning a status code
+///
+/// # Safety
+///
+/// `handle` must be null or a live handle returned by [`kdtree_create`], and `point` must be
+/// null or point to as many `double`s as the tree has dimensions.
+#[no_mangle]
+pub unsafe extern "C" fn kdtree_add(
+    handle: *mut KdTreeHandle,
+    point: *const f64,
+    data: u64,
+) -> i32 {
+    let added = call(
+        handle,
+        |tree| with_tree!(tree, tree => tree.add(read_point(point)?, data).map_err(code)),
+    );
+    added.err().unwrap_or(KDTREE_OK)
+}
+
+/// Removes every item at `point` whose payload is `data`, returning how many were removed or
+/// [`KDTREE_ERROR`]
+///
+/// # Safety
+///
+/// As for [`kdtree_add`].
+#[no_mangle]
+pub unsafe extern "C" fn kdtree_remove(
+    handle: *mut KdTreeHandle,
+    point: *const f64,
+    data: u64,
+) -> usize {
+    let removed = call(
+        handle,
+        |tree| with_tree!(tree, tree => tree.remove(&read_point(point)?, &data).map_err(code)),
+    );
+    removed.unwrap_or(KDTREE_ERROR)
+}
+
+/// Writes the `k` items nearest to `point`, nearest first, to `out_distances` and `out_data`,
+/// returning how many were written or [`KDTREE_ERROR`]
+///
+/// # Safety
+///
+/// As for [`kdtree_add`], and `out_distances` and `out_data` must point to at least `k`
+/// writable entries each, unless `k` is zero.
+#[no_mangle]
+pub unsafe extern "C" fn kdtree_nearest(
+    handle: *mut KdTreeHandle,
+    point: *const f64,
+    k: usize,
+    out_distances: *mut f64,
+    out_data: *mut u64,
+) -> usize {
+    let written = call(handle, |tree| {
+        with_tree!(tree, tree => {
+            let found = tree.nearest(&read_point(point)?, k, &distance).map_err(code)?;
+            write_out(&found, k, out_distances, out_data)?;
+            Ok(found.len())
+        })
+    });
+    written.unwrap_or(KDTREE_ERROR)
+}
+
+/// Writes the items within the squared distance `radius` of `point`, nearest first, to
+/// `out_distances` and `out_data`, up to `capacity` of them. Returns the number of items within
+/// `radius`, which may exceed `capacity`, or [`KDTREE_ERROR`].
+///
+/// # Safety
+///
+/// As for [`kdtree_add`], and `out_distances` and `out_data` must point to at least `capacity`
+/// writable entries each, unless `capacity` is zero.
+#[no_mangle]
+pub unsafe extern "C" fn kdtree_within(
+    handle: *mut KdTreeHandle,
+    point: *const f64,
+    radius: f64,
+    out_distances: *mut f64,
+    out_data: *mut u64,
+    capacity: usize,
+) -> usize {
+    let found = call(handle, |tree| {
+        if radius.is_nan() || radius < 0.0 {
+            return Err(KDTREE_INVALID_RADIUS);
+        }
+        with_tree!(tree, tree => {
+            let found = tree.within(&read_point(point)?, radius, &distance).map_err(code)?;
+            write_out(&found, capacity, out_distances, out_data)?;
+            Ok(found.len())
+        })
+    });
+    found.unwrap_or(KDTREE_ERROR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_queries_through_the_c_interface() {
+        unsafe {
+            let tree = kdtree_create(3, 2);
+            assert!(!tree.is_null());
+            for i in 0..20u64 {
+                let point = [i as f64, 0.0, (i % 2) as f64];
+                assert_eq!(kdtree_add(tree, point.as_ptr(), i), KDTREE_OK);
+            }
+            assert_eq!(kdtree_size(tree), 20);
+
+            let query = [4.2, 0.0, 0.0];
+            let mut distances = [0.0; 3];
+            let mut data = [0u64; 3];
+            let found = kdtree_nearest(
+                tree,
+                query.as_ptr(),
+                3,
+                distances.as_mut_ptr(),
+                data.as_mut_ptr(),
+            );
+            assert_eq!(found, 3);
+            assert_eq!(data, [4, 5, 3]);
+            assert_eq!(distances[0], squared_euclidean(&query, &[4.0, 0.0, 0.0]));
+
+            let mut distances = [0.0; 2];
+            let mut data = [0u64; 2];
+            let within = kdtree_within(
+                tree,
+                query.as_ptr(),
+                4.0,
+                distances.as_mut_ptr(),
+                data.as_mut_ptr(),
+                2,
+            );
+            assert_eq!(within, 4);
+            assert_eq!(data, [4, 5]);
+            let counted = kdtree_within(
+                tree,
+                query.as_ptr(),
+                4.0,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                0,
+            );
+            assert_eq!(counted, 4);
+
+            assert_eq!(kdtree_remove(tree, [4.0, 0.0, 0.0].as_ptr(), 4), 1);
+            assert_eq!(kdtree_remove(tree, [4.0, 0.0, 0.0].as_ptr(), 4), 0);
+            assert_eq!(kdtree_size(tree), 19);
+            assert_eq!(kdtree_last_error(tree), KDTREE_OK);
+            kdtree_destroy(tree);
+        }
+    }
+
+    #[test]
+    fn it_reports_errors_as_codes() {
+        unsafe {
+            assert!(kdtree_create(0, 8).is_null());
+            assert!(kdtree_create(KDTREE_MAX_DIMENSIONS + 1, 8).is_null());
+            assert!(kdtree_create(2, 0).is_null());
+
+            let tree = kdtree_create(2, 8);
+            let nan = [f64::NAN, 0.0];
+            assert_eq!(
+                kdtree_add(tree, nan.as_ptr(), 0),
+                KDTREE_NON_FINITE_COORDINATE
+            );
+            assert_eq!(kdtree_add(tree, std::ptr::null(), 0), KDTREE_NULL_POINTER);
+            let origin = [0.0, 0.0];
+            assert_eq!(kdtree_add(tree, origin.as_ptr(), 7), KDTREE_OK);
+            let nearest = kdtree_nearest(
+                tree,
+                origin.as_ptr(),
+                1,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            );
+            assert_eq!(nearest, KDTREE_ERROR);
+            assert_eq!(kdtree_last_error(tree), KDTREE_NULL_POINTER);
+            let within = kdtree_within(
+                tree,
+                origin.as_ptr(),
+                -1.0,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                0,
+            );
+            assert_eq!(within, KDTREE_ERROR);
+            assert_eq!(kdtree_last_error(tree), KDTREE_INVALID_RADIUS);
+            assert_eq!(kdtree_size(tree), 1);
+            assert_eq!(kdtree_last_error(tree), KDTREE_OK);
+            kdtree_destroy(tree);
+
+            let null = std::ptr::null_mut();
+            assert_eq!(kdtree_add(null, origin.as_ptr(), 0), KDTREE_NULL_POINTER);
+            assert_eq!(kdtree_size(null), KDTREE_ERROR);
+            assert_eq!(kdtree_last_error(null), KDTREE_NULL_POINTER);
+            kdtree_destroy(null);
+        }
+    }
+
+    #[test]
+    fn it_catches_panics() {
+        unsafe {
+            let tree = kdtree_create(1, 8);
+            let result = call(tree, |_| -> Result<(), i32> { panic!("inside the tree") });
+            assert_eq!(result, Err(KDTREE_PANIC));
+            assert_eq!(kdtree_last_error(tree), KDTREE_PANIC);
+            kdtree_destroy(tree);
+        }
+    }
+}
@@ -0,0 +1,869 @@
+//! A frozen [`OwnedKdTree`](crate::OwnedKdTree) stored in flat arrays rather than boxed nodes.
+//!
+//! With the `mmap` feature a [`FlatKdTree`] can be written to a file with `write_to` and queried
+//! through a `MappedKdTree` directly over the bytes of the file, without reading the nodes into
+//! memory first.
+
+use std::collections::BinaryHeap;
+
+use num_traits::Float;
+
+use crate::error::ErrorKind;
+use crate::geometry::distance_to_space_const;
+use crate::heap_element::Neighbour;
+use crate::priority::{MinByDistance, Priority};
+use crate::query::QueryStats;
+use crate::search::{self, belongs_in_left};
+
+/// Marks a leaf in the first link of a node, which otherwise holds the split dimension
+pub(crate) const LEAF: u64 = u64::MAX;
+
+/// A tree in preorder: node `i` has the bounds `min_bounds[i]` and `max_bounds[i]`. A stem has
+/// `links[i] == [split dimension, left child, right child]` and splits at `splits[i]`, while a
+/// leaf has `links[i] == [LEAF, first item, number of items]`. The items of every leaf are
+/// contiguous in `points` and `data`, sorted along the split dimension of the leaf's parent, or
+/// the first dimension if the leaf is the root.
+#[derive(Clone, Debug)]
+pub struct FlatKdTree<A, T, const D: usize> {
+    pub(crate) min_bounds: Vec<[A; D]>,
+    pub(crate) max_bounds: Vec<[A; D]>,
+    pub(crate) splits: Vec<A>,
+    pub(crate) links: Vec<[u64; 3]>,
+    pub(crate) points: Vec<[A; D]>,
+    pub(crate) data: Vec<T>,
+}
+
+impl<A: Float, T, const D: usize> FlatKdTree<A, T, D> {
+    pub(crate) fn empty() -> Self {
+        FlatKdTree {
+            min_bounds: vec![],
+            max_bounds: vec![],
+            splits: vec![],
+            links: vec![],
+            points: vec![],
+            data: vec![],
+        }
+    }
+
+    fn view(&self) -> Flat<'_, A, T, D> {
+        Flat {
+            min_bounds: &self.min_bounds,
+            max_bounds: &self.max_bounds,
+            splits: &self.splits,
+            links: &self.links,
+            points: &self.points,
+            data: &self.data,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.points.len()
+    }
+
+    /// All stored points, leaf by leaf
+    pub fn points(&self) -> &[[A; D]] {
+        &self.points
+    }
+
+    /// The payloads of [`points`](#method.points), in the same order
+    pub fn data(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn nearest<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        self.view().nearest(point, num, distance)
+    }
+
+    pub fn within<F>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        self.view().within(point, radius, distance)
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn nearest_where<F, K>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &F,
+        keep: &K,
+    ) -> Result<Vec<Neighbour<'_, A, &T, D>>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+        K: Fn(usize) -> bool + ?Sized,
+    {
+        self.view().nearest_where(point, num, distance, keep)
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn within_where<F, K>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        distance: &F,
+        keep: &K,
+    ) -> Result<Vec<Neighbour<'_, A, &T, D>>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+        K: Fn(usize) -> bool + ?Sized,
+    {
+        self.view().within_where(point, radius, distance, keep)
+    }
+
+    /// Items whose points lie within the box `[min, max]`, bounds included, leaf by leaf. As
+    /// the items of a leaf are sorted along one dimension, only those between the bounds of
+    /// the box along it are looked at.
+    pub fn within_box(&self, min: &[A; D], max: &[A; D]) -> Result<Vec<&T>, ErrorKind> {
+        self.view()
+            .within_box(min, max, false, &mut QueryStats::default())
+    }
+
+    /// The indices into [`points`](#method.points) of the items stored exactly at `point`
+    pub(crate) fn positions(&self, point: &[A; D]) -> Vec<usize> {
+        self.view().positions(point)
+    }
+}
+
+/// The arrays of a flat tree, owned or mapped
+struct Flat<'a, A, T, const D: usize> {
+    min_bounds: &'a [[A; D]],
+    max_bounds: &'a [[A; D]],
+    splits: &'a [A],
+    links: &'a [[u64; 3]],
+    points: &'a [[A; D]],
+    data: &'a [T],
+}
+
+impl<'a, A: Float, T, const D: usize> Flat<'a, A, T, D> {
+    fn check_point(point: &[A; D]) -> Result<(), ErrorKind> {
+        if point.iter().all(|n| n.is_finite()) {
+            Ok(())
+        } else {
+            Err(ErrorKind::NonFiniteCoordinate)
+        }
+    }
+
+    fn node_distance<F>(&self, point: &[A; D], node: usize, distance: &F) -> A
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        distance_to_space_const(
+            point,
+            &self.min_bounds[node],
+            &self.max_bounds[node],
+            distance,
+        )
+    }
+
+    /// The items of a leaf with their indices into `points` and `data`
+    fn items(&self, node: usize) -> impl Iterator<Item = (usize, &'a [A; D], &'a T)> {
+        let [_, first, len] = self.links[node];
+        let range = first as usize..(first + len) as usize;
+        let items = self.points[range.clone()].iter().zip(&self.data[range]);
+        (first as usize..).zip(items).map(|(i, (p, d))| (i, p, d))
+    }
+
+    fn nearest<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &F,
+    ) -> Result<Vec<(A, &'a T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let nearest = self.nearest_where(point, num, distance, &|_| true)?;
+        Ok(nearest.into_iter().map(Into::into).collect())
+    }
+
+    /// The `num` nearest items for which `keep` returns true given their index, nearest first
+    #[allow(clippy::type_complexity)]
+    fn nearest_where<F, K>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &F,
+        keep: &K,
+    ) -> Result<Vec<Neighbour<'a, A, &'a T, D>>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+        K: Fn(usize) -> bool + ?Sized,
+    {
+        let () = Self::check_point(point)?;
+        let num = std::cmp::min(num, self.points.len());
+        if num == 0 {
+            return Ok(vec![]);
+        }
+        let mut pending = BinaryHeap::new();
+        let mut evaluated = BinaryHeap::<Neighbour<A, &T, D>>::new();
+        pending.push(MinByDistance::new(A::zero(), 0));
+        while let Some(MinByDistance(Priority {
+            distance: node_dist,
+            element: mut node,
+        })) = pending.pop()
+        {
+            let worst = match evaluated.peek() {
+                Some(worst) if evaluated.len() == num => worst.distance,
+                _ => A::infinity(),
+            };
+            if node_dist > worst {
+                break;
+            }
+            loop {
+                let [dim, left, right] = self.links[node];
+                if dim == LEAF {
+                    break;
+                }
+                let (near, far) = if belongs_in_left(point, dim as usize, self.splits[node]) {
+                    (left, right)
+                } else {
+                    (right, left)
+                };
+                let far_dist = self.node_distance(point, far as usize, distance);
+                if far_dist <= worst {
+                    pending.push(MinByDistance::new(far_dist, far as usize));
+                }
+                node = near as usize;
+            }
+            for (i, p, d) in self.items(node) {
+                if !keep(i) {
+                    continue;
+                }
+                let element = Neighbour {
+                    distance: distance(point, p),
+                    point: p,
+                    element: d,
+                };
+                if element.distance.is_nan() {
+                    continue;
+                }
+                if evaluated.len() < num {
+                    evaluated.push(element);
+                } else if element < *evaluated.peek().unwrap() {
+                    evaluated.pop();
+                    evaluated.push(element);
+                }
+            }
+        }
+        Ok(evaluated.into_sorted_vec())
+    }
+
+    fn within<F>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        distance: &F,
+    ) -> Result<Vec<(A, &'a T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let within = self.within_where(point, radius, distance, &|_| true)?;
+        Ok(within.into_iter().map(Into::into).collect())
+    }
+
+    /// The items within `radius` for which `keep` returns true given their index, nearest first
+    #[allow(clippy::type_complexity)]
+    fn within_where<F, K>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        distance: &F,
+        keep: &K,
+    ) -> Result<Vec<Neighbour<'a, A, &'a T, D>>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+        K: Fn(usize) -> bool + ?Sized,
+    {
+        let () = Self::check_point(point)?;
+        let () = search::check_radius(radius)?;
+        let mut within = vec![];
+        let mut pending = vec![0];
+        while let Some(node) = pending.pop() {
+            if self.node_distance(point, node, distance) > radius {
+                continue;
+            }
+            match self.links[node] {
+                [LEAF, _, _] => {
+                    for (i, p, d) in self.items(node) {
+                        let dist = distance(point, p);
+                        if dist <= radius && keep(i) {
+                            within.push(Neighbour {
+                                distance: dist,
+                                point: p,
+                                element: d,
+                            });
+                        }
+                    }
+                }
+                [_, left, right] => pending.extend([left as usize, right as usize]),
+            }
+        }
+        within.sort();
+        Ok(within)
+    }
+
+    /// The items within the box `[min, max]`, looking only at the items of each leaf between
+    /// the bounds of the box along the dimension the leaf is sorted by, or at all of them if
+    /// `whole_leaves`. The work done is added to `stats`.
+    fn within_box(
+        &self,
+        min: &[A; D],
+        max: &[A; D],
+        whole_leaves: bool,
+        stats: &mut QueryStats,
+    ) -> Result<Vec<&'a T>, ErrorKind> {
+        let () = Self::check_point(min)?;
+        let () = Self::check_point(max)?;
+        let mut within = vec![];
+        if self.points.is_empty() {
+            return Ok(within);
+        }
+        // each node with the dimension its leaves are sorted by if it is one
+        let mut pending = vec![(0, 0)];
+        while let Some((node, sorted_by)) = pending.pop() {
+            stats.nodes_visited += 1;
+            let (node_min, node_max) = (&self.min_bounds[node], &self.max_bounds[node]);
+            if (0..D).any(|d| node_max[d] < min[d] || node_min[d] > max[d]) {
+                continue;
+            }
+            let [dim, first, len] = self.links[node];
+            if dim != LEAF {
+                let dim = dim as usize;
+                pending.extend([(len as usize, dim), (first as usize, dim)]);
+                continue;
+            }
+            stats.leaves_visited += 1;
+            let range = first as usize..(first + len) as usize;
+            let (points, data) = (&self.points[range.clone()], &self.data[range]);
+            let (start, end) = if whole_leaves {
+                (0, points.len())
+            } else {
+                let start = points.partition_point(|p| p[sorted_by] < min[sorted_by]);
+                let end = points.partition_point(|p| p[sorted_by] <= max[sorted_by]);
+                (start, end.max(start))
+            };
+            stats.points_visited += end - start;
+            for (p, d) in points[start..end].iter().zip(&data[start..end]) {
+                if (0..D).all(|i| min[i] <= p[i] && p[i] <= max[i]) {
+                    within.push(d);
+                }
+            }
+        }
+        Ok(within)
+    }
+
+    /// The indices of the items stored exactly at `point`
+    fn positions(&self, point: &[A; D]) -> Vec<usize> {
+        let mut positions = vec![];
+        let mut pending = vec![0];
+        while let Some(node) = pending.pop() {
+            let (min, max) = (&self.min_bounds[node], &self.max_bounds[node]);
+            if (0..D).any(|d| point[d] < min[d] || point[d] > max[d]) {
+                continue;
+            }
+            match self.links[node] {
+                [LEAF, _, _] => positions.extend(
+                    self.items(node)
+                        .filter(|&(_, p, _)| p == point)
+                        .map(|x| x.0),
+                ),
+                [_, left, right] => pending.extend([left as usize, right as usize]),
+            }
+        }
+        positions
+    }
+}
+
+#[cfg(feature = "mmap")]
+pub use self::mapped::{MappedKdTree, Pod};
+
+#[cfg(feature = "mmap")]
+mod mapped {
+    use std::convert::TryInto;
+    use std::io::Write;
+    use std::marker::PhantomData;
+    use std::mem::{align_of, size_of};
+
+    use num_traits::Float;
+
+    use super::{Flat, FlatKdTree, LEAF};
+    use crate::error::ErrorKind;
+    use crate::query::QueryStats;
+
+    const MAGIC: [u8; 4] = *b"KDTF";
+    const BYTE_ORDER: u32 = 0x0102_0304;
+    /// Version 2 sorts the items of each leaf as [`FlatKdTree`] describes
+    const VERSION: u32 = 2;
+    const HEADER_LEN: usize = 64;
+    /// Every section starts at a multiple of this
+    const SECTION_ALIGN: usize = 8;
+
+    /// Plain data types without padding for which every bit pattern is a valid value, so that
+    /// they can be read straight from the bytes of a file. Each type has a distinct tag which is
+    /// recorded in the file header.
+    ///
+    /// # Safety
+    ///
+    /// Implementors must be `Copy`, contain no padding or pointers, accept any bit pattern and
+    /// have an alignment of at most 8.
+    pub unsafe trait Pod: Copy + 'static {
+        const TAG: u8;
+    }
+
+    unsafe impl Pod for u8 {
+        const TAG: u8 = 1;
+    }
+    unsafe impl Pod for u16 {
+        const TAG: u8 = 2;
+    }
+    unsafe impl Pod for u32 {
+        const TAG: u8 = 3;
+    }
+    unsafe impl Pod for u64 {
+        const TAG: u8 = 4;
+    }
+    unsafe impl Pod for i32 {
+        const TAG: u8 = 5;
+    }
+    unsafe impl Pod for i64 {
+        const TAG: u8 = 6;
+    }
+    unsafe impl Pod for f32 {
+        const TAG: u8 = 7;
+    }
+    unsafe impl Pod for f64 {
+        const TAG: u8 = 8;
+    }
+
+    fn as_bytes<P: Pod>(values: &[P]) -> &[u8] {
+        // SAFETY: `Pod` types have no padding, so every byte is initialized
+        unsafe {
+            std::slice::from_raw_parts(values.as_ptr() as *const u8, std::mem::size_of_val(values))
+        }
+    }
+
+    fn padding(len: usize) -> usize {
+        (SECTION_ALIGN - len % SECTION_ALIGN) % SECTION_ALIGN
+    }
+
+    /// The byte ranges of the sections of a file, in the order they are written
+    struct Layout {
+        sections: [(usize, usize); 6],
+        len: usize,
+    }
+
+    impl Layout {
+        fn new<A, T, const D: usize>(nodes: usize, items: usize) -> Option<Layout> {
+            let sizes = [
+                nodes.checked_mul(size_of::<[A; D]>())?,
+                nodes.checked_mul(size_of::<[A; D]>())?,
+                nodes.checked_mul(size_of::<A>())?,
+                nodes.checked_mul(size_of::<[u64; 3]>())?,
+                items.checked_mul(size_of::<[A; D]>())?,
+                items.checked_mul(size_of::<T>())?,
+            ];
+            let mut sections = [(0, 0); 6];
+            let mut offset = HEADER_LEN;
+            for (section, size) in sections.iter_mut().zip(sizes) {
+                *section = (offset, offset.checked_add(size)?);
+                offset = section.1.checked_add(padding(section.1))?;
+            }
+            Some(Layout {
+                sections,
+                len: offset,
+            })
+        }
+    }
+
+    impl<A: Float + Pod, T: Pod, const D: usize> FlatKdTree<A, T, D> {
+        /// Writes the tree in the format read by [`MappedKdTree`]: a header recording the format
+        /// version, the byte order and the types of `A` and `T`, followed by the arrays of the
+        /// tree. Numbers are written in native byte order.
+        pub fn write_to<W: Write>(&self, mut w: W) -> std::io::Result<()> {
+            let mut header = [0u8; HEADER_LEN];
+            header[0..4].copy_from_slice(&MAGIC);
+            header[4..8].copy_from_slice(&BYTE_ORDER.to_ne_bytes());
+            header[8..12].copy_from_slice(&VERSION.to_ne_bytes());
+            header[12] = A::TAG;
+            header[13] = T::TAG;
+            header[16..24].copy_from_slice(&(D as u64).to_ne_bytes());
+            header[24..32].copy_from_slice(&(self.links.len() as u64).to_ne_bytes());
+            header[32..40].copy_from_slice(&(self.points.len() as u64).to_ne_bytes());
+            w.write_all(&header)?;
+
+            let sections: [&[u8]; 6] = [
+                as_bytes(flatten(&self.min_bounds)),
+                as_bytes(flatten(&self.max_bounds)),
+                as_bytes(&self.splits),
+                as_bytes(flatten(&self.links)),
+                as_bytes(flatten(&self.points)),
+                as_bytes(&self.data),
+            ];
+            for section in sections {
+                w.write_all(section)?;
+                w.write_all(&[0; SECTION_ALIGN][..padding(section.len())])?;
+            }
+            Ok(())
+        }
+    }
+
+    fn flatten<P, const N: usize>(arrays: &[[P; N]]) -> &[P] {
+        // SAFETY: `[P; N]` has the layout of `N` consecutive values of `P`
+        unsafe { std::slice::from_raw_parts(arrays.as_ptr() as *const P, arrays.len() * N) }
+    }
+
+    /// A flat tree read directly from the bytes written by [`FlatKdTree::write_to`], such as a
+    /// memory-mapped file. The header and the links between nodes are validated once when the
+    /// tree is opened; queries then read the nodes in place.
+    pub struct MappedKdTree<A, T, B, const D: usize> {
+        bytes: B,
+        layout: Layout,
+        marker: PhantomData<(A, T)>,
+    }
+
+    impl<A: Float + Pod, T: Pod, const D: usize> MappedKdTree<A, T, memmap2::Mmap, D> {
+        /// Maps the file at `path` into memory. Invalid files are reported as
+        /// `std::io::ErrorKind::InvalidData` wrapping the [`ErrorKind`] describing the problem.
+        pub fn open<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Self> {
+            let file = std::fs::File::open(path)?;
+            // SAFETY: the file must not be modified while it is mapped, as with any mapped file
+            let bytes = unsafe { memmap2::Mmap::map(&file)? };
+            MappedKdTree::from_bytes(bytes)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        }
+    }
+
+    impl<A: Float + Pod, T: Pod, B: AsRef<[u8]>, const D: usize> MappedKdTree<A, T, B, D> {
+        /// Reads a tree from `bytes`, which must be aligned to 8 bytes. Fails with
+        /// `ErrorKind::InvalidFormat` for bytes which do not hold a tree or are misaligned,
+        /// `ErrorKind::FormatVersionMismatch` for trees written by another version of the
+        /// format, `ErrorKind::UnsupportedType` if `A` or `T` differ from the written types
+        /// and `ErrorKind::DimensionMismatch` if `D` does.
+        pub fn from_bytes(bytes: B) -> Result<Self, ErrorKind> {
+            let data = bytes.as_ref();
+            if data.len() < HEADER_LEN
+                || data[0..4] != MAGIC
+                || !(data.as_ptr() as usize).is_multiple_of(SECTION_ALIGN)
+            {
+                return Err(ErrorKind::InvalidFormat);
+            }
+            let u32_at = |i: usize| u32::from_ne_bytes(data[i..i + 4].try_into().unwrap());
+            let u64_at = |i: usize| u64::from_ne_bytes(data[i..i + 8].try_into().unwrap());
+            if u32_at(4) != BYTE_ORDER {
+                return Err(ErrorKind::InvalidFormat);
+            }
+            if u32_at(8) != VERSION {
+                return Err(ErrorKind::FormatVersionMismatch);
+            }
+            if data[12] != A::TAG || data[13] != T::TAG {
+                return Err(ErrorKind::UnsupportedType);
+            }
+            if u64_at(16) != D as u64 {
+                return Err(ErrorKind::DimensionMismatch {
+                    expected: D,
+                    got: u64_at(16) as usize,
+                });
+            }
+            let (nodes, items) = (u64_at(24) as usize, u64_at(32) as usize);
+            let layout = Layout::new::<A, T, D>(nodes, items).ok_or(ErrorKind::InvalidFormat)?;
+            if nodes == 0 || data.len() < layout.len {
+                return Err(ErrorKind::InvalidFormat);
+            }
+            debug_assert!(align_of::<A>() <= SECTION_ALIGN && align_of::<T>() <= SECTION_ALIGN);
+            let tree = MappedKdTree {
+                bytes,
+                layout,
+                marker: PhantomData,
+            };
+            let () = tree.validate()?;
+            Ok(tree)
+        }
+
+        fn section<P>(&self, index: usize) -> &[P] {
+            let (start, end) = self.layout.sections[index];
+            let bytes = &self.bytes.as_ref()[start..end];
+            // SAFETY: sections are aligned, in bounds and hold `Pod` values, of which any bit
+            // pattern is valid
+            unsafe {
+                std::slice::from_raw_parts(bytes.as_ptr() as *const P, bytes.len() / size_of::<P>())
+            }
+        }
+
+        fn view(&self) -> Flat<'_, A, T, D> {
+            Flat {
+                min_bounds: self.section(0),
+                max_bounds: self.section(1),
+                splits: self.section(2),
+                links: self.section(3),
+                points: self.section(4),
+                data: self.section(5),
+            }
+        }
+
+        /// Checks that every link stays in bounds and points forward, so that queries always
+        /// terminate without panicking
+        fn validate(&self) -> Result<(), ErrorKind> {
+            let view = self.view();
+            let (nodes, items) = (view.links.len() as u64, view.points.len() as u64);
+            for (i, &[dim, a, b]) in view.links.iter().enumerate() {
+                let valid = if dim == LEAF {
+                    a.checked_add(b).is_some_and(|end| end <= items)
+                } else {
+                    dim < D as u64 && a > i as u64 && b > i as u64 && a < nodes && b < nodes
+                };
+                if !valid {
+                    return Err(ErrorKind::InvalidFormat);
+                }
+            }
+            Ok(())
+        }
+
+        pub fn size(&self) -> usize {
+            self.view().points.len()
+        }
+
+        pub fn nearest<F>(
+            &self,
+            point: &[A; D],
+            num: usize,
+            distance: &F,
+        ) -> Result<Vec<(A, &T)>, ErrorKind>
+        where
+            F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+        {
+            self.view().nearest(point, num, distance)
+        }
+
+        pub fn within<F>(
+            &self,
+            point: &[A; D],
+            radius: A,
+            distance: &F,
+        ) -> Result<Vec<(A, &T)>, ErrorKind>
+        where
+            F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+        {
+            self.view().within(point, radius, distance)
+        }
+
+        /// Items whose points lie within the box `[min, max]`, as
+        /// [`FlatKdTree::within_box`]
+        pub fn within_box(&self, min: &[A; D], max: &[A; D]) -> Result<Vec<&T>, ErrorKind> {
+            self.view()
+                .within_box(min, max, false, &mut QueryStats::default())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::distance::squared_euclidean;
+    use crate::tree::OwnedKdTree;
+
+    fn dist<const D: usize>(a: &[f64; D], b: &[f64; D]) -> f64 {
+        squared_euclidean(a, b)
+    }
+
+    fn tree() -> OwnedKdTree<f64, u64, 3> {
+        let items = (0..3000u64)
+            .map(|i| {
+                let p = [
+                    (i * 7919 % 1000) as f64,
+                    (i * 104729 % 997) as f64,
+                    (i % 13) as f64,
+                ];
+                (p, i)
+            })
+            .collect();
+        OwnedKdTree::from_points(items, 16).unwrap()
+    }
+
+    #[test]
+    fn it_answers_queries_like_the_tree() {
+        let tree = tree();
+        let mut added = OwnedKdTree::with_capacity(4);
+        for (p, &d) in tree.iter() {
+            added.add(*p, d).unwrap();
+        }
+        for i in 0..100 {
+            added
+                .remove(
+                    &[
+                        (i * 7919 % 1000) as f64,
+                        (i * 104729 % 997) as f64,
+                        (i % 13) as f64,
+                    ],
+                    &i,
+                )
+                .unwrap();
+        }
+        for tree in [tree, added] {
+            let flat = tree.clone().freeze();
+            assert_eq!(flat.size(), tree.size());
+            for i in 0..50 {
+                let query = [(i * 37 % 1000) as f64, (i * 91 % 1000) as f64, 6.5];
+                let distances = |found: Vec<(f64, &u64)>| -> Vec<f64> {
+                    found.into_iter().map(|(d, _)| d).collect()
+                };
+                assert_eq!(
+                    distances(flat.nearest(&query, 7, &dist).unwrap()),
+                    distances(tree.nearest(&query, 7, &dist).unwrap())
+                );
+                let mut within = flat.within(&query, 5000.0, &dist).unwrap();
+                let mut expected = tree.within(&query, 5000.0, &dist).unwrap();
+                within.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                assert_eq!(within, expected);
+            }
+        }
+        let empty: OwnedKdTree<f64, u64, 3> = OwnedKdTree::new();
+        assert!(empty
+            .freeze()
+            .nearest(&[0.0; 3], 3, &dist)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn it_scans_only_the_sorted_run_of_a_leaf_inside_a_box() {
+        use crate::query::QueryStats;
+
+        let tree = tree();
+        let flat = tree.clone().freeze();
+        let view = flat.view();
+        for [dim, first, len] in view.links.iter().copied() {
+            if dim != super::LEAF {
+                let [l, r] = [first as usize, len as usize];
+                for child in [l, r] {
+                    if let [super::LEAF, first, len] = view.links[child] {
+                        let points = &view.points[first as usize..(first + len) as usize];
+                        let d = dim as usize;
+                        assert!(points.windows(2).all(|w| w[0][d] <= w[1][d]));
+                    }
+                }
+            }
+        }
+        let (mut sorted, mut whole) = (QueryStats::default(), QueryStats::default());
+        for i in 0..100u64 {
+            let min = [(i * 37 % 900) as f64, (i * 91 % 900) as f64, (i % 5) as f64];
+            let max = [min[0] + 100.0, min[1] + 20.0 + i as f64, min[2] + 3.0];
+            let mut expected = tree.within_box(&min, &max).unwrap();
+            let mut found = view.within_box(&min, &max, false, &mut sorted).unwrap();
+            let mut scanned = view.within_box(&min, &max, true, &mut whole).unwrap();
+            expected.sort_unstable();
+            found.sort_unstable();
+            scanned.sort_unstable();
+            assert_eq!(found, expected);
+            assert_eq!(scanned, expected);
+        }
+        assert_eq!(sorted.nodes_visited, whole.nodes_visited);
+        assert_eq!(sorted.leaves_visited, whole.leaves_visited);
+        assert!(
+            sorted.points_visited * 4 < whole.points_visited * 3,
+            "{} {}",
+            sorted.points_visited,
+            whole.points_visited
+        );
+        assert!(flat.within_box(&[5.0; 3], &[4.0; 3]).unwrap().is_empty());
+        assert!(flat.within_box(&[f64::NAN; 3], &[4.0; 3]).is_err());
+    }
+
+    #[cfg(feature = "mmap")]
+    mod mapped {
+        use super::{dist, tree};
+        use crate::error::ErrorKind;
+        use crate::flat::MappedKdTree;
+
+        /// `bytes` copied into a buffer aligned to 8 bytes
+        fn aligned(bytes: &[u8]) -> Vec<u64> {
+            let mut buffer = vec![0u64; bytes.len().div_ceil(8)];
+            unsafe {
+                let start = buffer.as_mut_ptr() as *mut u8;
+                std::ptr::copy_nonoverlapping(bytes.as_ptr(), start, bytes.len());
+            }
+            buffer
+        }
+
+        fn as_bytes(buffer: &[u64], len: usize) -> &[u8] {
+            unsafe { std::slice::from_raw_parts(buffer.as_ptr() as *const u8, len) }
+        }
+
+        #[test]
+        fn it_round_trips_through_bytes() {
+            let tree = tree();
+            let flat = tree.clone().freeze();
+            let mut bytes = vec![];
+            flat.write_to(&mut bytes).unwrap();
+            let buffer = aligned(&bytes);
+
+            let mapped =
+                MappedKdTree::<f64, u64, _, 3>::from_bytes(as_bytes(&buffer, bytes.len())).unwrap();
+            assert_eq!(mapped.size(), 3000);
+            for i in 0..50 {
+                let query = [(i * 37 % 1000) as f64, (i * 91 % 1000) as f64, 6.5];
+                assert_eq!(
+                    mapped.nearest(&query, 5, &dist).unwrap(),
+                    flat.nearest(&query, 5, &dist).unwrap()
+                );
+                assert_eq!(
+                    mapped.within(&query, 2000.0, &dist).unwrap(),
+                    flat.within(&query, 2000.0, &dist).unwrap()
+                );
+            }
+        }
+
+        #[test]
+        fn it_rejects_corrupt_headers() {
+            let mut bytes = vec![];
+            tree().freeze().write_to(&mut bytes).unwrap();
+            let open = |bytes: &[u8]| {
+                let buffer = aligned(bytes);
+                MappedKdTree::<f64, u64, _, 3>::from_bytes(as_bytes(&buffer, bytes.len())).err()
+            };
+            assert_eq!(open(&bytes), None);
+
+            let mut corrupt = bytes.clone();
+            corrupt[0] = b'X';
+            assert_eq!(open(&corrupt), Some(ErrorKind::InvalidFormat));
+            let mut corrupt = bytes.clone();
+            corrupt[8] ^= 0xff;
+            assert_eq!(open(&corrupt), Some(ErrorKind::FormatVersionMismatch));
+            let mut corrupt = bytes.clone();
+            corrupt[12] = 7;
+            assert_eq!(open(&corrupt), Some(ErrorKind::UnsupportedType));
+            assert_eq!(
+                open(&bytes[..bytes.len() - 8]),
+                Some(ErrorKind::InvalidFormat)
+            );
+            assert_eq!(open(&bytes[..10]), Some(ErrorKind::InvalidFormat));
+
+            let buffer = aligned(&bytes);
+            let wrong_dimension =
+                MappedKdTree::<f64, u64, _, 2>::from_bytes(as_bytes(&buffer, bytes.len()));
+            assert_eq!(
+                wrong_dimension.err(),
+                Some(ErrorKind::DimensionMismatch {
+                    expected: 2,
+                    got: 3
+                })
+            );
+            let misaligned =
+                MappedKdTree::<f64, u64, _, 3>::from_bytes(&as_bytes(&buffer, bytes.len())[1..]);
+            assert_eq!(misaligned.err(), Some(ErrorKind::InvalidFormat));
+        }
+    }
+}
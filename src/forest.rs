@@ -0,0 +1,266 @@
+//! Queries over a dataset sharded into several trees, such as one tree per map tile.
+//!
+//! A [`KdForest`] answers queries as a single tree holding the items of all its shards would.
+//! Nearest searches visit the shards nearest first by the distance to their bounding boxes, and
+//! stop once the remaining shards are farther than the `num`th nearest item found so far.
+//!
+//! ```rust
+//! use kdtree::distance::squared_euclidean;
+//! use kdtree::forest::KdForest;
+//! use kdtree::OwnedKdTree;
+//!
+//! let distance = |a: &[f64; 2], b: &[f64; 2]| squared_euclidean(a, b);
+//! let mut forest = KdForest::new();
+//! for tile in 0..4 {
+//!     let items = (0..10).map(|i| ([(tile * 10 + i) as f64, 0.0], tile * 10 + i)).collect();
+//!     forest.add_shard(OwnedKdTree::from_points(items, 4).unwrap());
+//! }
+//! let nearest = forest.nearest(&[19.8, 0.0], 2, &distance).unwrap();
+//! assert_eq!(nearest.iter().map(|&(_, &i)| i).collect::<Vec<_>>(), vec![20, 19]);
+//! ```
+
+use num_traits::{Float, One, Zero};
+
+use crate::error::ErrorKind;
+use crate::geometry::distance_to_space_const;
+use crate::heap_element::Neighbour;
+use crate::tree::OwnedKdTree;
+
+/// Several trees queried as one, see the [module documentation](index.html). Queries return the
+/// same items in the same order as an [`OwnedKdTree`] holding the items of every shard, except
+/// for the order of items stored at the same point, which is unspecified in both.
+pub struct KdForest<A, T: PartialEq, const D: usize> {
+    shards: Vec<OwnedKdTree<A, T, D>>,
+}
+
+impl<A: Float + Zero + One, T: PartialEq, const D: usize> KdForest<A, T, D> {
+    /// Create a forest without shards
+    pub fn new() -> Self {
+        KdForest { shards: vec![] }
+    }
+
+    /// Add a shard, returning its index in [`shards`](#method.shards)
+    pub fn add_shard(&mut self, shard: OwnedKdTree<A, T, D>) -> usize {
+        self.shards.push(shard);
+        self.shards.len() - 1
+    }
+
+    /// The shards, in the order they were added
+    pub fn shards(&self) -> &[OwnedKdTree<A, T, D>] {
+        &self.shards
+    }
+
+    /// The shards, to add or remove items. A shard may hold items anywhere, but queries skip
+    /// shards more efficiently the less their bounding boxes overlap.
+    pub fn shards_mut(&mut self) -> &mut [OwnedKdTree<A, T, D>] {
+        &mut self.shards
+    }
+
+    /// The number of items in all shards
+    pub fn size(&self) -> usize {
+        self.shards.iter().map(OwnedKdTree::size).sum()
+    }
+
+    /// The `num` items nearest to `point`, nearest first, as returned by
+    /// [`OwnedKdTree::nearest`](crate::OwnedKdTree::nearest)
+    pub fn nearest<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let mut nearest: Vec<Neighbour<A, &T, D>> = vec![];
+        for (to_shard, shard) in self.shards_by_distance(point, distance)? {
+            // with no items wanted, there is no farthest item to compare against
+            let cannot_improve = match nearest.last() {
+                Some(farthest) => to_shard > farthest.distance,
+                None => true,
+            };
+            if nearest.len() == num && cannot_improve {
+                break;
+            }
+            let found = shard.nearest_neighbours(point, num, distance)?;
+            nearest = merge(nearest, found, num);
+        }
+        Ok(nearest.into_iter().map(Into::into).collect())
+    }
+
+    /// The items within `radius` of `point`, nearest first, as returned by
+    /// [`OwnedKdTree::within`](crate::OwnedKdTree::within)
+    pub fn within<F>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let mut within = vec![];
+        for (to_shard, shard) in self.shards_by_distance(point, distance)? {
+            if to_shard > radius {
+                break;
+            }
+            let found = shard.within_neighbours(point, radius, shard.size(), distance)?;
+            within = merge(within, found, usize::MAX);
+        }
+        Ok(within.into_iter().map(Into::into).collect())
+    }
+
+    /// The non-empty shards with the distance from `point` to their bounding boxes, nearest
+    /// first
+    #[allow(clippy::type_complexity)]
+    fn shards_by_distance<F>(
+        &self,
+        point: &[A; D],
+        distance: &F,
+    ) -> Result<Vec<(A, &OwnedKdTree<A, T, D>)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let mut shards = vec![];
+        for shard in &self.shards {
+            let () = shard.check_point(point)?;
+            if let Some((min, max)) = shard.extent() {
+                shards.push((distance_to_space_const(point, &min, &max, distance), shard));
+            }
+        }
+        shards.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(shards)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<A, T, const D: usize> KdForest<A, T, D>
+where
+    A: Float + Zero + One + Send + Sync,
+    T: PartialEq + Sync,
+{
+    /// [`nearest`](#method.nearest), searching every shard in parallel. No shard is skipped, as
+    /// the searches do not wait for each other's results.
+    pub fn par_nearest<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + Sync + ?Sized,
+    {
+        use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+        let found: Vec<_> = self
+            .shards_by_distance(point, distance)?
+            .par_iter()
+            .map(|&(_, shard)| shard.nearest_neighbours(point, num, distance))
+            .collect::<Result<_, _>>()?;
+        let nearest = found
+            .into_iter()
+            .fold(vec![], |nearest, found| merge(nearest, found, num));
+        Ok(nearest.into_iter().map(Into::into).collect())
+    }
+}
+
+/// The first `num` items of two lists sorted nearest first
+fn merge<'a, A: Float, T, const D: usize>(
+    a: Vec<Neighbour<'a, A, &'a T, D>>,
+    b: Vec<Neighbour<'a, A, &'a T, D>>,
+    num: usize,
+) -> Vec<Neighbour<'a, A, &'a T, D>> {
+    let mut merged = Vec::with_capacity(std::cmp::min(num, a.len() + b.len()));
+    let mut a = a.into_iter().peekable();
+    let mut b = b.into_iter().peekable();
+    while merged.len() < num {
+        let next = match (a.peek(), b.peek()) {
+            (Some(x), Some(y)) if y < x => b.next(),
+            (Some(_), _) => a.next(),
+            (None, _) => b.next(),
+        };
+        match next {
+            Some(next) => merged.push(next),
+            None => break,
+        }
+    }
+    merged
+}
+
+impl<A: Float + Zero + One, T: PartialEq, const D: usize> Default for KdForest<A, T, D> {
+    fn default() -> Self {
+        KdForest::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::{Rng, SeedableRng, StdRng};
+    use super::KdForest;
+    use crate::distance::squared_euclidean;
+    use crate::error::ErrorKind;
+    use crate::tree::OwnedKdTree;
+
+    fn dist(a: &[f64; 2], b: &[f64; 2]) -> f64 {
+        squared_euclidean(a, b)
+    }
+
+    #[test]
+    fn it_matches_a_single_tree_of_all_shards() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[6, 2, 8][..]);
+        // distinct points, as the order of items at the same point is unspecified
+        let mut items: Vec<([f64; 2], usize)> = (0..1600)
+            .map(|i| ([(i % 40) as f64 * 0.5, (i / 40) as f64 * 0.5], i))
+            .collect();
+        rng.shuffle(&mut items);
+        let union = OwnedKdTree::from_points(items.clone(), 8).unwrap();
+
+        let mut forest = KdForest::new();
+        // four tiles, a shard overlapping all of them and an empty shard
+        let mut tiles = vec![vec![]; 5];
+        for &(point, i) in &items {
+            let tile = if i % 9 == 0 {
+                4
+            } else {
+                (point[0] >= 10.0) as usize + 2 * (point[1] >= 10.0) as usize
+            };
+            tiles[tile].push((point, i));
+        }
+        for tile in tiles {
+            forest.add_shard(OwnedKdTree::from_points(tile, 8).unwrap());
+        }
+        assert_eq!(forest.add_shard(OwnedKdTree::new()), 5);
+        assert_eq!(forest.size(), union.size());
+
+        for _ in 0..100 {
+            let query = [rng.gen_range(-5.0, 25.0), rng.gen_range(-5.0, 25.0)];
+            for &num in &[0, 1, 6, 50, 2000] {
+                let expected = union.nearest(&query, num, &dist).unwrap();
+                assert_eq!(forest.nearest(&query, num, &dist).unwrap(), expected);
+                #[cfg(feature = "rayon")]
+                assert_eq!(forest.par_nearest(&query, num, &dist).unwrap(), expected);
+            }
+            for &radius in &[0.0, 0.3, 4.0, 40.0] {
+                assert_eq!(
+                    forest.within(&query, radius, &dist).unwrap(),
+                    union.within(&query, radius, &dist).unwrap()
+                );
+            }
+        }
+
+        forest.shards_mut()[0].add([100.0, 100.0], 5000).unwrap();
+        assert_eq!(
+            forest.nearest(&[90.0, 90.0], 1, &dist).unwrap(),
+            vec![(200.0, &5000)]
+        );
+        assert_eq!(
+            forest.nearest(&[f64::NAN, 0.0], 1, &dist),
+            Err(ErrorKind::NonFiniteCoordinate)
+        );
+        let empty = KdForest::<f64, usize, 2>::new();
+        assert_eq!(empty.nearest(&[0.0, 0.0], 3, &dist), Ok(vec![]));
+        assert_eq!(empty.within(&[0.0, 0.0], 3.0, &dist), Ok(vec![]));
+    }
+}
@@ -0,0 +1,340 @@
+//! Euclidean searches in the units of the coordinates, and aliases for two and three
+//! dimensional trees with helpers built on them. They are the simplest entry point: the general
+//! queries of [`OwnedKdTree`] take a distance function, and since the usual choice is
+//! [`squared_euclidean`], their radii and returned distances are then squared. [`Euclidean`]
+//! squares radii before searching and takes the square root of the distances it returns.
+//!
+//! ```rust
+//! use kdtree::geometry::KdTree2;
+//!
+//! let mut tree = KdTree2::new();
+//! tree.add([0.0, 0.0], "origin").unwrap();
+//! tree.add([3.0, 4.0], "corner").unwrap();
+//! tree.add([6.0, 0.0], "far").unwrap();
+//!
+//! // a radius of 5, not 25
+//! let found = tree.within_circle(0.0, 0.0, 5.0).unwrap();
+//! assert_eq!(found, vec![(0.0, &"origin"), (5.0, &"corner")]);
+//! ```
+//!
+//! [`Euclidean::nearest_neighbours`] and [`Euclidean::within_neighbours`] return
+//! [`Neighbour`]s instead, which keep the squared distance and only take its square root when
+//! asked, for callers comparing squared distances but showing a few true ones.
+//!
+//! With the `geo` feature, [`KdTree2`] also builds from and is queried with the points and
+//! polygons of the `geo` crate.
+//!
+//! The distances from a point to a box and between boxes, such as [`distance_to_space_const`],
+//! are the bounds the searches of the trees rule nodes out with, for traversals of their own
+//! over [`NodeRef`](crate::tree::NodeRef).
+
+use num_traits::{Float, One, Zero};
+
+use crate::distance::squared_euclidean;
+use crate::error::ErrorKind;
+use crate::heap_element;
+use crate::tree::OwnedKdTree;
+
+mod boxes;
+#[cfg(feature = "geo")]
+mod geo;
+
+pub use self::boxes::{
+    distance_between_far_corners_const, distance_between_spaces_const,
+    distance_to_far_corner_const, distance_to_space, distance_to_space_const,
+};
+
+pub type KdTree2<A, T> = OwnedKdTree<A, T, 2>;
+pub type KdTree3<A, T> = OwnedKdTree<A, T, 3>;
+
+fn squared_radius<A: Float>(radius: A) -> Result<A, ErrorKind> {
+    if radius.is_nan() || radius < A::zero() {
+        return Err(ErrorKind::InvalidRadius);
+    }
+    Ok(radius * radius)
+}
+
+fn unsquared<A: Float, T>(found: Vec<(A, &T)>) -> Vec<(A, &T)> {
+    found
+        .into_iter()
+        .map(|(d, item)| (d.sqrt(), item))
+        .collect()
+}
+
+fn squared<A: Float, const D: usize>(a: &[A; D], b: &[A; D]) -> A {
+    squared_euclidean(a, b)
+}
+
+/// An item found by a Euclidean query, with its squared distance to the query point
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Neighbour<'a, A, T, const D: usize> {
+    distance_squared: A,
+    point: &'a [A; D],
+    item: &'a T,
+}
+
+impl<'a, A: Float, T, const D: usize> Neighbour<'a, A, T, D> {
+    /// The squared euclidean distance, as the search compared it
+    pub fn distance_squared(&self) -> A {
+        self.distance_squared
+    }
+
+    /// The euclidean distance in the units of the coordinates, computed on each call
+    pub fn distance(&self) -> A {
+        self.distance_squared.sqrt()
+    }
+
+    pub fn point(&self) -> &'a [A; D] {
+        self.point
+    }
+
+    pub fn item(&self) -> &'a T {
+        self.item
+    }
+}
+
+fn neighbour<'a, A, T, const D: usize>(
+    found: heap_element::Neighbour<'a, A, &'a T, D>,
+) -> Neighbour<'a, A, T, D> {
+    Neighbour {
+        distance_squared: found.distance,
+        point: found.point,
+        item: found.element,
+    }
+}
+
+/// Euclidean queries on a tree, see [`OwnedKdTree::euclidean`]. Radii are taken and distances
+/// returned in the units of the coordinates, while the searches themselves compare squared
+/// distances.
+pub struct Euclidean<'a, A, T: PartialEq, const D: usize> {
+    tree: &'a OwnedKdTree<A, T, D>,
+}
+
+impl<'a, A: Float + Zero + One, T: PartialEq, const D: usize> Euclidean<'a, A, T, D> {
+    /// The `num` items nearest to `point` with their distances, nearest first
+    pub fn nearest(&self, point: &[A; D], num: usize) -> Result<Vec<(A, &'a T)>, ErrorKind> {
+        Ok(unsquared(self.tree.nearest(point, num, &squared)?))
+    }
+
+    /// The items within `radius` of `point` with their distances, nearest first. A negative or
+    /// NaN `radius` returns `ErrorKind::InvalidRadius`.
+    pub fn within(&self, point: &[A; D], radius: A) -> Result<Vec<(A, &'a T)>, ErrorKind> {
+        let radius = squared_radius(radius)?;
+        Ok(unsquared(self.tree.within(point, radius, &squared)?))
+    }
+
+    /// [`within`](#method.within), returning only the `max_results` nearest items
+    pub fn within_capped(
+        &self,
+        point: &[A; D],
+        radius: A,
+        max_results: usize,
+    ) -> Result<Vec<(A, &'a T)>, ErrorKind> {
+        let radius = squared_radius(radius)?;
+        let found = self
+            .tree
+            .within_capped(point, radius, max_results, &squared)?;
+        Ok(unsquared(found))
+    }
+
+    /// Like [`nearest`](#method.nearest), but keeping the squared distances
+    pub fn nearest_neighbours(
+        &self,
+        point: &[A; D],
+        num: usize,
+    ) -> Result<Vec<Neighbour<'a, A, T, D>>, ErrorKind> {
+        let found = self.tree.nearest_neighbours(point, num, &squared)?;
+        Ok(found.into_iter().map(neighbour).collect())
+    }
+
+    /// Like [`within`](#method.within), but keeping the squared distances. `radius` is still
+    /// in the units of the coordinates.
+    pub fn within_neighbours(
+        &self,
+        point: &[A; D],
+        radius: A,
+    ) -> Result<Vec<Neighbour<'a, A, T, D>>, ErrorKind> {
+        let radius = squared_radius(radius)?;
+        let found = self
+            .tree
+            .within_neighbours(point, radius, self.tree.size(), &squared)?;
+        Ok(found.into_iter().map(neighbour).collect())
+    }
+
+    /// Iterates over every item with its distance, nearest to `point` first
+    pub fn iter_nearest<'b>(
+        &self,
+        point: &'b [A; D],
+    ) -> Result<impl Iterator<Item = (A, &'a T)> + 'b, ErrorKind>
+    where
+        'a: 'b,
+    {
+        let iter = self.tree.iter_nearest(point, &squared)?;
+        Ok(iter.map(|(d, item)| (d.sqrt(), item)))
+    }
+}
+
+impl<A: Float + Zero + One, T: PartialEq, const D: usize> OwnedKdTree<A, T, D> {
+    /// Queries by euclidean distance in the units of the coordinates
+    pub fn euclidean(&self) -> Euclidean<'_, A, T, D> {
+        Euclidean { tree: self }
+    }
+}
+
+impl<A: Float + Zero + One, T: PartialEq> OwnedKdTree<A, T, 2> {
+    /// The `num` items nearest to `(x, y)` with their euclidean distances, nearest first
+    pub fn nearest_xy(&self, x: A, y: A, num: usize) -> Result<Vec<(A, &T)>, ErrorKind> {
+        self.euclidean().nearest(&[x, y], num)
+    }
+
+    /// The items within euclidean distance `radius` of `(x, y)` with their distances, nearest
+    /// first. A negative or NaN `radius` returns `ErrorKind::InvalidRadius`.
+    pub fn within_circle(&self, x: A, y: A, radius: A) -> Result<Vec<(A, &T)>, ErrorKind> {
+        self.euclidean().within(&[x, y], radius)
+    }
+}
+
+impl<A: Float + Zero + One, T: PartialEq> OwnedKdTree<A, T, 3> {
+    /// The `num` items nearest to `(x, y, z)` with their euclidean distances, nearest first
+    pub fn nearest_xyz(&self, x: A, y: A, z: A, num: usize) -> Result<Vec<(A, &T)>, ErrorKind> {
+        self.euclidean().nearest(&[x, y, z], num)
+    }
+
+    /// The items within euclidean distance `radius` of `(x, y, z)` with their distances, nearest
+    /// first. A negative or NaN `radius` returns `ErrorKind::InvalidRadius`.
+    pub fn within_sphere(&self, x: A, y: A, z: A, radius: A) -> Result<Vec<(A, &T)>, ErrorKind> {
+        self.euclidean().within(&[x, y, z], radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use super::{KdTree2, KdTree3};
+    use crate::distance::squared_euclidean;
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn it_searches_circles_by_true_radius() {
+        let mut tree = KdTree2::with_capacity(2);
+        for x in -6..=6 {
+            for y in -6..=6 {
+                tree.add([x as f64, y as f64], (x, y)).unwrap();
+            }
+        }
+        let found = tree.within_circle(0.0, 0.0, 5.0).unwrap();
+        let inside = (-6..=6)
+            .flat_map(|x: i32| (-6..=6).map(move |y: i32| (x, y)))
+            .filter(|&(x, y)| x * x + y * y <= 25)
+            .count();
+        assert_eq!(found.len(), inside);
+        assert!(found.iter().any(|&(d, &p)| d == 5.0 && p == (3, 4)));
+        assert!(found
+            .iter()
+            .all(|&(d, &(x, y))| d == ((x * x + y * y) as f64).sqrt()));
+
+        let nearest = tree.nearest_xy(0.2, 0.1, 2).unwrap();
+        assert_eq!(nearest[0].1, &(0, 0));
+        assert_eq!(nearest[0].0, (0.2f64 * 0.2 + 0.1 * 0.1).sqrt());
+        assert_eq!(nearest[1].1, &(1, 0));
+
+        assert_eq!(
+            tree.within_circle(0.0, 0.0, -1.0),
+            Err(ErrorKind::InvalidRadius)
+        );
+        assert_eq!(
+            tree.within_circle(0.0, 0.0, f64::NAN),
+            Err(ErrorKind::InvalidRadius)
+        );
+    }
+
+    #[test]
+    fn it_keeps_squared_distances_and_takes_their_roots_on_demand() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[19, 1][..]);
+        let mut tree = KdTree3::with_capacity(4);
+        for i in 0..1000 {
+            let point: [f64; 3] = [(); 3].map(|_| rng.gen_range(-10.0, 10.0));
+            tree.add(point, i).unwrap();
+        }
+        let euclidean = tree.euclidean();
+        for _ in 0..20 {
+            let point = [(); 3].map(|_| rng.gen_range(-10.0, 10.0));
+            let neighbours = euclidean.nearest_neighbours(&point, 10).unwrap();
+            let nearest = euclidean.nearest(&point, 10).unwrap();
+            assert_eq!(neighbours.len(), 10);
+            for (n, &(distance, item)) in neighbours.iter().zip(&nearest) {
+                assert_eq!(n.item(), item);
+                assert!((n.distance() - distance).abs() <= 1e-12 * distance.max(1.0));
+                assert!((n.distance() * n.distance() - n.distance_squared()).abs() <= 1e-9);
+                assert_eq!(n.distance_squared(), squared_euclidean(&point, n.point()));
+            }
+
+            let within = euclidean.within_neighbours(&point, 3.0).unwrap();
+            let expected = euclidean.within(&point, 3.0).unwrap();
+            assert_eq!(within.len(), expected.len());
+            assert!(within.iter().all(|n| n.distance_squared() <= 9.0));
+            for (n, &(distance, item)) in within.iter().zip(&expected) {
+                assert_eq!((n.distance(), n.item()), (distance, item));
+            }
+        }
+        assert_eq!(
+            euclidean.within_neighbours(&[0.0; 3], -1.0),
+            Err(ErrorKind::InvalidRadius)
+        );
+    }
+
+    #[test]
+    fn it_searches_spheres_by_true_radius() {
+        let mut tree = KdTree3::new();
+        tree.add([0.0, 0.0, 0.0], 0).unwrap();
+        tree.add([2.0, 3.0, 6.0], 1).unwrap();
+        tree.add([2.0, 3.0, 6.1], 2).unwrap();
+        assert_eq!(
+            tree.within_sphere(0.0, 0.0, 0.0, 7.0).unwrap(),
+            vec![(0.0, &0), (7.0, &1)]
+        );
+        assert_eq!(tree.nearest_xyz(2.0, 3.0, 6.0, 1).unwrap(), vec![(0.0, &1)]);
+        assert_eq!(
+            tree.within_sphere(0.0, 0.0, 0.0, -7.0),
+            Err(ErrorKind::InvalidRadius)
+        );
+    }
+
+    #[test]
+    fn it_measures_euclidean_distances_in_any_dimension() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+        use crate::tree::OwnedKdTree;
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[5, 5][..]);
+        let points: Vec<[f64; 4]> = (0..2000)
+            .map(|_| [(); 4].map(|_| rng.gen_range(-10.0, 10.0)))
+            .collect();
+        let tree = OwnedKdTree::from_points(points.iter().cloned().zip(0..).collect(), 8).unwrap();
+        let true_distance = |p: &[f64; 4]| p.iter().map(|x| x * x).sum::<f64>().sqrt();
+        let euclidean = tree.euclidean();
+
+        let found = euclidean.within(&[0.0; 4], 5.0).unwrap();
+        let mut expected: Vec<usize> = (0..points.len())
+            .filter(|&i| true_distance(&points[i]) <= 5.0)
+            .collect();
+        let mut ids: Vec<usize> = found.iter().map(|&(_, &i)| i).collect();
+        ids.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(ids, expected);
+        assert!(found
+            .iter()
+            .all(|&(d, &i)| d <= 5.0 && d == true_distance(&points[i])));
+
+        let capped = euclidean.within_capped(&[0.0; 4], 5.0, 3).unwrap();
+        assert_eq!(capped, found[..3]);
+        let nearest = euclidean.nearest(&[0.0; 4], 3).unwrap();
+        assert_eq!(nearest, found[..3]);
+        let iterated: Vec<(f64, &usize)> =
+            euclidean.iter_nearest(&[0.0; 4]).unwrap().take(3).collect();
+        assert_eq!(iterated, nearest);
+        assert!(euclidean.within(&[0.0; 4], -5.0).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! Distances from a point to a box, and between boxes, which bound the distances to the points
+//! inside them for a search to rule out the boxes of the nodes of a tree.
+
+use num_traits::Float;
+
+/// The distance from `p1` to the nearest point of the box `[min_bounds, max_bounds]`, zero if
+/// `p1` is inside it and infinity if the box is empty, with any minimum above its maximum
+pub fn distance_to_space<F, T>(p1: &[T], min_bounds: &[T], max_bounds: &[T], distance: &F) -> T
+where
+    F: Fn(&[T], &[T]) -> T,
+    T: Float,
+{
+    let mut p2 = vec![T::nan(); p1.len()];
+    for i in 0..p1.len() {
+        if min_bounds[i] > max_bounds[i] {
+            // the bounds of an empty node, there is nothing to reach
+            return T::infinity();
+        }
+        if p1[i] > max_bounds[i] {
+            p2[i] = max_bounds[i];
+        } else if p1[i] < min_bounds[i] {
+            p2[i] = min_bounds[i];
+        } else {
+            p2[i] = p1[i];
+        }
+    }
+    distance(p1, &p2[..])
+}
+
+/// [`distance_to_space`] for points of `D` coordinates. A NaN distance is returned as zero,
+/// so that a search cannot rule out a box it cannot tell the distance to.
+pub fn distance_to_space_const<F, T, const D: usize>(
+    p1: &[T; D],
+    min_bounds: &[T; D],
+    max_bounds: &[T; D],
+    distance: &F,
+) -> T
+where
+    F: Fn(&[T; D], &[T; D]) -> T + ?Sized,
+    T: Float,
+{
+    let mut p2 = [T::nan(); D];
+    let bounds = min_bounds.iter().zip(max_bounds);
+    for ((p2, &p1), (&min, &max)) in p2.iter_mut().zip(p1).zip(bounds) {
+        if min > max {
+            // the bounds of an empty node, there is nothing to reach
+            return T::infinity();
+        }
+        *p2 = if p1 > max {
+            max
+        } else if p1 < min {
+            min
+        } else {
+            p1
+        };
+    }
+    let dist = distance(p1, &p2);
+    if dist.is_nan() {
+        // a box at an unknown distance cannot be ruled out
+        T::zero()
+    } else {
+        dist
+    }
+}
+
+/// The distance from `p1` to the farthest corner of the box, an upper bound on the distance to
+/// anything inside it
+pub fn distance_to_far_corner_const<F, T, const D: usize>(
+    p1: &[T; D],
+    min_bounds: &[T; D],
+    max_bounds: &[T; D],
+    distance: &F,
+) -> T
+where
+    F: Fn(&[T; D], &[T; D]) -> T + ?Sized,
+    T: Float,
+{
+    let mut p2 = [T::nan(); D];
+    let bounds = min_bounds.iter().zip(max_bounds);
+    for ((p2, &p1), (&min, &max)) in p2.iter_mut().zip(p1).zip(bounds) {
+        if min > max {
+            // the bounds of an empty node, there is nothing to reach
+            return T::neg_infinity();
+        }
+        *p2 = if p1 - min > max - p1 { min } else { max };
+    }
+    let dist = distance(p1, &p2);
+    if dist.is_nan() {
+        // a box at an unknown distance cannot be ruled out
+        T::infinity()
+    } else {
+        dist
+    }
+}
+
+/// A lower bound on the distance between any point of the box `[min1, max1]` and any point of
+/// the box `[min2, max2]`, the distance between their nearest corners or faces
+pub fn distance_between_spaces_const<F, T, const D: usize>(
+    min1: &[T; D],
+    max1: &[T; D],
+    min2: &[T; D],
+    max2: &[T; D],
+    distance: &F,
+) -> T
+where
+    F: Fn(&[T; D], &[T; D]) -> T + ?Sized,
+    T: Float,
+{
+    let mut p1 = [T::nan(); D];
+    let mut p2 = [T::nan(); D];
+    for i in 0..D {
+        if min1[i] > max1[i] || min2[i] > max2[i] {
+            // the bounds of an empty node, there is nothing to reach
+            return T::infinity();
+        }
+        if max1[i] < min2[i] {
+            p1[i] = max1[i];
+            p2[i] = min2[i];
+        } else if max2[i] < min1[i] {
+            p1[i] = min1[i];
+            p2[i] = max2[i];
+        } else {
+            p1[i] = min1[i].max(min2[i]);
+            p2[i] = p1[i];
+        }
+    }
+    let dist = distance(&p1, &p2);
+    if dist.is_nan() {
+        // a box at an unknown distance cannot be ruled out
+        T::infinity()
+    } else {
+        dist
+    }
+}
+
+/// An upper bound on the distance between any point of the box `[min1, max1]` and any point of
+/// the box `[min2, max2]`, the distance between their farthest corners
+pub fn distance_between_far_corners_const<F, T, const D: usize>(
+    min1: &[T; D],
+    max1: &[T; D],
+    min2: &[T; D],
+    max2: &[T; D],
+    distance: &F,
+) -> T
+where
+    F: Fn(&[T; D], &[T; D]) -> T + ?Sized,
+    T: Float,
+{
+    let mut p1 = [T::nan(); D];
+    let mut p2 = [T::nan(); D];
+    for i in 0..D {
+        if min1[i] > max1[i] || min2[i] > max2[i] {
+            // the bounds of an empty node, there is nothing to reach
+            return T::neg_infinity();
+        }
+        if max2[i] - min1[i] > max1[i] - min2[i] {
+            p1[i] = min1[i];
+            p2[i] = max2[i];
+        } else {
+            p1[i] = max1[i];
+            p2[i] = min2[i];
+        }
+    }
+    let dist = distance(&p1, &p2);
+    if dist.is_nan() {
+        // a box at an unknown distance cannot be ruled in
+        T::infinity()
+    } else {
+        dist
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        distance_between_far_corners_const, distance_between_spaces_const,
+        distance_to_far_corner_const, distance_to_space,
+    };
+    use crate::distance::squared_euclidean;
+
+    #[test]
+    fn test_normal_distance_to_space() {
+        let dis = distance_to_space(&[0.0, 0.0], &[1.0, 1.0], &[2.0, 2.0], &squared_euclidean);
+        assert_eq!(dis, 2.0);
+    }
+
+    #[test]
+    fn test_distance_outside_inf() {
+        let dis = distance_to_space(
+            &[0.0, 0.0],
+            &[1.0, 1.0],
+            &[f64::INFINITY, f64::INFINITY],
+            &squared_euclidean,
+        );
+        assert_eq!(dis, 2.0);
+    }
+
+    #[test]
+    fn test_distance_inside_inf() {
+        let dis = distance_to_space(
+            &[2.0, 2.0],
+            &[f64::NEG_INFINITY, f64::NEG_INFINITY],
+            &[f64::INFINITY, f64::INFINITY],
+            &squared_euclidean,
+        );
+        assert_eq!(dis, 0.0);
+    }
+
+    #[test]
+    fn test_distance_inside_normal() {
+        let dis = distance_to_space(&[2.0, 2.0], &[0.0, 0.0], &[3.0, 3.0], &squared_euclidean);
+        assert_eq!(dis, 0.0);
+    }
+
+    #[test]
+    fn distance_to_empty_space() {
+        let dis = distance_to_space(
+            &[0.0, 0.0],
+            &[f64::INFINITY, f64::INFINITY],
+            &[f64::NEG_INFINITY, f64::NEG_INFINITY],
+            &squared_euclidean,
+        );
+        assert_eq!(dis, f64::INFINITY);
+    }
+
+    #[test]
+    fn distance_to_half_space() {
+        let dis = distance_to_space(
+            &[-2.0, 0.0],
+            &[0.0, f64::NEG_INFINITY],
+            &[f64::INFINITY, f64::INFINITY],
+            &squared_euclidean,
+        );
+        assert_eq!(dis, 4.0);
+    }
+
+    #[test]
+    fn distance_to_far_corner() {
+        let dis = distance_to_far_corner_const(
+            &[1.0, 0.0],
+            &[0.0, -1.0],
+            &[4.0, 2.0],
+            &|a: &[f64; 2], b: &[f64; 2]| squared_euclidean(a, b),
+        );
+        assert_eq!(dis, 13.0);
+    }
+
+    #[test]
+    fn distance_between_spaces() {
+        let squared = |a: &[f64; 2], b: &[f64; 2]| squared_euclidean(a, b);
+        let near = |min1, max1, min2, max2| {
+            distance_between_spaces_const(&min1, &max1, &min2, &max2, &squared)
+        };
+        let far = |min1, max1, min2, max2| {
+            distance_between_far_corners_const(&min1, &max1, &min2, &max2, &squared)
+        };
+        assert_eq!(near([0.0, 0.0], [1.0, 1.0], [3.0, 2.0], [4.0, 5.0]), 5.0);
+        assert_eq!(near([3.0, 2.0], [4.0, 5.0], [0.0, 0.0], [1.0, 1.0]), 5.0);
+        assert_eq!(near([0.0, 0.0], [2.0, 2.0], [1.0, 5.0], [4.0, 6.0]), 9.0);
+        assert_eq!(near([0.0, 0.0], [2.0, 2.0], [1.0, 1.0], [4.0, 6.0]), 0.0);
+        assert_eq!(far([0.0, 0.0], [1.0, 1.0], [3.0, 2.0], [4.0, 5.0]), 41.0);
+        assert_eq!(far([0.0, 0.0], [2.0, 2.0], [1.0, 1.0], [4.0, 6.0]), 52.0);
+        let empty = ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]);
+        assert_eq!(
+            near(empty.0, empty.1, [0.0, 0.0], [1.0, 1.0]),
+            f64::INFINITY
+        );
+        assert_eq!(
+            far([0.0, 0.0], [1.0, 1.0], empty.0, empty.1),
+            f64::NEG_INFINITY
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Conversions from and queries with the types of the `geo` crate. Distances are euclidean in the
+//! units of the coordinates, so for longitudes and latitudes they are in degrees rather than
+//! along the surface of the earth.
+
+use std::convert::TryFrom;
+use std::ops::ControlFlow;
+
+use geo::{BoundingRect, Intersects, Point, Polygon};
+
+use super::KdTree2;
+use crate::error::ErrorKind;
+use crate::tree::DEFAULT_LEAF_CAPACITY;
+
+impl<'a> TryFrom<&'a [Point<f64>]> for KdTree2<f64, usize> {
+    type Error = ErrorKind;
+
+    /// A tree of `points`, each with its index in `points`. A point with a non finite coordinate
+    /// returns `ErrorKind::NonFiniteCoordinate`.
+    fn try_from(points: &'a [Point<f64>]) -> Result<Self, ErrorKind> {
+        let items = points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| ([p.x(), p.y()], i))
+            .collect();
+        KdTree2::from_points(items, DEFAULT_LEAF_CAPACITY)
+    }
+}
+
+impl<T: PartialEq> KdTree2<f64, T> {
+    /// Adds `data` at `point`
+    pub fn add_geo(&mut self, point: Point<f64>, data: T) -> Result<(), ErrorKind> {
+        self.add([point.x(), point.y()], data)
+    }
+
+    /// The `num` items nearest to `point` with their euclidean distances, nearest first
+    pub fn nearest_geo(&self, point: &Point<f64>, num: usize) -> Result<Vec<(f64, &T)>, ErrorKind> {
+        self.nearest_xy(point.x(), point.y(), num)
+    }
+
+    /// The items within `radius` of `point` with their euclidean distances, nearest first. A
+    /// negative or NaN `radius` returns `ErrorKind::InvalidRadius`.
+    pub fn within_geo(&self, point: &Point<f64>, radius: f64) -> Result<Vec<(f64, &T)>, ErrorKind> {
+        self.within_circle(point.x(), point.y(), radius)
+    }
+
+    /// The items inside `polygon` or on its boundary, in no particular order. Only the leaves
+    /// overlapping the polygon's bounding box are visited, and each of their points is tested
+    /// against the polygon itself, so concave polygons and holes are respected.
+    pub fn within_polygon(&self, polygon: &Polygon<f64>) -> Vec<T>
+    where
+        T: Copy,
+    {
+        let rect = match polygon.bounding_rect() {
+            Some(rect) => rect,
+            None => return vec![],
+        };
+        let (low, high) = (rect.min(), rect.max());
+        let mut found = vec![];
+        let _ = self.traverse(
+            |min, max| min[0] <= high.x && low.x <= max[0] && min[1] <= high.y && low.y <= max[1],
+            |p, &data| {
+                let inside_rect =
+                    low.x <= p[0] && p[0] <= high.x && low.y <= p[1] && p[1] <= high.y;
+                if inside_rect && polygon.intersects(&Point::new(p[0], p[1])) {
+                    found.push(data);
+                }
+                ControlFlow::Continue(())
+            },
+        );
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use geo::{polygon, Point};
+
+    use crate::geometry::KdTree2;
+
+    fn points() -> Vec<Point<f64>> {
+        (0..121)
+            .map(|i| Point::new((i % 11) as f64, (i / 11) as f64))
+            .collect()
+    }
+
+    #[test]
+    fn it_builds_from_and_queries_with_points() {
+        let points = points();
+        let mut tree = KdTree2::try_from(&points[..]).unwrap();
+        assert_eq!(tree.size(), 121);
+        assert_eq!(
+            tree.nearest_geo(&Point::new(3.1, 0.0), 1).unwrap(),
+            vec![(0.10000000000000009, &3)]
+        );
+        let mut within: Vec<usize> = tree
+            .within_geo(&Point::new(5.0, 5.0), 1.0)
+            .unwrap()
+            .into_iter()
+            .map(|(_, &i)| i)
+            .collect();
+        within.sort_unstable();
+        assert_eq!(within, vec![49, 59, 60, 61, 71]);
+
+        tree.add_geo(Point::new(-4.0, -3.0), 1000).unwrap();
+        assert_eq!(
+            tree.nearest_geo(&Point::new(0.0, 0.0), 1).unwrap(),
+            vec![(0.0, &0)]
+        );
+        assert_eq!(
+            tree.nearest_geo(&Point::new(-4.0, -2.0), 1).unwrap(),
+            vec![(1.0, &1000)]
+        );
+        let bad = [Point::new(0.0, f64::NAN)];
+        assert!(KdTree2::try_from(&bad[..]).is_err());
+    }
+
+    #[test]
+    fn it_finds_points_inside_concave_polygons() {
+        let points = points();
+        let tree = KdTree2::try_from(&points[..]).unwrap();
+        // a U with its opening at the top: the notch between the arms lies inside the bounding
+        // box but outside the polygon
+        let u = polygon![
+            (x: 0.5, y: 0.5),
+            (x: 9.5, y: 0.5),
+            (x: 9.5, y: 9.5),
+            (x: 6.5, y: 9.5),
+            (x: 6.5, y: 3.5),
+            (x: 3.5, y: 3.5),
+            (x: 3.5, y: 9.5),
+            (x: 0.5, y: 9.5),
+        ];
+        let mut found = tree.within_polygon(&u);
+        found.sort_unstable();
+        let expected: Vec<usize> = (0..121)
+            .filter(|&i| {
+                let (x, y) = (i % 11, i / 11);
+                let in_box = (1..=9).contains(&x) && (1..=9).contains(&y);
+                let in_notch = (4..=6).contains(&x) && y >= 4;
+                in_box && !in_notch
+            })
+            .collect();
+        assert_eq!(found, expected);
+        assert!(found.len() < 81);
+        assert!(!found.contains(&(5 + 11 * 6)));
+        assert!(found.contains(&(5 + 11 * 2)));
+
+        // boundary points are included
+        let square =
+            polygon![(x: 2.0, y: 2.0), (x: 3.0, y: 2.0), (x: 3.0, y: 3.0), (x: 2.0, y: 3.0)];
+        let mut found = tree.within_polygon(&square);
+        found.sort_unstable();
+        assert_eq!(found, vec![24, 25, 35, 36]);
+    }
+}
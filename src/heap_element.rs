@@ -1,48 +1,59 @@
 use num_traits::Float;
 use std::cmp::Ordering;
 
-pub struct HeapElement<A, T> {
+use crate::priority::cmp_distance;
+
+/// An evaluated item. Items at equal distances are ordered lexicographically by their points, so
+/// that which of them are kept and the order they are returned in does not depend on the order
+/// they were added in.
+#[derive(Clone, Copy)]
+pub struct Neighbour<'a, A, T, const D: usize> {
     pub distance: A,
+    pub point: &'a [A; D],
     pub element: T,
 }
 
-impl<A: Float, T> Ord for HeapElement<A, T> {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.partial_cmp(other).unwrap_or(Ordering::Equal)
+impl<'a, A: Float, T, const D: usize> Neighbour<'a, A, T, D> {
+    /// Whether an item at `distance` from the query and stored at `point` orders before this
+    /// one, for deciding whether to keep an item before building its element
+    pub fn is_after(&self, distance: A, point: &[A; D]) -> bool {
+        cmp_items((distance, point), (self.distance, self.point)) == Ordering::Less
     }
 }
 
-impl<A: Float, T> PartialOrd for HeapElement<A, T> {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.distance.partial_cmp(&other.distance)
-    }
+fn cmp_items<A: Float, const D: usize>(a: (A, &[A; D]), b: (A, &[A; D])) -> Ordering {
+    let by_point = || {
+        a.1.iter()
+            .zip(b.1)
+            .map(|(a, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+            .find(|&o| o != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    };
+    cmp_distance(&a.0, &b.0).then_with(by_point)
 }
 
-impl<A: Float, T> PartialOrd<A> for HeapElement<A, T>
-where
-    HeapElement<A, T>: PartialEq<A>,
-{
-    fn partial_cmp(&self, other: &A) -> Option<Ordering> {
-        self.distance.partial_cmp(other)
+impl<'a, A: Float, T, const D: usize> Ord for Neighbour<'a, A, T, D> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        cmp_items((self.distance, self.point), (other.distance, other.point))
     }
 }
 
-impl<A: Float, T> Eq for HeapElement<A, T> {}
-
-impl<A: Float, T> PartialEq for HeapElement<A, T> {
-    fn eq(&self, other: &Self) -> bool {
-        self.distance == other.distance
+impl<'a, A: Float, T, const D: usize> PartialOrd for Neighbour<'a, A, T, D> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
-impl<A: Float, T> PartialEq<A> for HeapElement<A, T> {
-    fn eq(&self, other: &A) -> bool {
-        self.distance == *other
+impl<'a, A: Float, T, const D: usize> Eq for Neighbour<'a, A, T, D> {}
+
+impl<'a, A: Float, T, const D: usize> PartialEq for Neighbour<'a, A, T, D> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
     }
 }
 
-impl<A: Float, T> Into<(A, T)> for HeapElement<A, T> {
-    fn into(self) -> (A, T) {
-        (self.distance, self.element)
+impl<'a, A: Float, T, const D: usize> From<Neighbour<'a, A, T, D>> for (A, T) {
+    fn from(n: Neighbour<'a, A, T, D>) -> Self {
+        (n.distance, n.element)
     }
 }
@@ -0,0 +1,309 @@
+//! A tree for streams of insertions interleaved with queries, split into a large frozen base and
+//! a small mutable delta.
+//!
+//! Adding items one by one to a large [`OwnedKdTree`] leaves it less balanced than building it at
+//! once. A [`HybridKdTree`] instead adds items to a small delta tree, keeps the bulk of the items
+//! in a [`FlatKdTree`] built at once, and answers queries by merging the results from both.
+//! Removing an item of the base only marks it as removed. [`compact`](HybridKdTree::compact)
+//! rebuilds the base from every remaining item and empties the delta.
+//!
+//! ```rust
+//! use kdtree::distance::squared_euclidean;
+//! use kdtree::hybrid::HybridKdTree;
+//!
+//! let distance = |a: &[f64; 2], b: &[f64; 2]| squared_euclidean(a, b);
+//! let items = (0..100).map(|i| ([i as f64, 0.0], i)).collect();
+//! let mut tree = HybridKdTree::from_points(items, 16).unwrap();
+//! tree.add([10.4, 0.0], 1000).unwrap();
+//! tree.remove(&[10.0, 0.0], &10).unwrap();
+//!
+//! let nearest = tree.nearest(&[10.1, 0.0], 2, &distance).unwrap();
+//! assert_eq!(nearest.iter().map(|&(_, &i)| i).collect::<Vec<_>>(), vec![1000, 11]);
+//!
+//! tree.compact().unwrap();
+//! assert_eq!((tree.size(), tree.delta_size(), tree.removed_size()), (100, 0, 0));
+//! ```
+
+use num_traits::{Float, One, Zero};
+
+use crate::error::ErrorKind;
+use crate::flat::FlatKdTree;
+use crate::heap_element::Neighbour;
+use crate::tree::{OwnedKdTree, DEFAULT_LEAF_CAPACITY};
+
+/// A frozen base tree with a mutable delta tree for recent insertions, see the
+/// [module documentation](index.html). Queries return the same items in the same order as an
+/// [`OwnedKdTree`] holding the items of both trees, except for the order of items stored at the
+/// same point, which is unspecified in both.
+pub struct HybridKdTree<A, T: PartialEq, const D: usize> {
+    base: FlatKdTree<A, T, D>,
+    /// Marks the items of the base which were removed, by their index into its points
+    removed: Vec<bool>,
+    removed_size: usize,
+    delta: OwnedKdTree<A, T, D>,
+    capacity: usize,
+}
+
+impl<A: Float + Zero + One, T: PartialEq, const D: usize> HybridKdTree<A, T, D> {
+    /// Create an empty tree with leaves of [`DEFAULT_LEAF_CAPACITY`]
+    pub fn new() -> Self {
+        HybridKdTree::with_capacity(DEFAULT_LEAF_CAPACITY)
+    }
+
+    /// Create an empty tree, specifying the capacity of the leaves of the base and delta trees.
+    /// Panics if `capacity` is zero, like
+    /// [`OwnedKdTree::with_capacity`](crate::OwnedKdTree::with_capacity).
+    pub fn with_capacity(capacity: usize) -> Self {
+        HybridKdTree::from_base(OwnedKdTree::with_capacity(capacity), capacity)
+    }
+
+    /// Build the base from all of `items` at once, as
+    /// [`OwnedKdTree::from_points`](crate::OwnedKdTree::from_points) does
+    pub fn from_points(items: Vec<([A; D], T)>, capacity: usize) -> Result<Self, ErrorKind> {
+        let base = OwnedKdTree::from_points(items, capacity)?;
+        Ok(HybridKdTree::from_base(base, capacity))
+    }
+
+    fn from_base(base: OwnedKdTree<A, T, D>, capacity: usize) -> Self {
+        let base = base.freeze();
+        HybridKdTree {
+            removed: vec![false; base.size()],
+            removed_size: 0,
+            base,
+            delta: OwnedKdTree::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// The number of items which were not removed
+    pub fn size(&self) -> usize {
+        self.base.size() - self.removed_size + self.delta.size()
+    }
+
+    /// The number of items added since the base was built
+    pub fn delta_size(&self) -> usize {
+        self.delta.size()
+    }
+
+    /// The number of items removed from the base since it was built, which it still stores
+    pub fn removed_size(&self) -> usize {
+        self.removed_size
+    }
+
+    /// Add an item to the delta tree
+    pub fn add(&mut self, point: [A; D], data: T) -> Result<(), ErrorKind> {
+        self.delta.add(point, data)
+    }
+
+    /// Remove every item stored at `point` whose data equals `data`, returning how many were
+    /// removed. Items of the delta are removed from it, while items of the base are only marked
+    /// as removed until the next [`compact`](#method.compact).
+    pub fn remove(&mut self, point: &[A; D], data: &T) -> Result<usize, ErrorKind> {
+        let removed = self.delta.remove(point, data)?;
+        let mut from_base = 0;
+        for i in self.base.positions(point) {
+            if !self.removed[i] && self.base.data()[i] == *data {
+                self.removed[i] = true;
+                from_base += 1;
+            }
+        }
+        self.removed_size += from_base;
+        Ok(removed + from_base)
+    }
+
+    /// Rebuild the base from every remaining item of the base and the delta, emptying the delta
+    /// and dropping the removed items
+    pub fn compact(&mut self) -> Result<(), ErrorKind> {
+        let empty = OwnedKdTree::with_capacity(self.capacity).freeze();
+        let base = std::mem::replace(&mut self.base, empty);
+        let delta = std::mem::replace(&mut self.delta, OwnedKdTree::with_capacity(self.capacity));
+        let removed = std::mem::take(&mut self.removed);
+        let mut items: Vec<([A; D], T)> = base
+            .points
+            .into_iter()
+            .zip(base.data)
+            .zip(removed)
+            .filter(|&(_, removed)| !removed)
+            .map(|(item, _)| item)
+            .collect();
+        let (points, data) = delta.into_columns();
+        items.extend(points.into_iter().zip(data));
+        *self = HybridKdTree::from_points(items, self.capacity)?;
+        Ok(())
+    }
+
+    /// The `num` items nearest to `point`, nearest first, as returned by
+    /// [`OwnedKdTree::nearest`](crate::OwnedKdTree::nearest)
+    pub fn nearest<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let removed = &self.removed;
+        let base = self
+            .base
+            .nearest_where(point, num, distance, &|i| !removed[i])?;
+        let delta = self.delta.nearest_neighbours(point, num, distance)?;
+        Ok(merge(base, delta, num))
+    }
+
+    /// The items within `radius` of `point`, nearest first, as returned by
+    /// [`OwnedKdTree::within`](crate::OwnedKdTree::within)
+    pub fn within<F>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let removed = &self.removed;
+        let base = self
+            .base
+            .within_where(point, radius, distance, &|i| !removed[i])?;
+        let delta = self
+            .delta
+            .within_neighbours(point, radius, self.delta.size(), distance)?;
+        Ok(merge(base, delta, usize::MAX))
+    }
+}
+
+/// The first `num` items of two lists sorted nearest first
+fn merge<'a, A: Float, T, const D: usize>(
+    a: Vec<Neighbour<'a, A, &'a T, D>>,
+    b: Vec<Neighbour<'a, A, &'a T, D>>,
+    num: usize,
+) -> Vec<(A, &'a T)> {
+    let mut merged = Vec::with_capacity(std::cmp::min(num, a.len() + b.len()));
+    let mut a = a.into_iter().peekable();
+    let mut b = b.into_iter().peekable();
+    while merged.len() < num {
+        let next = match (a.peek(), b.peek()) {
+            (Some(x), Some(y)) if y < x => b.next(),
+            (Some(_), _) => a.next(),
+            (None, _) => b.next(),
+        };
+        match next {
+            Some(next) => merged.push(next.into()),
+            None => break,
+        }
+    }
+    merged
+}
+
+impl<A: Float + Zero + One, T: PartialEq, const D: usize> Default for HybridKdTree<A, T, D> {
+    fn default() -> Self {
+        HybridKdTree::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::{Rng, SeedableRng, StdRng};
+    use super::HybridKdTree;
+    use crate::distance::squared_euclidean;
+    use crate::error::ErrorKind;
+    use crate::tree::OwnedKdTree;
+
+    fn dist(a: &[f64; 2], b: &[f64; 2]) -> f64 {
+        squared_euclidean(a, b)
+    }
+
+    fn check(
+        hybrid: &HybridKdTree<f64, usize, 2>,
+        plain: &OwnedKdTree<f64, usize, 2>,
+        query: [f64; 2],
+    ) {
+        assert_eq!(hybrid.size(), plain.size());
+        for num in [1, 7, 40] {
+            assert_eq!(
+                hybrid.nearest(&query, num, &dist).unwrap(),
+                plain.nearest(&query, num, &dist).unwrap()
+            );
+        }
+        assert_eq!(
+            hybrid.within(&query, 4.0, &dist).unwrap(),
+            plain.within(&query, 4.0, &dist).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_matches_a_plain_tree() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[4, 1, 4, 3][..]);
+        // distinct points of a lattice, so that many items are at equal distances while the
+        // order of the results is fully specified
+        let mut lattice: Vec<[f64; 2]> = (0..4900)
+            .map(|i| [(i % 70) as f64 - 35.0, (i / 70) as f64 - 35.0])
+            .collect();
+        rng.shuffle(&mut lattice);
+        let initial: Vec<([f64; 2], usize)> =
+            (0..1500).map(|i| (lattice.pop().unwrap(), i)).collect();
+        let mut hybrid = HybridKdTree::from_points(initial.clone(), 8).unwrap();
+        let mut plain = OwnedKdTree::from_points(initial.clone(), 8).unwrap();
+        let mut live = initial;
+        for step in 0..1200 {
+            match step % 6 {
+                0..=2 => {
+                    let item = (lattice.pop().unwrap(), 1500 + step);
+                    hybrid.add(item.0, item.1).unwrap();
+                    plain.add(item.0, item.1).unwrap();
+                    live.push(item);
+                }
+                3 | 4 => {
+                    // removes items of both the base and the delta
+                    let (point, data) = live.swap_remove((step * 7919) % live.len());
+                    assert_eq!(hybrid.remove(&point, &data), plain.remove(&point, &data));
+                    assert_eq!(hybrid.remove(&point, &data), Ok(0));
+                }
+                _ => check(
+                    &hybrid,
+                    &plain,
+                    [rng.gen_range(-40.0, 40.0), rng.gen_range(-40.0, 40.0)],
+                ),
+            }
+            if step == 600 {
+                hybrid.compact().unwrap();
+                assert_eq!(hybrid.delta_size(), 0);
+                assert_eq!(hybrid.removed_size(), 0);
+            }
+        }
+        assert!(hybrid.delta_size() > 0 && hybrid.removed_size() > 0);
+        check(&hybrid, &plain, [0.5, -0.5]);
+        hybrid.compact().unwrap();
+        check(&hybrid, &plain, [0.5, -0.5]);
+    }
+
+    #[test]
+    fn it_removes_every_matching_item() {
+        let mut hybrid =
+            HybridKdTree::from_points(vec![([1.0, 1.0], 0), ([1.0, 1.0], 0), ([1.0, 1.0], 1)], 2)
+                .unwrap();
+        hybrid.add([1.0, 1.0], 0).unwrap();
+        assert_eq!(hybrid.remove(&[1.0, 1.0], &0), Ok(3));
+        assert_eq!((hybrid.size(), hybrid.removed_size()), (1, 2));
+        assert_eq!(
+            hybrid.nearest(&[0.0, 0.0], 5, &dist).unwrap(),
+            vec![(2.0, &1)]
+        );
+        assert_eq!(
+            hybrid.remove(&[f64::NAN, 1.0], &1),
+            Err(ErrorKind::NonFiniteCoordinate)
+        );
+        assert_eq!(
+            hybrid.nearest(&[f64::NAN, 1.0], 1, &dist),
+            Err(ErrorKind::NonFiniteCoordinate)
+        );
+
+        let mut empty = HybridKdTree::<f64, usize, 2>::new();
+        assert!(empty.nearest(&[0.0, 0.0], 3, &dist).unwrap().is_empty());
+        assert!(empty.within(&[0.0, 0.0], 1.0, &dist).unwrap().is_empty());
+        empty.compact().unwrap();
+    }
+}
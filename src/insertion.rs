@@ -0,0 +1,125 @@
+//! A tree which numbers its items in insertion order, answering queries as of an earlier point
+//! of the insertion sequence.
+
+use num_traits::{Float, One, Zero};
+
+use crate::aggregate::Aggregated;
+use crate::error::ErrorKind;
+use crate::tree::OwnedKdTree;
+
+/// An [`OwnedKdTree`] storing every item with an auto-incremented insertion index, starting at
+/// zero. Every subtree caches the smallest index below it, so queries restricted to early items
+/// skip subtrees filled later.
+pub struct InsertionOrdered<A, T: PartialEq, const D: usize> {
+    tree: Aggregated<A, (u64, T), u64, D>,
+    next: u64,
+}
+
+fn index<T>(entry: &(u64, T)) -> u64 {
+    entry.0
+}
+
+fn earliest(a: &u64, b: &u64) -> u64 {
+    std::cmp::min(*a, *b)
+}
+
+impl<A: Float + Zero + One, T: PartialEq, const D: usize> InsertionOrdered<A, T, D> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        InsertionOrdered {
+            tree: Aggregated::with_capacity(capacity, index, earliest),
+            next: 0,
+        }
+    }
+
+    /// The underlying tree, storing `(insertion index, data)` pairs
+    pub fn tree(&self) -> &OwnedKdTree<A, (u64, T), D> {
+        self.tree.tree()
+    }
+
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+
+    /// Adds an item, returning its insertion index
+    pub fn add(&mut self, point: [A; D], data: T) -> Result<u64, ErrorKind> {
+        let index = self.next;
+        let () = self.tree.add(point, (index, data))?;
+        self.next += 1;
+        Ok(index)
+    }
+
+    /// The `num` nearest items to `point` among those with an insertion index of at most
+    /// `max_index`, with their insertion indices
+    pub fn nearest_before<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        max_index: u64,
+        distance: &F,
+    ) -> Result<Vec<(A, u64, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let nearest = self.tree.nearest_where(
+            point,
+            num,
+            distance,
+            |&earliest| earliest <= max_index,
+            |&(index, _)| index <= max_index,
+        )?;
+        Ok(nearest
+            .into_iter()
+            .map(|(d, (index, data))| (d, *index, data))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::{Rng, SeedableRng, StdRng};
+    use super::InsertionOrdered;
+    use crate::distance::squared_euclidean;
+
+    fn dist<const D: usize>(a: &[f64; D], b: &[f64; D]) -> f64 {
+        squared_euclidean(a, b)
+    }
+
+    #[test]
+    fn it_finds_neighbours_as_of_earlier_insertions() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[4, 4, 2][..]);
+        let mut tree = InsertionOrdered::with_capacity(8);
+        let mut items = vec![];
+        for i in 0..3000 {
+            let p = [rng.gen_range(0.0, 100.0), rng.gen_range(0.0, 100.0)];
+            assert_eq!(tree.add(p, i).unwrap(), i as u64);
+            items.push(p);
+        }
+
+        for &cut in &[0, 1, 10, 500, 1999, 2999, 5000] {
+            for _ in 0..10 {
+                let query = [rng.gen_range(0.0, 100.0), rng.gen_range(0.0, 100.0)];
+                let nearest: Vec<(u64, i32)> = tree
+                    .nearest_before(&query, 5, cut, &dist)
+                    .unwrap()
+                    .into_iter()
+                    .map(|(_, index, &data)| (index, data))
+                    .collect();
+                let mut expected: Vec<(f64, u64)> = items
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, _)| i as u64 <= cut)
+                    .map(|(i, p)| (dist(p, &query), i as u64))
+                    .collect();
+                expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                let expected: Vec<(u64, i32)> = expected
+                    .into_iter()
+                    .take(5)
+                    .map(|(_, i)| (i, i as i32))
+                    .collect();
+                assert_eq!(nearest, expected);
+            }
+        }
+    }
+}
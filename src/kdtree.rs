@@ -2,8 +2,8 @@ use std::collections::BinaryHeap;
 
 use num_traits::{Float, One, Zero};
 
-use crate::heap_element::HeapElement;
-use crate::util;
+use crate::geometry;
+use crate::priority::{MaxByDistance, MinByDistance};
 
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 #[derive(Clone, Debug)]
@@ -30,6 +30,7 @@ pub enum ErrorKind {
     WrongDimension,
     NonFiniteCoordinate,
     ZeroCapacity,
+    InvalidRadius,
 }
 
 impl<A: Float + Zero + One, T: std::cmp::PartialEq, U: AsRef<[A]> + std::cmp::PartialEq>
@@ -72,22 +73,17 @@ impl<A: Float + Zero + One, T: std::cmp::PartialEq, U: AsRef<[A]> + std::cmp::Pa
     where
         F: Fn(&[A], &[A]) -> A,
     {
-        if let Err(err) = self.check_point(point) {
-            return Err(err);
-        }
+        self.check_point(point)?;
         let num = std::cmp::min(num, self.size);
         if num == 0 {
             return Ok(vec![]);
         }
         let mut pending = BinaryHeap::new();
-        let mut evaluated = BinaryHeap::<HeapElement<A, &T>>::new();
-        pending.push(HeapElement {
-            distance: A::zero(),
-            element: self,
-        });
+        let mut evaluated = BinaryHeap::<MaxByDistance<A, &T>>::new();
+        pending.push(MinByDistance::new(A::zero(), self));
         while !pending.is_empty()
             && (evaluated.len() < num
-                || (-pending.peek().unwrap().distance <= evaluated.peek().unwrap().distance))
+                || (pending.peek().unwrap().0.distance <= evaluated.peek().unwrap().0.distance))
         {
             self.nearest_step(
                 point,
@@ -106,23 +102,23 @@ impl<A: Float + Zero + One, T: std::cmp::PartialEq, U: AsRef<[A]> + std::cmp::Pa
             .collect())
     }
 
+    /// The items within `radius` of `point`, nearest first. A negative or NaN radius returns
+    /// `ErrorKind::InvalidRadius`.
     pub fn within<F>(&self, point: &[A], radius: A, distance: &F) -> Result<Vec<(A, &T)>, ErrorKind>
     where
         F: Fn(&[A], &[A]) -> A,
     {
-        if let Err(err) = self.check_point(point) {
-            return Err(err);
+        self.check_point(point)?;
+        if radius.is_nan() || radius < A::zero() {
+            return Err(ErrorKind::InvalidRadius);
         }
         if self.size == 0 {
             return Ok(vec![]);
         }
         let mut pending = BinaryHeap::new();
-        let mut evaluated = BinaryHeap::<HeapElement<A, &T>>::new();
-        pending.push(HeapElement {
-            distance: A::zero(),
-            element: self,
-        });
-        while !pending.is_empty() && (-pending.peek().unwrap().distance <= radius) {
+        let mut evaluated = BinaryHeap::<MaxByDistance<A, &T>>::new();
+        pending.push(MinByDistance::new(A::zero(), self));
+        while !pending.is_empty() && (pending.peek().unwrap().0.distance <= radius) {
             self.nearest_step(
                 point,
                 self.size,
@@ -145,18 +141,18 @@ impl<A: Float + Zero + One, T: std::cmp::PartialEq, U: AsRef<[A]> + std::cmp::Pa
         num: usize,
         max_dist: A,
         distance: &F,
-        pending: &mut BinaryHeap<HeapElement<A, &'b Self>>,
-        evaluated: &mut BinaryHeap<HeapElement<A, &'b T>>,
+        pending: &mut BinaryHeap<MinByDistance<A, &'b Self>>,
+        evaluated: &mut BinaryHeap<MaxByDistance<A, &'b T>>,
     ) where
         F: Fn(&[A], &[A]) -> A,
     {
-        let mut curr = &*pending.pop().unwrap().element;
+        let mut curr = pending.pop().unwrap().0.element;
         debug_assert!(evaluated.len() <= num);
         let evaluated_dist = if evaluated.len() == num {
             // We only care about the nearest `num` points, so if we already have `num` points,
             // any more point we add to `evaluated` must be nearer then one of the point already in
             // `evaluated`.
-            max_dist.min(evaluated.peek().unwrap().distance)
+            max_dist.min(evaluated.peek().unwrap().0.distance)
         } else {
             max_dist
         };
@@ -170,28 +166,24 @@ impl<A: Float + Zero + One, T: std::cmp::PartialEq, U: AsRef<[A]> + std::cmp::Pa
                 candidate = curr.left.as_ref().unwrap();
                 curr = curr.right.as_ref().unwrap();
             }
-            let candidate_to_space = util::distance_to_space(
+            let candidate_to_space = geometry::distance_to_space(
                 point,
-                &*candidate.min_bounds,
-                &*candidate.max_bounds,
+                &candidate.min_bounds,
+                &candidate.max_bounds,
                 distance,
             );
             if candidate_to_space <= evaluated_dist {
-                pending.push(HeapElement {
-                    distance: candidate_to_space * -A::one(),
-                    element: &**candidate,
-                });
+                pending.push(MinByDistance::new(candidate_to_space, &**candidate));
             }
         }
 
         let points = curr.points.as_ref().unwrap().iter();
         let bucket = curr.bucket.as_ref().unwrap().iter();
-        let iter = points.zip(bucket).map(|(p, d)| HeapElement {
-            distance: distance(point, p.as_ref()),
-            element: d,
-        });
+        let iter = points
+            .zip(bucket)
+            .map(|(p, d)| MaxByDistance::new(distance(point, p.as_ref()), d));
         for element in iter {
-            if element <= max_dist {
+            if element.0.distance <= max_dist {
                 if evaluated.len() < num {
                     evaluated.push(element);
                 } else if element < *evaluated.peek().unwrap() {
@@ -210,15 +202,10 @@ impl<A: Float + Zero + One, T: std::cmp::PartialEq, U: AsRef<[A]> + std::cmp::Pa
     where
         F: Fn(&[A], &[A]) -> A,
     {
-        if let Err(err) = self.check_point(point) {
-            return Err(err);
-        }
+        self.check_point(point)?;
         let mut pending = BinaryHeap::new();
-        let evaluated = BinaryHeap::<HeapElement<A, &T>>::new();
-        pending.push(HeapElement {
-            distance: A::zero(),
-            element: self,
-        });
+        let evaluated = BinaryHeap::<MinByDistance<A, &T>>::new();
+        pending.push(MinByDistance::new(A::zero(), self));
         Ok(NearestIter {
             point,
             pending,
@@ -235,15 +222,10 @@ impl<A: Float + Zero + One, T: std::cmp::PartialEq, U: AsRef<[A]> + std::cmp::Pa
     where
         F: Fn(&[A], &[A]) -> A,
     {
-        if let Err(err) = self.check_point(point) {
-            return Err(err);
-        }
+        self.check_point(point)?;
         let mut pending = BinaryHeap::new();
-        let evaluated = BinaryHeap::<HeapElement<A, &mut T>>::new();
-        pending.push(HeapElement {
-            distance: A::zero(),
-            element: self,
-        });
+        let evaluated = BinaryHeap::<MinByDistance<A, &mut T>>::new();
+        pending.push(MinByDistance::new(A::zero(), self));
         Ok(NearestIterMut {
             point,
             pending,
@@ -256,9 +238,7 @@ impl<A: Float + Zero + One, T: std::cmp::PartialEq, U: AsRef<[A]> + std::cmp::Pa
         if self.capacity == 0 {
             return Err(ErrorKind::ZeroCapacity);
         }
-        if let Err(err) = self.check_point(point.as_ref()) {
-            return Err(err);
-        }
+        self.check_point(point.as_ref())?;
         self.add_unchecked(point, data)
     }
 
@@ -294,16 +274,18 @@ impl<A: Float + Zero + One, T: std::cmp::PartialEq, U: AsRef<[A]> + std::cmp::Pa
 
     pub fn remove(&mut self, point: &U, data: &T) -> Result<usize, ErrorKind> {
         let mut removed = 0;
-        if let Err(err) = self.check_point(point.as_ref()) {
-            return Err(err);
-        }
+        self.check_point(point.as_ref())?;
         if let (Some(mut points), Some(mut bucket)) = (self.points.take(), self.bucket.take()) {
-            while let Some(p_index) = points.iter().position(|x| x == point) {
-                if &bucket[p_index] == data {
+            let mut p_index = 0;
+            while p_index < points.len() {
+                if &points[p_index] == point && &bucket[p_index] == data {
                     points.remove(p_index);
                     bucket.remove(p_index);
                     removed += 1;
                     self.size -= 1;
+                } else {
+                    // a point with other data, which used to be found again forever
+                    p_index += 1;
                 }
             }
             self.points = Some(points);
@@ -345,7 +327,13 @@ impl<A: Float + Zero + One, T: std::cmp::PartialEq, U: AsRef<[A]> + std::cmp::Pa
             Some(dim) => {
                 let min = self.min_bounds[dim];
                 let max = self.max_bounds[dim];
-                self.split_value = Some(min + (max - min) / A::from(2.0).unwrap());
+                let mut split_value = min + (max - min) / A::from(2.0).unwrap();
+                if split_value <= min {
+                    // the bounds are adjacent floats, and the points at `min` would go right
+                    // along with all others, splitting the right child forever
+                    split_value = max;
+                }
+                self.split_value = Some(split_value);
             }
         };
         let mut left = Box::new(KdTree::with_capacity(self.dimensions, self.capacity));
@@ -363,6 +351,8 @@ impl<A: Float + Zero + One, T: std::cmp::PartialEq, U: AsRef<[A]> + std::cmp::Pa
         self.right = Some(right);
     }
 
+    /// Points below the split value go left and all others right, including points exactly at
+    /// the split value, both when they are added and when they are looked for
     fn belongs_in_left(&self, point: &[A]) -> bool {
         point[self.split_dimension.unwrap()] < self.split_value.unwrap()
     }
@@ -411,8 +401,8 @@ pub struct NearestIter<
     F: 'a + Fn(&[A], &[A]) -> A,
 > {
     point: &'a [A],
-    pending: BinaryHeap<HeapElement<A, &'b KdTree<A, T, U>>>,
-    evaluated: BinaryHeap<HeapElement<A, &'b T>>,
+    pending: BinaryHeap<MinByDistance<A, &'b KdTree<A, T, U>>>,
+    evaluated: BinaryHeap<MinByDistance<A, &'b T>>,
     distance: &'a F,
 }
 
@@ -425,15 +415,18 @@ where
 {
     type Item = (A, &'b T);
     fn next(&mut self) -> Option<(A, &'b T)> {
-        use util::distance_to_space;
+        use crate::geometry::distance_to_space;
 
         let distance = self.distance;
         let point = self.point;
         while !self.pending.is_empty()
-            && (self.evaluated.peek().map_or(A::infinity(), |x| -x.distance)
-                >= -self.pending.peek().unwrap().distance)
+            && (self
+                .evaluated
+                .peek()
+                .map_or(A::infinity(), |x| x.0.distance)
+                >= self.pending.peek().unwrap().0.distance)
         {
-            let mut curr = &*self.pending.pop().unwrap().element;
+            let mut curr = self.pending.pop().unwrap().0.element;
             while !curr.is_leaf() {
                 let candidate;
                 if curr.belongs_in_left(point) {
@@ -443,25 +436,25 @@ where
                     candidate = curr.left.as_ref().unwrap();
                     curr = curr.right.as_ref().unwrap();
                 }
-                self.pending.push(HeapElement {
-                    distance: -distance_to_space(
+                self.pending.push(MinByDistance::new(
+                    distance_to_space(
                         point,
-                        &*candidate.min_bounds,
-                        &*candidate.max_bounds,
+                        &candidate.min_bounds,
+                        &candidate.max_bounds,
                         distance,
                     ),
-                    element: &**candidate,
-                });
+                    &**candidate,
+                ));
             }
             let points = curr.points.as_ref().unwrap().iter();
             let bucket = curr.bucket.as_ref().unwrap().iter();
-            self.evaluated
-                .extend(points.zip(bucket).map(|(p, d)| HeapElement {
-                    distance: -distance(point, p.as_ref()),
-                    element: d,
-                }));
+            self.evaluated.extend(
+                points
+                    .zip(bucket)
+                    .map(|(p, d)| MinByDistance::new(distance(point, p.as_ref()), d)),
+            );
         }
-        self.evaluated.pop().map(|x| (-x.distance, x.element))
+        self.evaluated.pop().map(Into::into)
     }
 }
 
@@ -474,8 +467,8 @@ pub struct NearestIterMut<
     F: 'a + Fn(&[A], &[A]) -> A,
 > {
     point: &'a [A],
-    pending: BinaryHeap<HeapElement<A, &'b mut KdTree<A, T, U>>>,
-    evaluated: BinaryHeap<HeapElement<A, &'b mut T>>,
+    pending: BinaryHeap<MinByDistance<A, &'b mut KdTree<A, T, U>>>,
+    evaluated: BinaryHeap<MinByDistance<A, &'b mut T>>,
     distance: &'a F,
 }
 
@@ -488,15 +481,18 @@ where
 {
     type Item = (A, &'b mut T);
     fn next(&mut self) -> Option<(A, &'b mut T)> {
-        use util::distance_to_space;
+        use crate::geometry::distance_to_space;
 
         let distance = self.distance;
         let point = self.point;
         while !self.pending.is_empty()
-            && (self.evaluated.peek().map_or(A::infinity(), |x| -x.distance)
-                >= -self.pending.peek().unwrap().distance)
+            && (self
+                .evaluated
+                .peek()
+                .map_or(A::infinity(), |x| x.0.distance)
+                >= self.pending.peek().unwrap().0.distance)
         {
-            let mut curr = &mut *self.pending.pop().unwrap().element;
+            let mut curr = &mut *self.pending.pop().unwrap().0.element;
             while !curr.is_leaf() {
                 let candidate;
                 if curr.belongs_in_left(point) {
@@ -506,41 +502,39 @@ where
                     candidate = curr.left.as_mut().unwrap();
                     curr = curr.right.as_mut().unwrap();
                 }
-                self.pending.push(HeapElement {
-                    distance: -distance_to_space(
+                self.pending.push(MinByDistance::new(
+                    distance_to_space(
                         point,
-                        &*candidate.min_bounds,
-                        &*candidate.max_bounds,
+                        &candidate.min_bounds,
+                        &candidate.max_bounds,
                         distance,
                     ),
-                    element: &mut **candidate,
-                });
+                    &mut **candidate,
+                ));
             }
             let points = curr.points.as_ref().unwrap().iter();
             let bucket = curr.bucket.as_mut().unwrap().iter_mut();
-            self.evaluated
-                .extend(points.zip(bucket).map(|(p, d)| HeapElement {
-                    distance: -distance(point, p.as_ref()),
-                    element: d,
-                }));
+            self.evaluated.extend(
+                points
+                    .zip(bucket)
+                    .map(|(p, d)| MinByDistance::new(distance(point, p.as_ref()), d)),
+            );
         }
-        self.evaluated.pop().map(|x| (-x.distance, x.element))
+        self.evaluated.pop().map(Into::into)
     }
 }
 
-impl std::error::Error for ErrorKind {
-    fn description(&self) -> &str {
-        match *self {
-            ErrorKind::WrongDimension => "wrong dimension",
-            ErrorKind::NonFiniteCoordinate => "non-finite coordinate",
-            ErrorKind::ZeroCapacity => "zero capacity",
-        }
-    }
-}
+impl std::error::Error for ErrorKind {}
 
 impl std::fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "KdTree error: {}", self.to_string())
+        let reason = match *self {
+            ErrorKind::WrongDimension => "wrong dimension",
+            ErrorKind::NonFiniteCoordinate => "non-finite coordinate",
+            ErrorKind::ZeroCapacity => "zero capacity",
+            ErrorKind::InvalidRadius => "invalid radius",
+        };
+        write!(f, "KdTree error: {}", reason)
     }
 }
 
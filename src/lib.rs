@@ -2,6 +2,17 @@
 //!
 //! K-dimensional tree for Rust (bucket point-region implementation)
 //!
+//! For points in two or three dimensions, start with [`geometry::KdTree2`] and
+//! [`geometry::KdTree3`], whose helpers take radii and return distances in the units of the
+//! coordinates.
+//!
+//! [`prelude`] gathers the items most uses need for a glob import.
+//!
+//! [`OwnedKdTree`] and its [`Builder`](tree::Builder) are in [`tree`], what its queries return
+//! in [`query`], the distances it searches by in [`metric`] and [`geometry`], and its errors in
+//! [`error`]. The paths below [`owned_kdtree`], where they all used to be, are deprecated
+//! aliases.
+//!
 //! ## Usage
 //!
 //! ```
@@ -52,20 +63,73 @@
 //!     vec![(0f64, &1), (2f64, &0), (2f64, &2), (8f64, &3)]
 //! );
 //! ```
+#[cfg(feature = "arrow")]
+extern crate arrow_array;
+// the code generated by pyo3's macros names `::core`, which edition 2015 only resolves once
+// the crate root declares it
+#[cfg(feature = "python")]
+extern crate core;
+#[cfg(feature = "geo")]
+extern crate geo;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "no-panic")]
+extern crate no_panic;
 extern crate num_traits;
+#[cfg(feature = "python")]
+extern crate pyo3;
+#[cfg(feature = "rayon")]
+extern crate rayon;
 
+#[cfg(feature = "serialize")]
+extern crate serde;
 #[cfg(feature = "serialize")]
 #[cfg_attr(feature = "serialize", macro_use)]
 extern crate serde_derive;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
 
+pub mod aggregate;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod attribute;
+pub mod bench_support;
+pub mod bounded;
+pub mod cache;
+pub mod curve;
 pub mod distance;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod flat;
+pub mod forest;
+pub mod geometry;
 mod heap_element;
+pub mod hybrid;
+pub mod insertion;
 pub mod kdtree;
-mod util;
+pub mod metric;
+#[cfg(feature = "no-panic")]
+pub mod panic_free;
+pub mod prelude;
+pub mod priority;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod query;
+pub mod ref_kdtree;
+mod search;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+pub mod trajectory;
+pub mod tree;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 pub use crate::kdtree::ErrorKind;
 pub use crate::kdtree::KdTree;
 
 pub mod owned_kdtree;
-pub use crate::owned_kdtree::ErrorKind as OwnedErrorKind;
-pub use crate::owned_kdtree::OwnedKdTree;
+#[cfg(feature = "rayon")]
+pub mod par_iter;
+pub use crate::error::ErrorKind as OwnedErrorKind;
+pub use crate::tree::OwnedKdTree;
@@ -0,0 +1,323 @@
+//! Distances together with a lower bound on the distance from a point to a box, for the
+//! `_metric` variants of the queries of [`OwnedKdTree`](crate::tree::OwnedKdTree). The plain
+//! distance functions, such as [`squared_euclidean`], are in [`distance`](crate::distance), which
+//! also re-exports the items of this module.
+
+use num_traits::Float;
+
+use crate::distance::squared_euclidean;
+use crate::error::ErrorKind;
+use crate::geometry::distance_to_space_const;
+
+/// A distance between points of `D` coordinates, together with a lower bound on the distance
+/// from a point to a box, which a search uses to rule out the nodes of a tree without looking
+/// at their items.
+///
+/// The query methods taking a closure, such as
+/// [`OwnedKdTree::nearest`](crate::OwnedKdTree::nearest), bound the distance to a box by the
+/// distance to its nearest point, found by clamping each coordinate to the box on its own, as
+/// in [`distance_to_space_const`]. That is only a lower bound if moving a point closer to
+/// another along one axis never takes it farther away, which holds for every weighted
+/// Minkowski distance but not for, say, a distance that wraps around. Such metrics should
+/// implement this trait and be passed to the `_metric` variants of the queries, like
+/// [`OwnedKdTree::nearest_metric`](crate::OwnedKdTree::nearest_metric).
+///
+/// # Examples
+///
+/// ```rust
+/// use kdtree::metric::{Manhattan, Metric};
+///
+/// assert_eq!(Manhattan.distance(&[0.0, 0.0], &[1.0, 2.0]), 3.0);
+/// assert_eq!(Manhattan.point_to_box(&[0.0, 5.0], &[1.0, 1.0], &[2.0, 2.0]), 4.0);
+/// ```
+pub trait Metric<A, const D: usize> {
+    /// The distance between `a` and `b`
+    fn distance(&self, a: &[A; D], b: &[A; D]) -> A;
+
+    /// A lower bound on the distance from `p` to any point of the box `[min, max]`, zero if `p`
+    /// is inside it. The box is never empty. A NaN bound rules nothing out, as if it were zero.
+    fn point_to_box(&self, p: &[A; D], min: &[A; D], max: &[A; D]) -> A;
+}
+
+/// The point of the box `[min, max]` nearest to `p`
+fn clamp<A: Float, const D: usize>(p: &[A; D], min: &[A; D], max: &[A; D]) -> [A; D] {
+    let mut clamped = *p;
+    for ((x, &min), &max) in clamped.iter_mut().zip(min).zip(max) {
+        *x = x.max(min).min(max);
+    }
+    clamped
+}
+
+/// The [`squared_euclidean`] distance
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SquaredEuclidean;
+
+impl<A: Float, const D: usize> Metric<A, D> for SquaredEuclidean {
+    fn distance(&self, a: &[A; D], b: &[A; D]) -> A {
+        squared_euclidean(a, b)
+    }
+
+    fn point_to_box(&self, p: &[A; D], min: &[A; D], max: &[A; D]) -> A {
+        squared_euclidean(p, &clamp(p, min, max))
+    }
+}
+
+/// The sum of the absolute differences of the coordinates
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Manhattan;
+
+impl<A: Float, const D: usize> Metric<A, D> for Manhattan {
+    fn distance(&self, a: &[A; D], b: &[A; D]) -> A {
+        a.iter()
+            .zip(b)
+            .fold(A::zero(), |sum, (&x, &y)| sum + (x - y).abs())
+    }
+
+    fn point_to_box(&self, p: &[A; D], min: &[A; D], max: &[A; D]) -> A {
+        self.distance(p, &clamp(p, min, max))
+    }
+}
+
+/// The largest absolute difference of the coordinates
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Chebyshev;
+
+impl<A: Float, const D: usize> Metric<A, D> for Chebyshev {
+    fn distance(&self, a: &[A; D], b: &[A; D]) -> A {
+        a.iter()
+            .zip(b)
+            .fold(A::zero(), |max, (&x, &y)| max.max((x - y).abs()))
+    }
+
+    fn point_to_box(&self, p: &[A; D], min: &[A; D], max: &[A; D]) -> A {
+        self.distance(p, &clamp(p, min, max))
+    }
+}
+
+/// `metric` measured after multiplying each coordinate by its `scale`, to weigh some axes
+/// more than others or bring coordinates of different units together
+///
+/// ```rust
+/// use kdtree::metric::{Metric, Scaled, SquaredEuclidean};
+///
+/// let metric = Scaled::new(SquaredEuclidean, [1.0, 10.0]);
+/// assert_eq!(metric.distance(&[0.0, 0.0], &[1.0, 1.0]), 101.0);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Scaled<M, A, const D: usize> {
+    pub metric: M,
+    pub scale: [A; D],
+}
+
+impl<M, A: Float, const D: usize> Scaled<M, A, D> {
+    pub fn new(metric: M, scale: [A; D]) -> Self {
+        Scaled { metric, scale }
+    }
+
+    fn scaled(&self, p: &[A; D]) -> [A; D] {
+        let mut scaled = *p;
+        for (x, &s) in scaled.iter_mut().zip(&self.scale) {
+            *x = *x * s;
+        }
+        scaled
+    }
+}
+
+impl<M: Metric<A, D>, A: Float, const D: usize> Metric<A, D> for Scaled<M, A, D> {
+    fn distance(&self, a: &[A; D], b: &[A; D]) -> A {
+        self.metric.distance(&self.scaled(a), &self.scaled(b))
+    }
+
+    fn point_to_box(&self, p: &[A; D], min: &[A; D], max: &[A; D]) -> A {
+        // a negative scale turns the box around
+        let (mut lower, mut upper) = (self.scaled(min), self.scaled(max));
+        for (lower, upper) in lower.iter_mut().zip(upper.iter_mut()) {
+            if *lower > *upper {
+                std::mem::swap(lower, upper);
+            }
+        }
+        self.metric.point_to_box(&self.scaled(p), &lower, &upper)
+    }
+}
+
+/// The quadratic form `(a - b)ᵀ M (a - b)` of a positive definite matrix `M`, such as the
+/// squared Mahalanobis distance with `M` the inverse of a covariance. Only the symmetric part
+/// of `M` matters to the form, so that is what is kept. The distance to a box is bounded by the
+/// squared euclidean distance to it times a lower bound on the smallest eigenvalue of `M`, so
+/// the more elongated the form, the less a search can rule out.
+///
+/// ```rust
+/// use kdtree::metric::{Metric, QuadraticForm};
+///
+/// let metric = QuadraticForm::new([[2.0, 1.0], [1.0, 2.0]]).unwrap();
+/// assert_eq!(metric.distance(&[0.0, 0.0], &[1.0, 1.0]), 6.0);
+/// assert!(QuadraticForm::new([[1.0, 2.0], [2.0, 1.0]]).is_err());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuadraticForm<A, const D: usize> {
+    matrix: [[A; D]; D],
+    /// At most the smallest eigenvalue of `matrix`
+    min_eigenvalue: A,
+}
+
+impl<A: Float, const D: usize> QuadraticForm<A, D> {
+    /// The quadratic form of `matrix`, or `ErrorKind::NotPositiveDefinite` if the symmetric
+    /// part of `matrix` is not positive definite or has a non-finite entry
+    #[allow(clippy::needless_range_loop)]
+    pub fn new(matrix: [[A; D]; D]) -> Result<Self, ErrorKind> {
+        let two = A::one() + A::one();
+        let mut symmetric = matrix;
+        for i in 0..D {
+            for j in 0..D {
+                symmetric[i][j] = (matrix[i][j] + matrix[j][i]) / two;
+            }
+        }
+        if !is_positive_definite(&symmetric, A::zero()) {
+            return Err(ErrorKind::NotPositiveDefinite);
+        }
+        // the smallest eigenvalue is the largest shift leaving the matrix positive definite,
+        // and at most its smallest diagonal entry
+        let mut low = A::zero();
+        let mut high = (0..D).fold(A::infinity(), |min, i| min.min(symmetric[i][i]));
+        for _ in 0..64 {
+            let mid = (low + high) / two;
+            if is_positive_definite(&symmetric, mid) {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(QuadraticForm {
+            matrix: symmetric,
+            min_eigenvalue: low,
+        })
+    }
+
+    /// The symmetric matrix of the form
+    pub fn matrix(&self) -> &[[A; D]; D] {
+        &self.matrix
+    }
+}
+
+/// Whether `matrix - shift I` is positive definite, that is whether its Cholesky factorization
+/// succeeds
+#[allow(clippy::needless_range_loop)]
+fn is_positive_definite<A: Float, const D: usize>(matrix: &[[A; D]; D], shift: A) -> bool {
+    let mut factor = [[A::zero(); D]; D];
+    for i in 0..D {
+        for j in 0..=i {
+            let mut sum = matrix[i][j];
+            for k in 0..j {
+                sum = sum - factor[i][k] * factor[j][k];
+            }
+            if i == j {
+                let pivot = sum - shift;
+                if !(pivot > A::zero() && pivot.is_finite()) {
+                    return false;
+                }
+                factor[i][i] = pivot.sqrt();
+            } else {
+                factor[i][j] = sum / factor[j][j];
+            }
+        }
+    }
+    true
+}
+
+impl<A: Float, const D: usize> Metric<A, D> for QuadraticForm<A, D> {
+    fn distance(&self, a: &[A; D], b: &[A; D]) -> A {
+        let mut diff = [A::zero(); D];
+        for ((d, &x), &y) in diff.iter_mut().zip(a).zip(b) {
+            *d = x - y;
+        }
+        let form = self
+            .matrix
+            .iter()
+            .zip(&diff)
+            .fold(A::zero(), |sum, (row, &d)| {
+                sum + d * row
+                    .iter()
+                    .zip(&diff)
+                    .fold(A::zero(), |dot, (&m, &e)| dot + m * e)
+            });
+        // rounding cannot take a positive definite form below zero
+        form.max(A::zero())
+    }
+
+    fn point_to_box(&self, p: &[A; D], min: &[A; D], max: &[A; D]) -> A {
+        self.min_eigenvalue * squared_euclidean(p, &clamp(p, min, max))
+    }
+}
+
+/// A closure passed to the query methods, bounding the distance to a box as
+/// [`distance_to_space_const`] does
+pub(crate) struct ClosureMetric<'a, F: ?Sized>(pub(crate) &'a F);
+
+impl<'a, A: Float, F, const D: usize> Metric<A, D> for ClosureMetric<'a, F>
+where
+    F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+{
+    fn distance(&self, a: &[A; D], b: &[A; D]) -> A {
+        (self.0)(a, b)
+    }
+
+    fn point_to_box(&self, p: &[A; D], min: &[A; D], max: &[A; D]) -> A {
+        distance_to_space_const(p, min, max, self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::{Rng, SeedableRng, StdRng};
+    use super::{Chebyshev, ClosureMetric, Manhattan, Metric, QuadraticForm, Scaled};
+    use super::{ErrorKind, SquaredEuclidean};
+    use crate::distance::squared_euclidean;
+
+    /// Checks that `point_to_box` is a lower bound on the distance to points of random boxes,
+    /// and zero for points inside them
+    fn assert_bounds_the_box<M: Metric<f64, 2>>(metric: &M) {
+        let mut rng: StdRng = SeedableRng::from_seed(&[4, 20][..]);
+        for _ in 0..500 {
+            let p = [rng.gen_range(-5.0, 5.0), rng.gen_range(-5.0, 5.0)];
+            let (a, b): (f64, f64) = (rng.gen_range(-5.0, 5.0), rng.gen_range(-5.0, 5.0));
+            let (c, d): (f64, f64) = (rng.gen_range(-5.0, 5.0), rng.gen_range(-5.0, 5.0));
+            let (min, max) = ([a.min(b), c.min(d)], [a.max(b), c.max(d)]);
+            let bound = metric.point_to_box(&p, &min, &max);
+            for _ in 0..10 {
+                let q = [rng.gen_range(min[0], max[0]), rng.gen_range(min[1], max[1])];
+                assert!(bound <= metric.distance(&p, &q) + 1e-9);
+                assert_eq!(metric.point_to_box(&q, &min, &max), 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn it_bounds_the_distance_to_a_box() {
+        assert_bounds_the_box(&SquaredEuclidean);
+        assert_bounds_the_box(&Manhattan);
+        assert_bounds_the_box(&Chebyshev);
+        assert_bounds_the_box(&Scaled::new(Manhattan, [2.0, -0.5]));
+        assert_bounds_the_box(&QuadraticForm::new([[3.0, 1.0], [1.0, 0.5]]).unwrap());
+        assert_bounds_the_box(&ClosureMetric(&|a: &[f64; 2], b: &[f64; 2]| {
+            squared_euclidean(a, b)
+        }));
+    }
+
+    #[test]
+    fn it_measures_the_distance_between_points() {
+        let (a, b) = ([1.0, -1.0], [4.0, 3.0]);
+        assert_eq!(SquaredEuclidean.distance(&a, &b), 25.0);
+        assert_eq!(Manhattan.distance(&a, &b), 7.0);
+        assert_eq!(Chebyshev.distance(&a, &b), 4.0);
+        assert_eq!(Scaled::new(Chebyshev, [2.0, 1.0]).distance(&a, &b), 6.0);
+        // only the symmetric part of the matrix counts
+        let form = QuadraticForm::new([[1.0, 4.0], [-4.0, 1.0]]).unwrap();
+        assert_eq!(form.distance(&a, &b), 25.0);
+        assert_eq!(
+            QuadraticForm::new([[1.0, 0.0], [0.0, f64::NAN]]),
+            Err(ErrorKind::NotPositiveDefinite)
+        );
+    }
+}
@@ -0,0 +1,95 @@
+//! The queries of a tree of `f64` points in three dimensions with `usize` payloads, measured by
+//! squared Euclidean distance, checked by the [`no-panic`](https://docs.rs/no-panic) crate to
+//! have no panicking branch. Enabled by the `no-panic` feature.
+//!
+//! The check happens when a binary calling these functions is linked, and only sees through
+//! the calls of an optimised build of a single codegen unit, so a debug build with the feature
+//! enabled fails to link. The `no-panic` profile builds them that way:
+//!
+//! ```text
+//! cargo test --profile no-panic --features no-panic --test panic_free
+//! ```
+//!
+//! Growing the heaps of a search aborts the process if the allocation fails rather than
+//! panicking. Building and modifying a tree are not covered.
+
+use no_panic::no_panic;
+
+use crate::distance::squared_euclidean;
+use crate::error::ErrorKind;
+use crate::query::{FarthestIter, NearestIter};
+use crate::tree::OwnedKdTree;
+
+/// The tree every query of this module takes
+pub type Tree = OwnedKdTree<f64, usize, 3>;
+
+fn distance(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    squared_euclidean(a, b)
+}
+
+/// [`OwnedKdTree::nearest`]
+#[no_panic]
+pub fn nearest<'b>(
+    tree: &'b Tree,
+    point: &[f64; 3],
+    num: usize,
+) -> Result<Vec<(f64, &'b usize)>, ErrorKind> {
+    tree.nearest(point, num, &distance)
+}
+
+/// [`OwnedKdTree::within`]
+#[no_panic]
+pub fn within<'b>(
+    tree: &'b Tree,
+    point: &[f64; 3],
+    radius: f64,
+) -> Result<Vec<(f64, &'b usize)>, ErrorKind> {
+    tree.within(point, radius, &distance)
+}
+
+/// [`OwnedKdTree::iter_nearest`], whose `next` is checked
+#[allow(clippy::type_complexity)]
+pub fn iter_nearest<'a, 'b>(
+    tree: &'b Tree,
+    point: &'a [f64; 3],
+) -> Result<Nearest<'a, 'b, impl Fn(&[f64; 3], &[f64; 3]) -> f64>, ErrorKind> {
+    Ok(Nearest(tree.iter_nearest(point, &distance)?))
+}
+
+/// [`OwnedKdTree::iter_farthest`], whose `next` is checked
+#[allow(clippy::type_complexity)]
+pub fn iter_farthest<'a, 'b>(
+    tree: &'b Tree,
+    point: &'a [f64; 3],
+) -> Result<Farthest<'a, 'b, impl Fn(&[f64; 3], &[f64; 3]) -> f64>, ErrorKind> {
+    Ok(Farthest(tree.iter_farthest(point, &distance)?))
+}
+
+/// Iterator returned by [`iter_nearest`]. The distance is a type parameter rather than a
+/// function pointer, which the check could not see through.
+pub struct Nearest<'a, 'b, F: Fn(&[f64; 3], &[f64; 3]) -> f64>(
+    NearestIter<'a, 'b, f64, usize, F, 3>,
+);
+
+impl<'a, 'b, F: Fn(&[f64; 3], &[f64; 3]) -> f64> Iterator for Nearest<'a, 'b, F> {
+    type Item = (f64, &'b usize);
+
+    #[no_panic]
+    fn next(&mut self) -> Option<(f64, &'b usize)> {
+        self.0.next()
+    }
+}
+
+/// Iterator returned by [`iter_farthest`]
+pub struct Farthest<'a, 'b, F: Fn(&[f64; 3], &[f64; 3]) -> f64>(
+    FarthestIter<'a, 'b, f64, usize, F, 3>,
+);
+
+impl<'a, 'b, F: Fn(&[f64; 3], &[f64; 3]) -> f64> Iterator for Farthest<'a, 'b, F> {
+    type Item = (f64, &'b usize);
+
+    #[no_panic]
+    fn next(&mut self) -> Option<(f64, &'b usize)> {
+        self.0.next()
+    }
+}
@@ -0,0 +1,356 @@
+//! Parallel iteration over the items of an [`OwnedKdTree`] using rayon, enabled by the `rayon`
+//! feature. Work is split along the structure of the tree, every subtree being a task.
+
+use num_traits::{Float, One, Zero};
+use rayon::iter::plumbing::{bridge_unindexed, Folder, UnindexedConsumer, UnindexedProducer};
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+};
+
+use crate::error::ErrorKind;
+use crate::search::belongs_in_left;
+use crate::tree::join::mean_of_leaves;
+use crate::tree::{build_split_value, leaf_capacity_at, Builder, NodeRef, OwnedKdTree};
+
+/// Nodes of at most this many items are built sequentially by
+/// [`par_from_points`](OwnedKdTree::par_from_points), as splitting them further costs more than
+/// it saves
+const PAR_BUILD_THRESHOLD: usize = 1 << 14;
+
+impl<A, T, const D: usize> OwnedKdTree<A, T, D>
+where
+    A: Float + Zero + One + Send + Sync,
+    T: PartialEq + Send + Sync,
+{
+    /// [`from_points`](#method.from_points), building the tree in parallel, see
+    /// [`Builder::par_from_points`]
+    pub fn par_from_points(items: Vec<([A; D], T)>, capacity: usize) -> Result<Self, ErrorKind> {
+        Builder::new().capacity(capacity).par_from_points(items)
+    }
+}
+
+/// Builds a node as `OwnedKdTree::build` does, finding the median, partitioning the items and
+/// building the two subtrees in parallel. Every step gives the same result as its sequential
+/// counterpart: the median is the same value however it is found, and the partition keeps the
+/// items in order.
+pub(crate) fn par_build<A, T, const D: usize>(
+    items: Vec<([A; D], T)>,
+    capacity: usize,
+    leaf_capacity_fn: Option<fn(usize) -> usize>,
+    depth: usize,
+) -> OwnedKdTree<A, T, D>
+where
+    A: Float + Zero + One + Send + Sync,
+    T: PartialEq + Send + Sync,
+{
+    // on a single thread the extra passes of the parallel steps would only slow the build down
+    if items.len() <= PAR_BUILD_THRESHOLD || rayon::current_num_threads() == 1 {
+        return OwnedKdTree::build_quietly(items, capacity, leaf_capacity_fn, depth);
+    }
+    let empty = || ([A::infinity(); D], [A::neg_infinity(); D]);
+    let (min, max) =
+        items
+            .par_iter()
+            .map(|(p, _)| (*p, *p))
+            .reduce(empty, |(mut min, mut max), (a, b)| {
+                for dim in 0..D {
+                    min[dim] = min[dim].min(a[dim]);
+                    max[dim] = max[dim].max(b[dim]);
+                }
+                (min, max)
+            });
+    let (items, dim) = match OwnedKdTree::build_leaf(items, capacity, min, max) {
+        Ok(leaf) => return leaf,
+        Err(split) => split,
+    };
+    let coordinates: Vec<A> = items.par_iter().map(|(p, _)| p[dim]).collect();
+    let median = par_select(coordinates, items.len() / 2);
+    let split_value = build_split_value(&items, dim, median, min[dim], max[dim]);
+    let (left, right): (Vec<_>, Vec<_>) = items
+        .into_par_iter()
+        .partition(|(p, _)| belongs_in_left(p, dim, split_value));
+    let child_capacity = leaf_capacity_at(leaf_capacity_fn, capacity, depth + 1);
+    let build = |items| par_build(items, child_capacity, leaf_capacity_fn, depth + 1);
+    let (left, right) = rayon::join(|| build(left), || build(right));
+    OwnedKdTree::build_stem(capacity, dim, split_value, left, right)
+}
+
+/// The `k`th smallest of `values`, which are all finite. Each round counts the values on either
+/// side of a pivot in parallel and keeps the side holding the `k`th, until few enough values
+/// remain to select it sequentially.
+fn par_select<A: Float + Send + Sync>(mut values: Vec<A>, mut k: usize) -> A {
+    while values.len() > PAR_BUILD_THRESHOLD {
+        // the median of nine spread out values, which keeps the rounds balanced on sorted input
+        let step = values.len() / 9;
+        let mut sample: Vec<A> = (0..9).map(|i| values[i * step]).collect();
+        sample.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+        let pivot = sample[4];
+        let (below, equal) = values
+            .par_iter()
+            .map(|&v| ((v < pivot) as usize, (v == pivot) as usize))
+            .reduce(|| (0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+        if k < below {
+            values = values.into_par_iter().filter(|&v| v < pivot).collect();
+        } else if k < below + equal {
+            return pivot;
+        } else {
+            values = values.into_par_iter().filter(|&v| v > pivot).collect();
+            k -= below + equal;
+        }
+    }
+    let (_, kth, _) = values.select_nth_unstable_by(k, |a, b| a.partial_cmp(b).unwrap());
+    *kth
+}
+
+impl<A: Float + Zero + One + Sync, T: PartialEq + Sync, const D: usize> OwnedKdTree<A, T, D> {
+    /// Iterate over all stored items in parallel, in no particular order
+    pub fn par_iter(&self) -> ParIter<'_, A, T, D> {
+        ParIter { root: self.root() }
+    }
+
+    /// [`chamfer_distance`](#method.chamfer_distance), searching for the nearest items to the
+    /// items of each leaf in parallel. The distances are still summed in order, so the result is
+    /// identical.
+    pub fn par_chamfer_distance<U, F>(
+        &self,
+        other: &OwnedKdTree<A, U, D>,
+        distance: &F,
+    ) -> Option<A>
+    where
+        A: Send,
+        U: PartialEq + Sync,
+        F: Fn(&[A; D], &[A; D]) -> A + Sync + ?Sized,
+    {
+        if self.size() == 0 || other.size() == 0 {
+            return None;
+        }
+        let forward: Vec<_> = self
+            .nonempty_leaves()
+            .par_iter()
+            .map(|leaf| leaf.leaf_nearest_distances(other, distance))
+            .collect();
+        let backward: Vec<_> = other
+            .nonempty_leaves()
+            .par_iter()
+            .map(|leaf| leaf.leaf_nearest_distances(self, distance))
+            .collect();
+        Some(mean_of_leaves(&forward) + mean_of_leaves(&backward))
+    }
+
+    /// [`correspondences`](#method.correspondences), searching for the sources in parallel
+    #[allow(clippy::type_complexity)]
+    pub fn par_correspondences<F>(
+        &self,
+        sources: &[[A; D]],
+        max_dist: A,
+        distance: &F,
+    ) -> Result<Vec<Option<(usize, A, &T)>>, ErrorKind>
+    where
+        A: Send,
+        F: Fn(&[A; D], &[A; D]) -> A + Sync + ?Sized,
+    {
+        sources
+            .par_iter()
+            .enumerate()
+            .map(|(i, source)| self.correspondence(i, source, max_dist, distance))
+            .collect()
+    }
+}
+
+pub struct ParIter<'a, A, T: PartialEq, const D: usize> {
+    root: NodeRef<'a, A, T, D>,
+}
+
+impl<'a, A, T, const D: usize> ParallelIterator for ParIter<'a, A, T, D>
+where
+    A: Float + Zero + One + Sync,
+    T: PartialEq + Sync,
+{
+    type Item = (&'a [A; D], &'a T);
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        bridge_unindexed(NodeProducer { node: self.root }, consumer)
+    }
+}
+
+struct NodeProducer<'a, A, T: PartialEq, const D: usize> {
+    node: NodeRef<'a, A, T, D>,
+}
+
+impl<'a, A, T, const D: usize> UnindexedProducer for NodeProducer<'a, A, T, D>
+where
+    A: Float + Zero + One + Sync,
+    T: PartialEq + Sync,
+{
+    type Item = (&'a [A; D], &'a T);
+
+    fn split(self) -> (Self, Option<Self>) {
+        match (self.node.left(), self.node.right()) {
+            (Some(left), Some(right)) => (
+                NodeProducer { node: left },
+                Some(NodeProducer { node: right }),
+            ),
+            _ => (self, None),
+        }
+    }
+
+    fn fold_with<F>(self, folder: F) -> F
+    where
+        F: Folder<Self::Item>,
+    {
+        folder.consume_iter(self.node.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::{Rng, SeedableRng, StdRng};
+    use super::{par_select, OwnedKdTree};
+    use crate::error::ErrorKind;
+    use crate::tree::{BuildOrder, Builder};
+    use rayon::iter::ParallelIterator;
+
+    #[test]
+    fn it_builds_the_same_tree_in_parallel() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[16, 4][..]);
+        for &n in &[0, 30, 20_000, 150_000] {
+            // few distinct values along the last axis, so many medians fall on duplicates
+            let items: Vec<([f64; 3], usize)> = (0..n)
+                .map(|i| {
+                    let point = [
+                        rng.gen_range(-1.0, 1.0),
+                        rng.gen_range(0.0, 1e3),
+                        rng.gen_range(0, 5) as f64,
+                    ];
+                    (point, i)
+                })
+                .collect();
+            for &order in &[
+                BuildOrder::Unsorted,
+                BuildOrder::Morton,
+                BuildOrder::Hilbert,
+            ] {
+                for &capacity in &[1, 16, 0] {
+                    // a capacity of zero stands for a schedule by depth
+                    let schedule = Some(|depth: usize| 8 + depth % 3).filter(|_| capacity == 0);
+                    let builder = || {
+                        Builder::new()
+                            .capacity(capacity)
+                            .order(order)
+                            .leaf_capacity_fn(schedule.map(|f| f as fn(usize) -> usize))
+                    };
+                    let sequential = builder().from_points(items.clone()).unwrap();
+                    // several threads even on one core, so that the parallel steps are taken
+                    let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build();
+                    let parallel = pool
+                        .unwrap()
+                        .install(|| builder().par_from_points(items.clone()))
+                        .unwrap();
+                    assert_eq!(parallel.structure_hash(), sequential.structure_hash());
+                    assert_eq!(parallel.stats(), sequential.stats());
+                }
+            }
+        }
+
+        let items = vec![([0.0, 0.0], 0), ([1.0, 0.0], 1)];
+        let full = Builder::new()
+            .max_items(Some(1))
+            .par_from_points(items.clone());
+        assert_eq!(full.err(), Some(ErrorKind::Full));
+        let tree = Builder::new().max_items(Some(2)).par_from_points(items);
+        assert_eq!(tree.unwrap().max_items(), Some(2));
+        assert_eq!(
+            OwnedKdTree::par_from_points(vec![([f64::NAN, 0.0], 0)], 4).err(),
+            Some(ErrorKind::NonFiniteCoordinate)
+        );
+    }
+
+    #[test]
+    fn it_selects_like_a_sort() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[16, 5][..]);
+        let mut values: Vec<f64> = (0..100_000).map(|_| rng.gen_range(0, 300) as f64).collect();
+        values.extend((0..50_000).map(|i| i as f64));
+        let mut sorted = values.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for &k in &[0, 1, 70_000, 75_000, 149_999] {
+            assert_eq!(par_select(values.clone(), k), sorted[k]);
+        }
+        assert_eq!(par_select(sorted.clone(), 100_000), sorted[100_000]);
+    }
+
+    #[test]
+    fn it_computes_chamfer_distances_in_parallel() {
+        let distance = |p: &[f64; 2], q: &[f64; 2]| crate::distance::squared_euclidean(p, q);
+        let a = OwnedKdTree::from_points(
+            (0..5000)
+                .map(|i| ([(i % 71) as f64 * 0.3, (i / 71) as f64 * 0.7], i))
+                .collect(),
+            8,
+        )
+        .unwrap();
+        let b = OwnedKdTree::from_points(
+            (0..3000)
+                .map(|i| ([(i % 53) as f64 * 0.41 + 0.1, (i / 53) as f64 * 0.9], i))
+                .collect(),
+            8,
+        )
+        .unwrap();
+        let sequential = a.chamfer_distance(&b, &distance).unwrap();
+        assert!(sequential > 0.0);
+        assert_eq!(a.par_chamfer_distance(&b, &distance), Some(sequential));
+        let empty = OwnedKdTree::<f64, i32, 2>::new();
+        assert_eq!(a.par_chamfer_distance(&empty, &distance), None);
+    }
+
+    #[test]
+    fn it_finds_correspondences_in_parallel() {
+        let distance = |p: &[f64; 3], q: &[f64; 3]| crate::distance::squared_euclidean(p, q);
+        let tree = OwnedKdTree::from_points(
+            (0..4000)
+                .map(|i| ([(i % 16) as f64, (i / 16 % 16) as f64, (i / 256) as f64], i))
+                .collect(),
+            8,
+        )
+        .unwrap();
+        let sources: Vec<[f64; 3]> = (0..3000)
+            .map(|i| {
+                [
+                    (i % 37) as f64 * 0.5,
+                    (i % 23) as f64 * 0.8,
+                    (i % 11) as f64 * 2.0,
+                ]
+            })
+            .collect();
+        let sequential = tree.correspondences(&sources, 0.2, &distance).unwrap();
+        assert!(sequential.iter().any(Option::is_some));
+        assert!(sequential.iter().any(Option::is_none));
+        assert_eq!(
+            tree.par_correspondences(&sources, 0.2, &distance),
+            Ok(sequential)
+        );
+    }
+
+    #[test]
+    fn it_sums_in_parallel() {
+        let items: Vec<([f64; 2], u64)> = (0..10_000)
+            .map(|i| ([(i % 100) as f64, (i / 100) as f64], i))
+            .collect();
+        let tree = OwnedKdTree::from_points(items, 8).unwrap();
+        let sequential: u64 = tree.iter().map(|(_, d)| d).sum();
+        let parallel: u64 = tree.par_iter().map(|(_, d)| d).sum();
+        assert_eq!(parallel, sequential);
+        assert_eq!(parallel, (0..10_000).sum());
+        assert_eq!(tree.par_iter().count(), 10_000);
+    }
+
+    #[test]
+    fn it_iterates_empty_trees() {
+        let tree: OwnedKdTree<f64, u64, 3> = OwnedKdTree::new();
+        assert_eq!(tree.par_iter().count(), 0);
+    }
+}
@@ -0,0 +1,16 @@
+//! The items most uses of the crate need, for a glob import:
+//!
+//! ```rust
+//! use kdtree::prelude::*;
+//!
+//! let mut tree: OwnedKdTree<f64, &str, 2> = Builder::new().capacity(8).build();
+//! tree.add([1.0, 1.0], "a").unwrap();
+//! let found = tree.nearest_metric(&[0.0, 0.0], 1, &SquaredEuclidean).unwrap();
+//! assert_eq!(found, vec![(2.0, &"a")]);
+//! ```
+
+pub use crate::distance::squared_euclidean;
+pub use crate::error::ErrorKind;
+pub use crate::geometry::{KdTree2, KdTree3};
+pub use crate::metric::{Metric, SquaredEuclidean};
+pub use crate::tree::{BuildOrder, Builder, OwnedKdTree, SplitStrategy};
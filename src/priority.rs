@@ -0,0 +1,175 @@
+//! Heap entries ordered by distance, for best-first traversals like the ones behind the nearest
+//! neighbour queries. Wrapping a [`Priority`] in [`MinByDistance`] or [`MaxByDistance`] decides
+//! whether a `BinaryHeap` pops the nearest or the farthest entry first, so distances never need
+//! to be negated.
+//!
+//! ```rust
+//! use std::collections::BinaryHeap;
+//! use kdtree::priority::{MaxByDistance, MinByDistance};
+//!
+//! let mut nearest = BinaryHeap::new();
+//! let mut farthest = BinaryHeap::new();
+//! for (distance, name) in [(2.0, "b"), (f64::NAN, "unknown"), (1.0, "a")] {
+//!     nearest.push(MinByDistance::new(distance, name));
+//!     farthest.push(MaxByDistance::new(distance, name));
+//! }
+//! assert_eq!(nearest.pop().unwrap().0.element, "a");
+//! assert_eq!(farthest.pop().unwrap().0.element, "unknown");
+//! ```
+
+use std::cmp::Ordering;
+
+use num_traits::Float;
+
+/// An element together with its distance. It is not ordered itself, see [`MinByDistance`] and
+/// [`MaxByDistance`].
+#[derive(Clone, Copy, Debug)]
+pub struct Priority<A, E> {
+    pub distance: A,
+    pub element: E,
+}
+
+impl<A, E> From<Priority<A, E>> for (A, E) {
+    fn from(p: Priority<A, E>) -> Self {
+        (p.distance, p.element)
+    }
+}
+
+/// Orders distances totally, with NaN farther than every other distance including infinity
+pub fn cmp_distance<A: Float>(a: &A, b: &A) -> Ordering {
+    a.partial_cmp(b)
+        .unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+}
+
+/// A [`Priority`] ordered so that the nearest is the greatest, which a `BinaryHeap` pops first
+#[derive(Clone, Copy, Debug)]
+pub struct MinByDistance<A, E>(pub Priority<A, E>);
+
+impl<A, E> MinByDistance<A, E> {
+    pub fn new(distance: A, element: E) -> Self {
+        MinByDistance(Priority { distance, element })
+    }
+}
+
+impl<A, E> From<MinByDistance<A, E>> for (A, E) {
+    fn from(p: MinByDistance<A, E>) -> Self {
+        p.0.into()
+    }
+}
+
+impl<A: Float, E> Ord for MinByDistance<A, E> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        cmp_distance(&other.0.distance, &self.0.distance)
+    }
+}
+
+impl<A: Float, E> PartialOrd for MinByDistance<A, E> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<A: Float, E> Eq for MinByDistance<A, E> {}
+
+impl<A: Float, E> PartialEq for MinByDistance<A, E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+/// A [`Priority`] ordered so that the farthest is the greatest, which a `BinaryHeap` pops first
+#[derive(Clone, Copy, Debug)]
+pub struct MaxByDistance<A, E>(pub Priority<A, E>);
+
+impl<A, E> MaxByDistance<A, E> {
+    pub fn new(distance: A, element: E) -> Self {
+        MaxByDistance(Priority { distance, element })
+    }
+}
+
+impl<A, E> From<MaxByDistance<A, E>> for (A, E) {
+    fn from(p: MaxByDistance<A, E>) -> Self {
+        p.0.into()
+    }
+}
+
+impl<A: Float, E> Ord for MaxByDistance<A, E> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        cmp_distance(&self.0.distance, &other.0.distance)
+    }
+}
+
+impl<A: Float, E> PartialOrd for MaxByDistance<A, E> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<A: Float, E> Eq for MaxByDistance<A, E> {}
+
+impl<A: Float, E> PartialEq for MaxByDistance<A, E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cmp_distance, MaxByDistance, MinByDistance};
+    use std::cmp::Ordering;
+    use std::collections::BinaryHeap;
+
+    const DISTANCES: [f64; 6] = [3.0, f64::NAN, 0.0, f64::INFINITY, -1.0, 3.0];
+
+    #[test]
+    fn it_orders_nan_after_infinity() {
+        assert_eq!(cmp_distance(&f64::NAN, &f64::INFINITY), Ordering::Greater);
+        assert_eq!(cmp_distance(&1.0, &f64::NAN), Ordering::Less);
+        assert_eq!(cmp_distance(&f64::NAN, &f64::NAN), Ordering::Equal);
+        assert_eq!(cmp_distance(&2.0, &2.0), Ordering::Equal);
+    }
+
+    #[test]
+    fn it_pops_the_nearest_first() {
+        let mut heap: BinaryHeap<_> = DISTANCES
+            .iter()
+            .enumerate()
+            .map(|(i, &d)| MinByDistance::new(d, i))
+            .collect();
+        let mut order = vec![];
+        while let Some(MinByDistance(p)) = heap.pop() {
+            order.push(p.distance);
+        }
+        assert_eq!(order[..5], [-1.0, 0.0, 3.0, 3.0, f64::INFINITY]);
+        assert!(order[5].is_nan());
+    }
+
+    #[test]
+    fn it_pops_the_farthest_first() {
+        let mut heap: BinaryHeap<_> = DISTANCES
+            .iter()
+            .enumerate()
+            .map(|(i, &d)| MaxByDistance::new(d, i))
+            .collect();
+        let mut order = vec![];
+        while let Some(MaxByDistance(p)) = heap.pop() {
+            order.push(p.distance);
+        }
+        assert!(order[0].is_nan());
+        assert_eq!(order[1..], [f64::INFINITY, 3.0, 3.0, 0.0, -1.0]);
+    }
+
+    #[test]
+    fn it_sorts_consistently() {
+        let mut sorted: Vec<_> = DISTANCES
+            .iter()
+            .map(|&d| MaxByDistance::new(d, ()))
+            .collect();
+        sorted.sort();
+        let distances: Vec<f64> = sorted.iter().map(|p| p.0.distance).collect();
+        assert_eq!(distances[..5], [-1.0, 0.0, 3.0, 3.0, f64::INFINITY]);
+        assert!(distances[5].is_nan());
+        assert!(MinByDistance::new(1.0, ()) > MinByDistance::new(2.0, ()));
+        assert!(MaxByDistance::new(1.0, ()) < MaxByDistance::new(2.0, ()));
+    }
+}
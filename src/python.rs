@@ -0,0 +1,204 @@
+//! Python bindings to an [`OwnedKdTree`] of `float` coordinates and `int` payloads, enabled by
+//! the `python` feature. An extension module importable as `kdtree` can be built with maturin,
+//! adding pyo3's `extension-module` feature.
+//!
+//! ```python
+//! from kdtree import KdTree
+//!
+//! tree = KdTree(2)
+//! tree.add([0.0, 0.0], 7)
+//! tree.nearest([1.0, 0.0], 1)  # [(1.0, 7)]
+//! ```
+//!
+//! Distances are squared euclidean distances. Points of the wrong length and non finite
+//! coordinates raise `ValueError`. The batch methods release the GIL while they search; the tree
+//! stays borrowed meanwhile, so another thread modifying it raises instead of racing.
+
+// the wrappers generated by `#[pymethods]` convert `PyErr` into itself
+#![allow(clippy::useless_conversion)]
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::distance::squared_euclidean;
+use crate::error::ErrorKind;
+use crate::tree::{OwnedKdTree, DEFAULT_LEAF_CAPACITY};
+
+enum Tree {
+    D2(OwnedKdTree<f64, u64, 2>),
+    D3(OwnedKdTree<f64, u64, 3>),
+}
+
+/// Evaluates `$body` with `$tree` bound to the tree of whichever dimension `$tree_enum` holds
+macro_rules! with_tree {
+    ($tree_enum:expr, $tree:ident => $body:expr) => {
+        match $tree_enum {
+            Tree::D2($tree) => $body,
+            Tree::D3($tree) => $body,
+        }
+    };
+}
+
+fn squared<const D: usize>(a: &[f64; D], b: &[f64; D]) -> f64 {
+    squared_euclidean(a, b)
+}
+
+fn value_error(error: ErrorKind) -> PyErr {
+    PyValueError::new_err(error.to_string())
+}
+
+fn found(found: Vec<(f64, &u64)>) -> Vec<(f64, u64)> {
+    found.into_iter().map(|(d, &data)| (d, data)).collect()
+}
+
+/// A tree of 2 or 3 dimensions, chosen when it is created
+#[pyclass(name = "KdTree", module = "kdtree")]
+pub struct PyKdTree {
+    tree: Tree,
+}
+
+impl PyKdTree {
+    fn nearest_found(&self, point: &[f64], k: usize) -> Result<Vec<(f64, u64)>, ErrorKind> {
+        with_tree!(&self.tree, tree => tree.nearest_slice(point, k, &squared).map(found))
+    }
+
+    fn within_found(&self, point: &[f64], radius: f64) -> Result<Vec<(f64, u64)>, ErrorKind> {
+        with_tree!(&self.tree, tree => tree.within_slice(point, radius, &squared).map(found))
+    }
+}
+
+#[pymethods]
+impl PyKdTree {
+    /// Creates an empty tree of `dimensions` dimensions, 2 or 3, with leaves of `capacity` items
+    #[new]
+    #[pyo3(signature = (dimensions, capacity = DEFAULT_LEAF_CAPACITY))]
+    fn new(dimensions: usize, capacity: usize) -> PyResult<Self> {
+        if capacity == 0 {
+            return Err(PyValueError::new_err("capacity must be positive"));
+        }
+        let tree = match dimensions {
+            2 => Tree::D2(OwnedKdTree::with_capacity(capacity)),
+            3 => Tree::D3(OwnedKdTree::with_capacity(capacity)),
+            _ => return Err(PyValueError::new_err("dimensions must be 2 or 3")),
+        };
+        Ok(PyKdTree { tree })
+    }
+
+    /// Adds `data` at `point`
+    fn add(&mut self, point: Vec<f64>, data: u64) -> PyResult<()> {
+        with_tree!(&mut self.tree, tree => tree.add_slice(&point, data)).map_err(value_error)
+    }
+
+    /// The `k` items nearest to `point`, as a list of `(distance, data)` from the nearest
+    fn nearest(&self, point: Vec<f64>, k: usize) -> PyResult<Vec<(f64, u64)>> {
+        self.nearest_found(&point, k).map_err(value_error)
+    }
+
+    /// The items within `radius` of `point`, as a list of `(distance, data)` from the nearest
+    fn within(&self, point: Vec<f64>, radius: f64) -> PyResult<Vec<(f64, u64)>> {
+        self.within_found(&point, radius).map_err(value_error)
+    }
+
+    /// Removes the items equal to `data` at `point`, returning how many were removed
+    fn remove(&mut self, point: Vec<f64>, data: u64) -> PyResult<usize> {
+        with_tree!(&mut self.tree, tree => tree.remove_slice(&point, &data)).map_err(value_error)
+    }
+
+    /// [`nearest`](#method.nearest) for each of `points`, without holding the GIL
+    fn nearest_many(
+        &self,
+        py: Python<'_>,
+        points: Vec<Vec<f64>>,
+        k: usize,
+    ) -> PyResult<Vec<Vec<(f64, u64)>>> {
+        py.allow_threads(|| {
+            points
+                .iter()
+                .map(|point| self.nearest_found(point, k))
+                .collect::<Result<_, _>>()
+        })
+        .map_err(value_error)
+    }
+
+    /// [`within`](#method.within) for each of `points`, without holding the GIL
+    fn within_many(
+        &self,
+        py: Python<'_>,
+        points: Vec<Vec<f64>>,
+        radius: f64,
+    ) -> PyResult<Vec<Vec<(f64, u64)>>> {
+        py.allow_threads(|| {
+            points
+                .iter()
+                .map(|point| self.within_found(point, radius))
+                .collect::<Result<_, _>>()
+        })
+        .map_err(value_error)
+    }
+
+    fn __len__(&self) -> usize {
+        with_tree!(&self.tree, tree => tree.size())
+    }
+}
+
+/// The `kdtree` extension module
+#[pymodule]
+#[pyo3(name = "kdtree")]
+pub fn kdtree_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyKdTree>()
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+
+    use super::PyKdTree;
+
+    #[test]
+    fn it_is_usable_from_python() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let globals = PyDict::new_bound(py);
+            globals
+                .set_item("KdTree", py.get_type_bound::<PyKdTree>())
+                .unwrap();
+            py.run_bound(
+                r#"
+tree = KdTree(2, capacity=2)
+for i in range(20):
+    tree.add([float(i % 5), float(i // 5)], i)
+assert len(tree) == 20
+assert tree.nearest([0.1, 0.0], 2) == [(0.010000000000000002, 0), (0.81, 1)]
+assert sorted(d for _, d in tree.within([2.0, 2.0], 1.0)) == [7, 11, 12, 13, 17]
+assert tree.nearest_many([[0.1, 0.0], [4.0, 3.0]], 1) == [[(0.010000000000000002, 0)], [(0.0, 19)]]
+assert [len(f) for f in tree.within_many([[2.0, 2.0], [9.0, 9.0]], 1.0)] == [5, 0]
+assert tree.remove([4.0, 3.0], 19) == 1
+assert tree.remove([4.0, 3.0], 19) == 0
+assert len(tree) == 19
+
+for i, bad in enumerate((lambda: tree.add([1.0], 0),
+            lambda: tree.add([float("nan"), 0.0], 0),
+            lambda: tree.nearest([1.0, 2.0, 3.0], 1),
+            lambda: tree.within([float("inf"), 0.0], 1.0),
+            lambda: tree.nearest_many([[0.0, 0.0], [0.0]], 1),
+            lambda: KdTree(4),
+            lambda: KdTree(3, capacity=0))):
+    try:
+        bad()
+    except ValueError:
+        pass
+    else:
+        raise AssertionError(f"expected a ValueError from case {i}")
+
+space = KdTree(3)
+space.add([1.0, 2.0, 3.0], 5)
+assert space.nearest([1.0, 2.0, 4.0], 3) == [(1.0, 5)]
+"#,
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+        });
+    }
+}
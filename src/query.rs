@@ -0,0 +1,588 @@
+//! What the queries of an [`OwnedKdTree`] return: the iterators behind [`OwnedKdTree::iter`]
+//! and the incremental nearest neighbour searches, the outcome of a capped radius search, and
+//! the work a query did.
+//!
+//! ```rust
+//! use kdtree::distance::squared_euclidean;
+//! use kdtree::query::NearestIter;
+//! use kdtree::tree::OwnedKdTree;
+//!
+//! let dist = |a: &[f64; 2], b: &[f64; 2]| squared_euclidean(a, b);
+//! let items = (0..10).map(|i| ([i as f64, 0.0], i)).collect();
+//! let tree: OwnedKdTree<f64, usize, 2> = OwnedKdTree::from_points(items, 4).unwrap();
+//! let nearest: NearestIter<f64, usize, _, 2> = tree.iter_nearest(&[3.2, 0.0], &dist).unwrap();
+//! let found: Vec<usize> = nearest.take(2).map(|(_, &i)| i).collect();
+//! assert_eq!(found, [3, 4]);
+//! ```
+
+use std::cmp::Reverse;
+use std::collections::binary_heap::PeekMut;
+use std::collections::BinaryHeap;
+
+use num_traits::{Float, One, Zero};
+
+use crate::curve;
+use crate::geometry::{distance_to_far_corner_const, distance_to_space_const};
+use crate::heap_element::Neighbour;
+use crate::priority::{MaxByDistance, MinByDistance};
+use crate::search::{self, belongs_in_left};
+use crate::tree::{Node, OwnedKdTree};
+
+/// A distance selected at runtime. Every query accepts `&DynDistance` in place of a closure, and
+/// is then compiled once for all such distances rather than once per closure type.
+pub type DynDistance<'a, A, const D: usize> = dyn Fn(&[A; D], &[A; D]) -> A + 'a;
+
+/// The work done by one query, see [`OwnedKdTree::nearest_with_stats`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueryStats {
+    /// Number of nodes, stems and leaves, the search went through
+    pub nodes_visited: usize,
+    /// Number of leaves whose points were measured
+    pub leaves_visited: usize,
+    /// Number of points the distance was measured to
+    pub points_visited: usize,
+}
+
+/// The result of [`OwnedKdTree::within_limited`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum WithinOutcome<A, T> {
+    /// Every item within the radius, nearest first
+    Complete(Vec<(A, T)>),
+    /// The nearest items within the radius, as many as allowed, when more were within it
+    Truncated {
+        results: Vec<(A, T)>,
+        /// The number of items within the radius, of which `results` are the nearest
+        found_at_least: usize,
+    },
+}
+
+impl<A, T> WithinOutcome<A, T> {
+    /// The items returned, nearest first, whether or not the outcome is complete
+    pub fn results(&self) -> &[(A, T)] {
+        match self {
+            WithinOutcome::Complete(results) | WithinOutcome::Truncated { results, .. } => results,
+        }
+    }
+
+    pub fn into_results(self) -> Vec<(A, T)> {
+        match self {
+            WithinOutcome::Complete(results) | WithinOutcome::Truncated { results, .. } => results,
+        }
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        matches!(self, WithinOutcome::Truncated { .. })
+    }
+}
+
+/// Iterator returned by [`OwnedKdTree::iter`], which yields the items below the lower side of
+/// every split before those below the upper side
+pub struct Iter<'a, A, T: PartialEq, const D: usize> {
+    pub(crate) pending: Vec<&'a OwnedKdTree<A, T, D>>,
+    pub(crate) current: std::iter::Zip<std::slice::Iter<'a, [A; D]>, std::slice::Iter<'a, T>>,
+}
+
+impl<'a, A, T: PartialEq, const D: usize> Iterator for Iter<'a, A, T, D> {
+    type Item = (&'a [A; D], &'a T);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.current.next() {
+                return Some(item);
+            }
+            match &self.pending.pop()?.node {
+                Node::Stem { left, right, .. } => {
+                    self.pending.push(right);
+                    self.pending.push(left);
+                }
+                Node::Leaf { points, bucket } => self.current = points.iter().zip(bucket.iter()),
+            }
+        }
+    }
+}
+
+/// Iterator returned by [`OwnedKdTree::iter_morton`]
+pub struct MortonIter<'a, A, T: PartialEq, const D: usize> {
+    pub(crate) pending: Vec<&'a OwnedKdTree<A, T, D>>,
+    pub(crate) min: [A; D],
+    pub(crate) max: [A; D],
+    /// The items of the current leaf, and their positions in it by Morton index
+    pub(crate) points: &'a [[A; D]],
+    pub(crate) bucket: &'a [T],
+    pub(crate) order: std::vec::IntoIter<usize>,
+}
+
+impl<'a, A: Float, T: PartialEq, const D: usize> Iterator for MortonIter<'a, A, T, D> {
+    type Item = (&'a [A; D], &'a T);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(i) = self.order.next() {
+                return Some((&self.points[i], &self.bucket[i]));
+            }
+            match &self.pending.pop()?.node {
+                Node::Stem { left, right, .. } => {
+                    self.pending.push(right);
+                    self.pending.push(left);
+                }
+                Node::Leaf { points, bucket } => {
+                    let bits = curve::bits_per_dimension(D);
+                    let (min, max) = (&self.min, &self.max);
+                    let mut order: Vec<usize> = (0..points.len()).collect();
+                    order.sort_by_cached_key(|&i| curve::morton_index(&points[i], min, max, bits));
+                    self.points = points;
+                    self.bucket = bucket;
+                    self.order = order.into_iter();
+                }
+            }
+        }
+    }
+}
+
+/// Iterator returned by [`OwnedKdTree::iter_nearest`]. It can be paused and resumed at will, and
+/// cloned to continue the search along two branches independently.
+pub struct NearestIter<
+    'a,
+    'b,
+    A: 'a + 'b + Float,
+    T: 'b + PartialEq,
+    F: 'a + ?Sized + Fn(&[A; D], &[A; D]) -> A,
+    const D: usize,
+> {
+    pub(crate) point: &'a [A; D],
+    pub(crate) pending: BinaryHeap<MinByDistance<A, &'b OwnedKdTree<A, T, D>>>,
+    pub(crate) evaluated: BinaryHeap<Reverse<Neighbour<'b, A, &'b T, D>>>,
+    pub(crate) distance: &'a F,
+    pub(crate) yielded: usize,
+}
+
+impl<'a, 'b, A: Float, T: PartialEq, F: ?Sized + Fn(&[A; D], &[A; D]) -> A, const D: usize> Clone
+    for NearestIter<'a, 'b, A, T, F, D>
+{
+    fn clone(&self) -> Self {
+        NearestIter {
+            point: self.point,
+            pending: self.pending.clone(),
+            evaluated: self.evaluated.clone(),
+            distance: self.distance,
+            yielded: self.yielded,
+        }
+    }
+}
+
+impl<'a, 'b, A: Float + Zero + One, T: 'b, const D: usize, F: 'a> NearestIter<'a, 'b, A, T, F, D>
+where
+    F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    T: PartialEq,
+{
+    /// The item the next call of `next` will return, without consuming it
+    pub fn peek(&mut self) -> Option<(A, &'b T)> {
+        self.advance();
+        self.evaluated.peek().map(|x| (x.0.distance, x.0.element))
+    }
+
+    /// How many items have been returned so far
+    pub fn yielded_count(&self) -> usize {
+        self.yielded
+    }
+
+    /// Checks leaves until no pending node can hold anything nearer than the nearest item found
+    fn advance(&mut self) {
+        let distance = self.distance;
+        let point = self.point;
+        // neither heap holds a NaN distance, so this stops exactly when the nearest pending node
+        // is farther than the nearest item found
+        loop {
+            let next = match self.pending.peek_mut() {
+                Some(next) => next,
+                None => break,
+            };
+            let nearest = self
+                .evaluated
+                .peek()
+                .map_or(A::infinity(), |x| x.0.distance);
+            if nearest < next.0.distance {
+                break;
+            }
+            let mut curr = PeekMut::pop(next).0.element;
+            let (points, bucket) = loop {
+                let (near, candidate) = match curr.sides(point) {
+                    Ok(sides) => sides,
+                    Err(leaf) => break leaf,
+                };
+                curr = near;
+                if candidate.size == 0 {
+                    continue;
+                }
+                let to_space = distance_to_space_const(
+                    point,
+                    &candidate.min_bounds,
+                    &candidate.max_bounds,
+                    distance,
+                );
+                search::push(&mut self.pending, MinByDistance::new(to_space, candidate));
+            };
+            for (p, d) in points.iter().zip(bucket) {
+                let distance = distance(point, p);
+                if !distance.is_nan() {
+                    let neighbour = Neighbour {
+                        distance,
+                        point: p,
+                        element: d,
+                    };
+                    search::push(&mut self.evaluated, Reverse(neighbour));
+                }
+            }
+        }
+    }
+}
+
+impl<'a, 'b, A: Float + Zero + One, T: 'b, const D: usize, F: 'a> Iterator
+    for NearestIter<'a, 'b, A, T, F, D>
+where
+    F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    T: PartialEq,
+{
+    type Item = (A, &'b T);
+    fn next(&mut self) -> Option<(A, &'b T)> {
+        self.advance();
+        let next = self.evaluated.pop().map(|x| x.0.into());
+        if next.is_some() {
+            self.yielded += 1;
+        }
+        next
+    }
+}
+
+/// Iterator returned by [`OwnedKdTree::iter_nearest_excluding_self`]
+pub struct NearestExcludingSelfIter<
+    'a,
+    'b,
+    A: 'a + 'b + Float,
+    T: 'a + 'b + PartialEq,
+    F: 'a + ?Sized + Fn(&[A; D], &[A; D]) -> A,
+    const D: usize,
+> {
+    pub(crate) iter: NearestIter<'a, 'b, A, T, F, D>,
+    /// The item still to be skipped
+    pub(crate) excluded: Option<&'a T>,
+}
+
+impl<'a, 'b, A: Float, T: PartialEq, F: ?Sized + Fn(&[A; D], &[A; D]) -> A, const D: usize> Clone
+    for NearestExcludingSelfIter<'a, 'b, A, T, F, D>
+{
+    fn clone(&self) -> Self {
+        NearestExcludingSelfIter {
+            iter: self.iter.clone(),
+            excluded: self.excluded,
+        }
+    }
+}
+
+impl<'a, 'b, A: Float + Zero + One, T: 'a + 'b, const D: usize, F: 'a>
+    NearestExcludingSelfIter<'a, 'b, A, T, F, D>
+where
+    F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    T: PartialEq,
+{
+    /// The item the next call of `next` will return, without consuming it
+    pub fn peek(&mut self) -> Option<(A, &'b T)> {
+        self.skip_excluded();
+        self.iter.peek()
+    }
+
+    /// How many items have been returned so far, not counting the skipped item
+    pub fn yielded_count(&self) -> usize {
+        self.iter.yielded_count()
+    }
+
+    fn skip_excluded(&mut self) {
+        let excluded = match self.excluded {
+            Some(excluded) => excluded,
+            None => return,
+        };
+        self.iter.advance();
+        let point = self.iter.point;
+        // the excluded item is checked for whenever it could be next, and skipped once it is
+        if let Some(Reverse(n)) = self.iter.evaluated.peek() {
+            if n.point == point && n.element == excluded {
+                self.iter.evaluated.pop();
+                self.excluded = None;
+            }
+        }
+    }
+}
+
+impl<'a, 'b, A: Float + Zero + One, T: 'a + 'b, const D: usize, F: 'a> Iterator
+    for NearestExcludingSelfIter<'a, 'b, A, T, F, D>
+where
+    F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    T: PartialEq,
+{
+    type Item = (A, &'b T);
+    fn next(&mut self) -> Option<(A, &'b T)> {
+        self.skip_excluded();
+        self.iter.next()
+    }
+}
+
+/// Iterator returned by [`OwnedKdTree::iter_nearest_mut`]. It is written without `unsafe`: every
+/// pending node is borrowed mutably exactly once, and is moved out of the heap before being split
+/// into disjoint borrows of its children or of the payloads of its leaf, so the borrow checker
+/// proves that no two yielded references alias. Like the tree, it is `Send` and `Sync` whenever
+/// all of its parts are.
+pub struct NearestIterMut<
+    'a,
+    'b,
+    A: 'a + 'b + Float,
+    T: 'b + PartialEq,
+    F: 'a + ?Sized + Fn(&[A; D], &[A; D]) -> A,
+    const D: usize,
+> {
+    pub(crate) point: &'a [A; D],
+    pub(crate) pending: BinaryHeap<MinByDistance<A, &'b mut OwnedKdTree<A, T, D>>>,
+    pub(crate) evaluated: BinaryHeap<Reverse<Neighbour<'b, A, &'b mut T, D>>>,
+    pub(crate) distance: &'a F,
+}
+
+impl<'a, 'b, A: Float + Zero + One, T: 'b, F: 'a, const D: usize> Iterator
+    for NearestIterMut<'a, 'b, A, T, F, D>
+where
+    F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    T: PartialEq,
+{
+    type Item = (A, &'b mut T);
+    fn next(&mut self) -> Option<(A, &'b mut T)> {
+        let distance = self.distance;
+        let point = self.point;
+        loop {
+            let next = match self.pending.peek_mut() {
+                Some(next) => next,
+                None => break,
+            };
+            let nearest = self
+                .evaluated
+                .peek()
+                .map_or(A::infinity(), |x| x.0.distance);
+            if nearest < next.0.distance {
+                break;
+            }
+            let mut curr = &mut *PeekMut::pop(next).0.element;
+            let (points, bucket) = loop {
+                let (near, candidate) = match &mut curr.node {
+                    Node::Stem {
+                        split_dimension,
+                        split_value,
+                        left,
+                        right,
+                    } => {
+                        if belongs_in_left(point, *split_dimension, *split_value) {
+                            (left, right)
+                        } else {
+                            (right, left)
+                        }
+                    }
+                    Node::Leaf { points, bucket } => break (points, bucket),
+                };
+                curr = near;
+                if candidate.size == 0 {
+                    continue;
+                }
+                self.pending.push(MinByDistance::new(
+                    distance_to_space_const(
+                        point,
+                        &candidate.min_bounds,
+                        &candidate.max_bounds,
+                        distance,
+                    ),
+                    &mut **candidate,
+                ));
+            };
+            self.evaluated.extend(
+                points
+                    .iter()
+                    .zip(bucket.iter_mut())
+                    .map(|(p, d)| {
+                        Reverse(Neighbour {
+                            distance: distance(point, p),
+                            point: p,
+                            element: d,
+                        })
+                    })
+                    .filter(|n| !n.0.distance.is_nan()),
+            );
+        }
+        self.evaluated.pop().map(|x| x.0.into())
+    }
+}
+
+/// Iterator returned by [`OwnedKdTree::iter_farthest`]
+pub struct FarthestIter<
+    'a,
+    'b,
+    A: 'a + 'b + Float,
+    T: 'b + PartialEq,
+    F: 'a + ?Sized + Fn(&[A; D], &[A; D]) -> A,
+    const D: usize,
+> {
+    pub(crate) point: &'a [A; D],
+    pub(crate) pending: BinaryHeap<MaxByDistance<A, &'b OwnedKdTree<A, T, D>>>,
+    pub(crate) evaluated: BinaryHeap<Neighbour<'b, A, &'b T, D>>,
+    pub(crate) distance: &'a F,
+}
+
+impl<'a, 'b, A: Float + Zero + One, T: 'b, F: 'a, const D: usize> Iterator
+    for FarthestIter<'a, 'b, A, T, F, D>
+where
+    F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    T: PartialEq,
+{
+    type Item = (A, &'b T);
+    fn next(&mut self) -> Option<(A, &'b T)> {
+        let distance = self.distance;
+        let point = self.point;
+        loop {
+            let next = match self.pending.peek_mut() {
+                Some(next) => next,
+                None => break,
+            };
+            let farthest = self
+                .evaluated
+                .peek()
+                .map_or(A::neg_infinity(), |x| x.distance);
+            if farthest > next.0.distance {
+                break;
+            }
+            let curr = PeekMut::pop(next).0.element;
+            let children = match &curr.node {
+                Node::Stem { left, right, .. } => [&**left, &**right],
+                Node::Leaf { points, bucket } => {
+                    for (p, d) in points.iter().zip(bucket) {
+                        let distance = distance(point, p);
+                        if !distance.is_nan() {
+                            let neighbour = Neighbour {
+                                distance,
+                                point: p,
+                                element: d,
+                            };
+                            search::push(&mut self.evaluated, neighbour);
+                        }
+                    }
+                    continue;
+                }
+            };
+            for child in children {
+                if child.size > 0 {
+                    let to_corner = distance_to_far_corner_const(
+                        point,
+                        &child.min_bounds,
+                        &child.max_bounds,
+                        distance,
+                    );
+                    search::push(&mut self.pending, MaxByDistance::new(to_corner, child));
+                }
+            }
+        }
+        self.evaluated.pop().map(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+    use super::{
+        FarthestIter, Iter, NearestExcludingSelfIter, NearestIter, NearestIterMut, WithinOutcome,
+    };
+    use crate::distance::squared_euclidean;
+    use crate::flat::FlatKdTree;
+    use crate::tree::OwnedKdTree;
+
+    fn dist<const D: usize>(a: &[f64; D], b: &[f64; D]) -> f64 {
+        squared_euclidean(a, b)
+    }
+
+    #[test]
+    fn it_visits_the_lower_side_of_each_split_first() {
+        let items: Vec<([f64; 1], usize)> = (0..40).rev().map(|i| ([i as f64], i)).collect();
+        // leaves of one item each, so that the order of the leaves is the order of the items
+        let tree = OwnedKdTree::from_points(items, 1).unwrap();
+        let seen: Vec<usize> = tree.iter().map(|(_, &i)| i).collect();
+        assert_eq!(seen, (0..40).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn it_resumes_cloned_nearest_iterators_independently() {
+        let items: Vec<([f64; 2], usize)> = (0..100).map(|i| ([i as f64, 0.0], i)).collect();
+        let tree = OwnedKdTree::from_points(items, 4).unwrap();
+        let mut iter = tree.iter_nearest(&[50.0, 0.0], &dist).unwrap();
+        assert_eq!(iter.next(), Some((0.0, &50)));
+        let mut copy = iter.clone();
+        let rest: Vec<f64> = iter.map(|(d, _)| d).collect();
+        assert_eq!(rest.len(), 99);
+        assert!(rest.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(copy.next().map(|(d, _)| d), Some(rest[0]));
+
+        let farthest: Vec<usize> = tree
+            .iter_farthest(&[50.0, 0.0], &dist)
+            .unwrap()
+            .take(2)
+            .map(|(_, &i)| i)
+            .collect();
+        assert_eq!(farthest, [0, 99]);
+    }
+
+    #[test]
+    fn it_reports_whether_a_capped_search_is_complete() {
+        let complete: WithinOutcome<f64, &str> = WithinOutcome::Complete(vec![(1.0, "a")]);
+        assert!(!complete.is_truncated());
+        assert_eq!(complete.results(), &[(1.0, "a")]);
+        let truncated = WithinOutcome::Truncated {
+            results: vec![(1.0, "a"), (2.0, "b")],
+            found_at_least: 5,
+        };
+        assert!(truncated.is_truncated());
+        assert_eq!(truncated.results().len(), 2);
+        assert_eq!(truncated.into_results(), vec![(1.0, "a"), (2.0, "b")]);
+    }
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn it_is_send_and_sync() {
+        #[allow(dead_code)]
+        #[derive(PartialEq)]
+        struct Data(Vec<u8>);
+        type Distance = fn(&[f64; 3], &[f64; 3]) -> f64;
+
+        assert_send_sync::<OwnedKdTree<f64, Data, 3>>();
+        assert_send_sync::<NearestIter<'static, 'static, f64, Data, Distance, 3>>();
+        assert_send_sync::<NearestIterMut<'static, 'static, f64, Data, Distance, 3>>();
+        assert_send_sync::<FarthestIter<'static, 'static, f64, Data, Distance, 3>>();
+        assert_send_sync::<NearestExcludingSelfIter<'static, 'static, f64, Data, Distance, 3>>();
+        assert_send_sync::<Iter<'static, f64, Data, 3>>();
+        assert_send_sync::<FlatKdTree<f64, Data, 3>>();
+    }
+
+    #[test]
+    fn it_keeps_nearest_iterators_small() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[1, 2][..]);
+        let items: Vec<([f64; 2], usize)> = (0..1_000_000)
+            .map(|i| ([rng.gen_range(0.0, 1.0), rng.gen_range(0.0, 1.0)], i))
+            .collect();
+        let tree = OwnedKdTree::from_points(items, 16).unwrap();
+        let depth = tree.stats().max_depth;
+        for query in [[0.5, 0.5], [0.0, 0.0], [0.123, 0.77]] {
+            let mut iter = tree.iter_nearest(&query, &dist).unwrap();
+            for _ in 0..3 {
+                iter.next().unwrap();
+            }
+            // one pending sibling per level of each of the few leaves checked so far
+            assert!(
+                iter.pending.len() <= 4 * depth,
+                "{} pending",
+                iter.pending.len()
+            );
+            assert!(iter.evaluated.len() <= 4 * 16);
+        }
+    }
+}
@@ -0,0 +1,516 @@
+//! A tree over points it borrows, for datasets too large to copy.
+//!
+//! An [`OwnedKdTree`] stores a copy of every point in its leaves. A [`RefKdTree`] borrows the
+//! points as a slice instead, and its leaves hold indices into that slice, so each item costs a
+//! `usize` rather than `D` coordinates: 8 bytes rather than 12 for `[f32; 3]`, or 24 for
+//! `[f64; 3]`, next to the slice the caller keeps anyway. The tree is built once from the whole
+//! slice and cannot be modified, as the slice cannot grow while borrowed. Queries share their
+//! search code with [`OwnedKdTree`] and return the same items in the same order.
+//!
+//! ```rust
+//! use kdtree::distance::squared_euclidean;
+//! use kdtree::ref_kdtree::RefKdTree;
+//!
+//! let distance = |a: &[f32; 3], b: &[f32; 3]| squared_euclidean(a, b);
+//! let points: Vec<[f32; 3]> = (0..100).map(|i| [i as f32, 0.0, 0.0]).collect();
+//! let names: Vec<String> = (0..100).map(|i| format!("point {}", i)).collect();
+//! let tree = RefKdTree::new(&points, names, 16).unwrap();
+//! let nearest = tree.nearest(&[41.8, 0.0, 0.0], 1, &distance).unwrap();
+//! assert_eq!(nearest[0].1, "point 42");
+//! ```
+//!
+//! [`OwnedKdTree`]: crate::OwnedKdTree
+
+use num_traits::{Float, One, Zero};
+
+use crate::error::ErrorKind;
+use crate::heap_element::Neighbour;
+use crate::metric::ClosureMetric;
+use crate::query::QueryStats;
+use crate::search::{self, belongs_in_left, PointStore, SearchNode};
+
+/// A tree over a borrowed slice of points, see the [module documentation](index.html)
+pub struct RefKdTree<'a, A, T, const D: usize> {
+    points: &'a [[A; D]],
+    /// The payloads, parallel to `points`
+    data: Vec<T>,
+    /// Indices into `points`, the items below each node being a contiguous range
+    order: Vec<usize>,
+    root: RefNode<A, D>,
+}
+
+struct RefNode<A, const D: usize> {
+    min_bounds: [A; D],
+    max_bounds: [A; D],
+    /// The range of `order` holding the items below the node
+    start: usize,
+    end: usize,
+    stem: Option<RefStem<A, D>>,
+}
+
+struct RefStem<A, const D: usize> {
+    split_dimension: usize,
+    /// Points with a coordinate below this value in `split_dimension` belong in `left`
+    split_value: A,
+    left: Box<RefNode<A, D>>,
+    right: Box<RefNode<A, D>>,
+}
+
+impl<'a, A: Float + Zero + One, T, const D: usize> RefKdTree<'a, A, T, D> {
+    /// Build a tree over `points`, with the payload of `points[i]` being `data[i]`, splitting
+    /// every node at the median of its widest dimension as
+    /// [`OwnedKdTree::from_points`](crate::OwnedKdTree::from_points) does
+    pub fn new(points: &'a [[A; D]], data: Vec<T>, capacity: usize) -> Result<Self, ErrorKind> {
+        const { assert!(D > 0, "a RefKdTree needs at least one dimension") };
+        if capacity == 0 {
+            return Err(ErrorKind::ZeroCapacity);
+        }
+        if points.len() != data.len() {
+            return Err(ErrorKind::LengthMismatch);
+        }
+        if points.iter().flatten().any(|x| !x.is_finite()) {
+            return Err(ErrorKind::NonFiniteCoordinate);
+        }
+        let mut order: Vec<usize> = (0..points.len()).collect();
+        let root = RefNode::build(points, &mut order, 0, capacity);
+        Ok(RefKdTree {
+            points,
+            data,
+            order,
+            root,
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.order.len()
+    }
+
+    /// The borrowed points
+    pub fn points(&self) -> &'a [[A; D]] {
+        self.points
+    }
+
+    /// The payloads, `data()[i]` being that of `points()[i]`
+    pub fn data(&self) -> &[T] {
+        &self.data
+    }
+
+    /// Consume the tree, returning the payloads in the order they were given
+    pub fn into_data(self) -> Vec<T> {
+        self.data
+    }
+
+    /// The bounding box `(min, max)` of the tree, `None` if the tree is empty
+    pub fn extent(&self) -> Option<([A; D], [A; D])> {
+        if self.order.is_empty() {
+            return None;
+        }
+        Some((self.root.min_bounds, self.root.max_bounds))
+    }
+
+    /// The `num` items nearest to `point`, nearest first, as
+    /// [`OwnedKdTree::nearest`](crate::OwnedKdTree::nearest) returns them
+    pub fn nearest<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let (nearest, _) = self.nearest_search(point, num, distance, usize::MAX, usize::MAX)?;
+        Ok(nearest.into_iter().map(Into::into).collect())
+    }
+
+    /// [`nearest`](#method.nearest) cut short after visiting `max_nodes` nodes, as
+    /// [`OwnedKdTree::nearest_budgeted`](crate::OwnedKdTree::nearest_budgeted)
+    #[allow(clippy::type_complexity)]
+    pub fn nearest_budgeted<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &F,
+        max_nodes: usize,
+    ) -> Result<(Vec<(A, &T)>, bool), ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let (nearest, complete) =
+            self.nearest_search(point, num, distance, max_nodes, usize::MAX)?;
+        Ok((nearest.into_iter().map(Into::into).collect(), complete))
+    }
+
+    /// [`nearest`](#method.nearest) checking at most `max_leaf_checks` leaves, as
+    /// [`OwnedKdTree::nearest_bbf`](crate::OwnedKdTree::nearest_bbf)
+    pub fn nearest_bbf<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        max_leaf_checks: usize,
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let (nearest, _) =
+            self.nearest_search(point, num, distance, usize::MAX, max_leaf_checks)?;
+        Ok(nearest.into_iter().map(Into::into).collect())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn nearest_search<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &F,
+        max_nodes: usize,
+        max_leaves: usize,
+    ) -> Result<(Vec<Neighbour<'_, A, &T, D>>, bool), ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let () = check_point(point)?;
+        Ok(search::nearest(
+            self.cursor(),
+            point,
+            num,
+            &ClosureMetric(distance),
+            max_nodes,
+            max_leaves,
+            &mut QueryStats::default(),
+        ))
+    }
+
+    /// The items within `radius` of `point`, nearest first
+    pub fn within<F>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        self.within_capped(point, radius, self.size(), distance)
+    }
+
+    /// The `max_results` nearest items within `radius` of `point`, as
+    /// [`OwnedKdTree::within_capped`](crate::OwnedKdTree::within_capped)
+    pub fn within_capped<F>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        max_results: usize,
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let () = check_point(point)?;
+        let () = search::check_radius(radius)?;
+        let within = search::within(
+            self.cursor(),
+            point,
+            radius,
+            max_results,
+            &ClosureMetric(distance),
+            &mut QueryStats::default(),
+        );
+        Ok(within.into_iter().map(Into::into).collect())
+    }
+
+    /// Items whose points lie within the box `[min, max]`, bounds included
+    pub fn within_box(&self, min: &[A; D], max: &[A; D]) -> Result<Vec<&T>, ErrorKind> {
+        let () = check_point(min)?;
+        let () = check_point(max)?;
+        let mut within = vec![];
+        search::within_box(self.cursor(), min, max, &mut within);
+        Ok(within)
+    }
+
+    /// Iterate over the items in the order of the leaves
+    pub fn iter(&self) -> impl Iterator<Item = (&'a [A; D], &T)> + '_ {
+        let points = self.points;
+        self.order.iter().map(move |&i| (&points[i], &self.data[i]))
+    }
+
+    fn cursor(&self) -> Cursor<'_, A, T, D> {
+        Cursor {
+            points: self.points,
+            data: &self.data,
+            order: &self.order,
+            node: &self.root,
+        }
+    }
+}
+
+impl<A: Float + Zero + One, const D: usize> RefNode<A, D> {
+    /// Builds the node over `order`, the items in `start..start + order.len()` of the whole
+    /// order, partitioning `order` in place
+    fn build(points: &[[A; D]], order: &mut [usize], start: usize, capacity: usize) -> Self {
+        let mut node = RefNode {
+            min_bounds: [A::infinity(); D],
+            max_bounds: [A::neg_infinity(); D],
+            start,
+            end: start + order.len(),
+            stem: None,
+        };
+        for &i in order.iter() {
+            let bounds = node.min_bounds.iter_mut().zip(node.max_bounds.iter_mut());
+            for ((min, max), &x) in bounds.zip(&points[i]) {
+                *min = min.min(x);
+                *max = max.max(x);
+            }
+        }
+        let dim = match node.widest_dimension() {
+            Some(dim) if order.len() > capacity => dim,
+            _ => return node,
+        };
+        // the same split as `OwnedKdTree::build`, so both trees have the same shape
+        let mut coordinates: Vec<A> = order.iter().map(|&i| points[i][dim]).collect();
+        let mid = coordinates.len() / 2;
+        let (_, median, _) =
+            coordinates.select_nth_unstable_by(mid, |a, b| a.partial_cmp(b).unwrap());
+        let mut split_value = *median;
+        if split_value <= node.min_bounds[dim] {
+            split_value = order
+                .iter()
+                .map(|&i| points[i][dim])
+                .filter(|&v| v > node.min_bounds[dim])
+                .fold(node.max_bounds[dim], A::min);
+        }
+        let mut mid = 0;
+        for j in 0..order.len() {
+            if belongs_in_left(&points[order[j]], dim, split_value) {
+                order.swap(mid, j);
+                mid += 1;
+            }
+        }
+        let (left, right) = order.split_at_mut(mid);
+        node.stem = Some(RefStem {
+            split_dimension: dim,
+            split_value,
+            left: Box::new(RefNode::build(points, left, start, capacity)),
+            right: Box::new(RefNode::build(points, right, start + mid, capacity)),
+        });
+        node
+    }
+
+    fn widest_dimension(&self) -> Option<usize> {
+        let mut max = A::zero();
+        let mut widest = None;
+        for dim in 0..D {
+            let diff = self.max_bounds[dim] - self.min_bounds[dim];
+            if diff > max {
+                max = diff;
+                widest = Some(dim);
+            }
+        }
+        widest
+    }
+}
+
+fn check_point<A: Float, const D: usize>(point: &[A; D]) -> Result<(), ErrorKind> {
+    if point.iter().all(|x| x.is_finite()) {
+        Ok(())
+    } else {
+        Err(ErrorKind::NonFiniteCoordinate)
+    }
+}
+
+/// A node together with the slices its indices point into
+struct Cursor<'b, A, T, const D: usize> {
+    points: &'b [[A; D]],
+    data: &'b [T],
+    order: &'b [usize],
+    node: &'b RefNode<A, D>,
+}
+
+impl<'b, A, T, const D: usize> Clone for Cursor<'b, A, T, D> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'b, A, T, const D: usize> Copy for Cursor<'b, A, T, D> {}
+
+/// The items of a leaf, as indices into the borrowed points
+struct RefLeaf<'b, A, T, const D: usize> {
+    points: &'b [[A; D]],
+    data: &'b [T],
+    indices: &'b [usize],
+}
+
+impl<'b, A, T, const D: usize> PointStore<'b, A, T, D> for RefLeaf<'b, A, T, D> {
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn item(&self, i: usize) -> Option<(&'b [A; D], &'b T)> {
+        let &j = self.indices.get(i)?;
+        Some((self.points.get(j)?, self.data.get(j)?))
+    }
+}
+
+impl<'b, A: Float, T, const D: usize> SearchNode<'b, A, T, D> for Cursor<'b, A, T, D> {
+    type Leaf = RefLeaf<'b, A, T, D>;
+
+    fn size(self) -> usize {
+        self.node.end - self.node.start
+    }
+
+    fn bounds(self) -> (&'b [A; D], &'b [A; D]) {
+        (&self.node.min_bounds, &self.node.max_bounds)
+    }
+
+    fn stem(self) -> Result<(usize, A, Self, Self), Self::Leaf> {
+        match &self.node.stem {
+            Some(stem) => {
+                let left = Cursor {
+                    node: &stem.left,
+                    ..self
+                };
+                let right = Cursor {
+                    node: &stem.right,
+                    ..self
+                };
+                Ok((stem.split_dimension, stem.split_value, left, right))
+            }
+            None => Err(RefLeaf {
+                points: self.points,
+                data: self.data,
+                indices: self
+                    .order
+                    .get(self.node.start..self.node.end)
+                    .unwrap_or_default(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::{Rng, SeedableRng, StdRng};
+    use super::RefKdTree;
+    use crate::distance::squared_euclidean;
+    use crate::error::ErrorKind;
+    use crate::tree::OwnedKdTree;
+
+    fn dist(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+        squared_euclidean(a, b)
+    }
+
+    fn points(rng: &mut StdRng, n: usize) -> Vec<[f32; 3]> {
+        (0..n)
+            .map(|_| {
+                [
+                    rng.gen_range(-10.0, 10.0),
+                    rng.gen_range(-10.0, 10.0),
+                    rng.gen_range(0, 8) as f32,
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_matches_an_owned_tree() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[16, 3][..]);
+        let points = points(&mut rng, 2000);
+        for &capacity in &[1, 4, 16] {
+            let data: Vec<usize> = (0..points.len()).collect();
+            let tree = RefKdTree::new(&points, data.clone(), capacity).unwrap();
+            let items = points.iter().copied().zip(data).collect();
+            let owned = OwnedKdTree::from_points(items, capacity).unwrap();
+            assert_eq!(tree.size(), owned.size());
+            assert_eq!(tree.extent(), owned.extent());
+            let mut all: Vec<usize> = tree.iter().map(|(_, &i)| i).collect();
+            all.sort_unstable();
+            assert_eq!(all, (0..points.len()).collect::<Vec<_>>());
+
+            for _ in 0..50 {
+                let query = [
+                    rng.gen_range(-12.0, 12.0),
+                    rng.gen_range(-12.0, 12.0),
+                    rng.gen_range(-1.0, 9.0),
+                ];
+                for &num in &[0, 1, 5, 40] {
+                    assert_eq!(
+                        tree.nearest(&query, num, &dist).unwrap(),
+                        owned.nearest(&query, num, &dist).unwrap()
+                    );
+                    assert_eq!(
+                        tree.nearest_budgeted(&query, num, &dist, 20).unwrap(),
+                        owned.nearest_budgeted(&query, num, &dist, 20).unwrap()
+                    );
+                    assert_eq!(
+                        tree.nearest_bbf(&query, num, 3, &dist).unwrap(),
+                        owned.nearest_bbf(&query, num, 3, &dist).unwrap()
+                    );
+                }
+                for &radius in &[0.0, 1.0, 9.0] {
+                    assert_eq!(
+                        tree.within(&query, radius, &dist).unwrap(),
+                        owned.within(&query, radius, &dist).unwrap()
+                    );
+                    assert_eq!(
+                        tree.within_capped(&query, radius, 3, &dist).unwrap(),
+                        owned.within_capped(&query, radius, 3, &dist).unwrap()
+                    );
+                }
+                let max = [query[0] + 2.0, query[1] + 3.0, query[2] + 1.0];
+                let mut in_box = tree.within_box(&query, &max).unwrap();
+                let mut expected = owned.within_box(&query, &max).unwrap();
+                in_box.sort_unstable();
+                expected.sort_unstable();
+                assert_eq!(in_box, expected);
+            }
+        }
+
+        assert_eq!(
+            tree_error(&points, vec![0; 3], 16),
+            Some(ErrorKind::LengthMismatch)
+        );
+        assert_eq!(
+            tree_error(&points, vec![0; 2000], 0),
+            Some(ErrorKind::ZeroCapacity)
+        );
+        let mut broken = points.clone();
+        broken[7][1] = f32::NAN;
+        assert_eq!(
+            tree_error(&broken, vec![0; 2000], 16),
+            Some(ErrorKind::NonFiniteCoordinate)
+        );
+        let tree = RefKdTree::new(&points, vec![0; 2000], 16).unwrap();
+        assert_eq!(
+            tree.nearest(&[f32::INFINITY, 0.0, 0.0], 1, &dist),
+            Err(ErrorKind::NonFiniteCoordinate)
+        );
+        let empty = RefKdTree::<f32, usize, 3>::new(&[], vec![], 16).unwrap();
+        assert_eq!(empty.nearest(&[0.0; 3], 3, &dist), Ok(vec![]));
+        assert_eq!(empty.extent(), None);
+    }
+
+    fn tree_error(points: &[[f32; 3]], data: Vec<usize>, capacity: usize) -> Option<ErrorKind> {
+        RefKdTree::new(points, data, capacity).err()
+    }
+
+    #[test]
+    fn it_stores_indices_rather_than_points() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[16, 4][..]);
+        let points = points(&mut rng, 1000);
+        let tree = RefKdTree::new(&points, vec![(); 1000], 16).unwrap();
+
+        // every point found is the borrowed one, not a copy
+        let range = points.as_ptr_range();
+        let (nearest, _) = tree
+            .nearest_search(&[0.0; 3], 100, &dist, usize::MAX, usize::MAX)
+            .unwrap();
+        assert_eq!(nearest.len(), 100);
+        assert!(nearest
+            .iter()
+            .all(|n| range.contains(&(n.point as *const [f32; 3]))));
+    }
+}
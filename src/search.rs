@@ -0,0 +1,448 @@
+//! The best-first searches behind the nearest and within queries, written once for every tree
+//! whose nodes are a [`SearchNode`] and whose leaves are a [`PointStore`], so that a tree owning
+//! its points and one borrowing them answer queries alike.
+//!
+//! The searches keep their pending nodes in a heap rather than recursing, so their stack use
+//! does not grow with the depth of the tree, and have no panicking branch of their own: heaps
+//! are read through `peek` and `pop` and grown through [`push`], and leaves are read through
+//! [`PointStore::item`], never unwrapped or indexed. The `no-panic` feature checks this for
+//! the instantiations of the `panic_free` module. The metric passed in can still panic, and so
+//! can building a tree, which unwraps the comparisons of coordinates.
+
+use std::collections::BinaryHeap;
+
+use num_traits::Float;
+
+use crate::error::ErrorKind;
+use crate::heap_element::Neighbour;
+use crate::metric::Metric;
+use crate::priority::MinByDistance;
+use crate::query::QueryStats;
+
+/// Rejects a radius no item can be within, a negative or NaN one, with
+/// `ErrorKind::InvalidRadius`. Every radius query checks it here, so that a radius of zero finds
+/// the items at the query point and an infinite one every item.
+pub(crate) fn check_radius<A: Float>(radius: A) -> Result<(), ErrorKind> {
+    if radius.is_nan() || radius < A::zero() {
+        return Err(ErrorKind::InvalidRadius);
+    }
+    Ok(())
+}
+
+/// Pushes `item` onto `heap` without a panicking branch. The heap grows through `try_reserve`,
+/// by as much as `push` would grow it, and the process aborts if that fails where `push` would
+/// have aborted or panicked.
+pub(crate) fn push<I: Ord>(heap: &mut BinaryHeap<I>, item: I) {
+    if heap.len() == heap.capacity() && heap.try_reserve(1).is_err() {
+        std::process::abort();
+    }
+    if heap.len() < heap.capacity() {
+        heap.push(item);
+    }
+}
+
+/// Collects `items` into a `Vec` without a panicking branch, see [`push`]
+pub(crate) fn collect<I: ExactSizeIterator>(items: I) -> Vec<I::Item> {
+    let mut collected = Vec::new();
+    if collected.try_reserve_exact(items.len()).is_err() {
+        std::process::abort();
+    }
+    for item in items {
+        if collected.len() < collected.capacity() {
+            collected.push(item);
+        }
+    }
+    collected
+}
+
+/// Whether `point` belongs in the left child of a stem splitting `split_dimension` at
+/// `split_value`. Every tree decides it here when it adds, moves, removes or looks for a point:
+/// a point below the split value goes left and any other point, one exactly at the split value
+/// included, goes right.
+#[inline]
+pub(crate) fn belongs_in_left<A: Float, const D: usize>(
+    point: &[A; D],
+    split_dimension: usize,
+    split_value: A,
+) -> bool {
+    point.get(split_dimension).is_some_and(|&x| x < split_value)
+}
+
+/// The items of a leaf, found by their position in the leaf
+pub(crate) trait PointStore<'b, A, T, const D: usize> {
+    fn len(&self) -> usize;
+
+    /// The point and payload of the `i`th item, `None` past the end of the leaf
+    #[allow(clippy::type_complexity)]
+    fn item(&self, i: usize) -> Option<(&'b [A; D], &'b T)>;
+}
+
+/// The items of `leaf` in order
+#[allow(clippy::type_complexity)]
+fn items<'b, 'l, A: 'b, T: 'b, L, const D: usize>(
+    leaf: &'l L,
+) -> impl Iterator<Item = (&'b [A; D], &'b T)> + 'l
+where
+    L: PointStore<'b, A, T, D>,
+{
+    (0..leaf.len()).filter_map(move |i| leaf.item(i))
+}
+
+/// The lower bound `metric` gives on the distance from `point` to the box `[min, max]`, zero
+/// if it cannot tell
+fn to_box<A: Float, M, const D: usize>(metric: &M, point: &[A; D], min: &[A; D], max: &[A; D]) -> A
+where
+    M: Metric<A, D> + ?Sized,
+{
+    let bound = metric.point_to_box(point, min, max);
+    if bound.is_nan() {
+        A::zero()
+    } else {
+        bound
+    }
+}
+
+/// Whether a pending node `node_dist` away may hold an item nearer than the farthest of the
+/// `num` nearest items in `evaluated`, always true until `num` items are found
+fn may_improve<A: Float, T, const D: usize>(
+    node_dist: A,
+    evaluated: &BinaryHeap<Neighbour<A, T, D>>,
+    num: usize,
+) -> bool {
+    match evaluated.peek() {
+        Some(farthest) if evaluated.len() >= num => node_dist <= farthest.distance,
+        _ => true,
+    }
+}
+
+/// A leaf owning its points and payloads as parallel slices
+impl<'b, A, T, const D: usize> PointStore<'b, A, T, D> for (&'b [[A; D]], &'b [T]) {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn item(&self, i: usize) -> Option<(&'b [A; D], &'b T)> {
+        Some((self.0.get(i)?, self.1.get(i)?))
+    }
+}
+
+/// A node of a tree, cheap to copy so that it can wait in the heap of pending nodes
+pub(crate) trait SearchNode<'b, A: 'b, T: 'b, const D: usize>: Copy {
+    type Leaf: PointStore<'b, A, T, D>;
+
+    /// Number of items below the node
+    fn size(self) -> usize;
+
+    /// The bounding box `(min, max)` of the items below the node
+    fn bounds(self) -> (&'b [A; D], &'b [A; D]);
+
+    /// The split dimension, split value and children `(left, right)` of a stem, or the items
+    /// of a leaf. Points belong in the child [`belongs_in_left`] says.
+    #[allow(clippy::type_complexity)]
+    fn stem(self) -> Result<(usize, A, Self, Self), Self::Leaf>;
+}
+
+/// The `num` items nearest to `point`, nearest first, together with whether the search
+/// completed before visiting `max_nodes` nodes or checking `max_leaves` leaves. The work done is
+/// added to `stats`.
+#[allow(clippy::type_complexity)]
+pub(crate) fn nearest<'b, A, T, N, M, const D: usize>(
+    root: N,
+    point: &[A; D],
+    num: usize,
+    metric: &M,
+    max_nodes: usize,
+    max_leaves: usize,
+    stats: &mut QueryStats,
+) -> (Vec<Neighbour<'b, A, &'b T, D>>, bool)
+where
+    A: Float + 'b,
+    T: 'b,
+    N: SearchNode<'b, A, T, D>,
+    M: Metric<A, D> + ?Sized,
+{
+    let limits = (max_nodes, max_leaves);
+    nearest_as(root, point, num, metric, limits, stats, &|data| data)
+}
+
+/// Like [`nearest`], keeping `element(data)` for each item instead of a reference to its
+/// payload. `element` is only called for the items which make it into the `num` nearest so
+/// far.
+#[allow(clippy::type_complexity)]
+pub(crate) fn nearest_as<'b, A, T, N, M, E, G, const D: usize>(
+    root: N,
+    point: &[A; D],
+    num: usize,
+    metric: &M,
+    (max_nodes, max_leaves): (usize, usize),
+    stats: &mut QueryStats,
+    element: &G,
+) -> (Vec<Neighbour<'b, A, E, D>>, bool)
+where
+    A: Float + 'b,
+    T: 'b,
+    N: SearchNode<'b, A, T, D>,
+    M: Metric<A, D> + ?Sized,
+    G: Fn(&'b T) -> E + ?Sized,
+{
+    let num = std::cmp::min(num, root.size());
+    if num == 0 {
+        return (vec![], true);
+    }
+    let mut pending = BinaryHeap::new();
+    let mut evaluated = BinaryHeap::new();
+    push(&mut pending, MinByDistance::new(A::zero(), root));
+    let (mut visited, mut leaves) = (0, 0);
+    let mut complete = true;
+    while let Some(next) = pending.pop() {
+        if !may_improve(next.0.distance, &evaluated, num) {
+            break;
+        }
+        if visited >= max_nodes || leaves >= max_leaves {
+            complete = false;
+            break;
+        }
+        let before = stats.nodes_visited;
+        step(
+            next.0.element,
+            point,
+            num,
+            A::infinity(),
+            metric,
+            &mut pending,
+            &mut evaluated,
+            stats,
+            element,
+        );
+        leaves += 1;
+        visited += stats.nodes_visited - before;
+    }
+    let mut nearest = evaluated.into_sorted_vec();
+    nearest.truncate(num);
+    (nearest, complete)
+}
+
+/// The `max_results` nearest items within `radius` of `point`, nearest first. The work done is
+/// added to `stats`.
+pub(crate) fn within<'b, A, T, N, M, const D: usize>(
+    root: N,
+    point: &[A; D],
+    radius: A,
+    max_results: usize,
+    metric: &M,
+    stats: &mut QueryStats,
+) -> Vec<Neighbour<'b, A, &'b T, D>>
+where
+    A: Float + 'b,
+    T: 'b,
+    N: SearchNode<'b, A, T, D>,
+    M: Metric<A, D> + ?Sized,
+{
+    within_as(root, point, radius, max_results, metric, stats, &|data| {
+        data
+    })
+}
+
+/// Like [`within`], keeping `element(data)` for each item as [`nearest_as`] does
+pub(crate) fn within_as<'b, A, T, N, M, E, G, const D: usize>(
+    root: N,
+    point: &[A; D],
+    radius: A,
+    max_results: usize,
+    metric: &M,
+    stats: &mut QueryStats,
+    element: &G,
+) -> Vec<Neighbour<'b, A, E, D>>
+where
+    A: Float + 'b,
+    T: 'b,
+    N: SearchNode<'b, A, T, D>,
+    M: Metric<A, D> + ?Sized,
+    G: Fn(&'b T) -> E + ?Sized,
+{
+    let num = std::cmp::min(max_results, root.size());
+    if num == 0 {
+        return vec![];
+    }
+    let mut pending = BinaryHeap::new();
+    let mut evaluated = BinaryHeap::new();
+    push(&mut pending, MinByDistance::new(A::zero(), root));
+    while let Some(next) = pending.pop() {
+        if !(next.0.distance <= radius && may_improve(next.0.distance, &evaluated, num)) {
+            break;
+        }
+        step(
+            next.0.element,
+            point,
+            num,
+            radius,
+            metric,
+            &mut pending,
+            &mut evaluated,
+            stats,
+            element,
+        );
+    }
+    evaluated.into_sorted_vec()
+}
+
+/// Whether any item lies within `radius` of `point`, stopping at the first one found. The
+/// nodes are searched depth first, the child `point` belongs in first, skipping those whose
+/// bounds are farther than `radius`. The work done is added to `stats`.
+pub(crate) fn any_within<'b, A, T, N, M, const D: usize>(
+    root: N,
+    point: &[A; D],
+    radius: A,
+    metric: &M,
+    stats: &mut QueryStats,
+) -> bool
+where
+    A: Float + 'b,
+    T: 'b,
+    N: SearchNode<'b, A, T, D>,
+    M: Metric<A, D> + ?Sized,
+{
+    let mut pending = vec![root];
+    while let Some(node) = pending.pop() {
+        stats.nodes_visited += 1;
+        let (min, max) = node.bounds();
+        if node.size() == 0 || to_box(metric, point, min, max) > radius {
+            continue;
+        }
+        match node.stem() {
+            Ok((dim, split_value, left, right)) => {
+                if belongs_in_left(point, dim, split_value) {
+                    pending.extend([right, left]);
+                } else {
+                    pending.extend([left, right]);
+                }
+            }
+            Err(leaf) => {
+                stats.leaves_visited += 1;
+                for (p, _) in items(&leaf) {
+                    stats.points_visited += 1;
+                    if metric.distance(point, p) <= radius {
+                        return true;
+                    }
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Descends from `curr`, the nearest pending node, to the leaf `point` belongs in, pushing the
+/// other children on the way, and keeps the nearest `num` items of that leaf within `max_dist`
+/// as `element` makes them, counting the nodes, the leaf and its points in `stats`
+#[allow(clippy::too_many_arguments)]
+fn step<'b, A, T, N, M, E, G, const D: usize>(
+    mut curr: N,
+    point: &[A; D],
+    num: usize,
+    max_dist: A,
+    metric: &M,
+    pending: &mut BinaryHeap<MinByDistance<A, N>>,
+    evaluated: &mut BinaryHeap<Neighbour<'b, A, E, D>>,
+    stats: &mut QueryStats,
+    element: &G,
+) where
+    A: Float + 'b,
+    T: 'b,
+    N: SearchNode<'b, A, T, D>,
+    M: Metric<A, D> + ?Sized,
+    G: Fn(&'b T) -> E + ?Sized,
+{
+    stats.nodes_visited += 1;
+    debug_assert!(evaluated.len() <= num);
+    let evaluated_dist = match evaluated.peek() {
+        // We only care about the nearest `num` points, so if we already have `num` points,
+        // any more point we add to `evaluated` must be nearer then one of the point already in
+        // `evaluated`.
+        Some(farthest) if evaluated.len() == num => max_dist.min(farthest.distance),
+        _ => max_dist,
+    };
+
+    let leaf = loop {
+        let (dim, split_value, left, right) = match curr.stem() {
+            Ok(stem) => stem,
+            Err(leaf) => break leaf,
+        };
+        let (near, candidate) = if belongs_in_left(point, dim, split_value) {
+            (left, right)
+        } else {
+            (right, left)
+        };
+        curr = near;
+        stats.nodes_visited += 1;
+        let (min, max) = candidate.bounds();
+        let candidate_to_space = to_box(metric, point, min, max);
+        if candidate.size() > 0 && candidate_to_space <= evaluated_dist {
+            push(pending, MinByDistance::new(candidate_to_space, candidate));
+        }
+    };
+
+    stats.leaves_visited += 1;
+    stats.points_visited += leaf.len();
+    for (p, data) in items(&leaf) {
+        let distance = metric.distance(point, p);
+        let full = evaluated.len() >= num;
+        let kept = distance <= max_dist
+            && (!full
+                || evaluated
+                    .peek()
+                    .is_some_and(|farthest| farthest.is_after(distance, p)));
+        if !kept {
+            continue;
+        }
+        if full {
+            evaluated.pop();
+        }
+        push(
+            evaluated,
+            Neighbour {
+                distance,
+                point: p,
+                element: element(data),
+            },
+        );
+    }
+}
+
+/// Pushes onto `within` the items below `node` whose points lie within the box `[min, max]`,
+/// bounds included, left child first
+pub(crate) fn within_box<'b, A, T, N, const D: usize>(
+    node: N,
+    min: &[A; D],
+    max: &[A; D],
+    within: &mut Vec<&'b T>,
+) where
+    A: Float + 'b,
+    T: 'b,
+    N: SearchNode<'b, A, T, D>,
+{
+    let (node_min, node_max) = node.bounds();
+    let overlaps = node_min
+        .iter()
+        .zip(node_max)
+        .zip(min.iter().zip(max))
+        .all(|((node_min, node_max), (min, max))| node_max >= min && node_min <= max);
+    if node.size() == 0 || !overlaps {
+        return;
+    }
+    let leaf = match node.stem() {
+        Ok((_, _, left, right)) => {
+            within_box(left, min, max, within);
+            within_box(right, min, max, within);
+            return;
+        }
+        Err(leaf) => leaf,
+    };
+    for (p, data) in items(&leaf) {
+        if p.iter()
+            .zip(min.iter().zip(max))
+            .all(|(x, (min, max))| min <= x && x <= max)
+        {
+            within.push(data);
+        }
+    }
+}
@@ -0,0 +1,387 @@
+//! Checks an [`OwnedKdTree`] against a brute force model, enabled by the `test-support` feature.
+//!
+//! ```rust
+//! use kdtree::test_support::{model_check, Op};
+//!
+//! let ops = (0..200).map(|i| match i % 4 {
+//!     0 | 1 => Op::Add([(i % 17) as f64, (i % 5) as f64], i),
+//!     2 => Op::Nearest { point: [3.0, 1.5], k: 4 },
+//!     _ => Op::Within { point: [8.0, 2.0], radius: 10.0 },
+//! });
+//! model_check(ops);
+//! ```
+//!
+//! [`fuzz`] decodes arbitrary bytes into ops for the `cargo fuzz` target in `fuzz/`, which
+//! runs it with `cargo fuzz run model` from the root of the repository.
+
+use std::fmt::Debug;
+use std::panic::{self, AssertUnwindSafe};
+
+use num_traits::{Float, One, Zero};
+
+use crate::distance::squared_euclidean;
+use crate::tree::OwnedKdTree;
+
+/// An operation applied to both the tree and the model
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op<A, T, const D: usize> {
+    Add([A; D], T),
+    /// Remove every item at the point equal to the payload
+    Remove([A; D], T),
+    Nearest {
+        point: [A; D],
+        k: usize,
+    },
+    /// Items within the squared Euclidean `radius`
+    Within {
+        point: [A; D],
+        radius: A,
+    },
+}
+
+/// The capacity of the trees built by [`model_check`], small enough to split often
+pub const MODEL_CHECK_CAPACITY: usize = 2;
+
+/// Applies `ops` to an empty [`OwnedKdTree`] and to a brute force model, using the squared
+/// Euclidean distance, and panics on the first divergence. The panic message describes the
+/// divergence and the shortest subsequence of `ops` found which still diverges.
+pub fn model_check<A, T, I, const D: usize>(ops: I)
+where
+    A: Float + Zero + One + Debug,
+    T: PartialEq + Clone + Debug,
+    I: IntoIterator<Item = Op<A, T, D>>,
+{
+    model_check_with_capacity(ops, MODEL_CHECK_CAPACITY)
+}
+
+/// [`model_check`] with trees of the given leaf `capacity`
+pub fn model_check_with_capacity<A, T, I, const D: usize>(ops: I, capacity: usize)
+where
+    A: Float + Zero + One + Debug,
+    T: PartialEq + Clone + Debug,
+    I: IntoIterator<Item = Op<A, T, D>>,
+{
+    let ops: Vec<_> = ops.into_iter().collect();
+    let divergence = match run(&ops, capacity) {
+        Ok(()) => return,
+        Err(divergence) => divergence,
+    };
+    let minimized = minimize(ops, capacity);
+    panic!(
+        "tree diverged from the model: {}\nminimized to {} ops: {:?}",
+        divergence,
+        minimized.len(),
+        minimized
+    );
+}
+
+/// Bytes [`decode_ops`] reads per op in `D` dimensions: a tag, a byte per coordinate and an
+/// argument
+pub const fn op_len(dimensions: usize) -> usize {
+    dimensions + 2
+}
+
+/// Decodes `bytes` into ops, [`op_len`] bytes each, ignoring a shorter tail. The tag chooses
+/// an add half of the time and otherwise a remove, a nearest or a within query. Coordinates
+/// take one of 16 values in `[0, 8)` and payloads one of 8, so that points often coincide and
+/// removes often find something: leaves then fill with items at a single point, which cannot
+/// be split apart.
+pub fn decode_ops<const D: usize>(bytes: &[u8]) -> Vec<Op<f64, u8, D>> {
+    bytes
+        .chunks_exact(op_len(D))
+        .map(|op| {
+            let point = std::array::from_fn(|d| (op[d + 1] % 16) as f64 * 0.5);
+            let argument = op[D + 1];
+            match op[0] % 8 {
+                0..=3 => Op::Add(point, argument % 8),
+                4 | 5 => Op::Remove(point, argument % 8),
+                6 => Op::Nearest {
+                    point,
+                    k: (argument % 16) as usize,
+                },
+                _ => Op::Within {
+                    point,
+                    radius: (argument % 32) as f64 * 0.5,
+                },
+            }
+        })
+        .collect()
+}
+
+/// The entry point of the fuzz target: [`model_check_with_capacity`] for the ops the bytes
+/// after the first decode to, with leaves of 1 to 4 items as the first byte chooses
+pub fn fuzz<const D: usize>(bytes: &[u8]) {
+    if let Some((&capacity, ops)) = bytes.split_first() {
+        model_check_with_capacity(decode_ops::<D>(ops), 1 + capacity as usize % 4);
+    }
+}
+
+/// Removes ops one at a time for as long as the remaining ops still diverge
+fn minimize<A, T, const D: usize>(mut ops: Vec<Op<A, T, D>>, capacity: usize) -> Vec<Op<A, T, D>>
+where
+    A: Float + Zero + One + Debug,
+    T: PartialEq + Clone + Debug,
+{
+    let mut i = ops.len();
+    while i > 0 {
+        i -= 1;
+        let mut fewer = ops.clone();
+        fewer.remove(i);
+        if run(&fewer, capacity).is_err() {
+            ops = fewer;
+            i = std::cmp::min(i, ops.len());
+        }
+    }
+    ops
+}
+
+fn run<A, T, const D: usize>(ops: &[Op<A, T, D>], capacity: usize) -> Result<(), String>
+where
+    A: Float + Zero + One + Debug,
+    T: PartialEq + Clone + Debug,
+{
+    let mut tree = OwnedKdTree::with_capacity(capacity);
+    let mut model: Vec<([A; D], T)> = vec![];
+    for (i, op) in ops.iter().enumerate() {
+        let diverged = |what: String| Err(format!("op {} ({:?}): {}", i, op, what));
+        // a panicking tree diverges too, so that the ops leading to it are minimized
+        let step = panic::catch_unwind(AssertUnwindSafe(|| apply(&mut tree, &mut model, op)));
+        match step {
+            Ok(Ok(())) => {}
+            Ok(Err(what)) => return diverged(what),
+            Err(_) => return diverged("the tree panicked".to_string()),
+        }
+        if let Err(what) = check(&tree, &model) {
+            return diverged(what);
+        }
+    }
+    Ok(())
+}
+
+/// Describes the first invariant the tree breaks, or how its size differs from the model's
+fn check<A, T, const D: usize>(
+    tree: &OwnedKdTree<A, T, D>,
+    model: &[([A; D], T)],
+) -> Result<(), String>
+where
+    A: Float + Zero + One + Debug,
+    T: PartialEq,
+{
+    tree.validate().map_err(str::to_string)?;
+    if tree.size() != model.len() {
+        return Err(format!("size {}, expected {}", tree.size(), model.len()));
+    }
+    Ok(())
+}
+
+/// Applies `op` to the tree and the model, describing how they differ if they do
+fn apply<A, T, const D: usize>(
+    tree: &mut OwnedKdTree<A, T, D>,
+    model: &mut Vec<([A; D], T)>,
+    op: &Op<A, T, D>,
+) -> Result<(), String>
+where
+    A: Float + Zero + One + Debug,
+    T: PartialEq + Clone + Debug,
+{
+    let distance = |a: &[A; D], b: &[A; D]| squared_euclidean(a, b);
+    match op {
+        Op::Add(point, data) => {
+            if let Err(e) = tree.add(*point, data.clone()) {
+                return Err(format!("add failed with {:?}", e));
+            }
+            model.push((*point, data.clone()));
+        }
+        Op::Remove(point, data) => {
+            let before = model.len();
+            model.retain(|(p, d)| !(p == point && d == data));
+            let expected = before - model.len();
+            match tree.remove(point, data) {
+                Ok(removed) if removed == expected => {}
+                other => return Err(format!("removed {:?}, expected {}", other, expected)),
+            }
+        }
+        Op::Nearest { point, k } => {
+            let found = match tree.nearest(point, *k, &distance) {
+                Ok(found) => found,
+                Err(e) => return Err(format!("nearest failed with {:?}", e)),
+            };
+            let mut expected: Vec<A> = model.iter().map(|(p, _)| distance(point, p)).collect();
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            expected.truncate(*k);
+            let distances: Vec<A> = found.iter().map(|&(d, _)| d).collect();
+            if distances != expected {
+                return Err(format!("found {:?}, expected {:?}", distances, expected));
+            }
+            if let Some(what) = unmatched(model, point, &found, &distance) {
+                return Err(what);
+            }
+        }
+        Op::Within { point, radius } => {
+            let found = match tree.within(point, *radius, &distance) {
+                Ok(found) => found,
+                Err(e) => return Err(format!("within failed with {:?}", e)),
+            };
+            let expected = model
+                .iter()
+                .filter(|(p, _)| distance(point, p) <= *radius)
+                .count();
+            if found.len() != expected {
+                return Err(format!(
+                    "found {} items, expected {}",
+                    found.len(),
+                    expected
+                ));
+            }
+            if let Some(what) = unmatched(model, point, &found, &distance) {
+                return Err(what);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Describes the first item of `found` which does not match a distinct item of the model
+fn unmatched<A, T, F, const D: usize>(
+    model: &[([A; D], T)],
+    point: &[A; D],
+    found: &[(A, &T)],
+    distance: &F,
+) -> Option<String>
+where
+    A: Float + Debug,
+    T: PartialEq + Debug,
+    F: Fn(&[A; D], &[A; D]) -> A,
+{
+    let mut used = vec![false; model.len()];
+    for &(dist, data) in found {
+        let matching = model
+            .iter()
+            .enumerate()
+            .position(|(i, (p, d))| !used[i] && d == data && distance(point, p) == dist);
+        match matching {
+            Some(i) => used[i] = true,
+            None => {
+                return Some(format!(
+                    "found {:?} at {:?}, which is not stored",
+                    data, dist
+                ))
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::{Rng, SeedableRng, StdRng};
+    use super::{apply, check, decode_ops, fuzz, minimize, model_check, op_len, run, Op};
+    use crate::OwnedKdTree;
+
+    #[test]
+    fn it_decodes_bytes_into_ops() {
+        let bytes = [0, 3, 17, 9, 4, 3, 1, 9, 6, 0, 0, 21, 7, 2, 2, 5, 1, 2];
+        assert_eq!(
+            decode_ops::<2>(&bytes),
+            vec![
+                Op::Add([1.5, 0.5], 1),
+                Op::Remove([1.5, 0.5], 1),
+                Op::Nearest {
+                    point: [0.0, 0.0],
+                    k: 5
+                },
+                Op::Within {
+                    point: [1.0, 1.0],
+                    radius: 2.5
+                },
+            ]
+        );
+        assert_eq!(op_len(2), 4);
+        // the tail too short for an op is dropped
+        assert_eq!(decode_ops::<2>(&bytes[..7]).len(), 1);
+        assert!(decode_ops::<3>(&[]).is_empty());
+    }
+
+    #[test]
+    fn it_fuzzes_random_bytes() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[18, 8][..]);
+        for round in 0..300 {
+            let len = rng.gen_range(0, 400);
+            let mut bytes: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            if round % 3 == 0 {
+                // a handful of points and payloads, so that leaves fill with one point
+                for op in bytes.chunks_mut(op_len(2)).skip(1) {
+                    for b in op.iter_mut().skip(1) {
+                        *b %= 2;
+                    }
+                }
+            }
+            fuzz::<1>(&bytes);
+            fuzz::<2>(&bytes);
+            fuzz::<3>(&bytes);
+        }
+        fuzz::<2>(&[]);
+    }
+
+    #[test]
+    fn it_minimizes_divergent_ops() {
+        let mut ops: Vec<Op<f64, usize, 1>> = (0..20).map(|i| Op::Add([i as f64], i)).collect();
+        assert_eq!(run(&ops, 2), Ok(()));
+        // the tree rejects non-finite points while the model accepts them
+        ops.insert(7, Op::Add([f64::INFINITY], 99));
+        ops.push(Op::Nearest { point: [3.0], k: 2 });
+        assert!(run(&ops, 2).is_err());
+        assert_eq!(minimize(ops, 2), vec![Op::Add([f64::INFINITY], 99)]);
+    }
+
+    #[test]
+    fn it_catches_broken_trees() {
+        let model: Vec<([f64; 2], usize)> = (0..20)
+            .map(|i| ([(i % 7) as f64, (i % 3) as f64], i))
+            .collect();
+        let build = || {
+            let mut tree = OwnedKdTree::with_capacity(2);
+            for &(point, data) in &model {
+                tree.add(point, data).unwrap();
+            }
+            tree
+        };
+        assert_eq!(check(&build(), &model), Ok(()));
+
+        let mut oversized = build();
+        oversized.break_size();
+        assert_eq!(
+            check(&oversized, &model),
+            Err("the sizes of the children of a stem do not add up".to_string())
+        );
+
+        let mut misplaced = build();
+        misplaced.misplace_item();
+        assert_eq!(
+            check(&misplaced, &model),
+            Err("a point lies on the wrong side of a split above it".to_string())
+        );
+        // the moved point is also found at the wrong distance
+        let nearest = Op::Nearest {
+            point: [0.0, 0.0],
+            k: model.len(),
+        };
+        assert!(apply(&mut misplaced, &mut model.clone(), &nearest).is_err());
+        assert_eq!(apply(&mut build(), &mut model.clone(), &nearest), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "minimized to 1 ops")]
+    fn it_panics_on_divergence() {
+        model_check(vec![
+            Op::Add([0.0, 0.0], 0),
+            Op::Add([f64::INFINITY, 0.0], 1),
+            Op::Nearest {
+                point: [0.0, 0.0],
+                k: 2,
+            },
+        ]);
+    }
+}
@@ -0,0 +1,315 @@
+//! Nearest neighbours among moving objects, whose positions are sampled at increasing times and
+//! linearly interpolated between the samples.
+//!
+//! A [`TrajectoryKdTree`] stores every segment between two consecutive samples as a point in
+//! space and time, at its first sample, in an [`OwnedKdTree`] of one more dimension than the
+//! space. Stable Rust cannot name that dimension as `D + 1`, so it is a second parameter `E`
+//! which must equal `D + 1`; [`Trajectory2`] and [`Trajectory3`] spell it out.
+//!
+//! ```rust
+//! use kdtree::trajectory::Trajectory2;
+//!
+//! let mut tree = Trajectory2::new();
+//! // along the x axis from the origin, and down from above
+//! tree.add_trajectory("east", &[(0.0, [0.0, 0.0]), (10.0, [10.0, 0.0])]).unwrap();
+//! tree.add_trajectory("south", &[(0.0, [5.0, 5.0]), (10.0, [5.0, -15.0])]).unwrap();
+//!
+//! assert_eq!(tree.nearest_at(&[5.0, 0.0], 5.0, 2).unwrap(), vec![(0.0, &"east"), (5.0, &"south")]);
+//! assert_eq!(tree.nearest_at(&[1.0, 0.0], 2.0, 1).unwrap(), vec![(1.0, &"east")]);
+//! assert!(tree.nearest_at(&[1.0, 0.0], 12.0, 1).unwrap().is_empty());
+//! ```
+
+use num_traits::{Float, One, Zero};
+
+use crate::error::ErrorKind;
+use crate::priority::cmp_distance;
+use crate::tree::{OwnedKdTree, DEFAULT_LEAF_CAPACITY};
+
+pub type Trajectory2<A, T> = TrajectoryKdTree<A, T, 2, 3>;
+pub type Trajectory3<A, T> = TrajectoryKdTree<A, T, 3, 4>;
+
+/// The linear motion between two consecutive samples of a trajectory
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SegmentInfo<A, const D: usize> {
+    start: [A; D],
+    start_time: A,
+    end: [A; D],
+    end_time: A,
+    /// Whether this is the last segment of its trajectory, which also covers its end time
+    last: bool,
+}
+
+impl<A: Float, const D: usize> SegmentInfo<A, D> {
+    /// The position at time `t`, if the segment covers it. Segments cover their start time but
+    /// not their end time, which is covered by the next segment, so that a trajectory has a
+    /// single position at every time.
+    fn position_at(&self, t: A) -> Option<[A; D]> {
+        let covers = self.start_time <= t && (t < self.end_time || self.last && t <= self.end_time);
+        if !covers {
+            return None;
+        }
+        let duration = self.end_time - self.start_time;
+        if duration <= A::zero() {
+            return Some(self.start);
+        }
+        let fraction = (t - self.start_time) / duration;
+        let mut position = self.start;
+        for (x, &end) in position.iter_mut().zip(&self.end) {
+            *x = *x + (end - *x) * fraction;
+        }
+        Some(position)
+    }
+}
+
+fn euclidean<A: Float, const D: usize>(a: &[A; D], b: &[A; D]) -> A {
+    a.iter()
+        .zip(b)
+        .map(|(&x, &y)| (x - y) * (x - y))
+        .fold(A::zero(), |sum, x| sum + x)
+        .sqrt()
+}
+
+/// Trajectories in `D` dimensions, stored as segments in a tree of `E = D + 1` dimensions whose
+/// last coordinate is the time. See the [module documentation](index.html).
+pub struct TrajectoryKdTree<A: PartialEq, T: PartialEq, const D: usize, const E: usize> {
+    segments: OwnedKdTree<A, (T, SegmentInfo<A, D>), E>,
+    /// The longest duration of a segment, the width of the slab of start times searched
+    max_duration: A,
+    /// The longest distance travelled along a segment
+    max_length: A,
+}
+
+impl<A: Float + Zero + One, T: PartialEq, const D: usize, const E: usize>
+    TrajectoryKdTree<A, T, D, E>
+{
+    const DIMENSIONS: () = assert!(E == D + 1, "E must be D + 1");
+
+    /// Create an empty tree with leaves of [`DEFAULT_LEAF_CAPACITY`]
+    pub fn new() -> Self {
+        TrajectoryKdTree::with_capacity(DEFAULT_LEAF_CAPACITY)
+    }
+
+    /// Create an empty tree, specifying the capacity of leaf nodes
+    pub fn with_capacity(capacity: usize) -> Self {
+        let () = Self::DIMENSIONS;
+        TrajectoryKdTree {
+            segments: OwnedKdTree::with_capacity(capacity),
+            max_duration: A::zero(),
+            max_length: A::zero(),
+        }
+    }
+
+    /// The number of stored segments
+    pub fn size(&self) -> usize {
+        self.segments.size()
+    }
+
+    /// Add the trajectory of `data` through `samples` of times and positions. Returns
+    /// `ErrorKind::InvalidTrajectory` without adding anything if there are no samples or their
+    /// times do not strictly increase, and `ErrorKind::NonFiniteCoordinate` if a time or
+    /// coordinate is not finite. A single sample is a trajectory which only exists at its time.
+    pub fn add_trajectory(&mut self, data: T, samples: &[(A, [A; D])]) -> Result<(), ErrorKind>
+    where
+        T: Clone,
+    {
+        for (t, point) in samples {
+            if !t.is_finite() || point.iter().any(|x| !x.is_finite()) {
+                return Err(ErrorKind::NonFiniteCoordinate);
+            }
+        }
+        if samples.is_empty() || samples.windows(2).any(|w| w[0].0 >= w[1].0) {
+            return Err(ErrorKind::InvalidTrajectory);
+        }
+        // a single sample is a segment from the sample to itself
+        let ends = if samples.len() == 1 {
+            samples
+        } else {
+            &samples[1..]
+        };
+        let segments = ends.len();
+        for (i, (&(start_time, start), &(end_time, end))) in samples.iter().zip(ends).enumerate() {
+            let segment = SegmentInfo {
+                start,
+                start_time,
+                end,
+                end_time,
+                last: i + 1 == segments,
+            };
+            self.max_duration = self.max_duration.max(end_time - start_time);
+            self.max_length = self.max_length.max(euclidean(&start, &end));
+            let mut key = [start_time; E];
+            key[..D].copy_from_slice(&start);
+            self.segments.add(key, (data.clone(), segment))?;
+        }
+        Ok(())
+    }
+
+    /// The `k` trajectories nearest to `point` at time `t` with their euclidean distances,
+    /// nearest first. Only trajectories whose samples span `t` have a position then.
+    ///
+    /// The search only visits segments starting in the slab of times `[t - d, t]`, where `d` is
+    /// the longest duration of a segment, in the order of the distance from `point` to their
+    /// first sample. Since no position along a segment is farther from its first sample than
+    /// the longest segment, the search stops once that distance, less the length of the longest
+    /// segment, exceeds the distance of the `k`th nearest position found. A few segments much
+    /// longer in space or time than the rest therefore slow down every query.
+    pub fn nearest_at(&self, point: &[A; D], t: A, k: usize) -> Result<Vec<(A, &T)>, ErrorKind> {
+        if !t.is_finite() || point.iter().any(|x| !x.is_finite()) {
+            return Err(ErrorKind::NonFiniteCoordinate);
+        }
+        let mut query = [t; E];
+        query[..D].copy_from_slice(point);
+        let earliest = t - self.max_duration;
+        let in_slab = |a: &[A; E], b: &[A; E]| {
+            if b[D] > a[D] || b[D] < earliest {
+                return A::infinity();
+            }
+            let mut sum = A::zero();
+            for i in 0..D {
+                sum = sum + (a[i] - b[i]) * (a[i] - b[i]);
+            }
+            sum
+        };
+        let mut nearest: Vec<(A, &T)> = Vec::with_capacity(k);
+        if k == 0 {
+            return Ok(nearest);
+        }
+        for (start_distance, (data, segment)) in self.segments.iter_nearest(&query, &in_slab)? {
+            if start_distance.is_infinite() {
+                break;
+            }
+            let bound = start_distance.sqrt() - self.max_length;
+            if nearest.len() == k && bound > nearest[k - 1].0 {
+                break;
+            }
+            let position = match segment.position_at(t) {
+                Some(position) => position,
+                None => continue,
+            };
+            let distance = euclidean(point, &position);
+            let index = nearest.partition_point(|&(d, _)| cmp_distance(&d, &distance).is_le());
+            if index < k {
+                nearest.truncate(k - 1);
+                nearest.insert(index, (distance, data));
+            }
+        }
+        Ok(nearest)
+    }
+}
+
+impl<A: Float + Zero + One, T: PartialEq, const D: usize, const E: usize> Default
+    for TrajectoryKdTree<A, T, D, E>
+{
+    fn default() -> Self {
+        TrajectoryKdTree::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::{Rng, SeedableRng, StdRng};
+    use super::{Trajectory2, Trajectory3};
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn it_finds_the_analytic_nearest_of_linear_trajectories() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[1, 4, 4][..]);
+        // objects moving at constant velocities, sampled at irregular times in their lifetimes
+        let objects: Vec<([f64; 2], [f64; 2], f64, f64)> = (0..300)
+            .map(|_| {
+                let origin = [rng.gen_range(-50.0, 50.0), rng.gen_range(-50.0, 50.0)];
+                let velocity = [rng.gen_range(-2.0, 2.0), rng.gen_range(-2.0, 2.0)];
+                let start = rng.gen_range(0.0, 40.0);
+                (origin, velocity, start, start + rng.gen_range(5.0, 60.0))
+            })
+            .collect();
+        let position = |&(origin, velocity, _, _): &([f64; 2], [f64; 2], f64, f64), t: f64| {
+            [origin[0] + velocity[0] * t, origin[1] + velocity[1] * t]
+        };
+        let mut tree = Trajectory2::with_capacity(8);
+        for (i, object) in objects.iter().enumerate() {
+            let (_, _, start, end) = *object;
+            let mut times = vec![start, end];
+            times.extend((0..rng.gen_range(0, 6)).map(|_| rng.gen_range(start, end)));
+            times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            times.dedup();
+            let samples: Vec<(f64, [f64; 2])> =
+                times.iter().map(|&t| (t, position(object, t))).collect();
+            tree.add_trajectory(i, &samples).unwrap();
+        }
+
+        for _ in 0..200 {
+            let query = [rng.gen_range(-60.0, 60.0), rng.gen_range(-60.0, 60.0)];
+            let t = rng.gen_range(-5.0, 105.0);
+            let mut expected: Vec<(f64, usize)> = objects
+                .iter()
+                .enumerate()
+                .filter(|(_, &(_, _, start, end))| start <= t && t <= end)
+                .map(|(i, object)| {
+                    let p = position(object, t);
+                    let d = ((p[0] - query[0]).powi(2) + (p[1] - query[1]).powi(2)).sqrt();
+                    (d, i)
+                })
+                .collect();
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            expected.truncate(5);
+            let found = tree.nearest_at(&query, t, 5).unwrap();
+            assert_eq!(found.len(), expected.len());
+            for (&(d, &i), &(expected_d, _)) in found.iter().zip(&expected) {
+                assert!((d - expected_d).abs() < 1e-9);
+                let p = position(&objects[i], t);
+                assert!(
+                    (d - ((p[0] - query[0]).powi(2) + (p[1] - query[1]).powi(2)).sqrt()).abs()
+                        < 1e-9
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn it_has_one_position_at_every_sampled_time() {
+        let mut tree = Trajectory3::with_capacity(2);
+        let samples = [
+            (0.0, [0.0, 0.0, 0.0]),
+            (1.0, [1.0, 0.0, 0.0]),
+            (2.0, [1.0, 1.0, 0.0]),
+        ];
+        tree.add_trajectory('a', &samples).unwrap();
+        tree.add_trajectory('b', &[(1.0, [9.0, 9.0, 9.0])]).unwrap();
+        assert_eq!(tree.size(), 3);
+        assert_eq!(
+            tree.nearest_at(&[0.0; 3], 1.0, 3).unwrap(),
+            vec![(1.0, &'a'), (243.0f64.sqrt(), &'b')]
+        );
+        assert_eq!(
+            tree.nearest_at(&[1.0, 1.0, 0.0], 2.0, 3).unwrap(),
+            vec![(0.0, &'a')]
+        );
+        assert_eq!(
+            tree.nearest_at(&[0.0; 3], 1.5, 3).unwrap(),
+            vec![(1.25f64.sqrt(), &'a')]
+        );
+        assert!(tree.nearest_at(&[0.0; 3], -0.5, 3).unwrap().is_empty());
+        assert!(tree.nearest_at(&[0.0; 3], 1.0, 0).unwrap().is_empty());
+
+        assert_eq!(
+            tree.add_trajectory('c', &[(1.0, [0.0; 3]), (1.0, [1.0; 3])]),
+            Err(ErrorKind::InvalidTrajectory)
+        );
+        assert_eq!(
+            tree.add_trajectory('c', &[]),
+            Err(ErrorKind::InvalidTrajectory)
+        );
+        assert_eq!(
+            tree.add_trajectory('c', &[(0.0, [f64::NAN; 3])]),
+            Err(ErrorKind::NonFiniteCoordinate)
+        );
+        assert_eq!(
+            tree.nearest_at(&[0.0; 3], f64::NAN, 1),
+            Err(ErrorKind::NonFiniteCoordinate)
+        );
+        assert_eq!(tree.size(), 3);
+    }
+}
@@ -0,0 +1,5573 @@
+//! [`OwnedKdTree`], a k-d tree owning its points and their payloads, the [`Builder`] configuring
+//! one, and [`NodeRef`] and [`NodeMut`], views of its nodes for traversals of their own. What its
+//! queries return is in [`query`](crate::query) and the errors they return in
+//! [`error`](crate::error).
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::ops::ControlFlow;
+
+use num_traits::{Float, One, Zero};
+
+use crate::curve;
+use crate::distance::squared_euclidean;
+use crate::error::ErrorKind;
+use crate::flat::{FlatKdTree, LEAF};
+use crate::geometry::{distance_to_far_corner_const, distance_to_space_const};
+use crate::heap_element::Neighbour;
+use crate::metric::{ClosureMetric, Metric};
+use crate::priority::{MaxByDistance, MinByDistance, Priority};
+use crate::query::{
+    DynDistance, FarthestIter, Iter, MortonIter, NearestExcludingSelfIter, NearestIter,
+    NearestIterMut, QueryStats, WithinOutcome,
+};
+use crate::search::{self, belongs_in_left, SearchNode};
+
+use self::leaf_vec::LeafVec;
+
+mod cast;
+mod closest;
+mod hash;
+pub(crate) mod join;
+mod leaf_vec;
+mod per_class;
+mod sampling;
+#[cfg(feature = "serialize")]
+mod serialize;
+mod slice;
+mod spanning;
+
+/// The capacity of leaf nodes used by [`OwnedKdTree::new`] and [`Builder::new`]
+pub const DEFAULT_LEAF_CAPACITY: usize = 16;
+
+/// A capacity of leaf nodes suited to points of `dimensions` dimensions, `128 / dimensions`
+/// clamped to `8..=64`. Low dimensional trees are faster with large leaves, since scanning a leaf
+/// is cheap compared to descending the tree, while high dimensional ones need small leaves to
+/// prune anything at all.
+pub const fn leaf_capacity_for_dimensions(dimensions: usize) -> usize {
+    if dimensions <= 2 {
+        64
+    } else if dimensions >= 16 {
+        8
+    } else {
+        128 / dimensions
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct OwnedKdTree<A, T: std::cmp::PartialEq, const D: usize> {
+    // common
+    capacity: usize,
+    pub(crate) size: usize,
+    pub(crate) min_bounds: [A; D],
+    pub(crate) max_bounds: [A; D],
+    // root
+    max_items: Option<usize>,
+    split_rules: SplitRules,
+    /// Bumped by every change to the items, see [`OwnedKdTree::generation`]
+    generation: u64,
+    pub(crate) node: Node<A, T, D>,
+}
+
+/// What a node holds besides its bounds: a stem always has a split and both children, and a
+/// leaf always has its items.
+///
+/// The searches skip a node by its bounds and pick a child by its split, so every node keeps
+/// two invariants: its bounds contain every point below it, though they may be wider than
+/// needed once items are removed, and every point below a stem is in the child
+/// [`belongs_in_left`] picks, so a point exactly at the split value is in `right`. Adding extends
+/// the bounds of each node on the way down before picking a child, and splitting a leaf adds
+/// its items to the new children the same way, so both hold after any adds and removes.
+/// [`OwnedKdTree::debug_validate`] checks them.
+#[derive(Clone, Debug)]
+pub(crate) enum Node<A, T: PartialEq, const D: usize> {
+    Stem {
+        split_dimension: usize,
+        /// Points with a coordinate below this value in `split_dimension` are in `left`, all
+        /// others in `right`
+        split_value: A,
+        left: Box<OwnedKdTree<A, T, D>>,
+        right: Box<OwnedKdTree<A, T, D>>,
+    },
+    Leaf {
+        points: LeafVec<[A; D]>,
+        bucket: LeafVec<T>,
+    },
+}
+
+/// The settings of a tree which decide how its leaves split as items are added, passed down to
+/// the leaf being split
+#[derive(Clone, Copy, Debug, Default)]
+struct SplitRules {
+    leaf_capacity_fn: Option<fn(usize) -> usize>,
+    strategy: SplitStrategy,
+    /// The imbalance factor and the largest size of a subtree to rebuild, see
+    /// [`Builder::resplit_imbalanced`]
+    resplit: Option<(usize, usize)>,
+    /// What happens to points outside the bounds the tree was created with, see
+    /// [`OwnedKdTree::with_bounds`], `None` if its bounds grow with its items
+    declared: Option<OutOfBounds>,
+}
+
+/// The points stored in one leaf, see [`OwnedKdTree::leaf_summaries`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LeafSummary<A, const D: usize> {
+    /// The mean of the points in the leaf
+    pub centroid: [A; D],
+    /// Number of items in the leaf
+    pub count: usize,
+    /// The bounding box `(min, max)` of the leaf, which contains all of its points
+    pub bounds: ([A; D], [A; D]),
+}
+
+/// A summary of the shape of a tree, see [`OwnedKdTree::stats`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TreeStats {
+    /// Number of items stored in the tree
+    pub size: usize,
+    /// Number of internal nodes
+    pub stems: usize,
+    /// Number of leaf nodes
+    pub leaves: usize,
+    /// Depth of the deepest leaf, the root being at depth 0
+    pub max_depth: usize,
+    /// Number of items in the fullest leaf
+    pub max_leaf_size: usize,
+}
+
+impl<A: Float + Zero + One, T: std::cmp::PartialEq, const D: usize> OwnedKdTree<A, T, D> {
+    /// Create a new KD tree with leaves of [`DEFAULT_LEAF_CAPACITY`]
+    pub fn new() -> Self {
+        OwnedKdTree::with_capacity(DEFAULT_LEAF_CAPACITY)
+    }
+
+    /// Create a new KD tree with leaves of [`leaf_capacity_for_dimensions(D)`](fn.leaf_capacity_for_dimensions.html)
+    pub fn with_per_dimension_default() -> Self {
+        OwnedKdTree::with_capacity(leaf_capacity_for_dimensions(D))
+    }
+
+    /// Create a new KD tree, specifying the dimension size of each point and the capacity of leaf nodes
+    ///
+    /// Every constructor goes through here, so a tree of points without coordinates, which
+    /// could neither be split nor told apart by distance, fails to compile:
+    ///
+    /// ```compile_fail
+    /// let tree = kdtree::OwnedKdTree::<f64, usize, 0>::with_capacity(4);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is zero, as such a tree could never hold an item. Use
+    /// [`try_with_capacity`](#method.try_with_capacity) for a capacity which may be zero.
+    pub fn with_capacity(capacity: usize) -> Self {
+        const { assert!(D > 0, "an OwnedKdTree needs at least one dimension") };
+        assert!(
+            capacity > 0,
+            "the leaf capacity of an OwnedKdTree must be positive"
+        );
+        let min_bounds = [A::infinity(); D];
+        let max_bounds = [A::neg_infinity(); D];
+        OwnedKdTree {
+            capacity,
+            size: 0,
+            max_items: None,
+            split_rules: SplitRules::default(),
+            generation: 0,
+            min_bounds,
+            max_bounds,
+            node: Node::Leaf {
+                points: LeafVec::default(),
+                bucket: LeafVec::default(),
+            },
+        }
+    }
+
+    /// Like [`with_capacity`](#method.with_capacity), but returns `ErrorKind::ZeroCapacity`
+    /// rather than panicking if `capacity` is zero
+    pub fn try_with_capacity(capacity: usize) -> Result<Self, ErrorKind> {
+        if capacity == 0 {
+            return Err(ErrorKind::ZeroCapacity);
+        }
+        Ok(OwnedKdTree::with_capacity(capacity))
+    }
+
+    /// An empty tree covering the box `[min, max]`, for items whose extent is known up front.
+    /// The box is the bounds of the root from the start, and a leaf splits its widest dimension
+    /// in the middle of the part of the box it covers rather than of its items, so that the
+    /// first items do not decide the shape of the tree when they are all in one corner. Points
+    /// outside the box are rejected with `ErrorKind::OutOfBounds`; see
+    /// [`with_bounds_policy`](#method.with_bounds_policy) to clamp them instead.
+    ///
+    /// The bounds of the nodes then cover their part of the box rather than their items, which
+    /// is coarser for searches in sparsely filled parts of it. A node whose items are all removed
+    /// forgets its part and grows its bounds from its items again, and so does every node of a
+    /// subtree rebuilt by [`Builder::resplit_imbalanced`], except for the root, which keeps the
+    /// box. [`from_points`](#method.from_points) and [`Builder`] do not take bounds.
+    pub fn with_bounds(capacity: usize, min: [A; D], max: [A; D]) -> Result<Self, ErrorKind> {
+        Self::with_bounds_policy(capacity, min, max, OutOfBounds::Reject)
+    }
+
+    /// Like [`with_bounds`](#method.with_bounds), with `policy` deciding what happens to points
+    /// outside the box
+    pub fn with_bounds_policy(
+        capacity: usize,
+        min: [A; D],
+        max: [A; D],
+        policy: OutOfBounds,
+    ) -> Result<Self, ErrorKind> {
+        let mut tree = OwnedKdTree::try_with_capacity(capacity)?;
+        let () = tree.check_point(&min)?;
+        let () = tree.check_point(&max)?;
+        if (0..D).any(|dim| min[dim] > max[dim]) {
+            return Err(ErrorKind::InvalidBounds);
+        }
+        tree.split_rules.declared = Some(policy);
+        tree.min_bounds = min;
+        tree.max_bounds = max;
+        Ok(tree)
+    }
+
+    /// The box the tree was created with by [`with_bounds`](#method.with_bounds), `None` if its
+    /// bounds grow with its items
+    pub fn declared_bounds(&self) -> Option<([A; D], [A; D])> {
+        self.split_rules
+            .declared
+            .map(|_| (self.min_bounds, self.max_bounds))
+    }
+
+    /// `point` as it is added to a tree with [`declared_bounds`](#method.declared_bounds),
+    /// following its [`OutOfBounds`] policy
+    fn within_declared(&self, mut point: [A; D]) -> Result<[A; D], ErrorKind> {
+        let policy = match self.split_rules.declared {
+            Some(policy) => policy,
+            None => return Ok(point),
+        };
+        for (dim, v) in point.iter_mut().enumerate() {
+            let (min, max) = (self.min_bounds[dim], self.max_bounds[dim]);
+            if min <= *v && *v <= max {
+                continue;
+            }
+            match policy {
+                OutOfBounds::Reject => return Err(ErrorKind::OutOfBounds { dim }),
+                OutOfBounds::Clamp => *v = v.max(min).min(max),
+            }
+        }
+        Ok(point)
+    }
+
+    /// Build a tree holding all of `items` at once, splitting every node at the median of its
+    /// widest dimension. This gives a more balanced tree than adding the items one by one.
+    pub fn from_points(items: Vec<([A; D], T)>, capacity: usize) -> Result<Self, ErrorKind> {
+        Builder::new().capacity(capacity).from_points(items)
+    }
+
+    /// Build a tree from parallel columns of points and payloads, which must have the same length
+    pub fn from_columns(
+        points: Vec<[A; D]>,
+        data: Vec<T>,
+        capacity: usize,
+    ) -> Result<Self, ErrorKind> {
+        if points.len() != data.len() {
+            return Err(ErrorKind::LengthMismatch);
+        }
+        OwnedKdTree::from_points(points.into_iter().zip(data).collect(), capacity)
+    }
+
+    /// Consume the tree, returning its points and payloads as parallel columns in no particular
+    /// order
+    pub fn into_columns(self) -> (Vec<[A; D]>, Vec<T>) {
+        self.into_items().into_iter().unzip()
+    }
+
+    /// Build a tree from `items` with every point passed through `f` first, rejecting the items
+    /// if any transformed point has a non-finite coordinate
+    pub fn from_points_transformed<F>(
+        items: Vec<([A; D], T)>,
+        capacity: usize,
+        f: F,
+    ) -> Result<Self, ErrorKind>
+    where
+        F: Fn([A; D]) -> [A; D],
+    {
+        let items = items.into_iter().map(|(p, d)| (f(p), d)).collect();
+        OwnedKdTree::from_points(items, capacity)
+    }
+
+    /// Builds the node at `depth` over `items`, with leaves of `capacity` or the capacity
+    /// `leaf_capacity_fn` gives at their depth
+    fn build(
+        items: Vec<([A; D], T)>,
+        capacity: usize,
+        leaf_capacity_fn: Option<fn(usize) -> usize>,
+        depth: usize,
+        progress: &mut Progress,
+    ) -> Result<Self, ErrorKind> {
+        let mut min = [A::infinity(); D];
+        let mut max = [A::neg_infinity(); D];
+        for (point, _) in &items {
+            for dim in 0..D {
+                min[dim] = min[dim].min(point[dim]);
+                max[dim] = max[dim].max(point[dim]);
+            }
+        }
+        let (items, dim) = match OwnedKdTree::build_leaf(items, capacity, min, max) {
+            Ok(leaf) => {
+                progress.advance(leaf.size)?;
+                return Ok(leaf);
+            }
+            Err(split) => split,
+        };
+        // select the median from a copy of the coordinates so the partition below keeps the items
+        // in the order they were given
+        let mut coordinates: Vec<A> = items.iter().map(|(p, _)| p[dim]).collect();
+        let mid = coordinates.len() / 2;
+        let (_, median, _) =
+            coordinates.select_nth_unstable_by(mid, |a, b| a.partial_cmp(b).unwrap());
+        let split_value = build_split_value(&items, dim, *median, min[dim], max[dim]);
+        let (left, right): (Vec<_>, Vec<_>) = items
+            .into_iter()
+            .partition(|(p, _)| belongs_in_left(p, dim, split_value));
+        let child_capacity = leaf_capacity_at(leaf_capacity_fn, capacity, depth + 1);
+        let mut build = |items| {
+            OwnedKdTree::build(items, child_capacity, leaf_capacity_fn, depth + 1, progress)
+        };
+        let left = build(left)?;
+        let right = build(right)?;
+        Ok(OwnedKdTree::build_stem(
+            capacity,
+            dim,
+            split_value,
+            left,
+            right,
+        ))
+    }
+
+    /// [`build`](#method.build) without reporting progress, which then cannot fail
+    pub(crate) fn build_quietly(
+        items: Vec<([A; D], T)>,
+        capacity: usize,
+        leaf_capacity_fn: Option<fn(usize) -> usize>,
+        depth: usize,
+    ) -> Self {
+        let mut callback = |_, _| ControlFlow::Continue(());
+        let mut progress = Progress::new(items.len(), &mut callback);
+        OwnedKdTree::build(items, capacity, leaf_capacity_fn, depth, &mut progress).unwrap()
+    }
+
+    /// The leaf holding `items`, whose bounds are `[min, max]`, if they fit in one or are all at
+    /// one point, and otherwise the items back with the dimension to split them along
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn build_leaf(
+        items: Vec<([A; D], T)>,
+        capacity: usize,
+        min: [A; D],
+        max: [A; D],
+    ) -> Result<Self, (Vec<([A; D], T)>, usize)> {
+        let mut node = OwnedKdTree::with_capacity(capacity);
+        node.min_bounds = min;
+        node.max_bounds = max;
+        match node.widest_dimension() {
+            Some(dim) if items.len() > capacity => Err((items, dim)),
+            _ => {
+                node.size = items.len();
+                let (points, bucket) = items.into_iter().unzip();
+                node.node = Node::Leaf { points, bucket };
+                Ok(node)
+            }
+        }
+    }
+
+    /// The stem over two non-empty children built from the items on either side of a split
+    pub(crate) fn build_stem(
+        capacity: usize,
+        split_dimension: usize,
+        split_value: A,
+        left: Self,
+        right: Self,
+    ) -> Self {
+        let mut node = OwnedKdTree::with_capacity(capacity);
+        node.size = left.size + right.size;
+        for dim in 0..D {
+            node.min_bounds[dim] = left.min_bounds[dim].min(right.min_bounds[dim]);
+            node.max_bounds[dim] = left.max_bounds[dim].max(right.max_bounds[dim]);
+        }
+        node.node = Node::Stem {
+            split_dimension,
+            split_value,
+            left: Box::new(left),
+            right: Box::new(right),
+        };
+        node
+    }
+
+    /// Consume the tree and rebuild it with every stored point passed through `f`, e.g. to
+    /// project geographic coordinates so that the bounds used for pruning live in the same
+    /// space as the distance function
+    pub fn transform_points<F>(self, f: F) -> Result<Self, ErrorKind>
+    where
+        F: Fn([A; D]) -> [A; D],
+    {
+        let capacity = self.capacity;
+        OwnedKdTree::from_points_transformed(self.into_items(), capacity, f)
+    }
+
+    /// Convert the tree into a [`FlatKdTree`] of the same structure, which answers queries
+    /// from contiguous arrays but can no longer be modified. The items of each leaf are sorted
+    /// along the split dimension of its parent, the first dimension for a root leaf, keeping
+    /// the order of items at equal coordinates.
+    pub fn freeze(self) -> FlatKdTree<A, T, D> {
+        let mut flat = FlatKdTree::empty();
+        self.freeze_into(&mut flat, 0);
+        flat
+    }
+
+    /// Appends this subtree to `flat` in preorder, with the items of a leaf sorted along
+    /// `sort_dimension`, returning the index of its root
+    fn freeze_into(self, flat: &mut FlatKdTree<A, T, D>, sort_dimension: usize) -> u64 {
+        let index = flat.links.len();
+        flat.min_bounds.push(self.min_bounds);
+        flat.max_bounds.push(self.max_bounds);
+        flat.links.push([LEAF, 0, 0]);
+        match self.node {
+            Node::Stem {
+                split_dimension,
+                split_value,
+                left,
+                right,
+            } => {
+                flat.splits.push(split_value);
+                let left = left.freeze_into(flat, split_dimension);
+                let right = right.freeze_into(flat, split_dimension);
+                flat.links[index] = [split_dimension as u64, left, right];
+            }
+            Node::Leaf { points, bucket } => {
+                flat.splits.push(A::nan());
+                let first = flat.points.len() as u64;
+                let mut items: Vec<([A; D], T)> = points.into_iter().zip(bucket).collect();
+                // stored points are finite, so they compare
+                items.sort_by(|(a, _), (b, _)| {
+                    a[sort_dimension]
+                        .partial_cmp(&b[sort_dimension])
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+                for (point, data) in items {
+                    flat.points.push(point);
+                    flat.data.push(data);
+                }
+                flat.links[index] = [LEAF, first, flat.points.len() as u64 - first];
+            }
+        }
+        index as u64
+    }
+
+    fn into_items(self) -> Vec<([A; D], T)> {
+        let mut items = Vec::with_capacity(self.size);
+        let mut pending = vec![self];
+        while let Some(node) = pending.pop() {
+            match node.node {
+                Node::Stem { left, right, .. } => {
+                    pending.push(*left);
+                    pending.push(*right);
+                }
+                Node::Leaf { points, bucket } => items.extend(points.into_iter().zip(bucket)),
+            }
+        }
+        items
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Iterate over all stored items, leaf by leaf, visiting the lower side of every split
+    /// before the upper side and the items of a leaf in the order it stores them
+    pub fn iter(&self) -> Iter<'_, A, T, D> {
+        Iter {
+            pending: vec![self],
+            current: [].iter().zip([].iter()),
+        }
+    }
+
+    /// Iterate over all stored items leaf by leaf in [`iter`](#method.iter) order, which visits
+    /// the lower side of every split before the upper side, and over the items of every leaf by
+    /// their [Morton index](crate::curve::morton_index) within the bounds of the tree. Since
+    /// splits alternate between the widest dimensions, this yields the items approximately in
+    /// Morton (Z-) order. The leaves of a tree built in [`BuildOrder::Morton`] already hold their
+    /// items in this order, so for such a tree it matches `iter`.
+    pub fn iter_morton(&self) -> MortonIter<'_, A, T, D> {
+        MortonIter {
+            pending: vec![self],
+            min: self.min_bounds,
+            max: self.max_bounds,
+            points: &[],
+            bucket: &[],
+            order: vec![].into_iter(),
+        }
+    }
+
+    /// Walk the tree and count its nodes, leaves and depth
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats {
+            size: self.size,
+            stems: 0,
+            leaves: 0,
+            max_depth: 0,
+            max_leaf_size: 0,
+        };
+        let mut pending = vec![(self, 0)];
+        while let Some((node, depth)) = pending.pop() {
+            match node.children() {
+                Some([left, right]) => {
+                    stats.stems += 1;
+                    pending.push((left, depth + 1));
+                    pending.push((right, depth + 1));
+                }
+                None => {
+                    stats.leaves += 1;
+                    stats.max_depth = stats.max_depth.max(depth);
+                    stats.max_leaf_size = stats.max_leaf_size.max(node.size);
+                }
+            }
+        }
+        stats
+    }
+
+    /// Check the invariants the searches rely on, panicking with the first one broken: the
+    /// bounds of every node contain all points below it, every point is on the side of each
+    /// split above it that [`add`](#method.add) puts it on, where a point exactly at a split
+    /// value is on the upper side, and the sizes of the nodes add up. Walks the whole tree, so
+    /// this is meant for tests and debugging.
+    pub fn debug_validate(&self) {
+        if let Err(broken) = self.validate() {
+            panic!("invalid OwnedKdTree: {}", broken);
+        }
+    }
+
+    /// The first invariant of [`debug_validate`](#method.debug_validate) the tree breaks
+    pub(crate) fn validate(&self) -> Result<(), &'static str> {
+        let unbounded = ([A::neg_infinity(); D], [A::infinity(); D]);
+        self.validate_in(unbounded, unbounded)
+    }
+
+    /// Checks the subtree, whose points must lie within the ancestors' bounds `enclosing` and
+    /// the half open region `[lower, upper)` left by the ancestors' splits
+    fn validate_in(
+        &self,
+        enclosing: ([A; D], [A; D]),
+        (lower, upper): ([A; D], [A; D]),
+    ) -> Result<(), &'static str> {
+        let (mut min, mut max) = enclosing;
+        for dim in 0..D {
+            min[dim] = min[dim].max(self.min_bounds[dim]);
+            max[dim] = max[dim].min(self.max_bounds[dim]);
+        }
+        match &self.node {
+            Node::Stem {
+                split_dimension,
+                split_value,
+                left,
+                right,
+            } => {
+                if *split_dimension >= D || split_value.is_nan() {
+                    return Err("a stem has an invalid split");
+                }
+                if left.size + right.size != self.size {
+                    return Err("the sizes of the children of a stem do not add up");
+                }
+                let (mut left_upper, mut right_lower) = (upper, lower);
+                left_upper[*split_dimension] = upper[*split_dimension].min(*split_value);
+                right_lower[*split_dimension] = lower[*split_dimension].max(*split_value);
+                left.validate_in((min, max), (lower, left_upper))?;
+                right.validate_in((min, max), (right_lower, upper))
+            }
+            Node::Leaf { points, bucket } => {
+                if points.len() != self.size || bucket.len() != self.size {
+                    return Err("a leaf does not hold as many items as its size");
+                }
+                for point in points {
+                    if (0..D).any(|d| !(min[d] <= point[d] && point[d] <= max[d])) {
+                        return Err("a point lies outside the bounds of a node above it");
+                    }
+                    if (0..D).any(|d| !(lower[d] <= point[d] && point[d] < upper[d])) {
+                        return Err("a point lies on the wrong side of a split above it");
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Counts one item too many at the root, for the tests of the checks in `test_support`
+    #[cfg(test)]
+    pub(crate) fn break_size(&mut self) {
+        self.size += 1;
+    }
+
+    /// Moves the first point of the leftmost leaf below the root's split onto the split value,
+    /// the upper side, and grows the bounds above it to match, so that only the split is
+    /// broken. For the tests of the checks in `test_support`.
+    #[cfg(test)]
+    pub(crate) fn misplace_item(&mut self) {
+        if let Node::Stem {
+            split_dimension,
+            split_value,
+            left,
+            ..
+        } = &mut self.node
+        {
+            left.move_first_point(*split_dimension, *split_value);
+        }
+    }
+
+    #[cfg(test)]
+    fn move_first_point(&mut self, dim: usize, value: A) {
+        self.max_bounds[dim] = self.max_bounds[dim].max(value);
+        match &mut self.node {
+            Node::Stem { left, .. } => left.move_first_point(dim, value),
+            Node::Leaf { points, .. } => points[0][dim] = value,
+        }
+    }
+
+    /// Shrink the bounds of every node to the smallest box holding its items.
+    ///
+    /// The bounds of a node are only ever grown while adding, so they always contain its
+    /// items, but removing items leaves them where the removed items were: a node that lost
+    /// its outliers still claims their space, and searches visit it without finding anything
+    /// there. After this call every non-empty node's bounds are tight, the minimum and maximum
+    /// of its items in each dimension, until the next removal loosens them again. The items and
+    /// the splits are left alone, so the [`generation`](#method.generation) does not change.
+    /// A tree with [`declared_bounds`](#method.declared_bounds) is left alone too, as its
+    /// nodes cover their part of the declared box by design.
+    pub fn recompute_bounds(&mut self) {
+        if self.split_rules.declared.is_some() {
+            return;
+        }
+        self.recompute_bounds_in();
+    }
+
+    fn recompute_bounds_in(&mut self) {
+        let (mut min, mut max) = ([A::infinity(); D], [A::neg_infinity(); D]);
+        match &mut self.node {
+            Node::Stem { left, right, .. } => {
+                left.recompute_bounds_in();
+                right.recompute_bounds_in();
+                for dim in 0..D {
+                    min[dim] = left.min_bounds[dim].min(right.min_bounds[dim]);
+                    max[dim] = left.max_bounds[dim].max(right.max_bounds[dim]);
+                }
+            }
+            Node::Leaf { points, .. } => {
+                for point in points.iter() {
+                    for dim in 0..D {
+                        min[dim] = min[dim].min(point[dim]);
+                        max[dim] = max[dim].max(point[dim]);
+                    }
+                }
+            }
+        }
+        self.min_bounds = min;
+        self.max_bounds = max;
+    }
+
+    /// The tight bounds of the items below the node if the bounds of it and of every node
+    /// below it are tight, `None` otherwise
+    #[cfg(test)]
+    fn tight_bounds(&self) -> Option<([A; D], [A; D])> {
+        let (mut min, mut max) = ([A::infinity(); D], [A::neg_infinity(); D]);
+        match &self.node {
+            Node::Stem { left, right, .. } => {
+                let (left, right) = (left.tight_bounds()?, right.tight_bounds()?);
+                for dim in 0..D {
+                    min[dim] = left.0[dim].min(right.0[dim]);
+                    max[dim] = left.1[dim].max(right.1[dim]);
+                }
+            }
+            Node::Leaf { points, .. } => {
+                for point in points {
+                    for dim in 0..D {
+                        min[dim] = min[dim].min(point[dim]);
+                        max[dim] = max[dim].max(point[dim]);
+                    }
+                }
+            }
+        }
+        let tight = self.size == 0 || (min == self.min_bounds && max == self.max_bounds);
+        if tight {
+            Some((min, max))
+        } else {
+            None
+        }
+    }
+
+    /// The mean of all stored points, `None` if the tree is empty
+    pub fn centroid(&self) -> Option<[A; D]> {
+        self.moments().map(|(mean, _)| mean)
+    }
+
+    /// The population variance of the stored points in every dimension, `None` if the tree is
+    /// empty
+    pub fn variance(&self) -> Option<[A; D]> {
+        self.moments().map(|(_, variance)| variance)
+    }
+
+    /// Mean and variance of the stored points, computed in a single pass with Welford's method
+    fn moments(&self) -> Option<([A; D], [A; D])> {
+        if self.size == 0 {
+            return None;
+        }
+        let mut count = A::zero();
+        let mut mean = [A::zero(); D];
+        let mut m2 = [A::zero(); D];
+        for (point, _) in self.iter() {
+            count = count + A::one();
+            for dim in 0..D {
+                let delta = point[dim] - mean[dim];
+                mean[dim] = mean[dim] + delta / count;
+                m2[dim] = m2[dim] + delta * (point[dim] - mean[dim]);
+            }
+        }
+        for v in m2.iter_mut() {
+            *v = *v / count;
+        }
+        Some((mean, m2))
+    }
+
+    /// The bounding box `(min, max)` of the tree, `None` if the tree is empty. The box contains
+    /// every stored point, but is not shrunk when items are removed unless the tree becomes
+    /// empty.
+    pub fn extent(&self) -> Option<([A; D], [A; D])> {
+        if self.size == 0 {
+            return None;
+        }
+        Some((self.min_bounds, self.max_bounds))
+    }
+
+    /// Whether `point` lies inside the [`extent`](#method.extent) of the tree, bounds
+    /// included, `false` if the tree is empty
+    pub fn contains_in_bounds(&self, point: &[A; D]) -> bool {
+        self.size > 0
+            && (0..D).all(|d| self.min_bounds[d] <= point[d] && point[d] <= self.max_bounds[d])
+    }
+
+    /// The distance from `point` to the nearest point of the [`extent`](#method.extent) of the
+    /// tree, zero inside it and infinity if the tree is empty, as given by
+    /// [`distance_to_space_const`]
+    pub fn distance_to_bounds<F>(&self, point: &[A; D], distance: &F) -> A
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        if self.size == 0 {
+            return A::infinity();
+        }
+        distance_to_space_const(point, &self.min_bounds, &self.max_bounds, distance)
+    }
+
+    /// The centroid, size and bounds of every non-empty leaf, lower sides of splits first
+    pub fn leaf_summaries(&self) -> Vec<LeafSummary<A, D>> {
+        let mut summaries = vec![];
+        let mut pending = vec![self];
+        while let Some(node) = pending.pop() {
+            if let Some([left, right]) = node.children() {
+                pending.push(right);
+                pending.push(left);
+                continue;
+            }
+            if let (Some(mut centroid), Some((min, max))) = (node.centroid(), node.extent()) {
+                // keep rounding from moving the mean out of the box
+                for dim in 0..D {
+                    centroid[dim] = centroid[dim].max(min[dim]).min(max[dim]);
+                }
+                summaries.push(LeafSummary {
+                    centroid,
+                    count: node.size,
+                    bounds: (min, max),
+                });
+            }
+        }
+        summaries
+    }
+
+    /// The `k` nearest other leaf centroids to the centroid of every leaf, by squared euclidean
+    /// distance. Both the outer list and the indices refer to the order of
+    /// [`leaf_summaries`](#method.leaf_summaries).
+    pub fn leaf_knn(&self, k: usize) -> Vec<Vec<(A, usize)>> {
+        let summaries = self.leaf_summaries();
+        let mut centroids = OwnedKdTree::with_capacity(self.capacity);
+        for (i, summary) in summaries.iter().enumerate() {
+            // clamped into finite bounds, so the centroids are finite
+            centroids.add_unchecked(summary.centroid, i, SplitRules::default(), 0);
+        }
+        let distance = |a: &[A; D], b: &[A; D]| squared_euclidean(a, b);
+        summaries
+            .iter()
+            .enumerate()
+            .map(|(i, summary)| {
+                let found = centroids.nearest(&summary.centroid, k + 1, &distance);
+                found
+                    .unwrap()
+                    .into_iter()
+                    .filter(|&(_, &j)| j != i)
+                    .take(k)
+                    .map(|(d, &j)| (d, j))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// A read-only handle to the root node, to inspect the structure of the tree
+    pub fn root(&self) -> NodeRef<'_, A, T, D> {
+        NodeRef { node: self }
+    }
+
+    /// A mutable handle to the root node, to steer the structure of the tree
+    pub fn root_mut(&mut self) -> NodeMut<'_, A, T, D> {
+        NodeMut {
+            split_rules: self.split_rules,
+            depth: 0,
+            node: self,
+        }
+    }
+
+    /// Consume the tree and apply `f` to every stored item, keeping the structure of the tree
+    /// (nodes, bounds and split values) identical
+    pub fn map<U: PartialEq, F: FnMut(&[A; D], T) -> U>(self, mut f: F) -> OwnedKdTree<A, U, D> {
+        self.map_with(&mut f)
+    }
+
+    fn map_with<U: PartialEq, F: FnMut(&[A; D], T) -> U>(self, f: &mut F) -> OwnedKdTree<A, U, D> {
+        let node = match self.node {
+            Node::Stem {
+                split_dimension,
+                split_value,
+                left,
+                right,
+            } => Node::Stem {
+                split_dimension,
+                split_value,
+                left: Box::new(left.map_with(f)),
+                right: Box::new(right.map_with(f)),
+            },
+            Node::Leaf { points, bucket } => {
+                let bucket = points.iter().zip(bucket).map(|(p, d)| f(p, d)).collect();
+                Node::Leaf { points, bucket }
+            }
+        };
+        OwnedKdTree {
+            capacity: self.capacity,
+            size: self.size,
+            max_items: self.max_items,
+            split_rules: self.split_rules,
+            generation: self.generation,
+            min_bounds: self.min_bounds,
+            max_bounds: self.max_bounds,
+            node,
+        }
+    }
+
+    /// Like [`map`](#method.map), but borrows the tree and copies the points into the new tree
+    pub fn map_ref<U: PartialEq, F: FnMut(&[A; D], &T) -> U>(
+        &self,
+        mut f: F,
+    ) -> OwnedKdTree<A, U, D> {
+        self.map_ref_with(&mut f)
+    }
+
+    fn map_ref_with<U: PartialEq, F: FnMut(&[A; D], &T) -> U>(
+        &self,
+        f: &mut F,
+    ) -> OwnedKdTree<A, U, D> {
+        let node = match &self.node {
+            Node::Stem {
+                split_dimension,
+                split_value,
+                left,
+                right,
+            } => Node::Stem {
+                split_dimension: *split_dimension,
+                split_value: *split_value,
+                left: Box::new(left.map_ref_with(f)),
+                right: Box::new(right.map_ref_with(f)),
+            },
+            Node::Leaf { points, bucket } => Node::Leaf {
+                points: points.clone(),
+                bucket: points.iter().zip(bucket).map(|(p, d)| f(p, d)).collect(),
+            },
+        };
+        OwnedKdTree {
+            capacity: self.capacity,
+            size: self.size,
+            max_items: self.max_items,
+            split_rules: self.split_rules,
+            generation: self.generation,
+            min_bounds: self.min_bounds,
+            max_bounds: self.max_bounds,
+            node,
+        }
+    }
+
+    /// The `num` items nearest to `point`, nearest first. Items at equal distances are kept and
+    /// returned in lexicographic order of their points, so the result does not depend on the
+    /// order the items were added in. The order of items stored at the same point is
+    /// unspecified. [`within`](#method.within) and [`iter_nearest`](#method.iter_nearest) order
+    /// ties the same way.
+    ///
+    /// If `distance` returns NaN for an item, the item is never returned and does not tighten the
+    /// search. If it returns NaN for the closest point of a node's bounding box, the node cannot
+    /// be ruled out and is searched.
+    ///
+    /// Nodes are ruled out by `distance` to the closest point of their bounding box, which is
+    /// only right if `distance` never grows as a point moves closer to another along one axis,
+    /// as for any weighted Minkowski distance. For other distances, use
+    /// [`nearest_metric`](#method.nearest_metric) with a [`Metric`] bounding the distance to a
+    /// box correctly.
+    pub fn nearest<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let nearest = self.nearest_neighbours(point, num, distance)?;
+        Ok(search::collect(nearest.into_iter().map(Into::into)))
+    }
+
+    /// Like [`nearest`](#method.nearest), measuring with `metric` and ruling out nodes by its
+    /// [`point_to_box`](Metric::point_to_box) bound
+    pub fn nearest_metric<M>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        metric: &M,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        M: Metric<A, D> + ?Sized,
+    {
+        let () = self.check_point(point)?;
+        let (nearest, _) = search::nearest(
+            self,
+            point,
+            num,
+            metric,
+            usize::MAX,
+            usize::MAX,
+            &mut QueryStats::default(),
+        );
+        Ok(nearest.into_iter().map(Into::into).collect())
+    }
+
+    /// [`nearest`](#method.nearest) keeping the points of the items
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn nearest_neighbours<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &F,
+    ) -> Result<Vec<Neighbour<'_, A, &T, D>>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let (nearest, _) = self.nearest_search(point, num, distance, usize::MAX, usize::MAX)?;
+        Ok(nearest)
+    }
+
+    /// Like [`nearest`](#method.nearest) with `point` being a stored item, but leaving out that
+    /// item: skips at most one item stored at `point` whose data equals `self_item`, while other
+    /// items at distance zero, including further items equal to it, are still returned and
+    /// counted toward `num`.
+    pub fn nearest_excluding_self<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &F,
+        self_item: &T,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let mut nearest = self.nearest_neighbours(point, num.saturating_add(1), distance)?;
+        if let Some(i) = nearest
+            .iter()
+            .position(|n| n.point == point && n.element == self_item)
+        {
+            nearest.remove(i);
+        }
+        nearest.truncate(num);
+        Ok(nearest.into_iter().map(Into::into).collect())
+    }
+
+    /// [`nearest`](#method.nearest) with a [`DynDistance`]
+    pub fn nearest_dyn(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &DynDistance<A, D>,
+    ) -> Result<Vec<(A, &T)>, ErrorKind> {
+        self.nearest(point, num, distance)
+    }
+
+    /// Like [`nearest`](#method.nearest), but stops expanding nodes once `max_nodes` nodes have
+    /// been visited, returning the best items found so far. The returned flag is `true` when the
+    /// search completed and the items are exactly the nearest ones, and `false` if it was cut
+    /// short by the budget.
+    #[allow(clippy::type_complexity)]
+    pub fn nearest_budgeted<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &F,
+        max_nodes: usize,
+    ) -> Result<(Vec<(A, &T)>, bool), ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let (nearest, complete) =
+            self.nearest_search(point, num, distance, max_nodes, usize::MAX)?;
+        Ok((nearest.into_iter().map(Into::into).collect(), complete))
+    }
+
+    /// Approximate nearest neighbours using best-bin-first search: leaves are checked strictly in
+    /// the order of the distance from `point` to their bounds, and the search stops after
+    /// `max_leaf_checks` leaves. In high dimensions this finds the exact nearest neighbours with
+    /// high probability while checking only a small fraction of the leaves.
+    pub fn nearest_bbf<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        max_leaf_checks: usize,
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let (nearest, _) =
+            self.nearest_search(point, num, distance, usize::MAX, max_leaf_checks)?;
+        Ok(nearest.into_iter().map(Into::into).collect())
+    }
+
+    /// The `num` items nearest to `point` among those in the half-space of points `x` with
+    /// `dot(normal, x - offset_point) >= 0`, the plane included, nearest first. A node is ruled
+    /// out without being visited when the corner of its bounds furthest along `normal` lies
+    /// outside the half-space. A zero `normal` is rejected with `ErrorKind::ZeroVector`.
+    pub fn nearest_in_halfspace<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        normal: &[A; D],
+        offset_point: &[A; D],
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let () = self.check_point(point)?;
+        let () = self.check_point(normal)?;
+        let () = self.check_point(offset_point)?;
+        if normal.iter().all(|x| x.is_zero()) {
+            return Err(ErrorKind::ZeroVector);
+        }
+        let metric = HalfSpace {
+            metric: &ClosureMetric(distance),
+            normal,
+            offset_point,
+        };
+        // the half-space metric puts everything outside at infinity, beyond the largest radius
+        let nearest = search::within(
+            self,
+            point,
+            A::max_value(),
+            num,
+            &metric,
+            &mut QueryStats::default(),
+        );
+        Ok(nearest.into_iter().map(Into::into).collect())
+    }
+
+    /// The `num` items most similar to `query` by cosine similarity, most similar first, with
+    /// the similarity in `[-1, 1]` returned alongside each item.
+    ///
+    /// **This is only correct if every item was added with
+    /// [`add_normalized`](#method.add_normalized)** (or was already of unit length): the search
+    /// relies on squared euclidean distance between unit vectors being `2 - 2 * cos`, which
+    /// does not hold for vectors of other lengths.
+    pub fn nearest_cosine(&self, query: &[A; D], num: usize) -> Result<Vec<(A, &T)>, ErrorKind> {
+        let query = normalized(*query)?;
+        let two = A::one() + A::one();
+        let nearest = self.nearest(&query, num, &|a: &[A; D], b: &[A; D]| {
+            squared_euclidean(a, b)
+        })?;
+        Ok(nearest
+            .into_iter()
+            .map(|(d, item)| (A::one() - d / two, item))
+            .collect())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn nearest_search<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &F,
+        max_nodes: usize,
+        max_leaves: usize,
+    ) -> Result<(Vec<Neighbour<'_, A, &T, D>>, bool), ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let () = self.check_point(point)?;
+        Ok(search::nearest(
+            self,
+            point,
+            num,
+            &ClosureMetric(distance),
+            max_nodes,
+            max_leaves,
+            &mut QueryStats::default(),
+        ))
+    }
+
+    /// Like [`nearest`](#method.nearest), also counting the work the search did
+    #[allow(clippy::type_complexity)]
+    pub fn nearest_with_stats<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &F,
+    ) -> Result<(Vec<(A, &T)>, QueryStats), ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let () = self.check_point(point)?;
+        let mut stats = QueryStats::default();
+        let (nearest, _) = search::nearest(
+            self,
+            point,
+            num,
+            &ClosureMetric(distance),
+            usize::MAX,
+            usize::MAX,
+            &mut stats,
+        );
+        Ok((nearest.into_iter().map(Into::into).collect(), stats))
+    }
+
+    /// The leaf capacity among `candidates` for which a tree of the same items, built at once
+    /// with [`from_points`](#method.from_points), answers the `k` nearest neighbour queries at
+    /// `sample_queries` with the least work. The work of a query is the number of nodes it
+    /// visits plus the number of points it measures the distance to, as counted by
+    /// [`nearest_with_stats`](#method.nearest_with_stats); ties go to the earlier candidate.
+    /// Without candidates the tree's own capacity is returned. Each candidate rebuilds the tree,
+    /// so the sample should be small but typical of the queries the tree will answer.
+    pub fn tune_leaf_capacity<F>(
+        &self,
+        sample_queries: &[[A; D]],
+        k: usize,
+        distance: &F,
+        candidates: &[usize],
+    ) -> Result<usize, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        for query in sample_queries {
+            let () = self.check_point(query)?;
+        }
+        let items: Vec<([A; D], &T)> = self.iter().map(|(p, d)| (*p, d)).collect();
+        let mut best = (usize::MAX, self.capacity);
+        for &capacity in candidates {
+            let tree = OwnedKdTree::from_points(items.clone(), capacity)?;
+            let mut work = 0;
+            for query in sample_queries {
+                let (_, stats) = tree.nearest_with_stats(query, k, distance)?;
+                work += stats.nodes_visited + stats.points_visited;
+            }
+            if work < best.0 {
+                best = (work, capacity);
+            }
+        }
+        Ok(best.1)
+    }
+
+    /// The items within `radius` of `point`, nearest first. Nodes are ruled out as in
+    /// [`nearest`](#method.nearest), so `distance` must not grow as a point moves closer to
+    /// another along one axis; see [`within_metric`](#method.within_metric) for other distances.
+    ///
+    /// A radius of zero finds the items at `point` and an infinite one every item. A negative or
+    /// NaN radius returns `ErrorKind::InvalidRadius`, as does every other radius query.
+    pub fn within<F>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let within = self.within_neighbours(point, radius, self.size, distance)?;
+        Ok(search::collect(within.into_iter().map(Into::into)))
+    }
+
+    /// Like [`within`](#method.within), measuring with `metric` and ruling out nodes by its
+    /// [`point_to_box`](Metric::point_to_box) bound
+    pub fn within_metric<M>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        metric: &M,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        M: Metric<A, D> + ?Sized,
+    {
+        let () = self.check_point(point)?;
+        let () = search::check_radius(radius)?;
+        let within = search::within(
+            self,
+            point,
+            radius,
+            self.size,
+            metric,
+            &mut QueryStats::default(),
+        );
+        Ok(within.into_iter().map(Into::into).collect())
+    }
+
+    /// The items within `radius` of `point` grouped by their points, each group holding the
+    /// distance, the point and the items stored at it in [`iter`](#method.iter) order. Groups
+    /// are ordered as [`within`](#method.within) orders items, so flattening them gives the
+    /// items `within` returns, up to the order of items at the same point. Items at the same
+    /// point always share a leaf, where they are grouped by comparing points instead of each
+    /// going through the ordering of results, which makes a query around a point stored many
+    /// times much cheaper.
+    #[allow(clippy::type_complexity)]
+    pub fn within_grouped<F>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        distance: &F,
+    ) -> Result<Vec<(A, [A; D], Vec<&T>)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let () = self.check_point(point)?;
+        let () = search::check_radius(radius)?;
+        let mut groups: Vec<Neighbour<A, Vec<&T>, D>> = vec![];
+        let mut pending = vec![self];
+        while let Some(node) = pending.pop() {
+            if node.size == 0
+                || distance_to_space_const(point, &node.min_bounds, &node.max_bounds, distance)
+                    > radius
+            {
+                continue;
+            }
+            let (points, bucket) = match &node.node {
+                Node::Stem { left, right, .. } => {
+                    pending.extend([&**right, &**left]);
+                    continue;
+                }
+                Node::Leaf { points, bucket } => (points, bucket),
+            };
+            // only the groups of this leaf can hold its points, the latest most likely
+            let leaf_groups = groups.len();
+            for (p, d) in points.iter().zip(bucket) {
+                let dist = distance(point, p);
+                if dist <= radius {
+                    match groups[leaf_groups..]
+                        .iter_mut()
+                        .rev()
+                        .find(|g| g.point == p)
+                    {
+                        Some(group) => group.element.push(d),
+                        None => groups.push(Neighbour {
+                            distance: dist,
+                            point: p,
+                            element: vec![d],
+                        }),
+                    }
+                }
+            }
+        }
+        groups.sort_unstable();
+        Ok(groups
+            .into_iter()
+            .map(|g| (g.distance, *g.point, g.element))
+            .collect())
+    }
+
+    /// Whether any item lies within `radius` of `point`, the same as
+    /// `!within(point, radius, distance)?.is_empty()` but without collecting the items: the
+    /// search returns as soon as it finds one, and skips every node farther than `radius` from
+    /// the start, so it typically looks into one or two leaves.
+    pub fn any_within<F>(&self, point: &[A; D], radius: A, distance: &F) -> Result<bool, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let () = self.check_point(point)?;
+        let () = search::check_radius(radius)?;
+        Ok(search::any_within(
+            self,
+            point,
+            radius,
+            &ClosureMetric(distance),
+            &mut QueryStats::default(),
+        ))
+    }
+
+    /// Like [`within`](#method.within), but returns only the `max_results` nearest items within
+    /// `radius`. Once that many items are found, the search is pruned to items nearer than the
+    /// farthest of them, as in [`nearest`](#method.nearest), rather than collecting everything
+    /// within `radius`.
+    pub fn within_capped<F>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        max_results: usize,
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        Ok(self
+            .within_neighbours(point, radius, max_results, distance)?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// Like [`within_capped`](#method.within_capped), for radii which may come from untrusted
+    /// queries: no more than `max_results` items are ever collected, and the outcome says
+    /// whether that was all of them. When it was not, the items within `radius` are also
+    /// counted, without collecting them: nodes entirely within `radius` add their size as a
+    /// whole, so only the leaves crossed by the edge of the radius are visited. The count
+    /// relies on the farthest corner of a box being at least as far as every point in it, as
+    /// for all the distances in [`distance`](crate::distance).
+    pub fn within_limited<F>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        distance: &F,
+        max_results: usize,
+    ) -> Result<WithinOutcome<A, &T>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let results = self.within_capped(point, radius, max_results, distance)?;
+        if results.len() < max_results {
+            return Ok(WithinOutcome::Complete(results));
+        }
+        let found = self.count_within(point, radius, distance);
+        if found <= results.len() {
+            return Ok(WithinOutcome::Complete(results));
+        }
+        Ok(WithinOutcome::Truncated {
+            results,
+            found_at_least: found,
+        })
+    }
+
+    /// The number of items within `radius` of `point`
+    fn count_within<F>(&self, point: &[A; D], radius: A, distance: &F) -> usize
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let (min, max) = (&self.min_bounds, &self.max_bounds);
+        if self.size == 0 || distance_to_space_const(point, min, max, distance) > radius {
+            return 0;
+        }
+        if distance_to_far_corner_const(point, min, max, distance) <= radius {
+            return self.size;
+        }
+        match &self.node {
+            Node::Stem { left, right, .. } => {
+                left.count_within(point, radius, distance)
+                    + right.count_within(point, radius, distance)
+            }
+            Node::Leaf { points, .. } => points
+                .iter()
+                .filter(|p| distance(point, p) <= radius)
+                .count(),
+        }
+    }
+
+    /// [`within_capped`](#method.within_capped) keeping the points of the items
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn within_neighbours<F>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        max_results: usize,
+        distance: &F,
+    ) -> Result<Vec<Neighbour<'_, A, &T, D>>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let () = self.check_point(point)?;
+        let () = search::check_radius(radius)?;
+        Ok(search::within(
+            self,
+            point,
+            radius,
+            max_results,
+            &ClosureMetric(distance),
+            &mut QueryStats::default(),
+        ))
+    }
+
+    /// Like [`within`](#method.within), but returning the points of the items rather than
+    /// references to them, so that the tree can be modified while going through the results,
+    /// for instance with [`remove_at`](#method.remove_at) or [`get_mut`](#method.get_mut). Every
+    /// item is listed, so a point holding several items is listed as often.
+    pub fn within_keys<F>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        distance: &F,
+    ) -> Result<Vec<(A, [A; D])>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        Ok(self
+            .within_neighbours(point, radius, self.size, distance)?
+            .into_iter()
+            .map(|n| (n.distance, *n.point))
+            .collect())
+    }
+
+    /// [`within`](#method.within) with a [`DynDistance`]
+    pub fn within_dyn(
+        &self,
+        point: &[A; D],
+        radius: A,
+        distance: &DynDistance<A, D>,
+    ) -> Result<Vec<(A, &T)>, ErrorKind> {
+        self.within(point, radius, distance)
+    }
+
+    /// Folds every item within `radius` of `point` into `init` without collecting them. Items are
+    /// passed to `f` with their distance in tree order rather than nearest first.
+    pub fn fold_within<B, F, G>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        distance: &F,
+        init: B,
+        mut f: G,
+    ) -> Result<B, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+        G: FnMut(B, A, &T) -> B,
+    {
+        let () = self.check_point(point)?;
+        let () = search::check_radius(radius)?;
+        Ok(self.fold_within_in(point, radius, distance, init, &mut f))
+    }
+
+    fn fold_within_in<B, F, G>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        distance: &F,
+        mut acc: B,
+        f: &mut G,
+    ) -> B
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+        G: FnMut(B, A, &T) -> B,
+    {
+        if self.size == 0
+            || distance_to_space_const(point, &self.min_bounds, &self.max_bounds, distance) > radius
+        {
+            return acc;
+        }
+        let (points, bucket) = match &self.node {
+            Node::Stem { left, right, .. } => {
+                let acc = left.fold_within_in(point, radius, distance, acc, f);
+                return right.fold_within_in(point, radius, distance, acc, f);
+            }
+            Node::Leaf { points, bucket } => (points, bucket),
+        };
+        for (p, d) in points.iter().zip(bucket) {
+            let dist = distance(point, p);
+            if dist <= radius {
+                acc = f(acc, dist, d);
+            }
+        }
+        acc
+    }
+
+    /// Items within each of several `radii` of `point` in a single traversal. The radii must not
+    /// be negative and be sorted in ascending order, otherwise `ErrorKind::InvalidRadius` is
+    /// returned. The results are cumulative: the `i`th list holds every item within `radii[i]`,
+    /// including those within smaller radii, nearest first.
+    pub fn within_multi<F>(
+        &self,
+        point: &[A; D],
+        radii: &[A],
+        distance: &F,
+    ) -> Result<Vec<Vec<(A, &T)>>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        if !radii.windows(2).all(|w| w[0] <= w[1]) {
+            return Err(ErrorKind::InvalidRadius);
+        }
+        for &radius in radii {
+            let () = search::check_radius(radius)?;
+        }
+        let within = match radii.last() {
+            Some(&largest) => self.within(point, largest, distance)?,
+            None => return Ok(vec![]),
+        };
+        Ok(radii
+            .iter()
+            .map(|&r| {
+                let count = within.partition_point(|&(d, _)| d <= r);
+                within[..count].to_vec()
+            })
+            .collect())
+    }
+
+    /// Items whose points lie within the box `[min, max]`, bounds included.
+    pub fn within_box(&self, min: &[A; D], max: &[A; D]) -> Result<Vec<&T>, ErrorKind> {
+        let () = self.check_point(min)?;
+        let () = self.check_point(max)?;
+        let mut within = vec![];
+        search::within_box(self, min, max, &mut within);
+        Ok(within)
+    }
+
+    /// Like [`within_box`](#method.within_box), but the payloads are mutable. Bumps the
+    /// [`generation`](#method.generation) whether or not an item is found.
+    pub fn within_box_mut(&mut self, min: &[A; D], max: &[A; D]) -> Result<Vec<&mut T>, ErrorKind> {
+        let () = self.check_point(min)?;
+        let () = self.check_point(max)?;
+        self.generation += 1;
+        let mut within = vec![];
+        self.within_box_mut_in(min, max, &mut within);
+        Ok(within)
+    }
+
+    fn within_box_mut_in<'b>(
+        &'b mut self,
+        min: &[A; D],
+        max: &[A; D],
+        within: &mut Vec<&'b mut T>,
+    ) {
+        if !self.overlaps_box(min, max) {
+            return;
+        }
+        match &mut self.node {
+            Node::Stem { left, right, .. } => {
+                left.within_box_mut_in(min, max, within);
+                right.within_box_mut_in(min, max, within);
+            }
+            Node::Leaf { points, bucket } => {
+                for (p, data) in points.iter().zip(bucket.iter_mut()) {
+                    if in_box(p, min, max) {
+                        within.push(data);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether the bounds of a non-empty node meet the box `[min, max]`
+    fn overlaps_box(&self, min: &[A; D], max: &[A; D]) -> bool {
+        let bounds = self.min_bounds.iter().zip(&self.max_bounds);
+        self.size > 0
+            && bounds
+                .zip(min.iter().zip(max))
+                .all(|((node_min, node_max), (min, max))| node_max >= min && node_min <= max)
+    }
+
+    /// Items inside the convex polytope given as the intersection of half-spaces. Each plane is
+    /// a `(normal, offset)` pair and a point is inside when `dot(normal, point) <= offset` holds
+    /// for every plane, so no planes select everything.
+    pub fn within_convex(&self, planes: &[([A; D], A)]) -> Vec<&T> {
+        let mut within = vec![];
+        self.within_convex_in(planes, &mut within);
+        within
+    }
+
+    fn within_convex_in<'b>(&'b self, planes: &[([A; D], A)], within: &mut Vec<&'b T>) {
+        if self.size == 0 {
+            return;
+        }
+        let mut contained = true;
+        for (normal, offset) in planes {
+            // the smallest and largest values of dot(normal, p) over the bounding box
+            let (mut lo, mut hi) = (A::zero(), A::zero());
+            let bounds = self.min_bounds.iter().zip(&self.max_bounds);
+            for (&n, (&min, &max)) in normal.iter().zip(bounds) {
+                let (a, b) = (n * min, n * max);
+                lo = lo + a.min(b);
+                hi = hi + a.max(b);
+            }
+            if lo > *offset {
+                return;
+            }
+            contained &= hi <= *offset;
+        }
+        if contained {
+            within.extend(self.iter().map(|(_, d)| d));
+            return;
+        }
+        match &self.node {
+            Node::Stem { left, right, .. } => {
+                left.within_convex_in(planes, within);
+                right.within_convex_in(planes, within);
+            }
+            Node::Leaf { points, bucket } => {
+                for (p, d) in points.iter().zip(bucket) {
+                    if planes.iter().all(|(n, offset)| dot(n, p) <= *offset) {
+                        within.push(d);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Items within Euclidean distance `radius` of `apex` whose direction from `apex` is within
+    /// `angle` radians of `dir`, nearest first, together with their Euclidean distances. An
+    /// `angle` of at least π selects the whole ball. A zero `dir` is rejected with
+    /// `ErrorKind::ZeroVector`, a negative or NaN `angle` with `ErrorKind::InvalidAngle` and
+    /// a negative or NaN `radius` with `ErrorKind::InvalidRadius`.
+    pub fn within_cone(
+        &self,
+        apex: &[A; D],
+        dir: &[A; D],
+        angle: A,
+        radius: A,
+    ) -> Result<Vec<(A, &T)>, ErrorKind> {
+        let () = self.check_point(apex)?;
+        let dir = normalized(*dir)?;
+        if angle.is_nan() || angle < A::zero() {
+            return Err(ErrorKind::InvalidAngle);
+        }
+        let () = search::check_radius(radius)?;
+        let cone = Cone {
+            apex,
+            dir: &dir,
+            angle,
+            radius,
+        };
+        let mut within = vec![];
+        self.within_cone_in(&cone, &mut within);
+        within.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(within)
+    }
+
+    fn within_cone_in<'b>(&'b self, cone: &Cone<A, D>, within: &mut Vec<(A, &'b T)>) {
+        if self.size == 0 || !cone.may_intersect(&self.min_bounds, &self.max_bounds) {
+            return;
+        }
+        let (points, bucket) = match &self.node {
+            Node::Stem { left, right, .. } => {
+                left.within_cone_in(cone, within);
+                right.within_cone_in(cone, within);
+                return;
+            }
+            Node::Leaf { points, bucket } => (points, bucket),
+        };
+        for (p, d) in points.iter().zip(bucket) {
+            if let Some(distance) = cone.contains(p) {
+                within.push((distance, d));
+            }
+        }
+    }
+
+    /// Visits the tree depth first, descending into a node only if `prune` returns `true` for its
+    /// bounds, and calls `leaf` with every item of the leaves reached. The traversal stops as soon
+    /// as `leaf` breaks, which is then returned.
+    ///
+    /// `prune` must be conservative: a node's bounds enclose all of its items, so it may only
+    /// return `false` if no point within the bounds is wanted. Items of the leaves which are
+    /// reached are passed to `leaf` unfiltered.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kdtree::OwnedKdTree;
+    /// use std::ops::ControlFlow;
+    ///
+    /// let items = (0..100).map(|i| ([(i % 10) as f64, (i / 10) as f64], i)).collect();
+    /// let tree: OwnedKdTree<f64, usize, 2> = OwnedKdTree::from_points(items, 4).unwrap();
+    ///
+    /// let (min, max) = ([2.5, 1.0], [6.0, 4.5]);
+    /// let mut within = vec![];
+    /// tree.traverse(
+    ///     |lo, hi| (0..2).all(|i| hi[i] >= min[i] && lo[i] <= max[i]),
+    ///     |p, &d| {
+    ///         if (0..2).all(|i| min[i] <= p[i] && p[i] <= max[i]) {
+    ///             within.push(d);
+    ///         }
+    ///         ControlFlow::Continue(())
+    ///     },
+    /// );
+    ///
+    /// let native: Vec<usize> = tree.within_box(&min, &max).unwrap().into_iter().copied().collect();
+    /// assert_eq!(within, native);
+    /// assert_eq!(within.len(), 16);
+    /// ```
+    pub fn traverse<'a, P, L>(&'a self, mut prune: P, mut leaf: L) -> ControlFlow<()>
+    where
+        P: FnMut(&[A; D], &[A; D]) -> bool,
+        L: FnMut(&'a [A; D], &'a T) -> ControlFlow<()>,
+    {
+        self.traverse_in(&mut prune, &mut leaf)
+    }
+
+    fn traverse_in<'a, P, L>(&'a self, prune: &mut P, leaf: &mut L) -> ControlFlow<()>
+    where
+        P: FnMut(&[A; D], &[A; D]) -> bool,
+        L: FnMut(&'a [A; D], &'a T) -> ControlFlow<()>,
+    {
+        if self.size == 0 || !prune(&self.min_bounds, &self.max_bounds) {
+            return ControlFlow::Continue(());
+        }
+        let (points, bucket) = match &self.node {
+            Node::Stem { left, right, .. } => {
+                left.traverse_in(prune, leaf)?;
+                return right.traverse_in(prune, leaf);
+            }
+            Node::Leaf { points, bucket } => (points, bucket),
+        };
+        for (p, d) in points.iter().zip(bucket) {
+            leaf(p, d)?;
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// The `num` nearest items to each of `queries`, exactly as returned by calling
+    /// [`nearest`](#method.nearest) for every query. Queries are sorted in Morton order and
+    /// searched in small groups which share one best-first traversal, pruning nodes against the
+    /// bounding box of the whole group, so batches of spatially coherent queries compute far
+    /// fewer node distances than independent searches.
+    pub fn nearest_multi<F>(
+        &self,
+        queries: &[[A; D]],
+        num: usize,
+        distance: &F,
+    ) -> Result<Vec<Vec<(A, &T)>>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let mut min = [A::infinity(); D];
+        let mut max = [A::neg_infinity(); D];
+        for query in queries {
+            let () = self.check_point(query)?;
+            for dim in 0..D {
+                min[dim] = min[dim].min(query[dim]);
+                max[dim] = max[dim].max(query[dim]);
+            }
+        }
+        let bits = curve::bits_per_dimension(D);
+        let mut order: Vec<usize> = (0..queries.len()).collect();
+        order.sort_by_cached_key(|&i| curve::morton_index(&queries[i], &min, &max, bits));
+
+        let mut results = vec![vec![]; queries.len()];
+        for group in order.chunks(MULTI_QUERY_GROUP) {
+            let nearest = self.nearest_group(queries, group, num, distance);
+            for (&i, nearest) in group.iter().zip(nearest) {
+                results[i] = nearest;
+            }
+        }
+        Ok(results)
+    }
+
+    /// The nearest item within `max_dist` of each of `sources`, as in the matching step of
+    /// iterative closest point. The results are aligned with `sources`: the `i`th is `None` if
+    /// no item is within `max_dist` of `sources[i]`, and `(i, distance, item)` otherwise.
+    #[allow(clippy::type_complexity)]
+    pub fn correspondences<F>(
+        &self,
+        sources: &[[A; D]],
+        max_dist: A,
+        distance: &F,
+    ) -> Result<Vec<Option<(usize, A, &T)>>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        sources
+            .iter()
+            .enumerate()
+            .map(|(i, source)| self.correspondence(i, source, max_dist, distance))
+            .collect()
+    }
+
+    pub(crate) fn correspondence<F>(
+        &self,
+        index: usize,
+        source: &[A; D],
+        max_dist: A,
+        distance: &F,
+    ) -> Result<Option<(usize, A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let nearest = self.within_neighbours(source, max_dist, 1, distance)?;
+        Ok(nearest.first().map(|n| (index, n.distance, n.element)))
+    }
+
+    /// One best-first traversal answering the queries of `group`. A node is expanded while its
+    /// box distance to the bounding box of the group is within the worst bound of any query, and
+    /// a leaf is only scanned for the queries it may still improve.
+    fn nearest_group<'b, F>(
+        &'b self,
+        queries: &[[A; D]],
+        group: &[usize],
+        num: usize,
+        distance: &F,
+    ) -> Vec<Vec<(A, &'b T)>>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let num = std::cmp::min(num, self.size);
+        if num == 0 {
+            return vec![vec![]; group.len()];
+        }
+        let mut min = queries[group[0]];
+        let mut max = queries[group[0]];
+        for &i in group {
+            for dim in 0..D {
+                min[dim] = min[dim].min(queries[i][dim]);
+                max[dim] = max[dim].max(queries[i][dim]);
+            }
+        }
+        // the distance between the group box and a node box, measured between their closest
+        // corners
+        let to_space = |node: &Self| {
+            let mut a = min;
+            let mut b = min;
+            for dim in 0..D {
+                if node.max_bounds[dim] < min[dim] {
+                    b[dim] = node.max_bounds[dim];
+                } else if node.min_bounds[dim] > max[dim] {
+                    a[dim] = max[dim];
+                    b[dim] = node.min_bounds[dim];
+                }
+            }
+            let dist = distance(&a, &b);
+            if dist.is_nan() {
+                A::zero()
+            } else {
+                dist
+            }
+        };
+
+        let mut evaluated: Vec<BinaryHeap<Neighbour<A, &T, D>>> =
+            group.iter().map(|_| BinaryHeap::new()).collect();
+        let bound = |evaluated: &[BinaryHeap<Neighbour<A, &T, D>>]| {
+            evaluated.iter().fold(A::neg_infinity(), |bound, heap| {
+                if heap.len() < num {
+                    A::infinity()
+                } else {
+                    bound.max(heap.peek().unwrap().distance)
+                }
+            })
+        };
+
+        let mut pending = BinaryHeap::new();
+        pending.push(MinByDistance::new(A::zero(), self));
+        while let Some(MinByDistance(Priority {
+            distance: node_dist,
+            element: node,
+        })) = pending.pop()
+        {
+            let worst = bound(&evaluated);
+            if node_dist > worst {
+                break;
+            }
+            let (points, bucket) = match &node.node {
+                Node::Stem { left, right, .. } => {
+                    for child in [&**left, &**right] {
+                        if child.size == 0 {
+                            continue;
+                        }
+                        let child_dist = to_space(child);
+                        if child_dist <= worst {
+                            pending.push(MinByDistance::new(child_dist, child));
+                        }
+                    }
+                    continue;
+                }
+                Node::Leaf { points, bucket } => (points, bucket),
+            };
+            for (&i, heap) in group.iter().zip(evaluated.iter_mut()) {
+                let point = &queries[i];
+                if heap.len() == num
+                    && distance_to_space_const(point, &node.min_bounds, &node.max_bounds, distance)
+                        > heap.peek().unwrap().distance
+                {
+                    continue;
+                }
+                for (p, d) in points.iter().zip(bucket) {
+                    let element = Neighbour {
+                        distance: distance(point, p),
+                        point: p,
+                        element: d,
+                    };
+                    if element.distance.is_nan() {
+                        continue;
+                    }
+                    if heap.len() < num {
+                        heap.push(element);
+                    } else if element < *heap.peek().unwrap() {
+                        heap.pop();
+                        heap.push(element);
+                    }
+                }
+            }
+        }
+        evaluated
+            .into_iter()
+            .map(|heap| heap.into_sorted_vec().into_iter().map(Into::into).collect())
+            .collect()
+    }
+
+    /// Iterates over every item, nearest to `point` first. Leaves are checked lazily: taking the
+    /// next item only checks the leaves which may hold something nearer than the items already
+    /// found, and each leaf checked leaves at most one pending node per level of the tree, so
+    /// taking a few items from a large tree stays cheap.
+    pub fn iter_nearest<'a, 'b, F>(
+        &'b self,
+        point: &'a [A; D],
+        distance: &'a F,
+    ) -> Result<NearestIter<'a, 'b, A, T, F, D>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let () = self.check_point(point)?;
+        let mut pending = BinaryHeap::new();
+        let evaluated = BinaryHeap::<Reverse<Neighbour<A, &T, D>>>::new();
+        pending.push(MinByDistance::new(A::zero(), self));
+        Ok(NearestIter {
+            point,
+            pending,
+            evaluated,
+            distance,
+            yielded: 0,
+        })
+    }
+
+    /// Iterates over every item, farthest from `point` first. Items at equal distances come in
+    /// the reverse of the order [`iter_nearest`](#method.iter_nearest) yields them in. Nodes are
+    /// pruned by the distance to their farthest corner, which bounds the distance to anything
+    /// inside them for the usual metrics.
+    pub fn iter_farthest<'a, 'b, F>(
+        &'b self,
+        point: &'a [A; D],
+        distance: &'a F,
+    ) -> Result<FarthestIter<'a, 'b, A, T, F, D>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let () = self.check_point(point)?;
+        let mut pending = BinaryHeap::new();
+        if self.size > 0 {
+            pending.push(MaxByDistance::new(A::infinity(), self));
+        }
+        Ok(FarthestIter {
+            point,
+            pending,
+            evaluated: BinaryHeap::new(),
+            distance,
+        })
+    }
+
+    /// [`iter_nearest`](#method.iter_nearest) leaving out one item stored at `point` whose data
+    /// equals `self_item`, as [`nearest_excluding_self`](#method.nearest_excluding_self) does
+    pub fn iter_nearest_excluding_self<'a, 'b, F>(
+        &'b self,
+        point: &'a [A; D],
+        distance: &'a F,
+        self_item: &'a T,
+    ) -> Result<NearestExcludingSelfIter<'a, 'b, A, T, F, D>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        Ok(NearestExcludingSelfIter {
+            iter: self.iter_nearest(point, distance)?,
+            excluded: Some(self_item),
+        })
+    }
+
+    /// [`iter_nearest`](#method.iter_nearest) with a [`DynDistance`]
+    pub fn iter_nearest_dyn<'a, 'b>(
+        &'b self,
+        point: &'a [A; D],
+        distance: &'a DynDistance<'a, A, D>,
+    ) -> Result<NearestIter<'a, 'b, A, T, DynDistance<'a, A, D>, D>, ErrorKind> {
+        self.iter_nearest(point, distance)
+    }
+
+    /// Like [`iter_nearest`](#method.iter_nearest), but yields mutable references to the
+    /// payloads. Every payload is yielded at most once, so the references never alias and may
+    /// all be held at the same time. Creating the iterator bumps the
+    /// [`generation`](#method.generation).
+    pub fn iter_nearest_mut<'a, 'b, F>(
+        &'b mut self,
+        point: &'a [A; D],
+        distance: &'a F,
+    ) -> Result<NearestIterMut<'a, 'b, A, T, F, D>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let () = self.check_point(point)?;
+        self.generation += 1;
+        let mut pending = BinaryHeap::new();
+        let evaluated = BinaryHeap::<Reverse<Neighbour<A, &mut T, D>>>::new();
+        pending.push(MinByDistance::new(A::zero(), self));
+        Ok(NearestIterMut {
+            point,
+            pending,
+            evaluated,
+            distance,
+        })
+    }
+
+    /// Scale `point` to unit length and add it, as required by
+    /// [`nearest_cosine`](#method.nearest_cosine). Returns `ErrorKind::ZeroVector` if `point` has
+    /// no direction.
+    pub fn add_normalized(&mut self, point: [A; D], data: T) -> Result<(), ErrorKind> {
+        let () = self.check_point(&point)?;
+        self.add(normalized(point)?, data)
+    }
+
+    /// Add `data` at `point`. A tree which already holds its
+    /// [`max_items`](#method.max_items) returns `ErrorKind::Full` without allocating.
+    pub fn add(&mut self, point: [A; D], data: T) -> Result<(), ErrorKind> {
+        // no tree has a capacity of zero, each constructor rejects it
+        if self.is_full() {
+            return Err(ErrorKind::Full);
+        }
+        let () = self.check_point(&point)?;
+        let point = self.within_declared(point)?;
+        self.add_unchecked(point, data, self.split_rules, 0);
+        self.generation += 1;
+        Ok(())
+    }
+
+    /// Add `data` at `point` unless an item already lies within `min_dist` of it, as for
+    /// [`any_within`](#method.any_within), returning whether it was added. The check and the
+    /// insertion share a single walk down to the leaf the item belongs in, checking the other
+    /// child of each stem on the way, which makes it suitable for Poisson disk sampling. A tree
+    /// which already holds its [`max_items`](#method.max_items) returns `ErrorKind::Full`
+    /// whether or not the item would have been added, and a negative or NaN `min_dist` returns
+    /// `ErrorKind::InvalidRadius` as the radius queries do.
+    pub fn add_if_far_enough<F>(
+        &mut self,
+        point: [A; D],
+        data: T,
+        min_dist: A,
+        distance: &F,
+    ) -> Result<bool, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        if self.is_full() {
+            return Err(ErrorKind::Full);
+        }
+        let () = self.check_point(&point)?;
+        let () = search::check_radius(min_dist)?;
+        let point = self.within_declared(point)?;
+        let added = self.add_if_far_in(point, data, min_dist, distance, self.split_rules, 0);
+        if added {
+            self.generation += 1;
+        }
+        Ok(added)
+    }
+
+    /// Adds an item to the node at `depth` as [`add_unchecked`](#method.add_unchecked) does,
+    /// unless an item below the node is within `min_dist` of `point`
+    fn add_if_far_in<F>(
+        &mut self,
+        point: [A; D],
+        data: T,
+        min_dist: A,
+        distance: &F,
+        rules: SplitRules,
+        depth: usize,
+    ) -> bool
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let added = match &mut self.node {
+            Node::Stem {
+                split_dimension,
+                split_value,
+                left,
+                right,
+            } => {
+                let (near, far) = if belongs_in_left(&point, *split_dimension, *split_value) {
+                    (left, right)
+                } else {
+                    (right, left)
+                };
+                let stats = &mut QueryStats::default();
+                !search::any_within(&**far, &point, min_dist, &ClosureMetric(distance), stats)
+                    && near.add_if_far_in(point, data, min_dist, distance, rules, depth + 1)
+            }
+            Node::Leaf { points, .. } => {
+                if points.iter().any(|p| distance(&point, p) <= min_dist) {
+                    return false;
+                }
+                self.add_unchecked(point, data, rules, depth);
+                return true;
+            }
+        };
+        if added {
+            self.extend(&point);
+            self.size += 1;
+        }
+        added
+    }
+
+    /// A counter bumped by every method which adds or removes items or hands out mutable
+    /// references to their payloads, so that a cache built from the tree's contents can tell
+    /// whether they changed since. Queries leave it alone, and so do leaves splitting, whether
+    /// as items are added or through [`NodeMut::split_now`], as they only move items around.
+    /// Failed and empty operations, such as an add to a full tree or a removal which finds
+    /// nothing to remove, do not count. The exceptions are the methods handing out mutable
+    /// references to payloads, [`get_mut`](#method.get_mut),
+    /// [`within_box_mut`](#method.within_box_mut), [`iter_nearest_mut`](#method.iter_nearest_mut),
+    /// [`closest_pair_mut`](#method.closest_pair_mut) and
+    /// [`retain_in_box`](#method.retain_in_box), whose `keep` may change the payloads it keeps:
+    /// whatever they find, the tree cannot tell whether a payload was changed through them, so
+    /// every call which does not return an error counts. A clone starts at the generation of its
+    /// original.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// The most items the tree may hold, as set by [`Builder::max_items`], or `None` if there
+    /// is no limit
+    pub fn max_items(&self) -> Option<usize> {
+        self.max_items
+    }
+
+    fn is_full(&self) -> bool {
+        self.max_items.is_some_and(|max| self.size >= max)
+    }
+
+    /// Add all of `items` to the tree, stopping at the first item that cannot be added. Returns
+    /// the number of items added, which is less than the number of `items` only if the tree
+    /// reached its [`max_items`](#method.max_items).
+    pub fn add_all(&mut self, items: Vec<([A; D], T)>) -> Result<usize, ErrorKind> {
+        self.add_all_with_progress(items, |_, _| ControlFlow::Continue(()))
+    }
+
+    /// Like [`add_all`](#method.add_all), but calls `progress` with the number of items added so far
+    /// and the total every [`PROGRESS_INTERVAL`] items and once all items are added. Returning
+    /// `ControlFlow::Break` from `progress` stops adding and returns `ErrorKind::Cancelled`, the
+    /// items added until then stay in the tree.
+    pub fn add_all_with_progress<P>(
+        &mut self,
+        items: Vec<([A; D], T)>,
+        mut progress: P,
+    ) -> Result<usize, ErrorKind>
+    where
+        P: FnMut(usize, usize) -> ControlFlow<()>,
+    {
+        let mut progress = Progress::new(items.len(), &mut progress);
+        let mut added = 0;
+        for (point, data) in items {
+            match self.add(point, data) {
+                Err(ErrorKind::Full) => break,
+                result => result?,
+            }
+            added += 1;
+            progress.advance(1)?;
+        }
+        Ok(added)
+    }
+
+    /// Adds an item to the node at `depth` of a tree whose leaves split following `rules`
+    fn add_unchecked(&mut self, point: [A; D], data: T, rules: SplitRules, depth: usize) {
+        if rules
+            .resplit
+            .is_some_and(|resplit| self.is_imbalanced(resplit))
+        {
+            return self.resplit(point, data, rules, depth);
+        }
+        let (points, bucket) = match &mut self.node {
+            Node::Stem {
+                split_dimension,
+                split_value,
+                left,
+                right,
+            } => {
+                let next = if belongs_in_left(&point, *split_dimension, *split_value) {
+                    left
+                } else {
+                    right
+                };
+                next.add_unchecked(point, data, rules, depth + 1);
+                // the stems above a leaf count an item only once the leaf holds it, like
+                // add_if_far_in, so that a leaf refusing it would leave their sizes alone
+                self.extend(&point);
+                self.size += 1;
+                return;
+            }
+            Node::Leaf { points, bucket } => (points, bucket),
+        };
+        if points.capacity() == 0 {
+            // a Vec grows to at least four items, which is most of the memory of a tree with
+            // smaller leaves
+            points.reserve_exact(self.capacity.min(4));
+            bucket.reserve_exact(self.capacity.min(4));
+        }
+        points.push(point);
+        bucket.push(data);
+        self.extend(&point);
+        self.size += 1;
+        if self.size > self.capacity {
+            self.split(rules, depth);
+        }
+    }
+
+    /// Whether this is a stem of at most `max_size` items, one of whose children holds more
+    /// than `imbalance` times the items of the other
+    fn is_imbalanced(&self, (imbalance, max_size): (usize, usize)) -> bool {
+        match &self.node {
+            Node::Stem { left, right, .. } if self.size <= max_size => {
+                let (small, large) = if left.size < right.size {
+                    (left.size, right.size)
+                } else {
+                    (right.size, left.size)
+                };
+                large > small.saturating_mul(imbalance)
+            }
+            _ => false,
+        }
+    }
+
+    /// Rebuilds the subtree at `depth` around its items and the one being added, splitting at
+    /// medians as [`from_points`](#method.from_points) does
+    fn resplit(&mut self, point: [A; D], data: T, rules: SplitRules, depth: usize) {
+        let old = std::mem::replace(self, OwnedKdTree::with_capacity(self.capacity));
+        let (capacity, max_items, generation) = (old.capacity, old.max_items, old.generation);
+        let (min_bounds, max_bounds) = (old.min_bounds, old.max_bounds);
+        let mut items = old.into_items();
+        items.push((point, data));
+        *self = OwnedKdTree::build_quietly(items, capacity, rules.leaf_capacity_fn, depth);
+        self.max_items = max_items;
+        self.split_rules = rules;
+        self.generation = generation;
+        if rules.declared.is_some() {
+            // the bounds of the rebuilt subtree only shrank from its part of the declared box
+            self.min_bounds = min_bounds;
+            self.max_bounds = max_bounds;
+            self.extend(&point);
+        }
+    }
+
+    pub fn remove(&mut self, point: &[A; D], data: &T) -> Result<usize, ErrorKind> {
+        self.remove_matching(point, |d| d == data)
+    }
+
+    /// Remove every item stored at `point` whose data `matches` accepts, returning the number
+    /// of removed items. Unlike [`remove`](#method.remove), this does not compare payloads for
+    /// equality, so it can remove the item at `point` with a given id out of payloads holding
+    /// floats.
+    pub fn remove_matching<P>(&mut self, point: &[A; D], mut matches: P) -> Result<usize, ErrorKind>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        let () = self.check_point(point)?;
+        let mut taken = vec![];
+        self.take_into(point, &mut matches, &mut taken);
+        if !taken.is_empty() {
+            self.generation += 1;
+        }
+        Ok(taken.len())
+    }
+
+    /// Remove every item stored at `point` whose data equals `data`, returning the removed data
+    /// in the order it was removed
+    pub fn take(&mut self, point: &[A; D], data: &T) -> Result<Vec<T>, ErrorKind> {
+        let () = self.check_point(point)?;
+        let mut taken = vec![];
+        self.take_into(point, &mut |d| d == data, &mut taken);
+        if !taken.is_empty() {
+            self.generation += 1;
+        }
+        Ok(taken)
+    }
+
+    /// Remove the item nearest to `point` within `radius`, as [`within`](#method.within) would
+    /// return it first, returning its distance, point and data, or `None` if no item is that
+    /// close. The item is removed from the leaf the search found it in, so this costs one
+    /// search and one walk down to that leaf.
+    #[allow(clippy::type_complexity)]
+    pub fn remove_nearest_within<F>(
+        &mut self,
+        point: &[A; D],
+        radius: A,
+        distance: &F,
+    ) -> Result<Option<(A, [A; D], T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let nearest = self.within_neighbours(point, radius, 1, distance)?;
+        let (dist, at, target) = match nearest.first() {
+            Some(n) => (n.distance, *n.point, n.element as *const T),
+            None => return Ok(None),
+        };
+        let data = self
+            .remove_located(&at, target)
+            .ok_or(ErrorKind::ItemNotFound)?;
+        self.generation += 1;
+        Ok(Some((dist, at, data)))
+    }
+
+    /// Removes the item whose data is at `target` from the leaf `point` belongs in, which holds
+    /// it as every item is in the leaf its point belongs in, or `None` if it is not there
+    fn remove_located(&mut self, point: &[A; D], target: *const T) -> Option<T> {
+        let data = match &mut self.node {
+            Node::Stem {
+                split_dimension,
+                split_value,
+                left,
+                right,
+            } => {
+                let next = if belongs_in_left(point, *split_dimension, *split_value) {
+                    left
+                } else {
+                    right
+                };
+                next.remove_located(point, target)
+            }
+            Node::Leaf { points, bucket } => {
+                let i = bucket.iter().position(|d| std::ptr::eq(d, target))?;
+                points.remove(i);
+                Some(bucket.remove(i))
+            }
+        }?;
+        self.size -= 1;
+        self.reset_if_empty();
+        Some(data)
+    }
+
+    /// Remove every item stored at `point`, returning the removed data in the order it was
+    /// removed
+    pub fn remove_at(&mut self, point: &[A; D]) -> Result<Vec<T>, ErrorKind> {
+        let () = self.check_point(point)?;
+        let mut taken = vec![];
+        self.take_into(point, &mut |_| true, &mut taken);
+        if !taken.is_empty() {
+            self.generation += 1;
+        }
+        Ok(taken)
+    }
+
+    /// The data of an item stored at `point`, or `None` if there is none. If several items are
+    /// stored at `point`, which of them is returned is unspecified. Looking for it bumps the
+    /// [`generation`](#method.generation) whether or not an item is found.
+    pub fn get_mut(&mut self, point: &[A; D]) -> Result<Option<&mut T>, ErrorKind> {
+        let () = self.check_point(point)?;
+        self.generation += 1;
+        Ok(self.get_mut_in(point))
+    }
+
+    fn get_mut_in(&mut self, point: &[A; D]) -> Option<&mut T> {
+        let outside =
+            (0..D).any(|d| point[d] < self.min_bounds[d] || point[d] > self.max_bounds[d]);
+        if self.size == 0 || outside {
+            return None;
+        }
+        match &mut self.node {
+            Node::Stem { left, right, .. } => {
+                if let Some(found) = left.get_mut_in(point) {
+                    return Some(found);
+                }
+                right.get_mut_in(point)
+            }
+            Node::Leaf { points, bucket } => {
+                let i = points.iter().position(|p| p == point)?;
+                Some(&mut bucket[i])
+            }
+        }
+    }
+
+    /// Remove every item matching one of `items`, like calling [`remove`](#method.remove) for
+    /// each of them, but in a single traversal that edits every affected leaf once. Returns the
+    /// total number of removed items.
+    pub fn remove_all(&mut self, items: &[([A; D], T)]) -> Result<usize, ErrorKind> {
+        for (point, _) in items {
+            let () = self.check_point(point)?;
+        }
+        let mut requests: Vec<_> = items.iter().collect();
+        let removed = self.remove_all_in(&mut requests);
+        if removed > 0 {
+            self.generation += 1;
+        }
+        Ok(removed)
+    }
+
+    fn remove_all_in(&mut self, requests: &mut [&([A; D], T)]) -> usize {
+        if requests.is_empty() {
+            return 0;
+        }
+        let removed = match &mut self.node {
+            Node::Stem {
+                split_dimension,
+                split_value,
+                left,
+                right,
+            } => {
+                let mut split = 0;
+                for i in 0..requests.len() {
+                    if belongs_in_left(&requests[i].0, *split_dimension, *split_value) {
+                        requests.swap(i, split);
+                        split += 1;
+                    }
+                }
+                let (to_left, to_right) = requests.split_at_mut(split);
+                left.remove_all_in(to_left) + right.remove_all_in(to_right)
+            }
+            Node::Leaf { points, bucket } => {
+                let before = points.len();
+                let mut i = 0;
+                while i < points.len() {
+                    if requests
+                        .iter()
+                        .any(|(p, d)| *p == points[i] && *d == bucket[i])
+                    {
+                        points.remove(i);
+                        bucket.remove(i);
+                    } else {
+                        i += 1;
+                    }
+                }
+                before - points.len()
+            }
+        };
+        self.size -= removed;
+        self.reset_if_empty();
+        removed
+    }
+
+    /// Remove the items whose points lie within the box `[min, max]`, bounds included as in
+    /// [`within_box`](#method.within_box), and for which `keep` returns false, visiting only the
+    /// leaves meeting the box. `keep` may also change the payloads it keeps. Returns the number
+    /// of removed items. Bumps the [`generation`](#method.generation) whether or not an item is
+    /// removed.
+    pub fn retain_in_box<P>(
+        &mut self,
+        min: &[A; D],
+        max: &[A; D],
+        mut keep: P,
+    ) -> Result<usize, ErrorKind>
+    where
+        P: FnMut(&[A; D], &mut T) -> bool,
+    {
+        let () = self.check_point(min)?;
+        let () = self.check_point(max)?;
+        self.generation += 1;
+        Ok(self.retain_in_box_in(min, max, &mut keep))
+    }
+
+    fn retain_in_box_in<P>(&mut self, min: &[A; D], max: &[A; D], keep: &mut P) -> usize
+    where
+        P: FnMut(&[A; D], &mut T) -> bool,
+    {
+        if !self.overlaps_box(min, max) {
+            return 0;
+        }
+        let removed = match &mut self.node {
+            Node::Stem { left, right, .. } => {
+                left.retain_in_box_in(min, max, keep) + right.retain_in_box_in(min, max, keep)
+            }
+            Node::Leaf { points, bucket } => {
+                let before = points.len();
+                let mut i = 0;
+                while i < points.len() {
+                    if in_box(&points[i], min, max) && !keep(&points[i], &mut bucket[i]) {
+                        points.remove(i);
+                        bucket.remove(i);
+                    } else {
+                        i += 1;
+                    }
+                }
+                before - points.len()
+            }
+        };
+        self.size -= removed;
+        self.reset_if_empty();
+        removed
+    }
+
+    /// Move the items whose points lie within the box `[min, max]`, bounds included as in
+    /// [`within_box`](#method.within_box), out of this tree into a new one with the same
+    /// settings, visiting only the nodes meeting the box. Subtrees whose bounds lie within the
+    /// box are moved over whole rather than item by item. Bumps the
+    /// [`generation`](#method.generation) if an item is moved.
+    pub fn crop_into(&mut self, min: &[A; D], max: &[A; D]) -> Result<Self, ErrorKind> {
+        let () = self.check_point(min)?;
+        let () = self.check_point(max)?;
+        let settings = (self.generation, self.max_items, self.split_rules);
+        let cropped = self.crop_into_in(min, max, self.split_rules);
+        // a box around the whole tree moves the root out, leaving an emptied node in its place
+        (self.generation, self.max_items, self.split_rules) = settings;
+        if cropped.size > 0 {
+            self.generation += 1;
+        }
+        Ok(self.cropped_root(cropped))
+    }
+
+    fn crop_into_in(&mut self, min: &[A; D], max: &[A; D], rules: SplitRules) -> Self {
+        let empty = self.emptied(rules);
+        if !self.overlaps_box(min, max) {
+            return empty;
+        }
+        if in_box(&self.min_bounds, min, max) && in_box(&self.max_bounds, min, max) {
+            return std::mem::replace(self, empty);
+        }
+        let cropped = match &mut self.node {
+            Node::Stem {
+                split_dimension,
+                split_value,
+                left,
+                right,
+            } => {
+                let (dim, value) = (*split_dimension, *split_value);
+                let left = left.crop_into_in(min, max, rules);
+                let right = right.crop_into_in(min, max, rules);
+                empty.joined(dim, value, left, right, rules)
+            }
+            Node::Leaf { points, bucket } => {
+                let (mut taken, mut taken_bucket) = (vec![], vec![]);
+                let mut i = 0;
+                while i < points.len() {
+                    if in_box(&points[i], min, max) {
+                        taken.push(points.remove(i));
+                        taken_bucket.push(bucket.remove(i));
+                    } else {
+                        i += 1;
+                    }
+                }
+                OwnedKdTree {
+                    size: taken.len(),
+                    node: Node::Leaf {
+                        points: taken.into(),
+                        bucket: taken_bucket.into(),
+                    },
+                    ..empty
+                }
+            }
+        };
+        self.size -= cropped.size;
+        self.reset_if_empty();
+        cropped
+    }
+
+    /// An empty leaf with the capacity of the node, covering its part of the box of a tree with
+    /// declared bounds
+    fn emptied(&self, rules: SplitRules) -> Self {
+        let mut empty = OwnedKdTree::with_capacity(self.capacity);
+        if rules.declared.is_some() {
+            empty.min_bounds = self.min_bounds;
+            empty.max_bounds = self.max_bounds;
+        }
+        empty
+    }
+
+    /// The emptied node `self` turned back into a stem over the cropped parts of its children.
+    /// If a part is empty the other needs no stem to separate it and takes the stem's place,
+    /// unless the tree has declared bounds, whose nodes must keep covering their part of them.
+    fn joined(
+        self,
+        split_dimension: usize,
+        split_value: A,
+        left: Self,
+        right: Self,
+        rules: SplitRules,
+    ) -> Self {
+        if left.size + right.size == 0 {
+            return self;
+        }
+        if rules.declared.is_none() {
+            if left.size == 0 {
+                return right;
+            }
+            if right.size == 0 {
+                return left;
+            }
+        }
+        OwnedKdTree {
+            size: left.size + right.size,
+            node: Node::Stem {
+                split_dimension,
+                split_value,
+                left: Box::new(left),
+                right: Box::new(right),
+            },
+            ..self
+        }
+    }
+
+    /// The root of a tree cropped out of this one, with its settings and, unless it has
+    /// declared bounds, the tight bounds of the cropped items
+    fn cropped_root(&self, mut cropped: Self) -> Self {
+        cropped.max_items = self.max_items;
+        cropped.split_rules = self.split_rules;
+        cropped.generation = 0;
+        if self.split_rules.declared.is_none() {
+            cropped.recompute_bounds_in();
+        }
+        cropped
+    }
+
+    fn take_into<P>(&mut self, point: &[A; D], matches: &mut P, taken: &mut Vec<T>)
+    where
+        P: FnMut(&T) -> bool + ?Sized,
+    {
+        let before = taken.len();
+        match &mut self.node {
+            Node::Stem { left, right, .. } => {
+                right.take_into(point, matches, taken);
+                left.take_into(point, matches, taken);
+            }
+            Node::Leaf { points, bucket } => {
+                let mut i = 0;
+                while i < points.len() {
+                    if points[i] == *point && matches(&bucket[i]) {
+                        points.remove(i);
+                        taken.push(bucket.remove(i));
+                    } else {
+                        i += 1;
+                    }
+                }
+            }
+        }
+        self.size -= taken.len() - before;
+        self.reset_if_empty();
+    }
+
+    fn widest_dimension(&self) -> Option<usize> {
+        let mut max = A::zero();
+        let mut widest = None;
+        for dim in 0..D {
+            let diff = self.max_bounds[dim] - self.min_bounds[dim];
+            if !diff.is_nan() && diff > max {
+                max = diff;
+                widest = Some(dim);
+            }
+        }
+        widest
+    }
+
+    /// Whether the points of a leaf are not all the same, `false` for a stem
+    fn has_distinct_points(&self) -> bool {
+        match &self.node {
+            Node::Stem { .. } => false,
+            Node::Leaf { points, .. } => points.iter().any(|p| *p != points[0]),
+        }
+    }
+
+    /// The middle of the bounds along `dim`, above the lower bound
+    fn midpoint(&self, dim: usize) -> A {
+        let min = self.min_bounds[dim];
+        let max = self.max_bounds[dim];
+        let two = A::one() + A::one();
+        let mut split_value = min + (max - min) / two;
+        if !split_value.is_finite() {
+            // the width overflowed
+            split_value = min / two + max / two;
+        }
+        if split_value <= min {
+            // the bounds are adjacent floats and the midpoint rounded down, which would leave the
+            // left child empty and split the right child forever
+            split_value = max;
+        }
+        split_value
+    }
+
+    /// The dimension along which the points of a leaf vary the most and their mean along it,
+    /// `None` for a stem or a leaf whose points are all the same
+    fn max_variance_split(&self) -> Option<(usize, A)> {
+        let points = match &self.node {
+            Node::Stem { .. } => return None,
+            Node::Leaf { points, .. } => points,
+        };
+        let n = A::from(points.len())?;
+        let mut best = None;
+        let mut max_variance = A::zero();
+        for dim in 0..D {
+            let mean = points.iter().fold(A::zero(), |sum, p| sum + p[dim]) / n;
+            let variance = points.iter().fold(A::zero(), |sum, p| {
+                let d = p[dim] - mean;
+                sum + d * d
+            });
+            // widely spread points may overflow, in which case their mean is no good either
+            if variance.is_finite() && mean.is_finite() && variance > max_variance {
+                max_variance = variance;
+                best = Some((dim, mean));
+            }
+        }
+        let (dim, mean) = best?;
+        let (min, max) = points
+            .iter()
+            .fold((A::infinity(), A::neg_infinity()), |(l, h), p| {
+                (l.min(p[dim]), h.max(p[dim]))
+            });
+        if mean > min && mean <= max {
+            Some((dim, mean))
+        } else {
+            // rounding moved the mean to either end, split right below the largest point so
+            // that neither child is empty
+            Some((dim, max))
+        }
+    }
+
+    /// Turn a leaf into a stem split as `rules.strategy` says. Leaves whose items are all at one
+    /// point and stems are left as they are.
+    fn split(&mut self, rules: SplitRules, depth: usize) {
+        let split = match rules.strategy {
+            // the bounds of a leaf of a tree with declared bounds may be wider than its points,
+            // which must differ for a split to separate them
+            SplitStrategy::Widest if rules.declared.is_some() && !self.has_distinct_points() => {
+                None
+            }
+            SplitStrategy::Widest => self.widest_dimension().map(|dim| (dim, self.midpoint(dim))),
+            SplitStrategy::MaxVariance => self.max_variance_split(),
+        };
+        let (dim, split_value) = match split {
+            Some(split) => split,
+            None => return,
+        };
+        let (mut points, mut bucket) = match &mut self.node {
+            Node::Stem { .. } => return,
+            Node::Leaf { points, bucket } => (std::mem::take(points), std::mem::take(bucket)),
+        };
+        let child_capacity = leaf_capacity_at(rules.leaf_capacity_fn, self.capacity, depth + 1);
+        let mut left = Box::new(OwnedKdTree::with_capacity(child_capacity));
+        let mut right = Box::new(OwnedKdTree::with_capacity(child_capacity));
+        if rules.declared.is_some() {
+            // the children cover the two halves of the part of the declared box this leaf covers
+            left.min_bounds = self.min_bounds;
+            left.max_bounds = self.max_bounds;
+            left.max_bounds[dim] = split_value;
+            right.min_bounds = self.min_bounds;
+            right.max_bounds = self.max_bounds;
+            right.min_bounds[dim] = split_value;
+        }
+        while !points.is_empty() {
+            let point = points.swap_remove(0);
+            let data = bucket.swap_remove(0);
+            if belongs_in_left(&point, dim, split_value) {
+                left.add_unchecked(point, data, rules, depth + 1);
+            } else {
+                right.add_unchecked(point, data, rules, depth + 1);
+            }
+        }
+        self.node = Node::Stem {
+            split_dimension: dim,
+            split_value,
+            left,
+            right,
+        };
+    }
+
+    /// The child of a stem `point` belongs in, followed by the other child, or the items of a
+    /// leaf
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn sides(&self, point: &[A; D]) -> Result<(&Self, &Self), (&[[A; D]], &[T])> {
+        match &self.node {
+            Node::Stem {
+                split_dimension,
+                split_value,
+                left,
+                right,
+            } => {
+                if belongs_in_left(point, *split_dimension, *split_value) {
+                    Ok((left, right))
+                } else {
+                    Ok((right, left))
+                }
+            }
+            Node::Leaf { points, bucket } => Err((points, bucket)),
+        }
+    }
+
+    /// Both children of a stem, `None` for a leaf
+    fn children(&self) -> Option<[&Self; 2]> {
+        match &self.node {
+            Node::Stem { left, right, .. } => Some([left, right]),
+            Node::Leaf { .. } => None,
+        }
+    }
+
+    fn extend(&mut self, point: &[A; D]) {
+        let min = self.min_bounds.iter_mut();
+        let max = self.max_bounds.iter_mut();
+        for ((l, h), v) in min.zip(max).zip(point.iter()) {
+            if v < l {
+                *l = *v
+            }
+            if v > h {
+                *h = *v
+            }
+        }
+    }
+
+    /// Turn a node which lost all its items back into an empty leaf, as if freshly created
+    fn reset_if_empty(&mut self) {
+        if self.size == 0 {
+            let (max_items, split_rules) = (self.max_items, self.split_rules);
+            let (generation, bounds) = (self.generation, (self.min_bounds, self.max_bounds));
+            *self = OwnedKdTree::with_capacity(self.capacity);
+            self.max_items = max_items;
+            self.split_rules = split_rules;
+            self.generation = generation;
+            if split_rules.declared.is_some() {
+                (self.min_bounds, self.max_bounds) = bounds;
+            }
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        matches!(self.node, Node::Leaf { .. })
+    }
+
+    pub(crate) fn check_point(&self, point: &[A; D]) -> Result<(), ErrorKind> {
+        for n in point {
+            if !n.is_finite() {
+                return Err(ErrorKind::NonFiniteCoordinate);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<A: Float + Zero + One, T: PartialEq + Clone, const D: usize> OwnedKdTree<A, T, D> {
+    /// Like [`nearest`](#method.nearest), but returning clones of the payloads, so that the
+    /// results do not borrow the tree. Payloads are cloned as the search keeps them, without
+    /// gathering references first.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use kdtree::distance::squared_euclidean;
+    /// use kdtree::OwnedKdTree;
+    ///
+    /// let dist = |a: &[f64; 2], b: &[f64; 2]| squared_euclidean(a, b);
+    /// let items = (0..10).map(|i| ([i as f64, 0.0], i)).collect();
+    /// let mut tree: OwnedKdTree<f64, usize, 2> = OwnedKdTree::from_points(items, 4).unwrap();
+    ///
+    /// let nearest = tree.nearest_owned(&[2.2, 0.0], 2, &dist).unwrap();
+    /// for (_, i) in &nearest {
+    ///     tree.remove(&[*i as f64, 0.0], i).unwrap();
+    /// }
+    /// assert_eq!(nearest.iter().map(|&(_, i)| i).collect::<Vec<_>>(), [2, 3]);
+    /// assert_eq!(tree.size(), 8);
+    /// ```
+    ///
+    /// whereas the references [`nearest`](#method.nearest) returns keep the tree borrowed:
+    ///
+    /// ```compile_fail,E0502
+    /// # use kdtree::distance::squared_euclidean;
+    /// # use kdtree::OwnedKdTree;
+    /// # let dist = |a: &[f64; 2], b: &[f64; 2]| squared_euclidean(a, b);
+    /// # let items = (0..10).map(|i| ([i as f64, 0.0], i)).collect();
+    /// # let mut tree: OwnedKdTree<f64, usize, 2> = OwnedKdTree::from_points(items, 4).unwrap();
+    /// let nearest = tree.nearest(&[2.2, 0.0], 2, &dist).unwrap();
+    /// for (_, i) in &nearest {
+    ///     tree.remove(&[**i as f64, 0.0], i).unwrap();
+    /// }
+    /// ```
+    pub fn nearest_owned<F>(
+        &self,
+        point: &[A; D],
+        num: usize,
+        distance: &F,
+    ) -> Result<Vec<(A, T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let () = self.check_point(point)?;
+        let (nearest, _) = search::nearest_as(
+            self,
+            point,
+            num,
+            &ClosureMetric(distance),
+            (usize::MAX, usize::MAX),
+            &mut QueryStats::default(),
+            &T::clone,
+        );
+        Ok(nearest.into_iter().map(Into::into).collect())
+    }
+
+    /// A new tree with the same settings holding clones of the items whose points lie within
+    /// the box `[min, max]`, bounds included as in [`within_box`](#method.within_box). Subtrees
+    /// whose bounds lie within the box are cloned whole, keeping their splits, and only the
+    /// nodes meeting the box are visited. [`crop_into`](#method.crop_into) moves the items out
+    /// instead.
+    pub fn crop(&self, min: &[A; D], max: &[A; D]) -> Result<Self, ErrorKind> {
+        let () = self.check_point(min)?;
+        let () = self.check_point(max)?;
+        let cropped = self.crop_in(min, max, self.split_rules);
+        Ok(self.cropped_root(cropped))
+    }
+
+    fn crop_in(&self, min: &[A; D], max: &[A; D], rules: SplitRules) -> Self {
+        let empty = self.emptied(rules);
+        if !self.overlaps_box(min, max) {
+            return empty;
+        }
+        if in_box(&self.min_bounds, min, max) && in_box(&self.max_bounds, min, max) {
+            return self.clone();
+        }
+        match &self.node {
+            Node::Stem {
+                split_dimension,
+                split_value,
+                left,
+                right,
+            } => {
+                let left = left.crop_in(min, max, rules);
+                let right = right.crop_in(min, max, rules);
+                empty.joined(*split_dimension, *split_value, left, right, rules)
+            }
+            Node::Leaf { points, bucket } => {
+                let (points, bucket): (LeafVec<[A; D]>, LeafVec<T>) = points
+                    .iter()
+                    .zip(bucket)
+                    .filter(|(p, _)| in_box(p, min, max))
+                    .map(|(p, data)| (*p, data.clone()))
+                    .unzip();
+                OwnedKdTree {
+                    size: points.len(),
+                    node: Node::Leaf { points, bucket },
+                    ..empty
+                }
+            }
+        }
+    }
+
+    /// Like [`within`](#method.within), but returning clones of the payloads as
+    /// [`nearest_owned`](#method.nearest_owned) does
+    pub fn within_owned<F>(
+        &self,
+        point: &[A; D],
+        radius: A,
+        distance: &F,
+    ) -> Result<Vec<(A, T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let () = self.check_point(point)?;
+        let () = search::check_radius(radius)?;
+        let within = search::within_as(
+            self,
+            point,
+            radius,
+            self.size,
+            &ClosureMetric(distance),
+            &mut QueryStats::default(),
+            &T::clone,
+        );
+        Ok(within.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<'b, A: Float + Zero + One, T: PartialEq, const D: usize> SearchNode<'b, A, T, D>
+    for &'b OwnedKdTree<A, T, D>
+{
+    type Leaf = (&'b [[A; D]], &'b [T]);
+
+    fn size(self) -> usize {
+        self.size
+    }
+
+    fn bounds(self) -> (&'b [A; D], &'b [A; D]) {
+        (&self.min_bounds, &self.max_bounds)
+    }
+
+    fn stem(self) -> Result<(usize, A, Self, Self), Self::Leaf> {
+        match &self.node {
+            Node::Stem {
+                split_dimension,
+                split_value,
+                left,
+                right,
+            } => Ok((*split_dimension, *split_value, left, right)),
+            Node::Leaf { points, bucket } => Err((points, bucket)),
+        }
+    }
+}
+
+/// `metric` within the half-space `dot(normal, x - offset_point) >= 0`, and infinitely far
+/// from any point or box outside of it
+struct HalfSpace<'a, M: ?Sized, A, const D: usize> {
+    metric: &'a M,
+    normal: &'a [A; D],
+    offset_point: &'a [A; D],
+}
+
+impl<'a, A: Float, M: Metric<A, D> + ?Sized, const D: usize> HalfSpace<'a, M, A, D> {
+    /// Whether `point` lies in the half-space. Rounding is monotonic, so no point of a box
+    /// reaches further along the normal than the corner chosen in `point_to_box`.
+    fn contains(&self, point: &[A; D]) -> bool {
+        let along = self.normal.iter().zip(point.iter().zip(self.offset_point));
+        along.fold(A::zero(), |sum, (&n, (&x, &o))| sum + n * (x - o)) >= A::zero()
+    }
+}
+
+impl<'a, A: Float, M: Metric<A, D> + ?Sized, const D: usize> Metric<A, D>
+    for HalfSpace<'a, M, A, D>
+{
+    fn distance(&self, a: &[A; D], b: &[A; D]) -> A {
+        if self.contains(b) {
+            self.metric.distance(a, b)
+        } else {
+            A::infinity()
+        }
+    }
+
+    fn point_to_box(&self, point: &[A; D], min: &[A; D], max: &[A; D]) -> A {
+        let mut corner = *max;
+        for ((c, &lo), &n) in corner.iter_mut().zip(min).zip(self.normal) {
+            if n < A::zero() {
+                *c = lo;
+            }
+        }
+        if self.contains(&corner) {
+            self.metric.point_to_box(point, min, max)
+        } else {
+            A::infinity()
+        }
+    }
+}
+
+/// A cone of finite length with a unit direction
+struct Cone<'a, A, const D: usize> {
+    apex: &'a [A; D],
+    dir: &'a [A; D],
+    angle: A,
+    radius: A,
+}
+
+impl<'a, A: Float, const D: usize> Cone<'a, A, D> {
+    fn offset(&self, point: &[A; D]) -> [A; D] {
+        let mut offset = *point;
+        for (x, &a) in offset.iter_mut().zip(self.apex) {
+            *x = *x - a;
+        }
+        offset
+    }
+
+    /// The distance of `point` from the apex if the cone contains it
+    fn contains(&self, point: &[A; D]) -> Option<A> {
+        let offset = self.offset(point);
+        let distance = dot(&offset, &offset).sqrt();
+        if distance > self.radius {
+            return None;
+        }
+        let pi = A::from(std::f64::consts::PI).unwrap();
+        if self.angle >= pi || distance.is_zero() {
+            return Some(distance);
+        }
+        let cos = (dot(&offset, self.dir) / distance)
+            .max(-A::one())
+            .min(A::one());
+        if cos.acos() <= self.angle {
+            Some(distance)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the box `[min, max]` may contain points of the cone. Compares the angle of the
+    /// box's bounding sphere as seen from the apex, so it never rejects a box which intersects
+    /// but may accept some which do not.
+    fn may_intersect(&self, min: &[A; D], max: &[A; D]) -> bool {
+        let squared = |a: &[A; D], b: &[A; D]| squared_euclidean(a, b);
+        if distance_to_space_const(self.apex, min, max, &squared) > self.radius * self.radius {
+            return false;
+        }
+        let pi = A::from(std::f64::consts::PI).unwrap();
+        if self.angle >= pi {
+            return true;
+        }
+        let two = A::one() + A::one();
+        let mut center = *min;
+        for (c, &m) in center.iter_mut().zip(max) {
+            *c = (*c + m) / two;
+        }
+        let sphere = squared(min, max).sqrt() / two;
+        let offset = self.offset(&center);
+        let distance = dot(&offset, &offset).sqrt();
+        if distance <= sphere {
+            return true;
+        }
+        let cos = (dot(&offset, self.dir) / distance)
+            .max(-A::one())
+            .min(A::one());
+        cos.acos() <= self.angle + (sphere / distance).asin()
+    }
+}
+
+/// Whether `point` lies within the box `[min, max]`, bounds included
+fn in_box<A: Float, const D: usize>(point: &[A; D], min: &[A; D], max: &[A; D]) -> bool {
+    point
+        .iter()
+        .zip(min.iter().zip(max))
+        .all(|(x, (min, max))| min <= x && x <= max)
+}
+
+fn dot<A: Float, const D: usize>(a: &[A; D], b: &[A; D]) -> A {
+    a.iter().zip(b).fold(A::zero(), |sum, (&x, &y)| sum + x * y)
+}
+
+fn normalized<A: Float, const D: usize>(mut point: [A; D]) -> Result<[A; D], ErrorKind> {
+    let norm = squared_euclidean(&point, &[A::zero(); D]).sqrt();
+    if !norm.is_finite() {
+        return Err(ErrorKind::NonFiniteCoordinate);
+    }
+    if norm.is_zero() {
+        return Err(ErrorKind::ZeroVector);
+    }
+    for x in point.iter_mut() {
+        *x = *x / norm;
+    }
+    Ok(point)
+}
+
+/// A read-only handle to a node of an [`OwnedKdTree`]
+pub struct NodeRef<'a, A, T: PartialEq, const D: usize> {
+    node: &'a OwnedKdTree<A, T, D>,
+}
+
+impl<'a, A: Float + Zero + One, T: PartialEq, const D: usize> NodeRef<'a, A, T, D> {
+    /// Number of items stored below this node
+    pub fn size(&self) -> usize {
+        self.node.size
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.node.is_leaf()
+    }
+
+    /// The number of items this node holds before splitting, while it is a leaf
+    pub fn capacity(&self) -> usize {
+        self.node.capacity
+    }
+
+    /// The bounding box `(min, max)` of the items stored below this node, `None` if the node
+    /// is empty
+    pub fn bounds(&self) -> Option<(&'a [A; D], &'a [A; D])> {
+        if self.node.size == 0 {
+            return None;
+        }
+        Some((&self.node.min_bounds, &self.node.max_bounds))
+    }
+
+    /// The dimension this node splits on, `None` for leaves
+    pub fn split_dimension(&self) -> Option<usize> {
+        match self.node.node {
+            Node::Stem {
+                split_dimension, ..
+            } => Some(split_dimension),
+            Node::Leaf { .. } => None,
+        }
+    }
+
+    /// Points with a coordinate below this value in the split dimension are stored in the left
+    /// child, all others in the right child. `None` for leaves.
+    pub fn split_value(&self) -> Option<A> {
+        match self.node.node {
+            Node::Stem { split_value, .. } => Some(split_value),
+            Node::Leaf { .. } => None,
+        }
+    }
+
+    pub fn left(&self) -> Option<NodeRef<'a, A, T, D>> {
+        self.node.children().map(|[node, _]| NodeRef { node })
+    }
+
+    pub fn right(&self) -> Option<NodeRef<'a, A, T, D>> {
+        self.node.children().map(|[_, node]| NodeRef { node })
+    }
+
+    /// Iterate over the items stored below this node
+    pub fn iter(&self) -> Iter<'a, A, T, D> {
+        self.node.iter()
+    }
+}
+
+/// A mutable handle to a node of an [`OwnedKdTree`]
+pub struct NodeMut<'a, A, T: PartialEq, const D: usize> {
+    node: &'a mut OwnedKdTree<A, T, D>,
+    /// Passed down from the root, to split the node as `add` would
+    split_rules: SplitRules,
+    depth: usize,
+}
+
+impl<'a, A: Float + Zero + One, T: PartialEq, const D: usize> NodeMut<'a, A, T, D> {
+    pub fn as_ref(&self) -> NodeRef<'_, A, T, D> {
+        NodeRef { node: self.node }
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.node.is_leaf()
+    }
+
+    pub fn into_left(self) -> Option<NodeMut<'a, A, T, D>> {
+        match &mut self.node.node {
+            Node::Stem { left, .. } => Some(NodeMut {
+                node: left,
+                split_rules: self.split_rules,
+                depth: self.depth + 1,
+            }),
+            Node::Leaf { .. } => None,
+        }
+    }
+
+    pub fn into_right(self) -> Option<NodeMut<'a, A, T, D>> {
+        match &mut self.node.node {
+            Node::Stem { right, .. } => Some(NodeMut {
+                node: right,
+                split_rules: self.split_rules,
+                depth: self.depth + 1,
+            }),
+            Node::Leaf { .. } => None,
+        }
+    }
+
+    /// Split this leaf right away, even if it holds fewer items than its capacity. Returns
+    /// whether the node was split, which it is not if it is a stem already or its items do not
+    /// have at least two distinct points.
+    pub fn split_now(&mut self) -> bool {
+        if !self.node.is_leaf() {
+            return false;
+        }
+        self.node.split(self.split_rules, self.depth);
+        !self.node.is_leaf()
+    }
+}
+
+/// The order in which [`Builder::from_points`] sorts the items before splitting them into nodes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildOrder {
+    /// Keep the items in the order they were given
+    Unsorted,
+    /// Sort the items by their [Morton index](crate::curve::morton_index) within the bounding box
+    /// of the data. The splits do not depend on the order of the items, so this gives the same
+    /// nodes as `Unsorted` and only orders the items inside every leaf along the curve, as
+    /// [`OwnedKdTree::iter_morton`] yields them.
+    Morton,
+    /// Sort the items by their [Hilbert index](crate::curve::hilbert_index) within the bounding
+    /// box of the data, which like `Morton` only orders the items inside every leaf
+    Hilbert,
+}
+
+/// How a leaf holding too many items picks where to split, see [`Builder::split_strategy`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SplitStrategy {
+    /// Split the widest dimension of the leaf's bounding box in the middle
+    #[default]
+    Widest,
+    /// Split the dimension in which the leaf's points have the largest variance at their mean.
+    /// A few outliers stretching the box along one dimension then do not decide the split on
+    /// their own.
+    MaxVariance,
+}
+
+/// What [`OwnedKdTree::add`] does with a point outside the bounds a tree was created with by
+/// [`OwnedKdTree::with_bounds_policy`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutOfBounds {
+    /// Return `ErrorKind::OutOfBounds` without adding the item
+    #[default]
+    Reject,
+    /// Move the point to the nearest point of the bounds and add the item there
+    Clamp,
+}
+
+/// Configures the construction of an [`OwnedKdTree`]
+#[derive(Clone, Debug)]
+pub struct Builder {
+    capacity: usize,
+    per_dimension: bool,
+    order: BuildOrder,
+    max_items: Option<usize>,
+    leaf_capacity_fn: Option<fn(usize) -> usize>,
+    split_strategy: SplitStrategy,
+    resplit: Option<(usize, usize)>,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Builder {
+            capacity: DEFAULT_LEAF_CAPACITY,
+            per_dimension: false,
+            order: BuildOrder::Unsorted,
+            max_items: None,
+            leaf_capacity_fn: None,
+            split_strategy: SplitStrategy::Widest,
+            resplit: None,
+        }
+    }
+
+    /// Set the capacity of leaf nodes, [`DEFAULT_LEAF_CAPACITY`] unless set
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self.per_dimension = false;
+        self
+    }
+
+    /// Pick the capacity of leaf nodes from the dimension of the points, see
+    /// [`leaf_capacity_for_dimensions`]
+    pub fn per_dimension_capacity(mut self) -> Self {
+        self.per_dimension = true;
+        self
+    }
+
+    /// The capacity of the root of a tree of points of `dimensions` dimensions
+    fn capacity_for(&self, dimensions: usize) -> usize {
+        let capacity = if self.per_dimension {
+            leaf_capacity_for_dimensions(dimensions)
+        } else {
+            self.capacity
+        };
+        leaf_capacity_at(self.leaf_capacity_fn, capacity, 0)
+    }
+
+    /// Set the order used by [`from_points`](#method.from_points)
+    pub fn order(mut self, order: BuildOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Limit the number of items of the tree, beyond which [`OwnedKdTree::add`] returns
+    /// `ErrorKind::Full`, or remove the limit with `None`, the default. The limit is not kept by
+    /// serialization or by methods which rebuild the tree, such as
+    /// [`transform_points`](struct.OwnedKdTree.html#method.transform_points).
+    pub fn max_items(mut self, max_items: Option<usize>) -> Self {
+        self.max_items = max_items;
+        self
+    }
+
+    /// Give every leaf the capacity `leaf_capacity_fn` returns for its depth, the root being at
+    /// depth 0, in place of the one set by [`capacity`](#method.capacity), or go back to that
+    /// capacity with `None`, the default. A capacity of zero is taken as one. The tree keeps the
+    /// schedule for the leaves split by [`OwnedKdTree::add`], but like
+    /// [`max_items`](#method.max_items) it is not kept by serialization or rebuilds.
+    pub fn leaf_capacity_fn(mut self, leaf_capacity_fn: Option<fn(usize) -> usize>) -> Self {
+        self.leaf_capacity_fn = leaf_capacity_fn;
+        self
+    }
+
+    /// Set how the leaves split by [`OwnedKdTree::add`] pick where to split,
+    /// [`SplitStrategy::Widest`] unless set. [`from_points`](#method.from_points) always splits
+    /// the widest dimension at its median, which keeps the tree balanced whatever the items.
+    pub fn split_strategy(mut self, split_strategy: SplitStrategy) -> Self {
+        self.split_strategy = split_strategy;
+        self
+    }
+
+    /// Rebuild a subtree of at most `max_size` items whenever [`OwnedKdTree::add`] passes
+    /// through its root while one of its children holds more than `imbalance` times the items
+    /// of the other, as happens to split planes left behind by items which drift away. The
+    /// subtree is rebuilt around its current items, the new one included, splitting at medians
+    /// as [`from_points`](#method.from_points) does, so each rebuild costs about as much as
+    /// building a tree of `max_size` items. The topmost such subtree on the way down is
+    /// rebuilt, and a child left empty counts as infinitely imbalanced. Off unless set, and
+    /// like [`max_items`](#method.max_items) not kept by serialization or rebuilds.
+    pub fn resplit_imbalanced(mut self, imbalance: usize, max_size: usize) -> Self {
+        self.resplit = Some((imbalance, max_size));
+        self
+    }
+
+    fn split_rules(&self) -> SplitRules {
+        SplitRules {
+            leaf_capacity_fn: self.leaf_capacity_fn,
+            strategy: self.split_strategy,
+            resplit: self.resplit,
+            declared: None,
+        }
+    }
+
+    /// Create an empty tree
+    ///
+    /// # Panics
+    ///
+    /// If the capacity is zero, like [`OwnedKdTree::with_capacity`]
+    pub fn build<A: Float + Zero + One, T: PartialEq, const D: usize>(
+        self,
+    ) -> OwnedKdTree<A, T, D> {
+        let mut tree = OwnedKdTree::with_capacity(self.capacity_for(D));
+        tree.max_items = self.max_items;
+        tree.split_rules = self.split_rules();
+        tree
+    }
+
+    /// Like [`build`](#method.build), but returns `ErrorKind::ZeroCapacity` rather than
+    /// panicking if the capacity is zero, as [`from_points`](#method.from_points) does
+    pub fn try_build<A: Float + Zero + One, T: PartialEq, const D: usize>(
+        self,
+    ) -> Result<OwnedKdTree<A, T, D>, ErrorKind> {
+        if self.capacity_for(D) == 0 {
+            return Err(ErrorKind::ZeroCapacity);
+        }
+        Ok(self.build())
+    }
+
+    /// Build a tree holding all of `items`, see [`OwnedKdTree::from_points`]
+    pub fn from_points<A: Float + Zero + One, T: PartialEq, const D: usize>(
+        self,
+        items: Vec<([A; D], T)>,
+    ) -> Result<OwnedKdTree<A, T, D>, ErrorKind> {
+        self.from_points_with_progress(items, |_, _| ControlFlow::Continue(()))
+    }
+
+    /// Like [`from_points`](#method.from_points), but calls `progress` with the number of items
+    /// placed so far and the total every [`PROGRESS_INTERVAL`] items and once the build is done.
+    /// Returning `ControlFlow::Break` from `progress` discards the partially built tree and
+    /// returns `ErrorKind::Cancelled`. More items than [`max_items`](#method.max_items) return
+    /// `ErrorKind::Full`.
+    pub fn from_points_with_progress<A, T, P, const D: usize>(
+        self,
+        mut items: Vec<([A; D], T)>,
+        mut progress: P,
+    ) -> Result<OwnedKdTree<A, T, D>, ErrorKind>
+    where
+        A: Float + Zero + One,
+        T: PartialEq,
+        P: FnMut(usize, usize) -> ControlFlow<()>,
+    {
+        let capacity = self.prepare(&mut items)?;
+        let mut progress = Progress::new(items.len(), &mut progress);
+        let mut tree =
+            OwnedKdTree::build(items, capacity, self.leaf_capacity_fn, 0, &mut progress)?;
+        tree.max_items = self.max_items;
+        tree.split_rules = self.split_rules();
+        Ok(tree)
+    }
+
+    /// Like [`from_points`](#method.from_points), but splits large nodes and builds their
+    /// subtrees in parallel. The tree is identical to the one `from_points` builds from the same
+    /// items, down to the order of the items in each leaf.
+    #[cfg(feature = "rayon")]
+    pub fn par_from_points<A, T, const D: usize>(
+        self,
+        mut items: Vec<([A; D], T)>,
+    ) -> Result<OwnedKdTree<A, T, D>, ErrorKind>
+    where
+        A: Float + Zero + One + Send + Sync,
+        T: PartialEq + Send + Sync,
+    {
+        let capacity = self.prepare(&mut items)?;
+        let mut tree = crate::par_iter::par_build(items, capacity, self.leaf_capacity_fn, 0);
+        tree.max_items = self.max_items;
+        tree.split_rules = self.split_rules();
+        Ok(tree)
+    }
+
+    /// Checks `items` against the settings and puts them in build order, returning the
+    /// capacity of the leaves
+    fn prepare<A, T, const D: usize>(&self, items: &mut [([A; D], T)]) -> Result<usize, ErrorKind>
+    where
+        A: Float + Zero + One,
+        T: PartialEq,
+    {
+        let capacity = self.capacity_for(D);
+        if capacity == 0 {
+            return Err(ErrorKind::ZeroCapacity);
+        }
+        if self.max_items.is_some_and(|max| items.len() > max) {
+            return Err(ErrorKind::Full);
+        }
+        let root: OwnedKdTree<A, T, D> = OwnedKdTree::with_capacity(capacity);
+        for (point, _) in items.iter() {
+            root.check_point(point)?;
+        }
+        if self.order != BuildOrder::Unsorted {
+            let mut min = [A::infinity(); D];
+            let mut max = [A::neg_infinity(); D];
+            for (point, _) in items.iter() {
+                for dim in 0..D {
+                    min[dim] = min[dim].min(point[dim]);
+                    max[dim] = max[dim].max(point[dim]);
+                }
+            }
+            let bits = curve::bits_per_dimension(D);
+            let index = match self.order {
+                BuildOrder::Hilbert => curve::hilbert_index,
+                _ => curve::morton_index,
+            };
+            items.sort_by_cached_key(|(p, _)| index(p, &min, &max, bits));
+        }
+        Ok(capacity)
+    }
+}
+
+/// The capacity of the leaves at `depth` of a tree scheduling them with `leaf_capacity_fn`, or
+/// `capacity` for a tree without a schedule
+pub(crate) fn leaf_capacity_at(
+    leaf_capacity_fn: Option<fn(usize) -> usize>,
+    capacity: usize,
+    depth: usize,
+) -> usize {
+    match leaf_capacity_fn {
+        Some(f) => f(depth).max(1),
+        None => capacity,
+    }
+}
+
+/// The value splitting `items` along `dim` at `median` when building a tree, given the bounds
+/// `[min, max]` of the items along `dim`
+pub(crate) fn build_split_value<A: Float, T, const D: usize>(
+    items: &[([A; D], T)],
+    dim: usize,
+    median: A,
+    min: A,
+    max: A,
+) -> A {
+    if median > min {
+        return median;
+    }
+    // the lower half is made of duplicates of the minimum, split right above it so the left
+    // child is not empty
+    items
+        .iter()
+        .map(|(p, _)| p[dim])
+        .filter(|&v| v > min)
+        .fold(max, A::min)
+}
+
+/// The number of queries sharing one traversal in `nearest_multi`
+const MULTI_QUERY_GROUP: usize = 8;
+
+/// How many items are processed between two calls of a progress callback
+pub const PROGRESS_INTERVAL: usize = 4096;
+
+struct Progress<'a> {
+    done: usize,
+    total: usize,
+    next_report: usize,
+    callback: &'a mut dyn FnMut(usize, usize) -> ControlFlow<()>,
+}
+
+impl<'a> Progress<'a> {
+    fn new(total: usize, callback: &'a mut dyn FnMut(usize, usize) -> ControlFlow<()>) -> Self {
+        Progress {
+            done: 0,
+            total,
+            next_report: PROGRESS_INTERVAL.min(total),
+            callback,
+        }
+    }
+
+    fn advance(&mut self, items: usize) -> Result<(), ErrorKind> {
+        self.done += items;
+        if self.done < self.next_report {
+            return Ok(());
+        }
+        self.next_report = (self.done + PROGRESS_INTERVAL).min(self.total);
+        match (self.callback)(self.done, self.total) {
+            ControlFlow::Continue(()) => Ok(()),
+            ControlFlow::Break(()) => Err(ErrorKind::Cancelled),
+        }
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder::new()
+    }
+}
+
+impl<A: Float + Zero + One, T: std::cmp::PartialEq, const D: usize> Default
+    for OwnedKdTree<A, T, D>
+{
+    fn default() -> Self {
+        OwnedKdTree::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+    use super::{LeafVec, Node, OwnedKdTree, TreeStats};
+    use crate::distance::squared_euclidean;
+
+    fn random_point() -> ([f64; 2], i32) {
+        rand::random::<([f64; 2], i32)>()
+    }
+
+    fn dist<const D: usize>(a: &[f64; D], b: &[f64; D]) -> f64 {
+        squared_euclidean(a, b)
+    }
+
+    #[test]
+    fn it_matches_the_model() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+        use crate::test_support::{model_check, model_check_with_capacity, Op};
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[6, 2, 8][..]);
+        let mut ops = vec![];
+        let mut added = vec![];
+        for i in 0..2000 {
+            // a coarse grid, so that there are duplicate points
+            let point = [
+                rng.gen_range(0, 20) as f64,
+                rng.gen_range(0, 20) as f64 / 4.0,
+            ];
+            let op = match rng.gen_range(0, 10) {
+                0..=4 => {
+                    added.push((point, i % 50));
+                    Op::Add(point, i % 50)
+                }
+                5 if !added.is_empty() => {
+                    let (point, data) = added[rng.gen_range(0, added.len())];
+                    Op::Remove(point, data)
+                }
+                5 | 6 => Op::Remove(point, i % 50),
+                7 | 8 => Op::Nearest {
+                    point,
+                    k: rng.gen_range(0, 12),
+                },
+                _ => Op::Within {
+                    point,
+                    radius: rng.gen_range(0.0, 30.0),
+                },
+            };
+            ops.push(op);
+        }
+        model_check(ops.clone());
+        model_check_with_capacity(ops.clone(), 1);
+        model_check_with_capacity(ops, 16);
+    }
+
+    // small enough to run under `cargo +nightly miri test --lib disjoint`, which checks that the
+    // references do not alias; the miri job of .travis.yml runs it
+    #[test]
+    fn it_hands_out_disjoint_mutable_payloads() {
+        let mut tree = OwnedKdTree::with_capacity(2);
+        for i in 0..40 {
+            tree.add([(i % 7) as f64, (i % 3) as f64], i).unwrap();
+        }
+        // leave some emptied subtrees behind
+        for i in 0..10 {
+            tree.remove(&[(i % 7) as f64, (i % 3) as f64], &i).unwrap();
+        }
+
+        // hold every reference at once, then write through all of them
+        let mut all: Vec<&mut i32> = tree
+            .iter_nearest_mut(&[3.0, 1.0], &dist)
+            .unwrap()
+            .map(|(_, d)| d)
+            .collect();
+        assert_eq!(all.len(), 30);
+        for d in all.iter_mut() {
+            **d += 100;
+        }
+        let mut payloads: Vec<i32> = tree.iter().map(|(_, &d)| d).collect();
+        payloads.sort_unstable();
+        assert_eq!(payloads, (110..140).collect::<Vec<_>>());
+
+        let nearest = tree
+            .iter_nearest_mut(&[0.0, 0.0], &dist)
+            .unwrap()
+            .take(3)
+            .map(|(d, _)| d)
+            .collect::<Vec<_>>();
+        assert!(nearest.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn it_has_default_capacity() {
+        let tree: OwnedKdTree<f64, i32, 2> = OwnedKdTree::new();
+        assert_eq!(tree.capacity, 2_usize.pow(4));
+        assert_eq!(tree.capacity, super::DEFAULT_LEAF_CAPACITY);
+        let tree: OwnedKdTree<f64, i32, 2> = super::Builder::new().build();
+        assert_eq!(tree.capacity, super::DEFAULT_LEAF_CAPACITY);
+    }
+
+    #[test]
+    fn it_picks_capacities_per_dimension() {
+        use super::{leaf_capacity_for_dimensions as per_dimension, Builder};
+
+        let capacities: Vec<usize> = [1, 2, 3, 4, 8, 12, 16, 64].map(per_dimension).to_vec();
+        assert_eq!(capacities, vec![64, 64, 42, 32, 16, 10, 8, 8]);
+        assert_eq!(
+            OwnedKdTree::<f64, i32, 2>::with_per_dimension_default().capacity,
+            64
+        );
+        assert_eq!(
+            OwnedKdTree::<f64, i32, 16>::with_per_dimension_default().capacity,
+            8
+        );
+
+        let built: OwnedKdTree<f64, i32, 4> = Builder::new().per_dimension_capacity().build();
+        assert_eq!(built.capacity, 32);
+        let overridden: OwnedKdTree<f64, i32, 4> =
+            Builder::new().per_dimension_capacity().capacity(3).build();
+        assert_eq!(overridden.capacity, 3);
+        let items = (0..100).map(|i| ([i as f64; 8], i)).collect();
+        let from_points = Builder::new().per_dimension_capacity().from_points(items);
+        assert_eq!(from_points.unwrap().capacity, 16);
+    }
+
+    #[test]
+    fn it_schedules_capacities_by_depth() {
+        use super::{Builder, NodeRef};
+
+        fn schedule(depth: usize) -> usize {
+            std::cmp::max(4, 64 >> depth.min(63))
+        }
+        // the depths and capacities of every node, checking that no leaf holds too many items
+        fn capacities(node: NodeRef<f64, usize, 2>, depth: usize, found: &mut Vec<(usize, usize)>) {
+            found.push((depth, node.capacity()));
+            match (node.left(), node.right()) {
+                (Some(left), Some(right)) => {
+                    capacities(left, depth + 1, found);
+                    capacities(right, depth + 1, found);
+                }
+                _ => assert!(node.size() <= node.capacity()),
+            }
+        }
+
+        let items: Vec<([f64; 2], usize)> = (0..5000)
+            .map(|i| ([(i % 70) as f64, (i / 70) as f64 * 1.5], i))
+            .collect();
+        let builder = || {
+            Builder::new()
+                .capacity(1000)
+                .leaf_capacity_fn(Some(schedule))
+        };
+        let built = builder().from_points(items.clone()).unwrap();
+        let mut added = builder().build();
+        for &(point, i) in &items {
+            added.add(point, i).unwrap();
+        }
+        for tree in [built, added] {
+            let mut found = vec![];
+            capacities(tree.root(), 0, &mut found);
+            let depths: Vec<usize> = found.iter().map(|&(depth, _)| depth).collect();
+            assert!(depths.contains(&0) && depths.contains(&3) && depths.contains(&6));
+            for (depth, capacity) in found {
+                assert_eq!(capacity, schedule(depth));
+            }
+            assert!(tree.stats().max_leaf_size <= 64);
+        }
+
+        // without a schedule every node has the capacity set
+        let default = Builder::new().capacity(1000).from_points(items).unwrap();
+        assert_eq!(default.stats().leaves, 8);
+        assert_eq!(default.root().capacity(), 1000);
+    }
+
+    #[test]
+    fn it_splits_at_the_mean_of_the_most_varied_axis() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+        use super::{Builder, SplitStrategy};
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[16, 6][..]);
+        // heavy tailed in both dimensions: most points lie within a few units of the origin but
+        // a few lie thousands of units out, leaving the middle of most boxes empty
+        let mut pareto = || 1.0 / rng.gen_range(1e-9, 1.0) - 1.0;
+        let items: Vec<([f64; 2], usize)> = (0..4000).map(|i| ([pareto(), pareto()], i)).collect();
+        let build = |strategy| {
+            let mut tree = Builder::new().capacity(8).split_strategy(strategy).build();
+            for &(point, i) in &items {
+                tree.add(point, i).unwrap();
+            }
+            tree
+        };
+        let widest = build(SplitStrategy::Widest);
+        let variance = build(SplitStrategy::MaxVariance);
+        for tree in [&widest, &variance] {
+            assert_eq!(tree.size(), items.len());
+            assert!(tree.stats().max_leaf_size <= 8);
+            let mut ids: Vec<usize> = tree.iter().map(|(_, &i)| i).collect();
+            ids.sort_unstable();
+            assert!(ids.into_iter().eq(0..items.len()));
+        }
+        assert!(variance.stats().max_depth < widest.stats().max_depth);
+
+        // the fewest node visits a search needs to be sure of the 5 points nearest to a point
+        // slightly off each of a sample of the items
+        let visits = |tree: &OwnedKdTree<f64, usize, 2>| -> usize {
+            (0..300)
+                .map(|i| {
+                    let p = items[i * 13].0;
+                    let query = [p[0] + 0.003, p[1] - 0.002];
+                    let expected = tree.nearest(&query, 5, &dist).unwrap();
+                    (1..)
+                        .find(|&budget| {
+                            let (nearest, complete) =
+                                tree.nearest_budgeted(&query, 5, &dist, budget).unwrap();
+                            complete && nearest == expected
+                        })
+                        .unwrap()
+                })
+                .sum()
+        };
+        assert!(visits(&variance) < visits(&widest));
+
+        // points all at one place are left in one leaf
+        let mut same = Builder::new()
+            .capacity(2)
+            .split_strategy(SplitStrategy::MaxVariance)
+            .build();
+        for i in 0..5 {
+            same.add([1.0, 2.0], i).unwrap();
+        }
+        assert_eq!(same.stats().leaves, 1);
+    }
+
+    #[test]
+    fn it_finds_points_exactly_at_split_values() {
+        use super::NodeRef;
+
+        fn split_values(node: NodeRef<f64, usize, 2>, found: &mut Vec<(usize, f64)>) {
+            if let (Some(dim), Some(value)) = (node.split_dimension(), node.split_value()) {
+                found.push((dim, value));
+                split_values(node.left().unwrap(), found);
+                split_values(node.right().unwrap(), found);
+            }
+        }
+        // every query and removal at `point` has to see exactly the items of `items` there
+        fn check(
+            tree: &mut OwnedKdTree<f64, usize, 2>,
+            items: &[([f64; 2], usize)],
+            point: [f64; 2],
+        ) {
+            tree.debug_validate();
+            let mut expected: Vec<usize> = items
+                .iter()
+                .filter(|(p, _)| *p == point)
+                .map(|&(_, i)| i)
+                .collect();
+            let ids = |found: Vec<(f64, &usize)>| {
+                let mut ids: Vec<usize> = found.into_iter().map(|(_, &i)| i).collect();
+                ids.sort_unstable();
+                ids
+            };
+            assert_eq!(ids(tree.within(&point, 0.0, &dist).unwrap()), expected);
+            let nearest = tree.nearest(&point, expected.len(), &dist).unwrap();
+            assert!(nearest.iter().all(|&(d, _)| d == 0.0));
+            assert_eq!(ids(nearest), expected);
+            let mut in_box: Vec<usize> = tree
+                .within_box(&point, &point)
+                .unwrap()
+                .into_iter()
+                .copied()
+                .collect();
+            in_box.sort_unstable();
+            assert_eq!(in_box, expected);
+
+            let mut removed = tree.remove_at(&point).unwrap();
+            removed.sort_unstable();
+            assert_eq!(removed, expected);
+            tree.debug_validate();
+            assert!(tree.within(&point, 0.0, &dist).unwrap().is_empty());
+            // added back in reverse, so the leaves may split differently
+            expected.reverse();
+            for i in expected {
+                tree.add(point, i).unwrap();
+            }
+            tree.debug_validate();
+            assert_eq!(tree.size(), items.len());
+        }
+
+        // the midpoint of many of the ranges of these coordinates is itself a coordinate, and
+        // so is the median of most of the subsets
+        let coordinates = [0.0, 1.0, 2.0, 4.0, 8.0, 16.0];
+        let items: Vec<([f64; 2], usize)> = (0..600)
+            .map(|i| ([coordinates[i % 6], coordinates[i * 7 / 6 % 6]], i))
+            .collect();
+        let built = OwnedKdTree::from_points(items.clone(), 3).unwrap();
+        let mut added = OwnedKdTree::with_capacity(3);
+        for &(point, i) in &items {
+            added.add(point, i).unwrap();
+        }
+        for mut tree in [built, added] {
+            let mut splits = vec![];
+            split_values(tree.root(), &mut splits);
+            let on_plane: Vec<[f64; 2]> = items
+                .iter()
+                .map(|&(p, _)| p)
+                .filter(|p| splits.iter().any(|&(dim, value)| p[dim] == value))
+                .collect();
+            assert!(on_plane.len() > items.len() / 2);
+            for point in on_plane {
+                check(&mut tree, &items, point);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "a point lies on the wrong side of a split above it")]
+    fn it_validates_the_side_of_every_split() {
+        let items = (0..20).map(|i| ([i as f64, 0.0], i)).collect();
+        let mut tree = OwnedKdTree::from_points(items, 10).unwrap();
+        tree.debug_validate();
+        assert_eq!(tree.stats().leaves, 2);
+        // a point of the left leaf moved onto the split, within the bounds of the root but where
+        // a search looking for it would take the right leaf
+        let (split_value, left) = match &mut tree.node {
+            Node::Stem {
+                split_value, left, ..
+            } => (*split_value, left),
+            Node::Leaf { .. } => unreachable!(),
+        };
+        if let Node::Leaf { points, .. } = &mut left.node {
+            points[0][0] = split_value;
+        }
+        left.max_bounds[0] = split_value;
+        tree.debug_validate();
+    }
+
+    #[test]
+    fn it_tunes_the_leaf_capacity_to_the_queries() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+        use super::ErrorKind;
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[16, 9][..]);
+        // tight clusters far apart, where a query for the nearest item only has to look into
+        // its own cluster and every point of a big leaf there is wasted work
+        let centers: Vec<[f64; 2]> = (0..100)
+            .map(|_| [rng.gen_range(0.0, 100.0), rng.gen_range(0.0, 100.0)])
+            .collect();
+        let mut jitter =
+            |c: [f64; 2], r: f64| [c[0] + rng.gen_range(-r, r), c[1] + rng.gen_range(-r, r)];
+        let items: Vec<([f64; 2], usize)> = (0..20000)
+            .map(|i| (jitter(centers[i % 100], 0.01), i))
+            .collect();
+        let queries: Vec<[f64; 2]> = (0..100)
+            .map(|i| jitter(centers[i * 7 % 100], 0.02))
+            .collect();
+        let tree = OwnedKdTree::from_points(items.clone(), 16).unwrap();
+        let work = |capacity| {
+            let tree = OwnedKdTree::from_points(items.clone(), capacity).unwrap();
+            let mut work = 0;
+            for query in &queries {
+                let (nearest, stats) = tree.nearest_with_stats(query, 1, &dist).unwrap();
+                assert_eq!(nearest, tree.nearest(query, 1, &dist).unwrap());
+                assert!(stats.leaves_visited >= 1);
+                assert!(stats.nodes_visited > stats.leaves_visited);
+                work += stats.nodes_visited + stats.points_visited;
+            }
+            work
+        };
+
+        let candidates = [2, 4, 8, 16, 32, 64, 128];
+        let tuned = tree
+            .tune_leaf_capacity(&queries, 1, &dist, &candidates)
+            .unwrap();
+        assert!(tuned < 16 && work(tuned) < work(16));
+        assert!(candidates.iter().all(|&c| work(tuned) <= work(c)));
+
+        assert_eq!(tree.tune_leaf_capacity(&queries, 1, &dist, &[]), Ok(16));
+        assert_eq!(
+            tree.tune_leaf_capacity(&queries, 1, &dist, &[8, 0]),
+            Err(ErrorKind::ZeroCapacity)
+        );
+        assert_eq!(
+            tree.tune_leaf_capacity(&[[f64::NAN, 0.0]], 1, &dist, &candidates),
+            Err(ErrorKind::NonFiniteCoordinate)
+        );
+    }
+
+    #[test]
+    fn it_groups_items_at_the_same_point() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+        use std::time::Instant;
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[17, 8][..]);
+        let hotspot = [3.0, 4.0];
+        let mut items: Vec<([f64; 2], usize)> = (0..100_000).map(|i| (hotspot, i)).collect();
+        for i in 0..5000 {
+            // snapped to a grid, so that other points repeat too
+            let point = [
+                (rng.gen_range(0.0, 10.0) * 4.0f64).round() / 4.0,
+                (rng.gen_range(0.0, 10.0) * 4.0f64).round() / 4.0,
+            ];
+            items.push((point, 100_000 + i));
+        }
+        let tree = OwnedKdTree::from_points(items, 16).unwrap();
+        for &(query, radius) in &[(hotspot, 1.0), ([3.1, 4.0], 0.0), ([8.0, 2.0], 2.0)] {
+            let grouped = tree.within_grouped(&query, radius, &dist).unwrap();
+            let mut flattened: Vec<(f64, usize)> = vec![];
+            for (i, (d, point, items)) in grouped.iter().enumerate() {
+                assert_eq!(*d, dist(&query, point));
+                assert!(!items.is_empty());
+                if let Some((previous, previous_point, _)) = i.checked_sub(1).map(|i| &grouped[i]) {
+                    assert!(previous < d || (previous == d && previous_point < point));
+                }
+                flattened.extend(items.iter().map(|&&i| (*d, i)));
+            }
+            let mut within: Vec<(f64, usize)> = tree
+                .within(&query, radius, &dist)
+                .unwrap()
+                .into_iter()
+                .map(|(d, &i)| (d, i))
+                .collect();
+            flattened.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            within.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert_eq!(flattened, within);
+        }
+        let at_hotspot = tree.within_grouped(&hotspot, 0.0, &dist).unwrap();
+        assert_eq!(at_hotspot.len(), 1);
+        // grid points may land on the hotspot too
+        let mut ids: Vec<usize> = at_hotspot[0].2.iter().map(|&&i| i).collect();
+        ids.sort_unstable();
+        assert!(ids[..100_000].iter().copied().eq(0..100_000));
+
+        let fastest = |query: &dyn Fn()| {
+            (0..3)
+                .map(|_| {
+                    let start = Instant::now();
+                    query();
+                    start.elapsed()
+                })
+                .min()
+                .unwrap()
+        };
+        let grouped = fastest(&|| {
+            tree.within_grouped(&hotspot, 0.5, &dist).unwrap();
+        });
+        let within = fastest(&|| {
+            tree.within(&hotspot, 0.5, &dist).unwrap();
+        });
+        assert!(grouped * 2 < within, "{:?} {:?}", grouped, within);
+    }
+
+    #[test]
+    fn it_edits_and_retains_items_in_a_box_like_a_list() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+
+        fn in_box(p: &[f64; 2], min: &[f64; 2], max: &[f64; 2]) -> bool {
+            (0..2).all(|d| min[d] <= p[d] && p[d] <= max[d])
+        }
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[18, 2][..]);
+        let mut tree = OwnedKdTree::<f64, (usize, u32), 2>::with_capacity(4);
+        let mut model: Vec<([f64; 2], (usize, u32))> = vec![];
+        // integer coordinates and bounds put many points exactly on the edges of the boxes
+        let coordinate = |rng: &mut StdRng| rng.gen_range(0, 20) as f64;
+        for step in 0..3000 {
+            let (a, b) = (
+                [coordinate(&mut rng), coordinate(&mut rng)],
+                [coordinate(&mut rng), coordinate(&mut rng)],
+            );
+            let min = [a[0].min(b[0]), a[1].min(b[1])];
+            let max = [a[0].max(b[0]), a[1].max(b[1])];
+            match rng.gen_range(0, 4) {
+                0 => {
+                    for data in tree.within_box_mut(&min, &max).unwrap() {
+                        data.1 += 1;
+                    }
+                    for (p, data) in &mut model {
+                        if in_box(p, &min, &max) {
+                            data.1 += 1;
+                        }
+                    }
+                }
+                1 => {
+                    let keep = |p: &[f64; 2], data: &mut (usize, u32)| {
+                        data.1 += 1;
+                        (p[0] + p[1]) as usize % 3 != data.0 % 3
+                    };
+                    let removed = tree.retain_in_box(&min, &max, keep).unwrap();
+                    let before = model.len();
+                    for (p, data) in &mut model {
+                        if in_box(p, &min, &max) && !keep(p, data) {
+                            data.0 = usize::MAX;
+                        }
+                    }
+                    model.retain(|(_, data)| data.0 != usize::MAX);
+                    assert_eq!(removed, before - model.len());
+                }
+                _ => {
+                    let point = [coordinate(&mut rng), coordinate(&mut rng)];
+                    tree.add(point, (step, 0)).unwrap();
+                    model.push((point, (step, 0)));
+                }
+            }
+            let mut found: Vec<(usize, u32)> = tree
+                .within_box(&min, &max)
+                .unwrap()
+                .into_iter()
+                .copied()
+                .collect();
+            let mut expected: Vec<(usize, u32)> = model
+                .iter()
+                .filter(|(p, _)| in_box(p, &min, &max))
+                .map(|&(_, data)| data)
+                .collect();
+            found.sort_unstable();
+            expected.sort_unstable();
+            assert_eq!(found, expected);
+            assert_eq!(tree.size(), model.len());
+        }
+        tree.debug_validate();
+        let mut all: Vec<_> = tree.iter().map(|(p, &data)| (*p, data)).collect();
+        all.sort_by_key(|a| a.1);
+        model.sort_by_key(|a| a.1);
+        assert_eq!(all, model);
+        assert!(tree.within_box_mut(&[f64::NAN, 0.0], &[1.0, 1.0]).is_err());
+        assert!(tree
+            .retain_in_box(&[0.0, 0.0], &[f64::INFINITY, 1.0], |_, _| false)
+            .is_err());
+        // unloading a tile leaves its neighbours alone, even those on its edge
+        let size = tree.size();
+        let on_edge = tree.within_box(&[10.0, 0.0], &[10.0, 19.0]).unwrap().len();
+        let tile = tree.within_box(&[0.0, 0.0], &[9.5, 19.0]).unwrap().len();
+        assert_eq!(
+            tree.retain_in_box(&[0.0, 0.0], &[9.5, 19.0], |_, _| false),
+            Ok(tile)
+        );
+        assert_eq!(tree.size(), size - tile);
+        assert_eq!(
+            tree.within_box(&[10.0, 0.0], &[10.0, 19.0]).unwrap().len(),
+            on_edge
+        );
+        assert!(tree
+            .within_box(&[0.0, 0.0], &[9.5, 19.0])
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            tree.retain_in_box(&[0.0, 0.0], &[19.0, 19.0], |_, _| false),
+            Ok(size - tile)
+        );
+        assert_eq!(tree.size(), 0);
+        tree.debug_validate();
+    }
+
+    #[test]
+    fn it_crops_to_a_box_like_a_filter() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+
+        fn in_box(p: &[f64; 2], min: &[f64; 2], max: &[f64; 2]) -> bool {
+            (0..2).all(|d| min[d] <= p[d] && p[d] <= max[d])
+        }
+
+        fn items(tree: &OwnedKdTree<f64, usize, 2>) -> Vec<([f64; 2], usize)> {
+            tree.debug_validate();
+            let mut items: Vec<_> = tree.iter().map(|(p, &data)| (*p, data)).collect();
+            items.sort_by_key(|a| a.1);
+            assert_eq!(tree.size(), items.len());
+            items
+        }
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[19, 9, 1][..]);
+        let mut tree = OwnedKdTree::<f64, usize, 2>::with_capacity(4);
+        // integer coordinates put many points exactly on the edges of the boxes
+        let coordinate = |rng: &mut StdRng| rng.gen_range(0, 20) as f64;
+        for i in 0..2000 {
+            let point = [coordinate(&mut rng), coordinate(&mut rng)];
+            tree.add(point, i).unwrap();
+        }
+        let all = items(&tree);
+        for _ in 0..200 {
+            let (a, b) = (
+                [coordinate(&mut rng), coordinate(&mut rng)],
+                [coordinate(&mut rng), coordinate(&mut rng)],
+            );
+            let min = [a[0].min(b[0]), a[1].min(b[1])];
+            let max = [a[0].max(b[0]), a[1].max(b[1])];
+            let (inside, outside): (Vec<_>, Vec<_>) =
+                all.iter().partition(|(p, _)| in_box(p, &min, &max));
+            assert_eq!(items(&tree.crop(&min, &max).unwrap()), inside);
+            let mut rest = tree.clone();
+            let generation = rest.generation();
+            assert_eq!(items(&rest.crop_into(&min, &max).unwrap()), inside);
+            assert_eq!(items(&rest), outside);
+            assert_eq!(rest.generation() > generation, !inside.is_empty());
+        }
+        assert!(tree.crop(&[f64::NAN, 0.0], &[1.0, 1.0]).is_err());
+        assert!(tree
+            .clone()
+            .crop_into(&[0.0, 0.0], &[f64::INFINITY, 1.0])
+            .is_err());
+        // a box around everything takes the tree as it is
+        let whole = tree.crop(&[0.0, 0.0], &[19.0, 19.0]).unwrap();
+        assert_eq!(whole.stats(), tree.stats());
+        let mut rest = tree.clone();
+        assert_eq!(
+            rest.crop_into(&[0.0, 0.0], &[19.0, 19.0]).unwrap().stats(),
+            tree.stats()
+        );
+        assert_eq!(rest.size(), 0);
+        assert!(tree
+            .crop(&[30.0, 30.0], &[40.0, 40.0])
+            .unwrap()
+            .iter()
+            .next()
+            .is_none());
+
+        // boxes ending exactly at the splits of a balanced tree, whose points at the split
+        // value are in the right child but inside both boxes
+        let grid: Vec<([f64; 2], usize)> = (0..256)
+            .map(|i| ([(i % 16) as f64, (i / 16) as f64], i))
+            .collect();
+        let tree = OwnedKdTree::from_points(grid.clone(), 4).unwrap();
+        let root = tree.root();
+        let (dim, value) = (root.split_dimension().unwrap(), root.split_value().unwrap());
+        let child = root.left().unwrap();
+        let (child_dim, child_value) = (
+            child.split_dimension().unwrap(),
+            child.split_value().unwrap(),
+        );
+        let (mut below, mut above) = ([0.0; 2], [15.0; 2]);
+        below[dim] = value;
+        above[dim] = value;
+        let mut corner = [15.0; 2];
+        corner[dim] = value;
+        corner[child_dim] = child_value;
+        for &(min, max) in &[([0.0; 2], below), (above, [15.0; 2]), ([0.0; 2], corner)] {
+            let inside: Vec<_> = grid
+                .iter()
+                .filter(|(p, _)| in_box(p, &min, &max))
+                .copied()
+                .collect();
+            assert_eq!(items(&tree.crop(&min, &max).unwrap()), inside);
+            let mut rest = tree.clone();
+            assert_eq!(items(&rest.crop_into(&min, &max).unwrap()), inside);
+            assert_eq!(rest.size(), 256 - inside.len());
+            rest.debug_validate();
+        }
+
+        // a crop of a tree with declared bounds keeps them
+        let mut tree = OwnedKdTree::with_bounds(4, [0.0; 2], [16.0; 2]).unwrap();
+        for &(point, data) in &grid {
+            tree.add(point, data).unwrap();
+        }
+        let mut cropped = tree.crop(&[0.0, 0.0], &[3.0, 3.0]).unwrap();
+        assert_eq!(cropped.size(), 16);
+        assert_eq!(cropped.declared_bounds(), Some(([0.0; 2], [16.0; 2])));
+        cropped.add([12.0, 12.0], 256).unwrap();
+        assert_eq!(
+            cropped.add([17.0, 0.0], 257),
+            Err(super::ErrorKind::OutOfBounds { dim: 0 })
+        );
+        let taken = tree.crop_into(&[4.0, 4.0], &[16.0, 16.0]).unwrap();
+        assert_eq!((taken.size(), tree.size()), (144, 112));
+        assert_eq!(taken.declared_bounds(), tree.declared_bounds());
+        taken.debug_validate();
+        tree.debug_validate();
+
+        // moving everything out leaves an empty tree with the same settings
+        let mut rest = tree.clone();
+        let generation = rest.generation();
+        let taken = rest.crop_into(&[0.0; 2], &[16.0; 2]).unwrap();
+        assert_eq!((taken.size(), rest.size()), (112, 0));
+        assert_eq!(rest.generation(), generation + 1);
+        assert_eq!(rest.declared_bounds(), Some(([0.0; 2], [16.0; 2])));
+        assert_eq!(taken.declared_bounds(), rest.declared_bounds());
+        assert_eq!(
+            rest.add([17.0, 0.0], 0),
+            Err(super::ErrorKind::OutOfBounds { dim: 0 })
+        );
+        assert!(rest
+            .crop_into(&[0.0; 2], &[16.0; 2])
+            .unwrap()
+            .iter()
+            .next()
+            .is_none());
+        assert_eq!(rest.generation(), generation + 1);
+        let mut limited = super::Builder::new()
+            .capacity(2)
+            .max_items(Some(10))
+            .build::<f64, usize, 2>();
+        for i in 0..5 {
+            limited.add([i as f64, 0.0], i).unwrap();
+        }
+        let taken = limited.crop_into(&[0.0; 2], &[4.0, 0.0]).unwrap();
+        assert_eq!((taken.size(), limited.size()), (5, 0));
+        assert_eq!((limited.generation(), limited.max_items()), (6, Some(10)));
+        assert_eq!(taken.max_items(), Some(10));
+        for i in 0..10 {
+            limited.add([i as f64, 0.0], i).unwrap();
+        }
+        assert_eq!(limited.add([0.0, 1.0], 10), Err(super::ErrorKind::Full));
+    }
+
+    #[test]
+    fn it_clones_only_the_payloads_it_keeps() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+        use std::cell::Cell;
+
+        struct Counted<'a>(usize, &'a Cell<usize>);
+        impl<'a> Clone for Counted<'a> {
+            fn clone(&self) -> Self {
+                self.1.set(self.1.get() + 1);
+                Counted(self.0, self.1)
+            }
+        }
+        impl<'a> PartialEq for Counted<'a> {
+            fn eq(&self, other: &Self) -> bool {
+                self.0 == other.0
+            }
+        }
+
+        let clones = Cell::new(0);
+        let mut rng: StdRng = SeedableRng::from_seed(&[18, 3][..]);
+        let items = (0..2000)
+            .map(|i| {
+                (
+                    [rng.gen_range(0.0, 10.0), rng.gen_range(0.0, 10.0)],
+                    Counted(i, &clones),
+                )
+            })
+            .collect();
+        let tree = OwnedKdTree::<f64, Counted, 2>::from_points(items, 8).unwrap();
+        let ids = |found: Vec<(f64, &Counted)>| -> Vec<(f64, usize)> {
+            found.into_iter().map(|(d, c)| (d, c.0)).collect()
+        };
+        let owned_ids = |found: Vec<(f64, Counted)>| -> Vec<(f64, usize)> {
+            found.into_iter().map(|(d, c)| (d, c.0)).collect()
+        };
+        for _ in 0..20 {
+            let point = [rng.gen_range(0.0, 10.0), rng.gen_range(0.0, 10.0)];
+            clones.set(0);
+            let nearest = tree.nearest_owned(&point, 10, &dist).unwrap();
+            assert!(clones.get() >= 10 && clones.get() < 100, "{}", clones.get());
+            assert_eq!(
+                owned_ids(nearest),
+                ids(tree.nearest(&point, 10, &dist).unwrap())
+            );
+
+            clones.set(0);
+            let within = tree.within_owned(&point, 1.5, &dist).unwrap();
+            assert_eq!(clones.get(), within.len());
+            assert_eq!(
+                owned_ids(within),
+                ids(tree.within(&point, 1.5, &dist).unwrap())
+            );
+        }
+        assert!(tree.nearest_owned(&[f64::NAN, 0.0], 1, &dist).is_err());
+        assert!(tree.within_owned(&[0.0, f64::NAN], 1.0, &dist).is_err());
+    }
+
+    #[test]
+    fn it_finds_the_nearest_in_a_halfspace_like_a_brute_force_search() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+        use super::{ErrorKind, HalfSpace, QueryStats};
+        use crate::metric::ClosureMetric;
+        use crate::search;
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[18, 5][..]);
+        let items: Vec<([f64; 2], usize)> = (0..3000)
+            .map(|i| ([rng.gen_range(-10.0, 10.0), rng.gen_range(-10.0, 10.0)], i))
+            .collect();
+        let tree = OwnedKdTree::from_points(items.clone(), 8).unwrap();
+        let brute_force = |point: &[f64; 2], num: usize, normal: &[f64; 2], offset: &[f64; 2]| {
+            let mut found: Vec<(f64, usize)> = items
+                .iter()
+                .filter(|(p, _)| {
+                    normal[0] * (p[0] - offset[0]) + normal[1] * (p[1] - offset[1]) >= 0.0
+                })
+                .map(|(p, i)| (dist(point, p), *i))
+                .collect();
+            found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            found.truncate(num);
+            found
+        };
+        let mut excluded_nearest = 0;
+        for round in 0..200 {
+            let point = [rng.gen_range(-12.0, 12.0), rng.gen_range(-12.0, 12.0)];
+            let (normal, offset) = if round % 2 == 0 {
+                let normal = [rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0)];
+                (
+                    normal,
+                    [rng.gen_range(-10.0, 10.0), rng.gen_range(-10.0, 10.0)],
+                )
+            } else {
+                // facing away from the unconstrained nearest neighbour, which is then excluded
+                let nearest = tree.nearest(&point, 1, &dist).unwrap()[0].1;
+                let p = items[*nearest].0;
+                ([point[0] - p[0], point[1] - p[1]], point)
+            };
+            let num = rng.gen_range(1, 12);
+            let found: Vec<(f64, usize)> = tree
+                .nearest_in_halfspace(&point, num, &normal, &offset, &dist)
+                .unwrap()
+                .into_iter()
+                .map(|(d, &i)| (d, i))
+                .collect();
+            let expected = brute_force(&point, num, &normal, &offset);
+            assert_eq!(found.len(), expected.len());
+            for (f, e) in found.iter().zip(&expected) {
+                assert_eq!(f.0, e.0);
+            }
+            if round % 2 == 1 && !expected.is_empty() {
+                let nearest = tree.nearest(&point, 1, &dist).unwrap()[0];
+                assert!(found.iter().all(|&(_, i)| i != *nearest.1));
+                excluded_nearest += 1;
+            }
+        }
+        assert!(excluded_nearest > 75, "{}", excluded_nearest);
+
+        // with everything in front of the query excluded, the other side of it is never visited
+        let (point, normal) = ([5.0, 0.0], [-1.0, 0.0]);
+        let metric = HalfSpace {
+            metric: &ClosureMetric(&dist),
+            normal: &normal,
+            offset_point: &point,
+        };
+        let mut stats = QueryStats::default();
+        let found = search::within(&tree, &point, f64::MAX, 5, &metric, &mut stats);
+        assert_eq!(found.len(), 5);
+        let in_front = items.iter().filter(|(p, _)| p[0] > 5.5).count();
+        assert!(
+            stats.points_visited + in_front * 9 / 10 < items.len(),
+            "{:?}",
+            stats
+        );
+
+        for (normal, offset) in [([1.0, 1.0], [30.0, 30.0]), ([0.0, 1.0], [0.0, 10.0])] {
+            let found = tree.nearest_in_halfspace(&[0.0, 0.0], 3, &normal, &offset, &dist);
+            assert_eq!(
+                found.unwrap().len(),
+                brute_force(&[0.0, 0.0], 3, &normal, &offset).len()
+            );
+        }
+        assert_eq!(
+            tree.nearest_in_halfspace(&[0.0, 0.0], 3, &[0.0, 0.0], &[0.0, 0.0], &dist),
+            Err(ErrorKind::ZeroVector)
+        );
+        assert_eq!(
+            tree.nearest_in_halfspace(&[0.0, 0.0], 3, &[f64::NAN, 0.0], &[0.0, 0.0], &dist),
+            Err(ErrorKind::NonFiniteCoordinate)
+        );
+    }
+
+    #[test]
+    fn it_removes_the_items_at_a_point_a_matcher_accepts() {
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        struct Reading {
+            id: u32,
+            value: f32,
+        }
+
+        let mut tree = OwnedKdTree::<f64, Reading, 2>::with_capacity(2);
+        for id in 0..10 {
+            // NaN readings never equal themselves, so `remove` cannot find them
+            let value = if id % 2 == 0 { f32::NAN } else { id as f32 };
+            tree.add([1.0, 1.0], Reading { id, value }).unwrap();
+            tree.add([id as f64, 0.0], Reading { id, value }).unwrap();
+        }
+        let nan = Reading {
+            id: 4,
+            value: f32::NAN,
+        };
+        assert_eq!(tree.remove(&[1.0, 1.0], &nan), Ok(0));
+        let generation = tree.generation();
+        assert_eq!(tree.remove_matching(&[1.0, 1.0], |r| r.id == 4), Ok(1));
+        assert!(tree.generation() > generation);
+        assert_eq!(tree.size(), 19);
+
+        let mut asked = 0;
+        let removed = tree.remove_matching(&[1.0, 1.0], |r| {
+            asked += 1;
+            r.id % 3 == 0
+        });
+        assert_eq!(removed, Ok(4));
+        // only the items at the point are asked about
+        assert_eq!(asked, 9);
+        let mut left: Vec<u32> = tree
+            .iter()
+            .filter(|(p, _)| **p == [1.0, 1.0])
+            .map(|(_, r)| r.id)
+            .collect();
+        left.sort_unstable();
+        assert_eq!(left, [1, 2, 5, 7, 8]);
+        assert_eq!(tree.size(), 15);
+
+        let generation = tree.generation();
+        assert_eq!(tree.remove_matching(&[1.0, 1.0], |r| r.id == 4), Ok(0));
+        assert_eq!(tree.remove_matching(&[2.5, 0.0], |_| true), Ok(0));
+        assert_eq!(tree.generation(), generation);
+        assert_eq!(
+            tree.remove_matching(&[f64::NAN, 0.0], |_| true),
+            Err(super::ErrorKind::NonFiniteCoordinate)
+        );
+        assert_eq!(tree.remove_matching(&[3.0, 0.0], |_| true), Ok(1));
+        tree.debug_validate();
+    }
+
+    #[test]
+    fn it_leaves_the_sizes_alone_when_an_add_is_refused() {
+        use super::{Builder, ErrorKind, NodeRef};
+
+        /// The size and bounds of every node in preorder
+        fn shape(node: NodeRef<f64, usize, 2>, nodes: &mut Vec<(usize, [f64; 4])>) {
+            let (min, max) = node
+                .bounds()
+                .map_or(([0.0; 2], [0.0; 2]), |(a, b)| (*a, *b));
+            nodes.push((node.size(), [min[0], min[1], max[0], max[1]]));
+            if let (Some(left), Some(right)) = (node.left(), node.right()) {
+                shape(left, nodes);
+                shape(right, nodes);
+            }
+        }
+        let snapshot = |tree: &OwnedKdTree<f64, usize, 2>| {
+            let mut nodes = vec![];
+            shape(tree.root(), &mut nodes);
+            nodes
+        };
+
+        let mut tree = Builder::new()
+            .capacity(2)
+            .max_items(Some(40))
+            .build::<f64, usize, 2>();
+        for i in 0..39 {
+            let added = tree.add_if_far_enough([(i % 13) as f64, (i / 13) as f64], i, 0.1, &dist);
+            assert_eq!(added, Ok(true));
+        }
+        let before = snapshot(&tree);
+        assert!(before.len() > 20);
+        // refused by the leaf the point belongs in, after passing every stem above it
+        for i in 0..39 {
+            let near = [(i % 13) as f64 + 0.01, (i / 13) as f64];
+            assert_eq!(tree.add_if_far_enough(near, 99, 0.1, &dist), Ok(false));
+            assert_eq!(snapshot(&tree), before);
+        }
+        tree.debug_validate();
+        tree.add([20.0, 20.0], 39).unwrap();
+        let full = snapshot(&tree);
+        assert_eq!(full[0].0, 40);
+        assert_eq!(tree.add([0.5, 0.5], 40), Err(ErrorKind::Full));
+        assert_eq!(
+            tree.add_if_far_enough([30.0, 0.5], 40, 0.1, &dist),
+            Err(ErrorKind::Full)
+        );
+        assert_eq!(snapshot(&tree), full);
+        tree.debug_validate();
+
+        // a size left behind by an add which went wrong half way is caught
+        let mut drifted = tree.clone();
+        drifted.size += 1;
+        assert_eq!(
+            drifted.validate(),
+            Err("the sizes of the children of a stem do not add up")
+        );
+        if let Node::Stem { left, .. } = &mut tree.node {
+            left.size += 1;
+        }
+        assert!(tree.validate().is_err());
+    }
+
+    #[test]
+    fn it_counts_generations_of_changes() {
+        use super::{Builder, ErrorKind};
+
+        let mut tree = Builder::new()
+            .capacity(2)
+            .max_items(Some(8))
+            .build::<f64, usize, 2>();
+        let mut generation = tree.generation();
+        let mut changed = |tree: &OwnedKdTree<f64, usize, 2>, expected: bool| {
+            assert_eq!(tree.generation() != generation, expected);
+            generation = tree.generation();
+        };
+        for i in 0..4 {
+            // the leaves split on the way without counting
+            tree.add([i as f64, 0.0], i).unwrap();
+            changed(&tree, true);
+        }
+        assert_eq!(tree.generation(), 4);
+        assert!(tree.add([f64::NAN, 0.0], 9).is_err());
+        changed(&tree, false);
+        assert_eq!(tree.add_all(vec![([4.0, 0.0], 4), ([5.0, 0.0], 5)]), Ok(2));
+        changed(&tree, true);
+        assert!(tree.add_if_far_enough([6.0, 0.0], 6, 0.5, &dist).unwrap());
+        changed(&tree, true);
+        assert!(!tree.add_if_far_enough([6.0, 0.1], 7, 0.5, &dist).unwrap());
+        changed(&tree, false);
+
+        tree.nearest(&[0.0, 0.0], 3, &dist).unwrap();
+        tree.within(&[0.0, 0.0], 3.0, &dist).unwrap();
+        tree.clone().freeze();
+        changed(&tree, false);
+        tree.root_mut().into_left().unwrap().split_now();
+        changed(&tree, false);
+
+        assert_eq!(tree.remove(&[0.0, 0.0], &1), Ok(0));
+        changed(&tree, false);
+        assert_eq!(tree.remove(&[0.0, 0.0], &0), Ok(1));
+        changed(&tree, true);
+        assert_eq!(tree.take(&[1.0, 0.0], &1), Ok(vec![1]));
+        changed(&tree, true);
+        assert_eq!(tree.remove_at(&[2.0, 0.0]), Ok(vec![2]));
+        changed(&tree, true);
+        assert!(tree.remove_at(&[2.0, 0.0]).unwrap().is_empty());
+        changed(&tree, false);
+        assert_eq!(tree.remove_all(&[([3.0, 0.0], 3)]), Ok(1));
+        changed(&tree, true);
+        assert_eq!(tree.remove_all(&[([3.0, 0.0], 3)]), Ok(0));
+        changed(&tree, false);
+        let removed = tree.remove_nearest_within(&[4.2, 0.0], 1.0, &dist).unwrap();
+        assert_eq!(removed.map(|(_, _, i)| i), Some(4));
+        changed(&tree, true);
+        assert_eq!(
+            tree.remove_nearest_within(&[40.0, 0.0], 1.0, &dist),
+            Ok(None)
+        );
+        changed(&tree, false);
+
+        *tree.get_mut(&[5.0, 0.0]).unwrap().unwrap() = 50;
+        changed(&tree, true);
+        for (_, data) in tree.iter_nearest_mut(&[0.0, 0.0], &dist).unwrap() {
+            *data += 1;
+        }
+        changed(&tree, true);
+        tree.within_box_mut(&[0.0, 0.0], &[0.0, 0.0]).unwrap();
+        changed(&tree, true);
+        assert_eq!(
+            tree.retain_in_box(&[0.0, 0.0], &[9.0, 9.0], |_, _| true),
+            Ok(0)
+        );
+        changed(&tree, true);
+
+        for i in 0..6 {
+            tree.add([i as f64, 1.0], i).unwrap();
+        }
+        changed(&tree, true);
+        assert_eq!(tree.add([9.0, 9.0], 9), Err(ErrorKind::Full));
+        changed(&tree, false);
+        assert_eq!(tree.clone().generation(), tree.generation());
+
+        // emptying the tree resets its nodes but not the counter
+        let items: Vec<([f64; 2], usize)> = tree.iter().map(|(p, &d)| (*p, d)).collect();
+        for (point, data) in &items {
+            assert_eq!(tree.remove(point, data), Ok(1));
+            changed(&tree, true);
+        }
+        assert_eq!(tree.size(), 0);
+        assert!(tree.generation() > items.len() as u64);
+        for i in 0..4 {
+            tree.add([i as f64, 2.0], i).unwrap();
+        }
+        changed(&tree, true);
+        let before = tree.generation();
+        let items: Vec<([f64; 2], usize)> = tree.iter().map(|(p, &d)| (*p, d)).collect();
+        assert_eq!(tree.remove_all(&items), Ok(4));
+        assert_eq!(tree.size(), 0);
+        assert!(tree.generation() > before);
+    }
+
+    #[test]
+    fn it_resplits_subtrees_left_behind_by_drifting_items() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+        use super::Builder;
+        use std::collections::VecDeque;
+
+        fn gaussian(rng: &mut StdRng) -> f64 {
+            let (u, v): (f64, f64) = (rng.gen_range(1e-9, 1.0), rng.gen_range(0.0, 1.0));
+            (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+        }
+
+        /// The depth of the tree and the nodes visited by queries around the swarm, at each
+        /// checkpoint of a swarm of 1000 items drifting along the first axis
+        fn drift(builder: Builder) -> Vec<(usize, usize)> {
+            let mut rng: StdRng = SeedableRng::from_seed(&[17, 9][..]);
+            let mut tree = builder.capacity(8).build::<f64, usize, 2>();
+            let mut live = VecDeque::new();
+            let mut checkpoints = vec![];
+            for step in 0..20000 {
+                let centre = step as f64 * 0.01;
+                let point = [centre + gaussian(&mut rng), gaussian(&mut rng)];
+                tree.add(point, step).unwrap();
+                live.push_back((point, step));
+                if live.len() > 1000 {
+                    let (point, i) = live.pop_front().unwrap();
+                    assert_eq!(tree.remove(&point, &i), Ok(1));
+                }
+                if step % 4000 == 3999 {
+                    let visits: usize = (0..50)
+                        .map(|_| {
+                            let query = [centre + gaussian(&mut rng), gaussian(&mut rng)];
+                            let (_, stats) = tree.nearest_with_stats(&query, 5, &dist).unwrap();
+                            stats.nodes_visited
+                        })
+                        .sum();
+                    checkpoints.push((tree.stats().max_depth, visits));
+                }
+            }
+            tree.debug_validate();
+            checkpoints
+        }
+
+        let fixed = drift(Builder::new());
+        let adaptive = drift(Builder::new().resplit_imbalanced(32, 4096));
+        for (&(fixed_depth, fixed_visits), &(depth, visits)) in fixed.iter().zip(&adaptive) {
+            assert!(
+                depth <= 30 && depth < fixed_depth,
+                "{:?} {:?}",
+                fixed,
+                adaptive
+            );
+            assert!(visits < fixed_visits, "{:?} {:?}", fixed, adaptive);
+        }
+        // without rebuilding, the split planes behind the swarm pile up
+        let (first, last) = (fixed[0], fixed[fixed.len() - 1]);
+        assert!(last.0 > 3 * first.0 && last.1 > 3 * first.1, "{:?}", fixed);
+        let most = adaptive.iter().map(|&(_, visits)| visits).max().unwrap();
+        assert!(most < 2 * adaptive[0].1, "{:?}", adaptive);
+    }
+
+    #[test]
+    fn it_stops_at_the_first_item_within_the_radius() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+        use super::QueryStats;
+        use crate::metric::ClosureMetric;
+        use crate::search;
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[17, 0][..]);
+        let items: Vec<([f64; 2], usize)> = (0..20000)
+            .map(|i| ([rng.gen_range(0.0, 10.0), rng.gen_range(0.0, 10.0)], i))
+            .collect();
+        let tree = OwnedKdTree::from_points(items, 16).unwrap();
+        let (mut any_leaves, mut within_leaves) = (0, 0);
+        for _ in 0..200 {
+            let point = [rng.gen_range(-1.0, 11.0), rng.gen_range(-1.0, 11.0)];
+            let radius = rng.gen_range(0.0, 1.0);
+            let any = tree.any_within(&point, radius, &dist).unwrap();
+            assert_eq!(any, !tree.within(&point, radius, &dist).unwrap().is_empty());
+
+            let mut stats = QueryStats::default();
+            search::any_within(&tree, &point, radius, &ClosureMetric(&dist), &mut stats);
+            any_leaves += stats.leaves_visited;
+            let mut stats = QueryStats::default();
+            let metric = ClosureMetric(&dist);
+            search::within(&tree, &point, radius, tree.size(), &metric, &mut stats);
+            within_leaves += stats.leaves_visited;
+        }
+        // the points are dense enough that most balls hold a few hundred of them
+        assert!(
+            any_leaves * 20 < within_leaves,
+            "{} {}",
+            any_leaves,
+            within_leaves
+        );
+
+        let far = [100.0, 100.0];
+        assert!(!tree.any_within(&far, 1.0, &dist).unwrap());
+        assert!(tree.any_within(&far, 1e6, &dist).unwrap());
+        assert!(!OwnedKdTree::<f64, usize, 2>::new()
+            .any_within(&far, 1e6, &dist)
+            .unwrap());
+        assert_eq!(
+            tree.any_within(&[f64::NAN, 0.0], 1.0, &dist),
+            Err(super::ErrorKind::NonFiniteCoordinate)
+        );
+    }
+
+    #[test]
+    fn it_adds_only_points_far_enough_from_all_others() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+        use super::ErrorKind;
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[17, 1][..]);
+        // squared distances, so points closer than 0.5 are rejected
+        let min_dist = 0.25;
+        let mut tree = OwnedKdTree::with_capacity(4);
+        let mut sample: Vec<[f64; 2]> = vec![];
+        for i in 0..5000 {
+            let point = [rng.gen_range(0.0, 10.0), rng.gen_range(0.0, 10.0)];
+            let near = sample.iter().any(|p| dist(p, &point) <= min_dist);
+            let added = tree.add_if_far_enough(point, i, min_dist, &dist).unwrap();
+            assert_eq!(added, !near);
+            if added {
+                sample.push(point);
+            }
+        }
+        tree.debug_validate();
+        assert_eq!(tree.size(), sample.len());
+        // the square is covered long before the last candidates
+        assert!(sample.len() > 200 && sample.len() < 500);
+        let closest = sample
+            .iter()
+            .enumerate()
+            .flat_map(|(i, a)| sample[..i].iter().map(move |b| dist(a, b)))
+            .fold(f64::INFINITY, f64::min);
+        assert!(closest > min_dist);
+
+        // a point exactly at the minimum distance is too close
+        let mut pair = OwnedKdTree::with_capacity(1);
+        assert!(pair.add_if_far_enough([0.0, 0.0], 0, 1.0, &dist).unwrap());
+        assert!(!pair.add_if_far_enough([1.0, 0.0], 1, 1.0, &dist).unwrap());
+        assert!(pair.add_if_far_enough([1.5, 0.0], 2, 1.0, &dist).unwrap());
+        assert_eq!(pair.size(), 2);
+        assert_eq!(
+            pair.add_if_far_enough([f64::NAN, 0.0], 3, 1.0, &dist),
+            Err(ErrorKind::NonFiniteCoordinate)
+        );
+        // no minimum distance would let the same point in twice
+        for &min_dist in &[-1.0, f64::NAN] {
+            for _ in 0..2 {
+                assert_eq!(
+                    pair.add_if_far_enough([1.5, 0.0], 3, min_dist, &dist),
+                    Err(ErrorKind::InvalidRadius)
+                );
+            }
+        }
+        assert_eq!(pair.size(), 2);
+        assert!(!pair.add_if_far_enough([1.5, 0.0], 3, 0.0, &dist).unwrap());
+    }
+
+    #[test]
+    fn it_removes_the_nearest_item_within_a_radius() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[17, 3][..]);
+        let mut random = || [rng.gen_range(0.0, 10.0), rng.gen_range(0.0, 10.0)];
+        let mut resources: Vec<([f64; 2], usize)> = (0..2000).map(|i| (random(), i)).collect();
+        let requests: Vec<[f64; 2]> = (0..3000).map(|_| random()).collect();
+        let mut tree = OwnedKdTree::from_points(resources.clone(), 8).unwrap();
+        let radius = 0.09;
+        let mut matched = 0;
+        for request in &requests {
+            let expected = resources
+                .iter()
+                .enumerate()
+                .map(|(i, (p, _))| (dist(p, request), i))
+                .filter(|&(d, _)| d <= radius)
+                .min_by(|a, b| a.partial_cmp(b).unwrap());
+            let removed = tree.remove_nearest_within(request, radius, &dist).unwrap();
+            match (removed, expected) {
+                (Some((d, point, id)), Some((expected, i))) => {
+                    assert_eq!((d, point, id), (expected, resources[i].0, resources[i].1));
+                    resources.swap_remove(i);
+                    matched += 1;
+                }
+                (None, None) => {}
+                (removed, expected) => panic!("{:?} {:?}", removed, expected),
+            }
+            assert_eq!(tree.size(), resources.len());
+        }
+        tree.debug_validate();
+        // many requests are matched, and at least a thousand find nothing within the radius
+        assert!(matched > 500 && matched < 2000);
+
+        // draining from the last request
+        let point = requests[requests.len() - 1];
+        let mut last = 0.0;
+        while let Some((d, _, _)) = tree.remove_nearest_within(&point, 200.0, &dist).unwrap() {
+            assert!(d >= last);
+            last = d;
+        }
+        assert_eq!(tree.size(), 0);
+        assert!(tree
+            .remove_nearest_within(&point, 200.0, &dist)
+            .unwrap()
+            .is_none());
+        tree.add(point, 0).unwrap();
+        assert_eq!(tree.size(), 1);
+    }
+
+    #[test]
+    fn it_measures_points_against_its_bounds() {
+        let items = vec![([1.0, 2.0], 0), ([4.0, -1.0], 1), ([2.5, 6.0], 2)];
+        let mut tree = OwnedKdTree::from_points(items, 1).unwrap();
+        // the box spans [1, 4] by [-1, 6]
+        assert_eq!(tree.extent(), Some(([1.0, -1.0], [4.0, 6.0])));
+        for point in [[1.0, -1.0], [4.0, 6.0], [2.0, 0.0], [1.0, 6.0]] {
+            assert!(tree.contains_in_bounds(&point));
+            assert_eq!(tree.distance_to_bounds(&point, &dist), 0.0);
+        }
+        let outside = [
+            ([0.0, 0.0], 1.0),
+            ([5.0, 7.0], 2.0),
+            ([2.0, 9.0], 9.0),
+            ([-2.0, -5.0], 25.0),
+        ];
+        for (point, expected) in outside {
+            assert!(!tree.contains_in_bounds(&point));
+            assert_eq!(tree.distance_to_bounds(&point, &dist), expected);
+        }
+        let manhattan = |a: &[f64; 2], b: &[f64; 2]| (a[0] - b[0]).abs() + (a[1] - b[1]).abs();
+        assert_eq!(tree.distance_to_bounds(&[-2.0, -5.0], &manhattan), 7.0);
+        assert!(!tree.contains_in_bounds(&[f64::NAN, 0.0]));
+
+        // removing a point does not shrink the box, but emptying the tree does
+        tree.remove(&[4.0, -1.0], &1).unwrap();
+        assert!(tree.contains_in_bounds(&[4.0, -1.0]));
+        tree.remove(&[1.0, 2.0], &0).unwrap();
+        tree.remove(&[2.5, 6.0], &2).unwrap();
+        assert!(!tree.contains_in_bounds(&[2.5, 6.0]));
+        assert_eq!(tree.distance_to_bounds(&[2.5, 6.0], &dist), f64::INFINITY);
+        let empty = OwnedKdTree::<f64, usize, 2>::new();
+        assert!(!empty.contains_in_bounds(&[0.0, 0.0]));
+        assert_eq!(empty.distance_to_bounds(&[0.0, 0.0], &dist), f64::INFINITY);
+    }
+
+    #[test]
+    fn it_prunes_by_the_box_bound_of_a_metric() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+        use crate::metric::{Chebyshev, Manhattan, Metric, Scaled, SquaredEuclidean};
+
+        /// The squared euclidean distance on a square of side 10 whose opposite edges meet,
+        /// for which the point of a box nearest to a point is not found axis by axis
+        struct Torus;
+
+        fn wrapped(x: f64, y: f64) -> f64 {
+            let d = (x - y).abs();
+            d.min(10.0 - d)
+        }
+
+        impl Metric<f64, 2> for Torus {
+            fn distance(&self, a: &[f64; 2], b: &[f64; 2]) -> f64 {
+                a.iter().zip(b).map(|(&x, &y)| wrapped(x, y).powi(2)).sum()
+            }
+
+            fn point_to_box(&self, p: &[f64; 2], min: &[f64; 2], max: &[f64; 2]) -> f64 {
+                let mut sum = 0.0;
+                for ((&x, &min), &max) in p.iter().zip(min).zip(max) {
+                    if x < min || x > max {
+                        sum += wrapped(x, min).min(wrapped(x, max)).powi(2);
+                    }
+                }
+                sum
+            }
+        }
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[17, 6][..]);
+        let items: Vec<([f64; 2], usize)> = (0..2000)
+            .map(|i| ([rng.gen_range(0.0, 10.0), rng.gen_range(0.0, 10.0)], i))
+            .collect();
+        let tree = OwnedKdTree::from_points(items.clone(), 8).unwrap();
+        let brute_force = |metric: &dyn Metric<f64, 2>, point: &[f64; 2]| {
+            let mut all: Vec<f64> = items
+                .iter()
+                .map(|(p, _)| metric.distance(point, p))
+                .collect();
+            all.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            all.truncate(5);
+            all
+        };
+        let distances =
+            |found: Vec<(f64, &usize)>| -> Vec<f64> { found.into_iter().map(|(d, _)| d).collect() };
+
+        let closure = |a: &[f64; 2], b: &[f64; 2]| Torus.distance(a, b);
+        let mut wrong = 0;
+        for _ in 0..100 {
+            // near the edges, where the nearest items are often across them
+            let point = [rng.gen_range(0.0, 0.5), rng.gen_range(0.0, 10.0)];
+            let expected = brute_force(&Torus, &point);
+            let found = distances(tree.nearest_metric(&point, 5, &Torus).unwrap());
+            assert_eq!(found, expected);
+            if distances(tree.nearest(&point, 5, &closure).unwrap()) != expected {
+                wrong += 1;
+            }
+            let within = tree.within_metric(&point, expected[4], &Torus).unwrap();
+            assert_eq!(distances(within)[..5], expected[..]);
+        }
+        // the closure's bound wrongly rules out the nodes across the edges
+        assert!(wrong > 20, "{}", wrong);
+
+        let scaled = Scaled::new(Manhattan, [2.0, -0.5]);
+        let metrics: [&dyn Metric<f64, 2>; 4] =
+            [&SquaredEuclidean, &Manhattan, &Chebyshev, &scaled];
+        for metric in metrics {
+            for _ in 0..20 {
+                let point = [rng.gen_range(-2.0, 12.0), rng.gen_range(-2.0, 12.0)];
+                let found = distances(tree.nearest_metric(&point, 5, metric).unwrap());
+                assert_eq!(found, brute_force(metric, &point));
+            }
+        }
+        assert_eq!(
+            tree.nearest_metric(&[0.0, 1.0], 5, &SquaredEuclidean)
+                .unwrap(),
+            tree.nearest(&[0.0, 1.0], 5, &dist).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_finds_the_nearest_under_a_quadratic_form() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+        use super::ErrorKind;
+        use crate::metric::{Metric, QuadraticForm};
+
+        fn check<const D: usize>(rng: &mut StdRng) {
+            // BᵀB is positive semidefinite, and adding a little of the identity makes it definite
+            let mut b = [[0.0; D]; D];
+            for row in b.iter_mut() {
+                for x in row.iter_mut() {
+                    *x = rng.gen_range(-1.0, 1.0);
+                }
+            }
+            let mut matrix = [[0.0; D]; D];
+            for (i, row) in matrix.iter_mut().enumerate() {
+                for (j, m) in row.iter_mut().enumerate() {
+                    *m = b.iter().map(|r| r[i] * r[j]).sum::<f64>();
+                }
+                row[i] += 0.05;
+            }
+            let metric = QuadraticForm::new(matrix).unwrap();
+            let items: Vec<([f64; D], usize)> = (0..2000)
+                .map(|i| {
+                    let mut point = [0.0; D];
+                    for x in point.iter_mut() {
+                        *x = rng.gen_range(-10.0, 10.0);
+                    }
+                    (point, i)
+                })
+                .collect();
+            let tree = OwnedKdTree::from_points(items.clone(), 8).unwrap();
+            for _ in 0..50 {
+                let mut point = [0.0; D];
+                for x in point.iter_mut() {
+                    *x = rng.gen_range(-12.0, 12.0);
+                }
+                let mut expected: Vec<f64> = items
+                    .iter()
+                    .map(|(p, _)| metric.distance(&point, p))
+                    .collect();
+                expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                let found: Vec<f64> = tree
+                    .nearest_metric(&point, 5, &metric)
+                    .unwrap()
+                    .into_iter()
+                    .map(|(d, _)| d)
+                    .collect();
+                assert_eq!(found, expected[..5]);
+                let within = tree.within_metric(&point, expected[20], &metric).unwrap();
+                assert_eq!(within.len(), 21);
+            }
+        }
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[17, 7][..]);
+        for _ in 0..3 {
+            check::<2>(&mut rng);
+            check::<3>(&mut rng);
+            check::<4>(&mut rng);
+        }
+
+        let not_definite = [
+            [[1.0, 2.0], [2.0, 1.0]],
+            [[1.0, 0.0], [0.0, 0.0]],
+            [[-1.0, 0.0], [0.0, 1.0]],
+            [[1.0, f64::NAN], [0.0, 1.0]],
+        ];
+        for matrix in not_definite {
+            assert_eq!(
+                QuadraticForm::new(matrix).err(),
+                Some(ErrorKind::NotPositiveDefinite)
+            );
+        }
+        // only the symmetric part counts, here the identity
+        let skewed = QuadraticForm::new([[1.0, 3.0], [-3.0, 1.0]]).unwrap();
+        assert_eq!(skewed.matrix(), &[[1.0, 0.0], [0.0, 1.0]]);
+        // the bound is found by bisection, and errs below the smallest eigenvalue
+        let bound = skewed.point_to_box(&[0.0, 0.0], &[3.0, 4.0], &[5.0, 5.0]);
+        assert!(bound <= 25.0 && bound > 25.0 - 1e-9, "{}", bound);
+    }
+
+    #[test]
+    fn it_can_be_cloned() {
+        let mut tree: OwnedKdTree<f64, i32, 2> = OwnedKdTree::new();
+        let (pos, data) = random_point();
+        tree.add(pos, data).unwrap();
+        let mut cloned_tree = tree.clone();
+        cloned_tree.add(pos, data).unwrap();
+        assert_eq!(tree.size(), 1);
+        assert_eq!(cloned_tree.size(), 2);
+    }
+
+    #[test]
+    fn it_holds_on_to_its_capacity_before_splitting() {
+        let mut tree: OwnedKdTree<f64, i32, 2> = OwnedKdTree::new();
+        let capacity = 2_usize.pow(4);
+        for _ in 0..capacity {
+            let (pos, data) = random_point();
+            tree.add(pos, data).unwrap();
+        }
+        assert_eq!(tree.size, capacity);
+        assert_eq!(tree.size(), capacity);
+        assert!(tree.is_leaf());
+        {
+            let (pos, data) = random_point();
+            tree.add(pos, data).unwrap();
+        }
+        assert_eq!(tree.size, capacity + 1);
+        assert_eq!(tree.size(), capacity + 1);
+        assert!(tree.children().is_some());
+    }
+
+    fn same_shape<T: PartialEq, U: PartialEq>(
+        a: &OwnedKdTree<f64, T, 2>,
+        b: &OwnedKdTree<f64, U, 2>,
+    ) -> bool {
+        let nodes = match (&a.node, &b.node) {
+            (
+                Node::Stem {
+                    split_dimension: a_dim,
+                    split_value: a_value,
+                    left: al,
+                    right: ar,
+                },
+                Node::Stem {
+                    split_dimension: b_dim,
+                    split_value: b_value,
+                    left: bl,
+                    right: br,
+                },
+            ) => a_dim == b_dim && a_value == b_value && same_shape(al, bl) && same_shape(ar, br),
+            (Node::Leaf { points: a, .. }, Node::Leaf { points: b, .. }) => a == b,
+            _ => false,
+        };
+        nodes && a.size == b.size && a.min_bounds == b.min_bounds && a.max_bounds == b.max_bounds
+    }
+
+    #[test]
+    fn it_maps_payloads_in_place() {
+        let mut tree: OwnedKdTree<f64, i32, 2> = OwnedKdTree::with_capacity(2);
+        for _ in 0..100 {
+            let (pos, data) = random_point();
+            tree.add(pos, data).unwrap();
+        }
+        let before = tree.clone();
+
+        let borrowed = tree.map_ref(|p, d| (p[0], *d as i64 * 2));
+        assert_eq!(borrowed.stats(), before.stats());
+        assert!(same_shape(&before, &borrowed));
+
+        let mapped = tree.map(|_, d| d.to_string());
+        assert_eq!(mapped.stats(), before.stats());
+        assert!(same_shape(&before, &mapped));
+
+        let query = [0.5, 0.5];
+        let expected: Vec<_> = before
+            .iter_nearest(&query, &dist)
+            .unwrap()
+            .map(|(dist, d)| (dist, d.to_string()))
+            .collect();
+        let actual: Vec<_> = mapped
+            .iter_nearest(&query, &dist)
+            .unwrap()
+            .map(|(dist, d)| (dist, d.clone()))
+            .collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn it_reports_stats() {
+        let mut tree: OwnedKdTree<f64, i32, 1> = OwnedKdTree::with_capacity(1);
+        assert_eq!(
+            tree.stats(),
+            TreeStats {
+                size: 0,
+                stems: 0,
+                leaves: 1,
+                max_depth: 0,
+                max_leaf_size: 0,
+            }
+        );
+        tree.add([0.0], 0).unwrap();
+        tree.add([1.0], 1).unwrap();
+        tree.add([3.0], 2).unwrap();
+        assert_eq!(
+            tree.stats(),
+            TreeStats {
+                size: 3,
+                stems: 2,
+                leaves: 3,
+                max_depth: 2,
+                max_leaf_size: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn it_stops_nearest_after_the_budget() {
+        let mut tree: OwnedKdTree<f64, i32, 2> = OwnedKdTree::with_capacity(4);
+        for _ in 0..1000 {
+            let (pos, data) = random_point();
+            tree.add(pos, data).unwrap();
+        }
+        let query = [0.5, 0.5];
+        let exact = tree.nearest(&query, 10, &dist).unwrap();
+        let (budgeted, complete) = tree
+            .nearest_budgeted(&query, 10, &dist, usize::MAX)
+            .unwrap();
+        assert!(complete);
+        assert_eq!(budgeted, exact);
+
+        let mut first_leaf = &tree;
+        let bucket = loop {
+            match first_leaf.sides(&query) {
+                Ok((near, _)) => first_leaf = near,
+                Err((_, bucket)) => break bucket,
+            }
+        };
+        let (budgeted, complete) = tree.nearest_budgeted(&query, 10, &dist, 1).unwrap();
+        assert!(!complete);
+        assert!(!budgeted.is_empty());
+        assert!(budgeted.iter().all(|(_, d)| bucket.contains(d)));
+    }
+
+    #[test]
+    fn it_splits_leaves_on_demand() {
+        let mut tree: OwnedKdTree<f64, i32, 2> = OwnedKdTree::new();
+        tree.add([0.0, 0.0], 0).unwrap();
+        tree.add([0.0, 0.0], 1).unwrap();
+        assert!(!tree.root_mut().split_now());
+        assert!(tree.root().is_leaf());
+
+        tree.add([4.0, 1.0], 2).unwrap();
+        assert!(tree.root_mut().split_now());
+        assert!(!tree.root_mut().split_now());
+
+        let root = tree.root();
+        assert!(!root.is_leaf());
+        assert_eq!(root.split_dimension(), Some(0));
+        assert_eq!(root.split_value(), Some(2.0));
+        let (left, right) = (root.left().unwrap(), root.right().unwrap());
+        assert!(left.is_leaf() && right.is_leaf());
+        assert_eq!(left.size() + right.size(), root.size());
+        assert_eq!((left.size(), right.size()), (2, 1));
+        assert_eq!(left.split_value(), None);
+        let (root_min, root_max) = root.bounds().unwrap();
+        let (left_min, left_max) = left.bounds().unwrap();
+        let (right_min, right_max) = right.bounds().unwrap();
+        assert_eq!((left_min, right_max), (root_min, root_max));
+        assert!(left_max[0] < 2.0 && right_min[0] >= 2.0);
+
+        assert_eq!(
+            tree.nearest(&[3.0, 3.0], 1, &dist).unwrap(),
+            vec![(5.0, &2)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "the leaf capacity of an OwnedKdTree must be positive")]
+    fn it_panics_on_a_zero_capacity() {
+        let _: OwnedKdTree<f64, i32, 2> = OwnedKdTree::with_capacity(0);
+    }
+
+    #[test]
+    fn it_rejects_a_zero_capacity_when_constructed() {
+        use super::{Builder, ErrorKind};
+
+        let zero = OwnedKdTree::<f64, i32, 2>::try_with_capacity(0);
+        assert_eq!(zero.err(), Some(ErrorKind::ZeroCapacity));
+        let built = Builder::new().capacity(0).try_build::<f64, i32, 2>();
+        assert_eq!(built.err(), Some(ErrorKind::ZeroCapacity));
+        let bulk = Builder::new()
+            .capacity(0)
+            .from_points::<f64, i32, 2>(vec![]);
+        assert_eq!(bulk.err(), Some(ErrorKind::ZeroCapacity));
+
+        let mut one = OwnedKdTree::<f64, i32, 2>::try_with_capacity(1).unwrap();
+        let (pos, data) = random_point();
+        one.add(pos, data).unwrap();
+        assert_eq!(one.size(), 1);
+        // a schedule never gives a leaf a capacity of zero
+        fn zero_schedule(_: usize) -> usize {
+            0
+        }
+        let scheduled = Builder::new()
+            .capacity(0)
+            .leaf_capacity_fn(Some(zero_schedule))
+            .try_build::<f64, i32, 2>()
+            .unwrap();
+        assert_eq!(scheduled.root().capacity(), 1);
+    }
+
+    #[test]
+    fn it_sizes_small_leaves_exactly() {
+        let mut tree = OwnedKdTree::with_capacity(1);
+        for i in 0..200 {
+            tree.add(random_point().0, i).unwrap();
+        }
+        let mut pending = vec![&tree];
+        while let Some(node) = pending.pop() {
+            match &node.node {
+                Node::Leaf { points, bucket } => {
+                    assert!(points.capacity() <= 1 && bucket.capacity() <= 1);
+                    // a single item is held inline, with no allocation of its own
+                    if !points.is_empty() {
+                        assert!(matches!(points, LeafVec::One(_)));
+                        assert!(matches!(bucket, LeafVec::One(_)));
+                    }
+                }
+                Node::Stem { left, right, .. } => pending.extend([&**left, &**right]),
+            }
+        }
+    }
+
+    #[test]
+    fn it_tightens_bounds_left_loose_by_removals() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[19, 2][..]);
+        let queries: Vec<[f64; 2]> = (0..200)
+            .map(|_| [rng.gen_range(-30.0, 30.0), rng.gen_range(-30.0, 30.0)])
+            .collect();
+        let nodes_visited = |tree: &OwnedKdTree<f64, usize, 2>| -> usize {
+            let visited = queries.iter().map(|q| {
+                let (nearest, stats) = tree.nearest_with_stats(q, 3, &dist).unwrap();
+                assert_eq!(nearest.len(), 3.min(tree.size()));
+                stats.nodes_visited
+            });
+            visited.sum()
+        };
+        let mut sequences: Vec<OwnedKdTree<f64, usize, 2>> = vec![];
+
+        // items spread wide, then everything outside the middle removed, leaving a few leaves
+        let mut tree: OwnedKdTree<f64, usize, 2> = OwnedKdTree::with_capacity(8);
+        for i in 0..4000 {
+            let point = [rng.gen_range(-50.0, 50.0), rng.gen_range(-50.0, 50.0)];
+            tree.add(point, i).unwrap();
+        }
+        tree.retain_in_box(&[-50.0; 2], &[50.0; 2], |p, _| {
+            p[0].abs() < 2.0 && p[1].abs() < 2.0
+        })
+        .unwrap();
+        sequences.push(tree);
+
+        // items all over, then one at a time all but those near the points of a coarse grid,
+        // so most leaves keep a few items but lose the ones at their edges
+        let mut tree = OwnedKdTree::with_capacity(16);
+        let mut added = vec![];
+        for i in 0..4000 {
+            let point = [rng.gen_range(-30.0, 30.0), rng.gen_range(-30.0, 30.0)];
+            tree.add(point, i).unwrap();
+            added.push((point, i));
+        }
+        let off_grid = |x: f64| (x / 10.0 - (x / 10.0).round()).abs() > 0.05;
+        for (point, i) in &added {
+            if off_grid(point[0]) || off_grid(point[1]) {
+                assert_eq!(tree.remove(point, i).unwrap(), 1);
+            }
+        }
+        sequences.push(tree);
+
+        // items drifting away from where the first ones were added, the oldest removed
+        let mut tree = OwnedKdTree::with_capacity(8);
+        let mut added = std::collections::VecDeque::new();
+        for i in 0..3000 {
+            let centre = 10.0 - i as f64 / 300.0;
+            let point = [centre + rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0)];
+            tree.add(point, i).unwrap();
+            added.push_back((point, i));
+            if added.len() > 500 {
+                let (point, i) = added.pop_front().unwrap();
+                assert_eq!(tree.remove(&point, &i).unwrap(), 1);
+            }
+        }
+        sequences.push(tree);
+
+        for tree in &mut sequences {
+            assert!(tree.tight_bounds().is_none());
+            let loose = nodes_visited(tree);
+            let nearest: Vec<Vec<usize>> = queries
+                .iter()
+                .map(|q| {
+                    tree.nearest(q, 3, &dist)
+                        .unwrap()
+                        .into_iter()
+                        .map(|(_, &i)| i)
+                        .collect()
+                })
+                .collect();
+            let generation = tree.generation();
+            tree.recompute_bounds();
+            assert!(tree.tight_bounds().is_some());
+            tree.debug_validate();
+            assert_eq!(tree.generation(), generation);
+            let tight = nodes_visited(tree);
+            assert!(
+                tight < loose,
+                "{} nodes visited with tight bounds, {} with loose",
+                tight,
+                loose
+            );
+            for (q, expected) in queries.iter().zip(&nearest) {
+                let found: Vec<usize> = tree
+                    .nearest(q, 3, &dist)
+                    .unwrap()
+                    .into_iter()
+                    .map(|(_, &i)| i)
+                    .collect();
+                assert_eq!(&found, expected);
+            }
+            // a second repair finds nothing left to tighten
+            let bounds = tree.tight_bounds();
+            tree.recompute_bounds();
+            assert_eq!(tree.tight_bounds(), bounds);
+        }
+
+        let mut empty = OwnedKdTree::<f64, usize, 2>::with_capacity(2);
+        empty.recompute_bounds();
+        assert!(empty.tight_bounds().is_some());
+        assert_eq!(empty.nearest(&[0.0; 2], 1, &dist).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn it_flags_truncated_within_queries_with_their_count() {
+        use super::WithinOutcome;
+
+        let items: Vec<([f64; 2], usize)> = (0..10_000)
+            .map(|i| ([(i % 100) as f64, (i / 100) as f64], i))
+            .collect();
+        let tree = OwnedKdTree::from_points(items.clone(), 8).unwrap();
+        let count = |point: &[f64; 2], radius: f64| {
+            items
+                .iter()
+                .filter(|(p, _)| dist(point, p) <= radius)
+                .count()
+        };
+        for &(point, radius) in &[
+            ([50.0, 50.0], 1e9),
+            ([50.0, 50.0], 400.0),
+            ([0.5, 99.5], 30.0),
+            ([-10.0, 20.0], 900.0),
+            ([50.0, 50.0], 2.0),
+            ([500.0, 500.0], 1.0),
+        ] {
+            let within = tree.within(&point, radius, &dist).unwrap();
+            let expected = count(&point, radius);
+            assert_eq!(within.len(), expected);
+            for &max_results in &[0, 1, 5, 100, expected, 20_000] {
+                let outcome = tree
+                    .within_limited(&point, radius, &dist, max_results)
+                    .unwrap();
+                let results = outcome.results();
+                assert_eq!(results.len(), expected.min(max_results));
+                let distances =
+                    |r: &[(f64, &usize)]| -> Vec<f64> { r.iter().map(|r| r.0).collect() };
+                assert_eq!(distances(results), distances(&within[..results.len()]));
+                match outcome {
+                    WithinOutcome::Complete(_) => assert!(expected <= max_results),
+                    WithinOutcome::Truncated { found_at_least, .. } => {
+                        assert!(expected > max_results);
+                        assert_eq!(found_at_least, expected);
+                    }
+                }
+            }
+        }
+        // an adversarial radius costs about as much as a nearest search
+        let measured = std::cell::Cell::new(0);
+        let counting = |a: &[f64; 2], b: &[f64; 2]| {
+            measured.set(measured.get() + 1);
+            dist(a, b)
+        };
+        let outcome = tree
+            .within_limited(&[50.0, 50.0], 1e9, &counting, 3)
+            .unwrap();
+        assert!(
+            measured.get() < 500,
+            "{} distances measured",
+            measured.get()
+        );
+        assert!(outcome.is_truncated());
+        assert_eq!(outcome.into_results().len(), 3);
+        assert!(tree
+            .within_limited(&[f64::NAN, 0.0], 1.0, &dist, 3)
+            .is_err());
+    }
+
+    #[test]
+    fn it_balances_trees_by_their_declared_bounds() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[19, 7][..]);
+        // the first items all lie in one corner of the world
+        let mut items: Vec<[f64; 2]> = (0..200)
+            .map(|_| [rng.gen_range(0.0, 512.0), rng.gen_range(0.0, 512.0)])
+            .collect();
+        items.extend((0..20_000).map(|_| [rng.gen_range(0.0, 4096.0), rng.gen_range(0.0, 4096.0)]));
+        let mut grown = OwnedKdTree::with_capacity(16);
+        let mut declared = OwnedKdTree::with_bounds(16, [0.0; 2], [4096.0; 2]).unwrap();
+        for (i, &point) in items.iter().enumerate() {
+            grown.add(point, i).unwrap();
+            declared.add(point, i).unwrap();
+        }
+        declared.debug_validate();
+        fn item_depths(node: &OwnedKdTree<f64, usize, 2>, depth: usize) -> usize {
+            match &node.node {
+                Node::Stem { left, right, .. } => {
+                    item_depths(left, depth + 1) + item_depths(right, depth + 1)
+                }
+                Node::Leaf { .. } => node.size * depth,
+            }
+        }
+        assert!(declared.stats().max_depth + 3 <= grown.stats().max_depth);
+        assert!(item_depths(&declared, 0) * 5 < item_depths(&grown, 0) * 4);
+        for query in items.iter().step_by(500) {
+            let distances = |tree: &OwnedKdTree<f64, usize, 2>| -> Vec<f64> {
+                let nearest = tree.nearest(query, 5, &dist).unwrap();
+                nearest.into_iter().map(|(d, _)| d).collect()
+            };
+            assert_eq!(distances(&declared), distances(&grown));
+        }
+
+        // the root keeps the declared bounds, even once emptied
+        assert_eq!(declared.declared_bounds(), Some(([0.0; 2], [4096.0; 2])));
+        assert_eq!(grown.declared_bounds(), None);
+        for (i, point) in items.iter().enumerate() {
+            declared.remove(point, &i).unwrap();
+        }
+        assert_eq!(declared.size(), 0);
+        assert_eq!(declared.declared_bounds(), Some(([0.0; 2], [4096.0; 2])));
+        declared.recompute_bounds();
+        assert_eq!(declared.declared_bounds(), Some(([0.0; 2], [4096.0; 2])));
+    }
+
+    #[test]
+    fn it_rejects_or_clamps_points_outside_the_declared_bounds() {
+        use super::{ErrorKind, OutOfBounds};
+
+        let mut rejecting = OwnedKdTree::with_bounds(2, [0.0, -1.0], [10.0, 1.0]).unwrap();
+        assert_eq!(
+            rejecting.add([5.0, 2.0], 0),
+            Err(ErrorKind::OutOfBounds { dim: 1 })
+        );
+        assert_eq!(
+            rejecting.add_if_far_enough([-0.5, 0.0], 0, 1.0, &dist),
+            Err(ErrorKind::OutOfBounds { dim: 0 })
+        );
+        assert_eq!(rejecting.size(), 0);
+        assert_eq!(rejecting.generation(), 0);
+        for (i, point) in [[0.0, -1.0], [10.0, 1.0], [5.0, 0.0], [2.5, 0.5]]
+            .iter()
+            .enumerate()
+        {
+            rejecting.add(*point, i).unwrap();
+        }
+        assert_eq!(rejecting.size(), 4);
+        assert!(rejecting.stats().stems > 0);
+        rejecting.debug_validate();
+
+        let mut clamping =
+            OwnedKdTree::with_bounds_policy(2, [0.0, -1.0], [10.0, 1.0], OutOfBounds::Clamp)
+                .unwrap();
+        clamping.add([12.0, -3.0], 0).unwrap();
+        clamping.add([-1.0, 0.5], 1).unwrap();
+        assert!(clamping
+            .add_if_far_enough([11.0, -1.0], 2, 0.5, &dist)
+            .is_ok_and(|added| !added));
+        let mut points: Vec<[f64; 2]> = clamping.iter().map(|(p, _)| *p).collect();
+        points.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(points, vec![[0.0, 0.5], [10.0, -1.0]]);
+        assert_eq!(clamping.declared_bounds(), Some(([0.0, -1.0], [10.0, 1.0])));
+
+        assert_eq!(
+            OwnedKdTree::<f64, usize, 2>::with_bounds(2, [1.0, 0.0], [0.0, 1.0]).err(),
+            Some(ErrorKind::InvalidBounds)
+        );
+        assert_eq!(
+            OwnedKdTree::<f64, usize, 2>::with_bounds(2, [0.0, 0.0], [f64::INFINITY, 1.0]).err(),
+            Some(ErrorKind::NonFiniteCoordinate)
+        );
+        assert_eq!(
+            OwnedKdTree::<f64, usize, 2>::with_bounds(0, [0.0; 2], [1.0; 2]).err(),
+            Some(ErrorKind::ZeroCapacity)
+        );
+        // items at the same point do not split a leaf covering more than them
+        let mut stacked = OwnedKdTree::with_bounds(2, [0.0; 2], [1.0; 2]).unwrap();
+        for i in 0..10 {
+            stacked.add([0.25, 0.25], i).unwrap();
+        }
+        assert_eq!(stacked.stats().stems, 0);
+    }
+}
@@ -0,0 +1,141 @@
+//! Conversions of points and trees between coordinate types, such as storing `f64` data in a
+//! tree of `f32` points to halve its memory. A coordinate too large for the coordinate type of
+//! the tree returns `ErrorKind::CastOverflow` instead of becoming infinite, while one too small
+//! in magnitude rounds to a subnormal or to zero as `as` would round it.
+
+use num_traits::{Float, One, Zero};
+
+use super::{ErrorKind, OwnedKdTree};
+
+/// `point` with its coordinates converted to `A`
+fn cast_point<A: Float, B: Float, const D: usize>(point: &[B; D]) -> Result<[A; D], ErrorKind> {
+    let mut cast = [A::zero(); D];
+    for (dim, (c, &x)) in cast.iter_mut().zip(point).enumerate() {
+        if !x.is_finite() {
+            return Err(ErrorKind::NonFiniteCoordinate);
+        }
+        *c = match A::from(x) {
+            Some(c) if c.is_finite() => c,
+            _ => return Err(ErrorKind::CastOverflow { dim }),
+        };
+    }
+    Ok(cast)
+}
+
+impl<A: Float + Zero + One, T: PartialEq, const D: usize> OwnedKdTree<A, T, D> {
+    /// [`add`](#method.add) with a point of another coordinate type, converted to `A`
+    pub fn add_cast<B: Float>(&mut self, point: [B; D], data: T) -> Result<(), ErrorKind> {
+        self.add(cast_point(&point)?, data)
+    }
+
+    /// A tree of the items of `tree` with their points converted to `A`, keeping its leaf
+    /// capacity, [`max_items`](#method.max_items), split rules, declared bounds and
+    /// [`generation`](#method.generation). The tree is rebuilt, as rounding may move a point
+    /// onto a split value.
+    pub fn cast_from<B: Float + Zero + One>(tree: OwnedKdTree<B, T, D>) -> Result<Self, ErrorKind> {
+        let (capacity, max_items) = (tree.capacity, tree.max_items);
+        let (split_rules, generation) = (tree.split_rules, tree.generation);
+        let declared = match tree.declared_bounds() {
+            Some((min, max)) => Some((cast_point(&min)?, cast_point(&max)?)),
+            None => None,
+        };
+        let items = tree
+            .into_items()
+            .into_iter()
+            .map(|(p, d)| Ok((cast_point(&p)?, d)))
+            .collect::<Result<_, ErrorKind>>()?;
+        let mut cast = OwnedKdTree::build_quietly(items, capacity, split_rules.leaf_capacity_fn, 0);
+        cast.max_items = max_items;
+        cast.split_rules = split_rules;
+        cast.generation = generation;
+        if let Some((min, max)) = declared {
+            // rounding is monotonic, so the cast points stay within the cast bounds
+            cast.min_bounds = min;
+            cast.max_bounds = max;
+        }
+        Ok(cast)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::distance::squared_euclidean;
+    use crate::error::ErrorKind;
+    use crate::tree::{Builder, OwnedKdTree};
+
+    #[test]
+    fn it_casts_coordinates_which_fit() {
+        let mut tree = OwnedKdTree::<f32, usize, 2>::with_capacity(2);
+        let largest = f32::MAX as f64;
+        tree.add_cast([largest, -largest], 0).unwrap();
+        // rounds down to the largest f32 rather than up to infinity
+        tree.add_cast([largest * (1.0 + 1e-9), 0.0], 1).unwrap();
+        let subnormal = f32::MIN_POSITIVE as f64 / 4.0;
+        tree.add_cast([subnormal, 1e-60], 2).unwrap();
+        tree.add_cast([0.5f32, 0.25], 3).unwrap();
+        assert_eq!(tree.size(), 4);
+
+        let points: Vec<[f32; 2]> = tree.iter().map(|(p, _)| *p).collect();
+        assert!(points.contains(&[f32::MAX, -f32::MAX]));
+        assert!(points.contains(&[f32::MAX, 0.0]));
+        assert!(points.contains(&[f32::MIN_POSITIVE / 4.0, 0.0]));
+        assert!((f32::MIN_POSITIVE / 4.0).is_subnormal());
+        tree.debug_validate();
+    }
+
+    #[test]
+    fn it_reports_coordinates_which_overflow() {
+        let mut tree = OwnedKdTree::<f32, usize, 3>::with_capacity(2);
+        assert_eq!(
+            tree.add_cast([0.0, f64::MAX, 1.0], 0),
+            Err(ErrorKind::CastOverflow { dim: 1 })
+        );
+        assert_eq!(
+            tree.add_cast([0.0, 0.0, -(f32::MAX as f64) * 2.0], 0),
+            Err(ErrorKind::CastOverflow { dim: 2 })
+        );
+        assert_eq!(
+            tree.add_cast([f64::INFINITY, 0.0, 0.0], 0),
+            Err(ErrorKind::NonFiniteCoordinate)
+        );
+        assert_eq!(tree.size(), 0);
+        assert_eq!(tree.generation(), 0);
+
+        let big: OwnedKdTree<f64, usize, 3> =
+            OwnedKdTree::from_points(vec![([0.0; 3], 0), ([1e39, 0.0, 0.0], 1)], 2).unwrap();
+        assert_eq!(
+            OwnedKdTree::<f32, usize, 3>::cast_from(big).err(),
+            Some(ErrorKind::CastOverflow { dim: 0 })
+        );
+    }
+
+    #[test]
+    fn it_casts_a_tree_between_coordinate_types() {
+        let mut wide = Builder::new()
+            .capacity(3)
+            .max_items(Some(500))
+            .build::<f64, usize, 2>();
+        for i in 0..400 {
+            // rounding to f32 brings neighbouring points and split values together
+            let x = 1.0 + i as f64 * 1e-9;
+            wide.add([x, (i % 7) as f64 * 1e-40], i).unwrap();
+        }
+        let generation = wide.generation();
+        let narrow = OwnedKdTree::<f32, usize, 2>::cast_from(wide.clone()).unwrap();
+        narrow.debug_validate();
+        assert_eq!(narrow.size(), 400);
+        assert_eq!(narrow.max_items(), Some(500));
+        assert_eq!(narrow.generation(), generation);
+        let mut ids: Vec<usize> = narrow.iter().map(|(_, &i)| i).collect();
+        ids.sort_unstable();
+        assert!(ids.into_iter().eq(0..400));
+        let dist = |a: &[f32; 2], b: &[f32; 2]| squared_euclidean(a, b);
+        assert_eq!(narrow.within(&[1.0, 0.0], 1e-5, &dist).unwrap().len(), 400);
+
+        let back = OwnedKdTree::<f64, usize, 2>::cast_from(narrow).unwrap();
+        back.debug_validate();
+        for ((p, _), (q, _)) in back.iter().zip(wide.iter()) {
+            assert!((p[0] - q[0]).abs() < 1e-6);
+        }
+    }
+}
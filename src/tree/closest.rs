@@ -0,0 +1,347 @@
+//! The closest pair of items of a tree. The pairs within each child of a stem are found first,
+//! then the pairs between its children, descending both children together and skipping pairs
+//! of nodes whose boxes are no nearer to each other than the closest pair found so far.
+
+use num_traits::{Float, One, Zero};
+
+use super::{Node, OwnedKdTree};
+use crate::geometry::distance_between_spaces_const;
+
+/// The closest pair found so far, as positions of its items in iteration order, the lower first
+struct Closest<A> {
+    distance: A,
+    pair: Option<(usize, usize)>,
+}
+
+impl<A: Float> Closest<A> {
+    fn consider(&mut self, distance: A, i: usize, j: usize) {
+        // the first of equally close pairs is kept, and pairs at a NaN distance never are
+        if distance < self.distance || (self.pair.is_none() && distance == self.distance) {
+            self.distance = distance;
+            self.pair = Some((i, j));
+        }
+    }
+}
+
+impl<A: Float + Zero + One, T: PartialEq, const D: usize> OwnedKdTree<A, T, D> {
+    /// The two items nearest to each other and their distance, `None` if the tree holds fewer
+    /// than two items. Items at the same point are a pair at distance zero. Of several equally
+    /// close pairs, the one whose items come first in [`iter`](#method.iter) order is returned,
+    /// the item coming first in that order first.
+    #[allow(clippy::type_complexity)]
+    pub fn closest_pair<F>(&self, distance: &F) -> Option<(A, (&[A; D], &T), (&[A; D], &T))>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let (d, i, j) = self.closest_positions(distance)?;
+        Some((d, self.item_at(i), self.item_at(j)))
+    }
+
+    /// Like [`closest_pair`](#method.closest_pair), but the payloads are mutable, so they can be
+    /// swapped or merged in place. Bumps the [`generation`](#method.generation) whether or not a
+    /// pair is found.
+    pub fn closest_pair_mut<F>(&mut self, distance: &F) -> Option<(A, &mut T, &mut T)>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        self.generation += 1;
+        let (d, i, j) = self.closest_positions(distance)?;
+        let (a, b) = self.pair_at_mut(i, j);
+        Some((d, a, b))
+    }
+
+    fn closest_positions<F>(&self, distance: &F) -> Option<(A, usize, usize)>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let mut closest = Closest {
+            distance: A::infinity(),
+            pair: None,
+        };
+        self.closest_within(0, &mut closest, distance);
+        let (i, j) = closest.pair?;
+        Some((closest.distance, i, j))
+    }
+
+    /// Considers the pairs of items below the node, whose first item is at `start`
+    fn closest_within<F>(&self, start: usize, closest: &mut Closest<A>, distance: &F)
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        if self.size < 2 {
+            return;
+        }
+        match &self.node {
+            Node::Stem { left, right, .. } => {
+                left.closest_within(start, closest, distance);
+                right.closest_within(start + left.size, closest, distance);
+                left.closest_between(start, right, start + left.size, closest, distance);
+            }
+            Node::Leaf { points, .. } => {
+                for (i, p) in points.iter().enumerate() {
+                    for (j, q) in points.iter().enumerate().skip(i + 1) {
+                        closest.consider(distance(p, q), start + i, start + j);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Considers the pairs of an item below this node and an item below `other`, which come
+    /// after all of them in iteration order
+    fn closest_between<F>(
+        &self,
+        start: usize,
+        other: &Self,
+        other_start: usize,
+        closest: &mut Closest<A>,
+        distance: &F,
+    ) where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        if self.size == 0 || other.size == 0 {
+            return;
+        }
+        let bound = self.distance_between_nodes(other, distance);
+        if closest.pair.is_some() && bound >= closest.distance {
+            return;
+        }
+        let split_ours = |[left, right]: [&Self; 2], closest: &mut Closest<A>| {
+            let halves = [(left, start), (right, start + left.size)];
+            for &(node, start) in &Self::nearer_first(halves, other, distance) {
+                node.closest_between(start, other, other_start, closest, distance);
+            }
+        };
+        let split_theirs = |[left, right]: [&Self; 2], closest: &mut Closest<A>| {
+            let halves = [(left, other_start), (right, other_start + left.size)];
+            for &(other, other_start) in &Self::nearer_first(halves, self, distance) {
+                self.closest_between(start, other, other_start, closest, distance);
+            }
+        };
+        // descend into the larger of two stems, the child with the nearer box first
+        match (&self.node, &other.node) {
+            (
+                Node::Leaf { points, .. },
+                Node::Leaf {
+                    points: other_points,
+                    ..
+                },
+            ) => {
+                for (i, p) in points.iter().enumerate() {
+                    for (j, q) in other_points.iter().enumerate() {
+                        closest.consider(distance(p, q), start + i, other_start + j);
+                    }
+                }
+            }
+            (Node::Stem { left, right, .. }, Node::Stem { .. }) if self.size >= other.size => {
+                split_ours([left, right], closest)
+            }
+            (Node::Stem { left, right, .. }, Node::Leaf { .. }) => {
+                split_ours([left, right], closest)
+            }
+            (_, Node::Stem { left, right, .. }) => split_theirs([left, right], closest),
+        }
+    }
+
+    fn distance_between_nodes<F>(&self, other: &Self, distance: &F) -> A
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        distance_between_spaces_const(
+            &self.min_bounds,
+            &self.max_bounds,
+            &other.min_bounds,
+            &other.max_bounds,
+            distance,
+        )
+    }
+
+    /// The two halves of a node ordered by the distance of their boxes to that of `other`
+    fn nearer_first<'a, F>(
+        halves: [(&'a Self, usize); 2],
+        other: &Self,
+        distance: &F,
+    ) -> [(&'a Self, usize); 2]
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let [a, b] = halves;
+        if b.0.distance_between_nodes(other, distance) < a.0.distance_between_nodes(other, distance)
+        {
+            [b, a]
+        } else {
+            [a, b]
+        }
+    }
+
+    /// The item at position `i` in iteration order, which must be below the size
+    fn item_at(&self, i: usize) -> (&[A; D], &T) {
+        match &self.node {
+            Node::Stem { left, .. } if i < left.size => left.item_at(i),
+            Node::Stem { left, right, .. } => right.item_at(i - left.size),
+            Node::Leaf { points, bucket } => (&points[i], &bucket[i]),
+        }
+    }
+
+    fn item_at_mut(&mut self, i: usize) -> &mut T {
+        match &mut self.node {
+            Node::Stem { left, right, .. } => {
+                let n = left.size;
+                if i < n {
+                    left.item_at_mut(i)
+                } else {
+                    right.item_at_mut(i - n)
+                }
+            }
+            Node::Leaf { bucket, .. } => &mut bucket[i],
+        }
+    }
+
+    /// The payloads at positions `i < j` in iteration order, which must be below the size
+    fn pair_at_mut(&mut self, i: usize, j: usize) -> (&mut T, &mut T) {
+        match &mut self.node {
+            Node::Stem { left, right, .. } => {
+                let n = left.size;
+                if j < n {
+                    left.pair_at_mut(i, j)
+                } else if i >= n {
+                    right.pair_at_mut(i - n, j - n)
+                } else {
+                    (left.item_at_mut(i), right.item_at_mut(j - n))
+                }
+            }
+            Node::Leaf { bucket, .. } => {
+                let (before, after) = bucket.split_at_mut(j);
+                (&mut before[i], &mut after[0])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::{Rng, SeedableRng, StdRng};
+    use crate::distance::squared_euclidean;
+    use crate::tree::OwnedKdTree;
+
+    fn dist(a: &[f64; 2], b: &[f64; 2]) -> f64 {
+        squared_euclidean(a, b)
+    }
+
+    fn brute_force(items: &[([f64; 2], usize)]) -> Option<f64> {
+        let mut closest = None;
+        for (i, (p, _)) in items.iter().enumerate() {
+            for (q, _) in &items[..i] {
+                let d = dist(p, q);
+                if closest.is_none_or(|c| d < c) {
+                    closest = Some(d);
+                }
+            }
+        }
+        closest
+    }
+
+    #[test]
+    fn it_finds_the_closest_pair_like_a_brute_force_search() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[19, 3][..]);
+        for &(n, capacity) in &[(2, 1), (3, 16), (50, 1), (400, 4), (1500, 16)] {
+            let items: Vec<([f64; 2], usize)> = (0..n)
+                .map(|i| {
+                    (
+                        [rng.gen_range(-100.0, 100.0), rng.gen_range(-100.0, 100.0)],
+                        i,
+                    )
+                })
+                .collect();
+            let mut tree = OwnedKdTree::with_capacity(capacity);
+            for &(point, i) in &items {
+                tree.add(point, i).unwrap();
+            }
+            let (d, (p, &a), (q, &b)) = tree.closest_pair(&dist).unwrap();
+            assert_eq!(Some(d), brute_force(&items));
+            assert_eq!(d, dist(p, q));
+            assert_eq!((*p, *q), (items[a].0, items[b].0));
+            assert_ne!(a, b);
+        }
+    }
+
+    #[test]
+    fn it_skips_pairs_of_distant_nodes() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[19, 5][..]);
+        let items: Vec<([f64; 2], usize)> = (0..5000)
+            .map(|i| {
+                (
+                    [rng.gen_range(-100.0, 100.0), rng.gen_range(-100.0, 100.0)],
+                    i,
+                )
+            })
+            .collect();
+        let tree = OwnedKdTree::from_points(items, 16).unwrap();
+        let measured = std::cell::Cell::new(0);
+        let counting = |a: &[f64; 2], b: &[f64; 2]| {
+            measured.set(measured.get() + 1);
+            dist(a, b)
+        };
+        let (d, _, _) = tree.closest_pair(&counting).unwrap();
+        assert_eq!(d, tree.closest_pair(&dist).unwrap().0);
+        // a brute force search measures 5000 * 4999 / 2 pairs
+        assert!(
+            measured.get() < 200_000,
+            "{} distances measured",
+            measured.get()
+        );
+    }
+
+    #[test]
+    fn it_pairs_items_at_the_same_point() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[19, 4][..]);
+        let mut items: Vec<([f64; 2], usize)> = (0..1000)
+            .map(|i| ([rng.gen_range(0.0, 10.0), rng.gen_range(0.0, 10.0)], i))
+            .collect();
+        let twin = (items[637].0, 1000);
+        items.push(twin);
+        let tree = OwnedKdTree::from_points(items, 8).unwrap();
+        let (d, (p, &a), (q, &b)) = tree.closest_pair(&dist).unwrap();
+        assert_eq!(d, 0.0);
+        assert_eq!((p, q), (&twin.0, &twin.0));
+        let mut ids = [a, b];
+        ids.sort_unstable();
+        assert_eq!(ids, [637, 1000]);
+
+        // on a grid of items every pair of neighbours is equally close
+        let grid: Vec<([f64; 2], usize)> = (0..400)
+            .map(|i| ([(i % 20) as f64, (i / 20) as f64], i))
+            .collect();
+        let tree = OwnedKdTree::from_points(grid, 4).unwrap();
+        assert_eq!(tree.closest_pair(&dist).unwrap().0, 1.0);
+        let stacked: Vec<([f64; 2], usize)> = (0..100).map(|i| ([3.0, 4.0], i)).collect();
+        let tree = OwnedKdTree::from_points(stacked, 4).unwrap();
+        assert_eq!(tree.closest_pair(&dist).unwrap().0, 0.0);
+    }
+
+    #[test]
+    fn it_swaps_the_payloads_of_the_closest_pair() {
+        let mut tree = OwnedKdTree::with_capacity(2);
+        assert!(tree.closest_pair(&dist).is_none());
+        assert!(tree.closest_pair_mut(&dist).is_none());
+        tree.add([0.0, 0.0], 0).unwrap();
+        assert!(tree.closest_pair(&dist).is_none());
+        for (i, point) in [[10.0, 0.0], [0.0, 10.0], [10.0, 10.5], [10.0, 10.0]]
+            .iter()
+            .enumerate()
+        {
+            tree.add(*point, i + 1).unwrap();
+        }
+        let generation = tree.generation();
+        let (d, a, b) = tree.closest_pair_mut(&dist).unwrap();
+        assert_eq!(d, 0.25);
+        std::mem::swap(a, b);
+        assert_eq!(tree.generation(), generation + 1);
+        let at = |point: [f64; 2]| tree.iter().find(|(p, _)| **p == point).map(|(_, &i)| i);
+        assert_eq!(at([10.0, 10.0]), Some(3));
+        assert_eq!(at([10.0, 10.5]), Some(4));
+        tree.debug_validate();
+    }
+}
@@ -0,0 +1,191 @@
+//! Fingerprints of the contents and the structure of a tree, for snapshot tests.
+//!
+//! Both hashes are 64 bit FNV-1a, which is simple enough to reimplement and does not change
+//! between Rust releases, unlike `std::collections::hash_map::DefaultHasher`. A coordinate is
+//! hashed as the 8 little endian bytes of its `f64` bits, with `-0.0` taken as `0.0` so that
+//! equal points hash alike, and a payload as the bytes its `Hash` implementation writes.
+
+use std::hash::{Hash, Hasher};
+
+use num_traits::{Float, One, Zero};
+
+use super::{Node, OwnedKdTree};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Fnv1a(FNV_OFFSET_BASIS)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+fn coordinate_bits<A: Float>(x: A) -> u64 {
+    let x = x.to_f64().unwrap();
+    if x == 0.0 {
+        0
+    } else {
+        x.to_bits()
+    }
+}
+
+fn payload_hash<T: Hash>(data: &T) -> u64 {
+    let mut hasher = Fnv1a::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<A: Float + Zero + One, T: PartialEq + Hash, const D: usize> OwnedKdTree<A, T, D> {
+    /// A hash of the items of the tree which does not depend on its structure, so trees built
+    /// from the same items in any order, with any leaf capacity, hash alike. Each item is
+    /// turned into the bits of its coordinates followed by the hash of its payload, the items
+    /// are sorted, and the hash is taken over the number of items and then each item's `D + 1`
+    /// words as little endian bytes.
+    pub fn content_hash(&self) -> u64 {
+        let mut items: Vec<([u64; D], u64)> = self
+            .iter()
+            .map(|(point, data)| {
+                let mut bits = [0; D];
+                for (b, &x) in bits.iter_mut().zip(point) {
+                    *b = coordinate_bits(x);
+                }
+                (bits, payload_hash(data))
+            })
+            .collect();
+        items.sort_unstable();
+        let mut hasher = Fnv1a::new();
+        hasher.write(&(items.len() as u64).to_le_bytes());
+        for (bits, payload) in &items {
+            for b in bits {
+                hasher.write(&b.to_le_bytes());
+            }
+            hasher.write(&payload.to_le_bytes());
+        }
+        hasher.finish()
+    }
+
+    /// A hash of the structure of the tree along with its items, which changes whenever a
+    /// split or the order of the items in a leaf does. The nodes are hashed in preorder: a stem
+    /// as the byte 0, its split dimension as a little endian `u64` and the bits of its split
+    /// value, a leaf as the byte 1, its number of items as a little endian `u64` and each item's
+    /// coordinate bits and payload hash as in [`content_hash`](#method.content_hash).
+    pub fn structure_hash(&self) -> u64 {
+        let mut hasher = Fnv1a::new();
+        self.structure_hash_in(&mut hasher);
+        hasher.finish()
+    }
+
+    fn structure_hash_in(&self, hasher: &mut Fnv1a) {
+        match &self.node {
+            Node::Stem {
+                split_dimension,
+                split_value,
+                left,
+                right,
+            } => {
+                hasher.write(&[0]);
+                hasher.write(&(*split_dimension as u64).to_le_bytes());
+                hasher.write(&coordinate_bits(*split_value).to_le_bytes());
+                left.structure_hash_in(hasher);
+                right.structure_hash_in(hasher);
+            }
+            Node::Leaf { points, bucket } => {
+                hasher.write(&[1]);
+                hasher.write(&(points.len() as u64).to_le_bytes());
+                for (point, data) in points.iter().zip(bucket) {
+                    for &x in point {
+                        hasher.write(&coordinate_bits(x).to_le_bytes());
+                    }
+                    hasher.write(&payload_hash(data).to_le_bytes());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::{Rng, SeedableRng, StdRng};
+    use crate::tree::OwnedKdTree;
+
+    fn items() -> Vec<([f64; 3], String)> {
+        (0..300)
+            .map(|i| {
+                let point = [(i % 7) as f64, (i % 11) as f64 * 0.5, (i / 77) as f64];
+                (point, format!("item {}", i % 40))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_hashes_contents_regardless_of_structure() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[2, 7][..]);
+        let items = items();
+        let built = OwnedKdTree::from_points(items.clone(), 8).unwrap();
+        let mut shuffled = items.clone();
+        rng.shuffle(&mut shuffled);
+        let mut added = OwnedKdTree::with_capacity(3);
+        for (point, data) in shuffled {
+            added.add(point, data).unwrap();
+        }
+        assert_eq!(built.content_hash(), added.content_hash());
+        assert_ne!(built.structure_hash(), added.structure_hash());
+        assert_eq!(built.structure_hash(), built.clone().structure_hash());
+
+        // a point of zeros hashes alike whatever the sign of its zeros
+        let mut negative = OwnedKdTree::from_points(items.clone(), 8).unwrap();
+        assert_eq!(items[0].0, [0.0, 0.0, 0.0]);
+        negative.remove(&items[0].0, &items[0].1).unwrap();
+        negative.add([-0.0, 0.0, -0.0], items[0].1.clone()).unwrap();
+        assert_eq!(negative.content_hash(), built.content_hash());
+    }
+
+    #[test]
+    fn it_changes_with_any_item() {
+        let items = items();
+        let hash = |items: &[([f64; 3], String)]| {
+            let tree = OwnedKdTree::from_points(items.to_vec(), 8).unwrap();
+            (tree.content_hash(), tree.structure_hash())
+        };
+        let (content, structure) = hash(&items);
+        let mut moved = items.clone();
+        moved[120].0[1] += 1e-9;
+        let mut renamed = items.clone();
+        renamed[45].1.push('!');
+        let mut duplicated = items.clone();
+        duplicated.push(items[3].clone());
+        let mut dropped = items.clone();
+        dropped.truncate(299);
+        for changed in &[moved, renamed, duplicated, dropped] {
+            let (c, s) = hash(changed);
+            assert_ne!(c, content);
+            assert_ne!(s, structure);
+        }
+
+        // pinned, as fingerprints must stay identical across versions
+        let tree = OwnedKdTree::from_points(vec![([1.0, -2.5], 7u32), ([0.0, 3.0], 8)], 1).unwrap();
+        assert_eq!(tree.content_hash(), 0xb640_22c1_2fc2_7390);
+        assert_eq!(tree.structure_hash(), 0x96f3_944e_30d5_cab5);
+        assert_eq!(
+            OwnedKdTree::<f64, u32, 2>::new().content_hash(),
+            0xa8c7_f832_281a_39c5
+        );
+    }
+}
@@ -0,0 +1,651 @@
+//! Queries between two trees over the same space.
+
+use num_traits::{Float, One, Zero};
+
+use super::{Node, OwnedKdTree};
+use crate::geometry::{
+    distance_between_far_corners_const, distance_between_spaces_const,
+    distance_to_far_corner_const, distance_to_space_const,
+};
+
+/// The intersection of two boxes `(min, max)`, `None` if they are disjoint. Boxes which only
+/// touch intersect in a degenerate box.
+fn intersection<A: Float, const D: usize>(
+    a: (&[A; D], &[A; D]),
+    b: (&[A; D], &[A; D]),
+) -> Option<([A; D], [A; D])> {
+    let (mut min, mut max) = (*a.0, *a.1);
+    for i in 0..D {
+        min[i] = min[i].max(b.0[i]);
+        max[i] = max[i].min(b.1[i]);
+        if min[i] > max[i] {
+            return None;
+        }
+    }
+    Some((min, max))
+}
+
+/// The mean of the `distances` of each leaf, summed in order so that sequential and parallel
+/// computations agree exactly
+pub(crate) fn mean_of_leaves<A: Float>(distances: &[Vec<A>]) -> A {
+    let mut sum = A::zero();
+    let mut count = 0;
+    for leaf in distances {
+        for &d in leaf {
+            sum = sum + d;
+        }
+        count += leaf.len();
+    }
+    sum / A::from(count).unwrap()
+}
+
+impl<A: Float + Zero + One, T: PartialEq, const D: usize> OwnedKdTree<A, T, D> {
+    /// The intersection of the bounding boxes of both trees, `None` if either tree is empty or
+    /// the boxes are disjoint. Like [`extent`](#method.extent), it may be larger than needed
+    /// after removals.
+    pub fn bounds_intersection<U: PartialEq>(
+        &self,
+        other: &OwnedKdTree<A, U, D>,
+    ) -> Option<([A; D], [A; D])> {
+        self.node_intersection(other)
+    }
+
+    /// The intersections of the bounding boxes of the nodes of both trees which overlap,
+    /// descending both trees together for up to `max_depth` levels. The regions cover every
+    /// point within the bounding boxes of a leaf of each tree, so items of the two trees can
+    /// only be close to each other in or near them. A `max_depth` of zero returns the
+    /// [`bounds_intersection`](#method.bounds_intersection), and larger depths make the map
+    /// finer at the cost of up to four times as many node pairs per level.
+    pub fn overlap_regions<U: PartialEq>(
+        &self,
+        other: &OwnedKdTree<A, U, D>,
+        max_depth: usize,
+    ) -> Vec<([A; D], [A; D])> {
+        let mut regions = vec![];
+        self.overlap_regions_in(other, max_depth, &mut regions);
+        regions
+    }
+
+    fn overlap_regions_in<U: PartialEq>(
+        &self,
+        other: &OwnedKdTree<A, U, D>,
+        depth: usize,
+        regions: &mut Vec<([A; D], [A; D])>,
+    ) {
+        let region = match self.node_intersection(other) {
+            Some(region) => region,
+            None => return,
+        };
+        if depth == 0 || (self.is_leaf() && other.is_leaf()) {
+            regions.push(region);
+            return;
+        }
+        match (self.children(), other.children()) {
+            (Some(ours), Some(theirs)) => {
+                for node in ours.iter() {
+                    for other in theirs.iter() {
+                        node.overlap_regions_in(other, depth - 1, regions);
+                    }
+                }
+            }
+            (Some(ours), None) => {
+                for node in ours.iter() {
+                    node.overlap_regions_in(other, depth - 1, regions);
+                }
+            }
+            (None, Some(theirs)) => {
+                for other in theirs.iter() {
+                    self.overlap_regions_in(other, depth - 1, regions);
+                }
+            }
+            (None, None) => regions.push(region),
+        }
+    }
+
+    /// Every pair of an item of this tree and an item of `other` within `radius` of each other,
+    /// with their distance, in no particular order. The trees are descended together, skipping
+    /// pairs of nodes whose boxes are farther apart than `radius`, and pairs of nodes whose boxes
+    /// are entirely within `radius` of each other are paired up without descending further.
+    pub fn join_within<'a, 'b, U, F>(
+        &'a self,
+        other: &'b OwnedKdTree<A, U, D>,
+        radius: A,
+        distance: &F,
+    ) -> Vec<(A, &'a T, &'b U)>
+    where
+        U: PartialEq,
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let mut pairs = vec![];
+        self.for_each_join_within(other, radius, distance, |d, t, u| pairs.push((d, t, u)));
+        pairs
+    }
+
+    /// Like [`join_within`](#method.join_within), but passes each pair to `f` instead of
+    /// collecting them
+    pub fn for_each_join_within<'a, 'b, U, F, G>(
+        &'a self,
+        other: &'b OwnedKdTree<A, U, D>,
+        radius: A,
+        distance: &F,
+        mut f: G,
+    ) where
+        U: PartialEq,
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+        G: FnMut(A, &'a T, &'b U),
+    {
+        self.join_within_in(other, radius, distance, &mut f)
+    }
+
+    fn join_within_in<'a, 'b, U, F, G>(
+        &'a self,
+        other: &'b OwnedKdTree<A, U, D>,
+        radius: A,
+        distance: &F,
+        f: &mut G,
+    ) where
+        U: PartialEq,
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+        G: FnMut(A, &'a T, &'b U),
+    {
+        if self.size == 0 || other.size == 0 {
+            return;
+        }
+        let (min, max) = (&self.min_bounds, &self.max_bounds);
+        let (other_min, other_max) = (&other.min_bounds, &other.max_bounds);
+        if distance_between_spaces_const(min, max, other_min, other_max, distance) > radius {
+            return;
+        }
+        let all_within =
+            distance_between_far_corners_const(min, max, other_min, other_max, distance) <= radius;
+        if all_within || (self.is_leaf() && other.is_leaf()) {
+            // the distances are still compared, as they may round differently from the bound
+            for (p, t) in self.iter() {
+                for (q, u) in other.iter() {
+                    let d = distance(p, q);
+                    if d <= radius {
+                        f(d, t, u);
+                    }
+                }
+            }
+            return;
+        }
+        // descend into the larger of two stems
+        let ours = self
+            .children()
+            .filter(|_| other.is_leaf() || self.size >= other.size);
+        match (ours, other.children()) {
+            (Some(ours), _) => {
+                for node in ours {
+                    node.join_within_in(other, radius, distance, f);
+                }
+            }
+            (None, Some(theirs)) => {
+                for other in theirs {
+                    self.join_within_in(other, radius, distance, f);
+                }
+            }
+            (None, None) => {}
+        }
+    }
+
+    /// The directed Hausdorff distance from this tree to `other`: the largest distance from an
+    /// item of this tree to its nearest item in `other`, `None` if either tree is empty. Subtrees
+    /// whose items are all nearer to `other` than the largest distance found so far are skipped,
+    /// as is the rest of a nearest search once it finds an item nearer than that.
+    pub fn directed_hausdorff<U, F>(&self, other: &OwnedKdTree<A, U, D>, distance: &F) -> Option<A>
+    where
+        U: PartialEq,
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        if self.size == 0 || other.size == 0 {
+            return None;
+        }
+        let mut max = A::neg_infinity();
+        self.directed_hausdorff_in(other, distance, &mut max);
+        Some(max)
+    }
+
+    /// The Hausdorff distance between the trees, the larger of the
+    /// [`directed_hausdorff`](#method.directed_hausdorff) distances in both directions
+    pub fn hausdorff<U, F>(&self, other: &OwnedKdTree<A, U, D>, distance: &F) -> Option<A>
+    where
+        U: PartialEq,
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let forward = self.directed_hausdorff(other, distance)?;
+        let backward = other.directed_hausdorff(self, distance)?;
+        Some(forward.max(backward))
+    }
+
+    fn directed_hausdorff_in<U, F>(&self, other: &OwnedKdTree<A, U, D>, distance: &F, max: &mut A)
+    where
+        U: PartialEq,
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        if self.size == 0 {
+            return;
+        }
+        let children = match &self.node {
+            Node::Stem { left, right, .. } => [&**left, &**right],
+            Node::Leaf { points, .. } => {
+                for p in points {
+                    let mut nearest = A::infinity();
+                    other.nearest_distance_above(p, *max, distance, &mut nearest);
+                    if nearest > *max {
+                        *max = nearest;
+                    }
+                }
+                return;
+            }
+        };
+        let mut children = children.map(|node| {
+            let mut bound = A::infinity();
+            other.farthest_nearest_bound(&node.min_bounds, &node.max_bounds, distance, &mut bound);
+            (bound, node)
+        });
+        // the child which may hold the farthest item first, to raise `max` early
+        if children[1].0 > children[0].0 {
+            children.swap(0, 1);
+        }
+        for (bound, node) in children.iter() {
+            if *bound > *max {
+                node.directed_hausdorff_in(other, distance, max);
+            }
+        }
+    }
+
+    /// Lowers `nearest` to the distance from `point` to its nearest item, stopping early once
+    /// `nearest` is at most `floor`
+    fn nearest_distance_above<F>(&self, point: &[A; D], floor: A, distance: &F, nearest: &mut A)
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        if self.size == 0 || *nearest <= floor {
+            return;
+        }
+        if distance_to_space_const(point, &self.min_bounds, &self.max_bounds, distance) >= *nearest
+        {
+            return;
+        }
+        let children = match &self.node {
+            Node::Stem { left, right, .. } => [&**left, &**right],
+            Node::Leaf { points, .. } => {
+                for p in points {
+                    let d = distance(point, p);
+                    if d < *nearest {
+                        *nearest = d;
+                        if d <= floor {
+                            return;
+                        }
+                    }
+                }
+                return;
+            }
+        };
+        let to = |node: &Self| {
+            distance_to_space_const(point, &node.min_bounds, &node.max_bounds, distance)
+        };
+        let [mut first, mut second] = children;
+        if to(second) < to(first) {
+            std::mem::swap(&mut first, &mut second);
+        }
+        first.nearest_distance_above(point, floor, distance, nearest);
+        second.nearest_distance_above(point, floor, distance, nearest);
+    }
+
+    /// Lowers `bound` to an upper bound on the distance from any point of the box `[min, max]`
+    /// to its nearest item: the smallest distance from an item to the farthest corner of the box
+    fn farthest_nearest_bound<F>(&self, min: &[A; D], max: &[A; D], distance: &F, bound: &mut A)
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        if self.size == 0 {
+            return;
+        }
+        // an item is at least as far from the farthest corner as from the box
+        let near = |node: &Self| {
+            distance_between_spaces_const(&node.min_bounds, &node.max_bounds, min, max, distance)
+        };
+        if near(self) >= *bound {
+            return;
+        }
+        let children = match &self.node {
+            Node::Stem { left, right, .. } => [&**left, &**right],
+            Node::Leaf { points, .. } => {
+                for p in points {
+                    let far = distance_to_far_corner_const(p, min, max, distance);
+                    if far < *bound {
+                        *bound = far;
+                    }
+                }
+                return;
+            }
+        };
+        let [mut first, mut second] = children;
+        if near(second) < near(first) {
+            std::mem::swap(&mut first, &mut second);
+        }
+        first.farthest_nearest_bound(min, max, distance, bound);
+        second.farthest_nearest_bound(min, max, distance, bound);
+    }
+
+    /// The chamfer distance between the trees: the mean distance from an item of this tree to
+    /// its nearest item in `other` plus the mean distance from an item of `other` to its nearest
+    /// item in this tree, `None` if either tree is empty. The nearest items to the items of each
+    /// leaf are found together, see [`nearest_multi`](#method.nearest_multi).
+    /// [`par_chamfer_distance`](#method.par_chamfer_distance) returns the same value computing
+    /// the leaves in parallel.
+    pub fn chamfer_distance<U, F>(&self, other: &OwnedKdTree<A, U, D>, distance: &F) -> Option<A>
+    where
+        U: PartialEq,
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        if self.size == 0 || other.size == 0 {
+            return None;
+        }
+        let forward: Vec<_> = self
+            .nonempty_leaves()
+            .iter()
+            .map(|leaf| leaf.leaf_nearest_distances(other, distance))
+            .collect();
+        let backward: Vec<_> = other
+            .nonempty_leaves()
+            .iter()
+            .map(|leaf| leaf.leaf_nearest_distances(self, distance))
+            .collect();
+        Some(mean_of_leaves(&forward) + mean_of_leaves(&backward))
+    }
+
+    /// The leaves holding items, lower sides of splits first as in [`iter`](#method.iter)
+    pub(crate) fn nonempty_leaves(&self) -> Vec<&Self> {
+        let mut leaves = vec![];
+        let mut pending = vec![self];
+        while let Some(node) = pending.pop() {
+            if node.size == 0 {
+                continue;
+            }
+            match node.children() {
+                Some([left, right]) => {
+                    pending.push(right);
+                    pending.push(left);
+                }
+                None => leaves.push(node),
+            }
+        }
+        leaves
+    }
+
+    /// The distance from each item of this leaf to its nearest item in `other`, which must not
+    /// be empty
+    pub(crate) fn leaf_nearest_distances<U, F>(
+        &self,
+        other: &OwnedKdTree<A, U, D>,
+        distance: &F,
+    ) -> Vec<A>
+    where
+        U: PartialEq,
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let points = match &self.node {
+            Node::Leaf { points, .. } => points,
+            Node::Stem { .. } => return vec![],
+        };
+        // stored points are finite, so the queries are valid
+        let nearest = other.nearest_multi(points, 1, distance).unwrap();
+        nearest.into_iter().map(|found| found[0].0).collect()
+    }
+
+    fn node_intersection<U: PartialEq>(
+        &self,
+        other: &OwnedKdTree<A, U, D>,
+    ) -> Option<([A; D], [A; D])> {
+        if self.size == 0 || other.size == 0 {
+            return None;
+        }
+        intersection(
+            (&self.min_bounds, &self.max_bounds),
+            (&other.min_bounds, &other.max_bounds),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use crate::distance::squared_euclidean;
+    use crate::tree::{LeafSummary, OwnedKdTree};
+
+    fn grid(from: f64, to: f64, steps: usize) -> OwnedKdTree<f64, usize, 2> {
+        let mut tree = OwnedKdTree::with_capacity(4);
+        let step = (to - from) / (steps - 1) as f64;
+        for i in 0..steps * steps {
+            let point = [
+                from + (i % steps) as f64 * step,
+                from + (i / steps) as f64 * step,
+            ];
+            tree.add(point, i).unwrap();
+        }
+        tree
+    }
+
+    fn inside(point: &[f64; 2], (min, max): &([f64; 2], [f64; 2])) -> bool {
+        (0..2).all(|i| min[i] <= point[i] && point[i] <= max[i])
+    }
+
+    #[test]
+    fn it_intersects_the_bounds_of_two_trees() {
+        let a = grid(0.0, 10.0, 11);
+        let nested = grid(2.0, 4.0, 5);
+        let partial = grid(5.0, 15.0, 11);
+        let disjoint = grid(20.0, 30.0, 11);
+        let empty = OwnedKdTree::<f64, (), 2>::new();
+
+        assert_eq!(
+            a.bounds_intersection(&nested),
+            Some(([2.0, 2.0], [4.0, 4.0]))
+        );
+        assert_eq!(
+            nested.bounds_intersection(&a),
+            Some(([2.0, 2.0], [4.0, 4.0]))
+        );
+        assert_eq!(
+            a.bounds_intersection(&partial),
+            Some(([5.0, 5.0], [10.0, 10.0]))
+        );
+        assert_eq!(a.bounds_intersection(&disjoint), None);
+        assert_eq!(a.bounds_intersection(&empty), None);
+        assert_eq!(empty.bounds_intersection(&a), None);
+
+        assert!(a.overlap_regions(&disjoint, 8).is_empty());
+        assert!(a.overlap_regions(&empty, 8).is_empty());
+        assert_eq!(
+            a.overlap_regions(&partial, 0),
+            vec![([5.0, 5.0], [10.0, 10.0])]
+        );
+    }
+
+    #[test]
+    fn it_maps_overlapping_regions() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[5, 2][..]);
+        let mut a = OwnedKdTree::with_capacity(4);
+        let mut b = OwnedKdTree::with_capacity(4);
+        for i in 0..400 {
+            a.add([rng.gen_range(0.0, 10.0), rng.gen_range(0.0, 10.0)], i)
+                .unwrap();
+            b.add([rng.gen_range(6.0, 16.0), rng.gen_range(3.0, 8.0)], i)
+                .unwrap();
+        }
+        let area = |(min, max): &([f64; 2], [f64; 2])| (max[0] - min[0]) * (max[1] - min[1]);
+        let root = a.bounds_intersection(&b).unwrap();
+        let mut previous = area(&root);
+        for depth in 1..8 {
+            let regions = a.overlap_regions(&b, depth);
+            // the regions of each level lie within those of the level above
+            let covered_area: f64 = regions.iter().map(area).sum();
+            assert!(covered_area <= previous);
+            previous = covered_area;
+            for (min, max) in &regions {
+                assert!(inside(min, &root) && inside(max, &root));
+            }
+            // a point of one tree inside a leaf of the other could pair up with its items, so it
+            // lies in a region
+            let covered = |point: &[f64; 2], leaves: &[LeafSummary<f64, 2>]| {
+                let in_leaf = leaves.iter().any(|leaf| inside(point, &leaf.bounds));
+                !in_leaf || regions.iter().any(|r| inside(point, r))
+            };
+            let (a_leaves, b_leaves) = (a.leaf_summaries(), b.leaf_summaries());
+            assert!(a.iter().all(|(p, _)| covered(p, &b_leaves)));
+            assert!(b.iter().all(|(p, _)| covered(p, &a_leaves)));
+        }
+        assert!(previous < area(&root) / 2.0);
+    }
+
+    #[test]
+    fn it_joins_pairs_within_a_radius() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+
+        let mut rng: StdRng = SeedableRng::from_seed(&[9, 1, 5][..]);
+        // clustered points, so that some node pairs are entirely within the radius
+        let mut pings = vec![];
+        let mut vertices = vec![];
+        for i in 0..3000 {
+            let centre = [(i % 7) as f64 * 3.0, (i % 5) as f64 * 4.0];
+            let mut near = |spread: f64| {
+                [
+                    centre[0] + rng.gen_range(-spread, spread),
+                    centre[1] + rng.gen_range(-spread, spread),
+                ]
+            };
+            pings.push((near(0.5), i));
+            vertices.push((near(1.5), i as u32));
+        }
+        let a = OwnedKdTree::from_points(pings.clone(), 8).unwrap();
+        let b = OwnedKdTree::from_points(vertices.clone(), 5).unwrap();
+        let distance = |p: &[f64; 2], q: &[f64; 2]| squared_euclidean(p, q);
+
+        let radii = [0.0, 0.01, 0.3, 4.0];
+        let mut nested_loop = vec![];
+        for (p, t) in &pings {
+            for (q, u) in &vertices {
+                let d = distance(p, q);
+                if d <= radii[3] {
+                    nested_loop.push((*t, *u, d));
+                }
+            }
+        }
+        nested_loop.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        for &radius in &radii {
+            let expected: Vec<_> = nested_loop.iter().filter(|x| x.2 <= radius).collect();
+
+            let mut joined: Vec<_> = a
+                .join_within(&b, radius, &distance)
+                .into_iter()
+                .map(|(d, &t, &u)| (t, u, d))
+                .collect();
+            joined.sort_by(|x, y| x.partial_cmp(y).unwrap());
+            assert_eq!(joined.iter().collect::<Vec<_>>(), expected);
+
+            let mut count = 0;
+            a.for_each_join_within(&b, radius, &distance, |d, _, _| {
+                assert!(d <= radius);
+                count += 1;
+            });
+            assert_eq!(count, expected.len());
+        }
+        let empty = OwnedKdTree::<f64, (), 2>::new();
+        assert!(a.join_within(&empty, 100.0, &distance).is_empty());
+        assert!(empty.join_within(&b, 100.0, &distance).is_empty());
+    }
+
+    #[test]
+    fn it_measures_hausdorff_distances() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+
+        let distance = |p: &[f64; 3], q: &[f64; 3]| squared_euclidean(p, q);
+        let nearest = |p: &[f64; 3], to: &[[f64; 3]]| {
+            to.iter()
+                .map(|q| distance(p, q))
+                .fold(f64::INFINITY, f64::min)
+        };
+        let directed = |from: &[[f64; 3]], to: &[[f64; 3]]| {
+            from.iter().map(|p| nearest(p, to)).fold(0.0, f64::max)
+        };
+        let mut rng: StdRng = SeedableRng::from_seed(&[3, 3, 7][..]);
+        for &(size_a, size_b, spread) in
+            &[(300, 200, 1.0), (50, 400, 5.0), (1, 1, 1.0), (200, 3, 0.1)]
+        {
+            let mut points = |n: usize, offset: f64| -> Vec<[f64; 3]> {
+                (0..n)
+                    .map(|_| {
+                        let mut p = [0.0; 3];
+                        for x in p.iter_mut() {
+                            *x = offset + rng.gen_range(-spread, spread);
+                        }
+                        p
+                    })
+                    .collect()
+            };
+            let (pa, pb) = (points(size_a, 0.0), points(size_b, 0.5));
+            let items = |points: &[[f64; 3]]| points.iter().map(|&p| (p, ())).collect();
+            let a = OwnedKdTree::from_points(items(&pa), 4).unwrap();
+            let b = OwnedKdTree::from_points(items(&pb), 4).unwrap();
+            assert_eq!(
+                a.directed_hausdorff(&b, &distance),
+                Some(directed(&pa, &pb))
+            );
+            assert_eq!(
+                b.directed_hausdorff(&a, &distance),
+                Some(directed(&pb, &pa))
+            );
+            let both = directed(&pa, &pb).max(directed(&pb, &pa));
+            assert_eq!(a.hausdorff(&b, &distance), Some(both));
+            assert_eq!(a.hausdorff(&a.clone(), &distance), Some(0.0));
+        }
+        let empty = OwnedKdTree::<f64, (), 3>::new();
+        let one = OwnedKdTree::from_points(vec![([1.0, 2.0, 3.0], ())], 4).unwrap();
+        assert_eq!(one.directed_hausdorff(&empty, &distance), None);
+        assert_eq!(empty.directed_hausdorff(&one, &distance), None);
+        assert_eq!(empty.hausdorff(&empty, &distance), None);
+    }
+
+    #[test]
+    fn it_measures_chamfer_distances() {
+        use self::rand::{Rng, SeedableRng, StdRng};
+
+        let distance = |p: &[f64; 2], q: &[f64; 2]| squared_euclidean(p, q);
+        let mut rng: StdRng = SeedableRng::from_seed(&[8, 1][..]);
+        let mut a = OwnedKdTree::with_capacity(6);
+        let mut b = OwnedKdTree::with_capacity(3);
+        for i in 0..700 {
+            a.add([rng.gen_range(0.0, 4.0), rng.gen_range(0.0, 4.0)], i)
+                .unwrap();
+            if i % 3 == 0 {
+                b.add([rng.gen_range(1.0, 6.0), rng.gen_range(-1.0, 3.0)], i)
+                    .unwrap();
+            }
+        }
+        // summed in the order of iteration, as the tree does
+        let mean = |from: &OwnedKdTree<f64, i32, 2>, to: &OwnedKdTree<f64, i32, 2>| {
+            let mut sum = 0.0;
+            for (p, _) in from.iter() {
+                sum += to
+                    .iter()
+                    .map(|(q, _)| distance(p, q))
+                    .fold(f64::INFINITY, f64::min);
+            }
+            sum / from.size() as f64
+        };
+        let expected = mean(&a, &b) + mean(&b, &a);
+        assert_eq!(a.chamfer_distance(&b, &distance), Some(expected));
+        assert_eq!(a.chamfer_distance(&a, &distance), Some(0.0));
+
+        let empty = OwnedKdTree::<f64, i32, 2>::new();
+        assert_eq!(a.chamfer_distance(&empty, &distance), None);
+        assert_eq!(empty.chamfer_distance(&b, &distance), None);
+        assert_eq!(empty.chamfer_distance(&empty, &distance), None);
+    }
+}
@@ -0,0 +1,215 @@
+//! The storage of the points and of the payloads of a leaf. A leaf of a single item, the only
+//! kind a tree of capacity 1 has, keeps it inline rather than in a heap allocation of its own,
+//! and any other leaf keeps its items in a `Vec`. Either way a leaf reads as a slice.
+
+use std::iter::FromIterator;
+use std::ops::{Deref, DerefMut};
+
+/// The items of one column of a leaf
+#[derive(Clone, Debug)]
+pub(crate) enum LeafVec<X> {
+    One(X),
+    Many(Vec<X>),
+}
+
+/// Iterator returned by [`LeafVec::into_iter`]
+pub(crate) type IntoIter<X> = std::iter::Chain<std::option::IntoIter<X>, std::vec::IntoIter<X>>;
+
+impl<X> LeafVec<X> {
+    /// How many items the column holds without growing
+    pub(crate) fn capacity(&self) -> usize {
+        match self {
+            LeafVec::One(_) => 1,
+            LeafVec::Many(items) => items.capacity(),
+        }
+    }
+
+    /// Makes room for `additional` more items, which a column with no room is left to store
+    /// inline when `additional` is one
+    pub(crate) fn reserve_exact(&mut self, additional: usize) {
+        match self {
+            LeafVec::Many(items) if items.capacity() == 0 && additional <= 1 => {}
+            LeafVec::Many(items) => items.reserve_exact(additional),
+            LeafVec::One(_) => {
+                let mut items = Vec::with_capacity(1 + additional);
+                items.extend(std::mem::take(self));
+                *self = LeafVec::Many(items);
+            }
+        }
+    }
+
+    pub(crate) fn push(&mut self, item: X) {
+        match self {
+            LeafVec::Many(items) if items.capacity() == 0 => *self = LeafVec::One(item),
+            LeafVec::Many(items) => items.push(item),
+            LeafVec::One(_) => {
+                self.reserve_exact(1);
+                self.push(item);
+            }
+        }
+    }
+
+    /// Removes the item at `i`, shifting the items after it down as `Vec::remove` does
+    ///
+    /// # Panics
+    ///
+    /// If `i` is out of bounds
+    pub(crate) fn remove(&mut self, i: usize) -> X {
+        match self {
+            LeafVec::Many(items) => items.remove(i),
+            LeafVec::One(_) => self.take_one(i),
+        }
+    }
+
+    /// Removes the item at `i`, moving the last item in its place as `Vec::swap_remove` does
+    ///
+    /// # Panics
+    ///
+    /// If `i` is out of bounds
+    pub(crate) fn swap_remove(&mut self, i: usize) -> X {
+        match self {
+            LeafVec::Many(items) => items.swap_remove(i),
+            LeafVec::One(_) => self.take_one(i),
+        }
+    }
+
+    fn take_one(&mut self, i: usize) -> X {
+        assert_eq!(i, 0, "removal index should be < len");
+        match std::mem::take(self) {
+            LeafVec::One(item) => item,
+            LeafVec::Many(_) => unreachable!(),
+        }
+    }
+}
+
+impl<X> Default for LeafVec<X> {
+    fn default() -> Self {
+        LeafVec::Many(Vec::new())
+    }
+}
+
+impl<X> From<Vec<X>> for LeafVec<X> {
+    fn from(mut items: Vec<X>) -> Self {
+        match items.pop() {
+            Some(item) if items.is_empty() => LeafVec::One(item),
+            Some(item) => {
+                items.push(item);
+                LeafVec::Many(items)
+            }
+            None => LeafVec::Many(items),
+        }
+    }
+}
+
+impl<X> Deref for LeafVec<X> {
+    type Target = [X];
+
+    fn deref(&self) -> &[X] {
+        match self {
+            LeafVec::One(item) => std::slice::from_ref(item),
+            LeafVec::Many(items) => items,
+        }
+    }
+}
+
+impl<X> DerefMut for LeafVec<X> {
+    fn deref_mut(&mut self) -> &mut [X] {
+        match self {
+            LeafVec::One(item) => std::slice::from_mut(item),
+            LeafVec::Many(items) => items,
+        }
+    }
+}
+
+impl<X: PartialEq> PartialEq for LeafVec<X> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<X> Extend<X> for LeafVec<X> {
+    fn extend<I: IntoIterator<Item = X>>(&mut self, items: I) {
+        for item in items {
+            self.push(item);
+        }
+    }
+}
+
+impl<X> FromIterator<X> for LeafVec<X> {
+    fn from_iter<I: IntoIterator<Item = X>>(items: I) -> Self {
+        Vec::from_iter(items).into()
+    }
+}
+
+impl<X> IntoIterator for LeafVec<X> {
+    type Item = X;
+    type IntoIter = IntoIter<X>;
+
+    fn into_iter(self) -> IntoIter<X> {
+        match self {
+            LeafVec::One(item) => Some(item).into_iter().chain(Vec::new()),
+            LeafVec::Many(items) => None.into_iter().chain(items),
+        }
+    }
+}
+
+impl<'a, X> IntoIterator for &'a LeafVec<X> {
+    type Item = &'a X;
+    type IntoIter = std::slice::Iter<'a, X>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, X> IntoIterator for &'a mut LeafVec<X> {
+    type Item = &'a mut X;
+    type IntoIter = std::slice::IterMut<'a, X>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LeafVec;
+
+    #[test]
+    fn it_keeps_a_single_item_inline() {
+        let mut items = LeafVec::default();
+        items.push(1);
+        assert!(matches!(items, LeafVec::One(1)));
+        assert_eq!(&*items, &[1]);
+        items.push(2);
+        items.push(3);
+        assert_eq!(&*items, &[1, 2, 3]);
+        assert_eq!(items.remove(0), 1);
+        assert_eq!(items.swap_remove(0), 2);
+        assert_eq!(&*items, &[3]);
+
+        let mut one = LeafVec::from(vec![4]);
+        assert!(matches!(one, LeafVec::One(4)));
+        assert_eq!(one, LeafVec::Many(vec![4]));
+        one[0] = 5;
+        assert_eq!(one.swap_remove(0), 5);
+        assert!(one.is_empty());
+        one.push(6);
+        assert!(matches!(one, LeafVec::One(6)));
+        assert_eq!(one.into_iter().collect::<Vec<_>>(), [6]);
+
+        let mut none = LeafVec::default();
+        assert_eq!(none.capacity(), 0);
+        none.reserve_exact(1);
+        assert_eq!(none.capacity(), 0);
+        none.push(7);
+        assert_eq!(none.capacity(), 1);
+        none.reserve_exact(3);
+        assert_eq!(none.capacity(), 4);
+        assert_eq!(&*none, &[7]);
+
+        let many: LeafVec<i32> = (0..4).collect();
+        assert!(many.capacity() >= 4);
+        assert_eq!(many.into_iter().collect::<Vec<_>>(), [0, 1, 2, 3]);
+    }
+}
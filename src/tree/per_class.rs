@@ -0,0 +1,258 @@
+//! Nearest neighbours of each class of items in a single search. Every class keeps its own
+//! nearest items, and a node is only ruled out once no class which may still gain an item
+//! could gain one from it: once every class is full and the node is farther than the farthest
+//! item kept by any of them.
+
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap};
+use std::hash::Hash;
+
+use num_traits::{Float, One, Zero};
+
+use super::{ErrorKind, OwnedKdTree};
+use crate::geometry::distance_to_space_const;
+use crate::heap_element::Neighbour;
+use crate::priority::MinByDistance;
+
+impl<A: Float + Zero + One, T: PartialEq, const D: usize> OwnedKdTree<A, T, D> {
+    /// The `per_class` items nearest to `point` of each class `class_of` puts the items in,
+    /// nearest first, keyed by class. As any item not yet seen may belong to a class of its
+    /// own, no node can be ruled out and every item is measured; use
+    /// [`nearest_per_class_of`](#method.nearest_per_class_of) when the classes are known.
+    #[allow(clippy::type_complexity)]
+    pub fn nearest_per_class<K, C, F>(
+        &self,
+        point: &[A; D],
+        per_class: usize,
+        class_of: C,
+        distance: &F,
+    ) -> Result<HashMap<K, Vec<(A, &T)>>, ErrorKind>
+    where
+        K: Eq + Hash,
+        C: Fn(&T) -> K,
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let () = self.check_point(point)?;
+        Ok(self.nearest_per_class_in(point, per_class, HashMap::new(), true, &class_of, distance))
+    }
+
+    /// Like [`nearest_per_class`](#method.nearest_per_class), but only for the items of
+    /// `classes`, each of which is in the result even if it has no item. Nodes farther than
+    /// the farthest item kept by any class are ruled out once every class has `per_class`
+    /// items.
+    #[allow(clippy::type_complexity)]
+    pub fn nearest_per_class_of<K, C, F>(
+        &self,
+        point: &[A; D],
+        per_class: usize,
+        classes: &[K],
+        class_of: C,
+        distance: &F,
+    ) -> Result<HashMap<K, Vec<(A, &T)>>, ErrorKind>
+    where
+        K: Eq + Hash + Clone,
+        C: Fn(&T) -> K,
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let () = self.check_point(point)?;
+        let found = classes
+            .iter()
+            .map(|k| (k.clone(), BinaryHeap::new()))
+            .collect();
+        Ok(self.nearest_per_class_in(point, per_class, found, false, &class_of, distance))
+    }
+
+    /// The best-first search behind both, starting with a heap for each class in `found`
+    /// and adding one for each class met if `open`
+    fn nearest_per_class_in<'b, K, C, F>(
+        &'b self,
+        point: &[A; D],
+        per_class: usize,
+        mut found: HashMap<K, BinaryHeap<Neighbour<'b, A, &'b T, D>>>,
+        open: bool,
+        class_of: &C,
+        distance: &F,
+    ) -> HashMap<K, Vec<(A, &'b T)>>
+    where
+        K: Eq + Hash,
+        C: Fn(&T) -> K,
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        // the distance a node must lie within to possibly improve some class
+        let bound = |found: &HashMap<K, BinaryHeap<Neighbour<'b, A, &'b T, D>>>| {
+            if open {
+                return A::infinity();
+            }
+            found
+                .values()
+                .fold(A::neg_infinity(), |bound, heap| match heap.peek() {
+                    Some(farthest) if heap.len() >= per_class => bound.max(farthest.distance),
+                    _ => A::infinity(),
+                })
+        };
+        let mut pending = BinaryHeap::new();
+        if per_class > 0 && self.size > 0 {
+            pending.push(MinByDistance::new(A::zero(), self));
+        }
+        let mut within = bound(&found);
+        while let Some(next) = pending.pop() {
+            if next.0.distance > within {
+                break;
+            }
+            let mut curr = next.0.element;
+            let (points, bucket) = loop {
+                let (near, candidate) = match curr.sides(point) {
+                    Ok(sides) => sides,
+                    Err(leaf) => break leaf,
+                };
+                curr = near;
+                if candidate.size == 0 {
+                    continue;
+                }
+                let (min, max) = (&candidate.min_bounds, &candidate.max_bounds);
+                let to_space = distance_to_space_const(point, min, max, distance);
+                // a node whose distance cannot be told is never ruled out
+                let to_space = if to_space.is_nan() {
+                    A::zero()
+                } else {
+                    to_space
+                };
+                if to_space <= within {
+                    pending.push(MinByDistance::new(to_space, candidate));
+                }
+            };
+            for (p, data) in points.iter().zip(bucket) {
+                let element = Neighbour {
+                    distance: distance(point, p),
+                    point: p,
+                    element: data,
+                };
+                if element.distance.is_nan() {
+                    continue;
+                }
+                let heap = match found.entry(class_of(data)) {
+                    Entry::Occupied(heap) => heap.into_mut(),
+                    Entry::Vacant(heap) if open => heap.insert(BinaryHeap::new()),
+                    Entry::Vacant(_) => continue,
+                };
+                if heap.len() < per_class {
+                    heap.push(element);
+                } else if heap.peek().is_some_and(|farthest| element < *farthest) {
+                    heap.pop();
+                    heap.push(element);
+                }
+            }
+            within = bound(&found);
+        }
+        found
+            .into_iter()
+            .map(|(k, heap)| {
+                let nearest = heap.into_sorted_vec().into_iter().map(Into::into).collect();
+                (k, nearest)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use std::collections::HashMap;
+
+    use self::rand::{Rng, SeedableRng, StdRng};
+    use crate::distance::squared_euclidean;
+    use crate::tree::OwnedKdTree;
+
+    fn dist(a: &[f64; 2], b: &[f64; 2]) -> f64 {
+        squared_euclidean(a, b)
+    }
+
+    /// The distances of the `num` items of class `class` nearest to `point`
+    fn brute_force(
+        items: &[([f64; 2], usize)],
+        point: &[f64; 2],
+        num: usize,
+        class: usize,
+    ) -> Vec<f64> {
+        let mut found: Vec<f64> = items
+            .iter()
+            .filter(|(_, i)| i % 7 == class)
+            .map(|(p, _)| dist(point, p))
+            .collect();
+        found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        found.truncate(num);
+        found
+    }
+
+    fn distances(found: &HashMap<usize, Vec<(f64, &usize)>>, class: usize) -> Vec<f64> {
+        found[&class].iter().map(|&(d, _)| d).collect()
+    }
+
+    #[test]
+    fn it_finds_the_nearest_of_each_class_like_a_filtered_search() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[18, 6][..]);
+        // class 6 is rare, so its nearest items are far from most queries
+        let items: Vec<([f64; 2], usize)> = (0..3000)
+            .filter(|i| i % 7 != 6 || i % 140 == 6)
+            .map(|i| ([rng.gen_range(0.0, 10.0), rng.gen_range(0.0, 10.0)], i))
+            .collect();
+        let tree = OwnedKdTree::from_points(items.clone(), 8).unwrap();
+        let class_of = |i: &usize| i % 7;
+        let classes: Vec<usize> = (0..8).collect();
+        for _ in 0..50 {
+            let point = [rng.gen_range(-1.0, 11.0), rng.gen_range(-1.0, 11.0)];
+            let per_class = rng.gen_range(1, 30);
+            let open = tree
+                .nearest_per_class(&point, per_class, class_of, &dist)
+                .unwrap();
+            let known = tree
+                .nearest_per_class_of(&point, per_class, &classes, class_of, &dist)
+                .unwrap();
+            assert_eq!(open.len(), 7);
+            assert_eq!(known.len(), 8);
+            assert!(known[&7].is_empty());
+            for class in 0..7 {
+                let expected = brute_force(&items, &point, per_class, class);
+                assert_eq!(distances(&open, class), expected);
+                assert_eq!(distances(&known, class), expected);
+                assert!(open[&class].iter().all(|(_, &i)| i % 7 == class));
+            }
+        }
+
+        // knowing the classes rules out the leaves far from the query
+        let measured = std::cell::Cell::new(0);
+        let counting = |i: &usize| {
+            measured.set(measured.get() + 1);
+            i % 7
+        };
+        tree.nearest_per_class(&[5.0, 5.0], 2, counting, &dist)
+            .unwrap();
+        assert_eq!(measured.replace(0), items.len());
+        tree.nearest_per_class_of(&[5.0, 5.0], 2, &classes[..7], counting, &dist)
+            .unwrap();
+        assert!(measured.get() * 2 < items.len(), "{}", measured.get());
+
+        // only the classes asked for are collected
+        let some = tree
+            .nearest_per_class_of(&[5.0, 5.0], 3, &[2, 4], class_of, &dist)
+            .unwrap();
+        let mut keys: Vec<usize> = some.keys().copied().collect();
+        keys.sort_unstable();
+        assert_eq!(keys, [2, 4]);
+        assert_eq!(distances(&some, 4), brute_force(&items, &[5.0, 5.0], 3, 4));
+
+        assert!(tree
+            .nearest_per_class(&[5.0, 5.0], 0, class_of, &dist)
+            .unwrap()
+            .is_empty());
+        assert!(tree
+            .nearest_per_class(&[f64::NAN, 5.0], 3, class_of, &dist)
+            .is_err());
+        let empty = OwnedKdTree::<f64, usize, 2>::new();
+        assert!(empty
+            .nearest_per_class(&[0.0, 0.0], 3, class_of, &dist)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,298 @@
+//! Farthest point sampling of the items of a tree: starting from one item, repeatedly take the
+//! item farthest from all items taken so far. The distance from every item to its nearest
+//! sample is kept along with the largest of them in each node, so adding a sample only visits
+//! the nodes it could bring closer, those whose bounds are nearer to it than their farthest
+//! item is to its nearest sample.
+
+use num_traits::{Float, One, Zero};
+
+use super::{ErrorKind, Node, OwnedKdTree};
+use crate::geometry::distance_to_space_const;
+
+/// A node of the tree in preorder, with the largest distance of its items to their nearest
+/// samples
+struct Sampled<'a, A, T: PartialEq, const D: usize> {
+    node: &'a OwnedKdTree<A, T, D>,
+    /// The position of the first item of the node in iteration order
+    start: usize,
+    /// The index of the right child of a stem, 0 for a leaf
+    right: usize,
+    /// Negative infinity once every item of the node is a sample
+    farthest: A,
+    /// The items of a leaf, empty for a stem
+    points: &'a [[A; D]],
+    bucket: &'a [T],
+}
+
+fn flatten<'a, A: Float + Zero + One, T: PartialEq, const D: usize>(
+    node: &'a OwnedKdTree<A, T, D>,
+    start: usize,
+    nodes: &mut Vec<Sampled<'a, A, T, D>>,
+) {
+    let i = nodes.len();
+    let farthest = if node.size == 0 {
+        A::neg_infinity()
+    } else {
+        A::infinity()
+    };
+    let (points, bucket): (&[_], &[_]) = match &node.node {
+        Node::Leaf { points, bucket } => (points, bucket),
+        Node::Stem { .. } => (&[], &[]),
+    };
+    nodes.push(Sampled {
+        node,
+        start,
+        right: 0,
+        farthest,
+        points,
+        bucket,
+    });
+    if let Node::Stem { left, right, .. } = &node.node {
+        flatten(left, start, nodes);
+        nodes[i].right = nodes.len();
+        flatten(right, start + left.size, nodes);
+    }
+}
+
+/// Brings the items below `nodes[i]` as close to their nearest sample as to `sample`, returning
+/// the new largest distance of an item below it
+fn update<A, T, F, const D: usize>(
+    nodes: &mut [Sampled<A, T, D>],
+    i: usize,
+    sample: &[A; D],
+    nearest: &mut [A],
+    distance: &F,
+) -> A
+where
+    A: Float + Zero + One,
+    T: PartialEq,
+    F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+{
+    let node = nodes[i].node;
+    let to_space = distance_to_space_const(sample, &node.min_bounds, &node.max_bounds, distance);
+    if to_space > nodes[i].farthest {
+        return nodes[i].farthest;
+    }
+    let farthest = if nodes[i].right == 0 {
+        let start = nodes[i].start;
+        let nearest = &mut nearest[start..start + node.size];
+        for (d, p) in nearest.iter_mut().zip(nodes[i].points) {
+            *d = d.min(distance(sample, p));
+        }
+        nearest.iter().fold(A::neg_infinity(), |a, &b| a.max(b))
+    } else {
+        let right = nodes[i].right;
+        let left = update(nodes, i + 1, sample, nearest, distance);
+        left.max(update(nodes, right, sample, nearest, distance))
+    };
+    nodes[i].farthest = farthest;
+    farthest
+}
+
+/// The leaf and the position in it of the first item, in iteration order, at the largest
+/// distance from its nearest sample, `None` once every item is a sample
+fn farthest<A: Float, T: PartialEq, const D: usize>(
+    nodes: &[Sampled<A, T, D>],
+    nearest: &[A],
+) -> Option<(usize, usize)> {
+    let farthest = nodes[0].farthest;
+    if farthest == A::neg_infinity() {
+        return None;
+    }
+    let mut i = 0;
+    while nodes[i].right != 0 {
+        i = if nodes[i + 1].farthest == farthest {
+            i + 1
+        } else {
+            nodes[i].right
+        };
+    }
+    let start = nodes[i].start;
+    let j = nearest[start..start + nodes[i].node.size]
+        .iter()
+        .position(|&d| d == farthest)?;
+    Some((i, j))
+}
+
+impl<A: Float + Zero + One, T: PartialEq, const D: usize> OwnedKdTree<A, T, D> {
+    /// `k` items spread over the tree by farthest point sampling: the first item in
+    /// [`iter`](#method.iter) order, then repeatedly the item farthest from the nearest of
+    /// those already taken, ties going to the earlier item in `iter` order. Returns every item
+    /// if `k` is at least the size of the tree. This is the usual way to seed k-means or pick
+    /// landmarks.
+    pub fn farthest_point_samples<F>(&self, k: usize, distance: &F) -> Vec<(&[A; D], &T)>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        self.sample_farthest(None, k, distance)
+    }
+
+    /// Like [`farthest_point_samples`](#method.farthest_point_samples), but starting from the
+    /// item nearest to `start`
+    #[allow(clippy::type_complexity)]
+    pub fn farthest_point_samples_from<F>(
+        &self,
+        start: &[A; D],
+        k: usize,
+        distance: &F,
+    ) -> Result<Vec<(&[A; D], &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let first = self.nearest_neighbours(start, 1, distance)?;
+        Ok(self.sample_farthest(first.first().map(|n| n.point), k, distance))
+    }
+
+    /// Farthest point sampling starting from `first`, a reference to a point stored in the tree,
+    /// or from the first item if `None`
+    fn sample_farthest<F>(
+        &self,
+        first: Option<&[A; D]>,
+        k: usize,
+        distance: &F,
+    ) -> Vec<(&[A; D], &T)>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let k = k.min(self.size);
+        if k == 0 {
+            return vec![];
+        }
+        let mut nodes = vec![];
+        flatten(self, 0, &mut nodes);
+        let mut nearest = vec![A::infinity(); self.size];
+        let mut leaves = (0..nodes.len()).filter(|&i| nodes[i].right == 0);
+        let mut next = match first {
+            None => leaves.find(|&i| nodes[i].node.size > 0).map(|i| (i, 0)),
+            Some(first) => leaves.find_map(|i| {
+                let j = nodes[i]
+                    .points
+                    .iter()
+                    .position(|p| std::ptr::eq(p, first))?;
+                Some((i, j))
+            }),
+        };
+        let mut samples = Vec::with_capacity(k);
+        while let Some((i, j)) = next {
+            let (points, bucket) = (nodes[i].points, nodes[i].bucket);
+            samples.push((&points[j], &bucket[j]));
+            if samples.len() == k {
+                break;
+            }
+            // a sample is never taken again, even when all other items are at distance zero
+            nearest[nodes[i].start + j] = A::neg_infinity();
+            update(&mut nodes, 0, &points[j], &mut nearest, distance);
+            next = farthest(&nodes, &nearest);
+        }
+        samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::{Rng, SeedableRng, StdRng};
+    use crate::distance::squared_euclidean;
+    use crate::tree::OwnedKdTree;
+
+    fn dist(a: &[f64; 2], b: &[f64; 2]) -> f64 {
+        squared_euclidean(a, b)
+    }
+
+    /// Farthest point sampling over a list, starting from its first point
+    fn brute_force(points: &[[f64; 2]], k: usize) -> Vec<[f64; 2]> {
+        let mut nearest = vec![f64::INFINITY; points.len()];
+        let mut samples = vec![];
+        let mut next = 0;
+        while samples.len() < k.min(points.len()) {
+            samples.push(points[next]);
+            nearest[next] = f64::NEG_INFINITY;
+            for (d, p) in nearest.iter_mut().zip(points) {
+                *d = d.min(dist(p, &points[next]));
+            }
+            next = (0..points.len()).fold(
+                0,
+                |best, i| if nearest[i] > nearest[best] { i } else { best },
+            );
+        }
+        samples
+    }
+
+    fn min_separation(points: &[[f64; 2]]) -> f64 {
+        let mut min = f64::INFINITY;
+        for (i, a) in points.iter().enumerate() {
+            for b in &points[..i] {
+                min = min.min(dist(a, b));
+            }
+        }
+        min
+    }
+
+    #[test]
+    fn it_spreads_samples_like_a_brute_force_search() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[17, 2][..]);
+        let items: Vec<([f64; 2], usize)> = (0..3000)
+            .map(|i| {
+                let centre = [(i % 5) as f64 * 10.0, (i % 3) as f64 * 10.0];
+                let point = [
+                    centre[0] + rng.gen_range(-2.0, 2.0),
+                    centre[1] + rng.gen_range(-2.0, 2.0),
+                ];
+                (point, i)
+            })
+            .collect();
+        let tree = OwnedKdTree::from_points(items, 8).unwrap();
+        let points: Vec<[f64; 2]> = tree.iter().map(|(p, _)| *p).collect();
+        for &k in &[1, 2, 15, 40] {
+            let samples: Vec<[f64; 2]> = tree
+                .farthest_point_samples(k, &dist)
+                .into_iter()
+                .map(|(p, _)| *p)
+                .collect();
+            assert_eq!(samples.len(), k);
+            assert_eq!(samples[0], points[0]);
+            let expected = brute_force(&points, k);
+            assert!(min_separation(&samples) >= min_separation(&expected));
+            assert_eq!(samples, expected);
+        }
+        // one sample in each of the 15 clusters before any cluster gets a second
+        let samples = tree.farthest_point_samples(15, &dist);
+        let mut clusters: Vec<(i64, i64)> = samples
+            .iter()
+            .map(|(p, _)| ((p[0] / 10.0).round() as i64, (p[1] / 10.0).round() as i64))
+            .collect();
+        clusters.sort_unstable();
+        clusters.dedup();
+        assert_eq!(clusters.len(), 15);
+
+        let from = tree
+            .farthest_point_samples_from(&[40.0, 20.0], 3, &dist)
+            .unwrap();
+        let start = tree.nearest(&[40.0, 20.0], 1, &dist).unwrap()[0].1;
+        assert_eq!(from[0].1, start);
+        assert!(tree
+            .farthest_point_samples_from(&[f64::NAN, 0.0], 3, &dist)
+            .is_err());
+    }
+
+    #[test]
+    fn it_samples_every_item_once() {
+        let items: Vec<([f64; 2], usize)> = (0..200)
+            .map(|i| ([(i % 7) as f64, (i % 3) as f64], i))
+            .collect();
+        let tree = OwnedKdTree::from_points(items, 4).unwrap();
+        for &k in &[200, 500] {
+            let mut ids: Vec<usize> = tree
+                .farthest_point_samples(k, &dist)
+                .into_iter()
+                .map(|(_, &i)| i)
+                .collect();
+            ids.sort_unstable();
+            assert!(ids.into_iter().eq(0..200));
+        }
+        assert!(tree.farthest_point_samples(0, &dist).is_empty());
+        let empty = OwnedKdTree::<f64, usize, 2>::new();
+        assert!(empty.farthest_point_samples(5, &dist).is_empty());
+    }
+}
@@ -0,0 +1,453 @@
+//! Serialization of an [`OwnedKdTree`] with the `serialize` feature. The nodes are written in
+//! preorder after a header recording the format version, the byte order, the scalar type and
+//! the number of dimensions, which are checked before anything else is read.
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use num_traits::Float;
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeTuple, Serializer};
+
+use super::{ErrorKind, Node as TreeNode, OwnedKdTree};
+
+/// The version of the serialized form, bumped on every incompatible change
+const FORMAT_VERSION: u32 = 1;
+/// Written as a number, so formats which do not define a byte order can be detected
+const BYTE_ORDER: u32 = 0x0102_0304;
+
+/// Distinguishes `f32` from `f64` by the size of the scalar in bytes
+fn scalar_tag<A>() -> u8 {
+    std::mem::size_of::<A>() as u8
+}
+
+impl<A: Float, T: PartialEq, const D: usize> OwnedKdTree<A, T, D> {
+    /// The version of the serialized form written by this version of the crate. Trees written
+    /// with another version are rejected with `ErrorKind::FormatVersionMismatch`.
+    pub fn serialized_version() -> u32 {
+        FORMAT_VERSION
+    }
+
+    fn preorder<'a>(&'a self, nodes: &mut Vec<&'a Self>) {
+        nodes.push(self);
+        if let Some([left, right]) = self.children() {
+            left.preorder(nodes);
+            right.preorder(nodes);
+        }
+    }
+}
+
+struct Point<'a, A, const D: usize>(&'a [A; D]);
+
+impl<'a, A: Serialize, const D: usize> Serialize for Point<'a, A, D> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(D)?;
+        for x in self.0 {
+            tuple.serialize_element(x)?;
+        }
+        tuple.end()
+    }
+}
+
+struct Points<'a, A, const D: usize>(&'a [[A; D]]);
+
+impl<'a, A: Serialize, const D: usize> Serialize for Points<'a, A, D> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().map(Point))
+    }
+}
+
+struct Node<'a, A, T: PartialEq, const D: usize>(&'a OwnedKdTree<A, T, D>);
+
+impl<'a, A: Serialize, T: PartialEq + Serialize, const D: usize> Serialize for Node<'a, A, T, D> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let node = self.0;
+        let (split, points, bucket) = match &node.node {
+            TreeNode::Stem {
+                split_dimension,
+                split_value,
+                ..
+            } => (Some((*split_dimension as u64, split_value)), None, None),
+            TreeNode::Leaf { points, bucket } => (None, Some(Points(points)), Some(&**bucket)),
+        };
+        let mut tuple = serializer.serialize_tuple(6)?;
+        tuple.serialize_element(&(node.size as u64))?;
+        tuple.serialize_element(&Point(&node.min_bounds))?;
+        tuple.serialize_element(&Point(&node.max_bounds))?;
+        tuple.serialize_element(&split)?;
+        tuple.serialize_element(&points)?;
+        tuple.serialize_element(&bucket)?;
+        tuple.end()
+    }
+}
+
+struct Nodes<'a, A, T: PartialEq, const D: usize>(&'a [&'a OwnedKdTree<A, T, D>]);
+
+impl<'a, A: Serialize, T: PartialEq + Serialize, const D: usize> Serialize for Nodes<'a, A, T, D> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().map(|&node| Node(node)))
+    }
+}
+
+impl<A: Float + Serialize, T: PartialEq + Serialize, const D: usize> Serialize
+    for OwnedKdTree<A, T, D>
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut nodes = vec![];
+        self.preorder(&mut nodes);
+        let mut tuple = serializer.serialize_tuple(7)?;
+        tuple.serialize_element(&FORMAT_VERSION)?;
+        tuple.serialize_element(&BYTE_ORDER)?;
+        tuple.serialize_element(&scalar_tag::<A>())?;
+        tuple.serialize_element(&(D as u64))?;
+        tuple.serialize_element(&(nodes.len() as u64))?;
+        tuple.serialize_element(&(self.capacity as u64))?;
+        tuple.serialize_element(&Nodes(&nodes))?;
+        tuple.end()
+    }
+}
+
+struct PointBuf<A, const D: usize>([A; D]);
+
+impl<'de, A: Float + Deserialize<'de>, const D: usize> Deserialize<'de> for PointBuf<A, D> {
+    fn deserialize<De: Deserializer<'de>>(deserializer: De) -> Result<Self, De::Error> {
+        struct PointVisitor<A, const D: usize>(PhantomData<A>);
+
+        impl<'de, A: Float + Deserialize<'de>, const D: usize> Visitor<'de> for PointVisitor<A, D> {
+            type Value = PointBuf<A, D>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a point of {} coordinates", D)
+            }
+
+            fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<Self::Value, S::Error> {
+                let mut point = [A::nan(); D];
+                for (i, x) in point.iter_mut().enumerate() {
+                    *x = seq
+                        .next_element()?
+                        .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+                }
+                Ok(PointBuf(point))
+            }
+        }
+
+        deserializer.deserialize_tuple(D, PointVisitor(PhantomData))
+    }
+}
+
+/// A node as read, before the tree is linked together
+struct NodeBuf<A, T, const D: usize> {
+    size: u64,
+    min_bounds: [A; D],
+    max_bounds: [A; D],
+    split: Option<(u64, A)>,
+    points: Option<Vec<PointBuf<A, D>>>,
+    bucket: Option<Vec<T>>,
+}
+
+impl<'de, A, T, const D: usize> Deserialize<'de> for NodeBuf<A, T, D>
+where
+    A: Float + Deserialize<'de>,
+    T: Deserialize<'de>,
+{
+    fn deserialize<De: Deserializer<'de>>(deserializer: De) -> Result<Self, De::Error> {
+        struct NodeVisitor<A, T, const D: usize>(PhantomData<(A, T)>);
+
+        impl<'de, A, T, const D: usize> Visitor<'de> for NodeVisitor<A, T, D>
+        where
+            A: Float + Deserialize<'de>,
+            T: Deserialize<'de>,
+        {
+            type Value = NodeBuf<A, T, D>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a kd-tree node")
+            }
+
+            fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<Self::Value, S::Error> {
+                let missing = |i| de::Error::invalid_length(i, &"6 fields");
+                let size = seq.next_element()?.ok_or_else(|| missing(0))?;
+                let PointBuf(min_bounds) = seq.next_element()?.ok_or_else(|| missing(1))?;
+                let PointBuf(max_bounds) = seq.next_element()?.ok_or_else(|| missing(2))?;
+                Ok(NodeBuf {
+                    size,
+                    min_bounds,
+                    max_bounds,
+                    split: seq.next_element()?.ok_or_else(|| missing(3))?,
+                    points: seq.next_element()?.ok_or_else(|| missing(4))?,
+                    bucket: seq.next_element()?.ok_or_else(|| missing(5))?,
+                })
+            }
+        }
+
+        deserializer.deserialize_tuple(6, NodeVisitor(PhantomData))
+    }
+}
+
+/// Links the next subtree of `nodes`, given in preorder, checking that it is consistent
+fn link<A: Float, T: PartialEq, I, const D: usize>(
+    nodes: &mut I,
+    capacity: usize,
+) -> Result<OwnedKdTree<A, T, D>, ErrorKind>
+where
+    I: Iterator<Item = NodeBuf<A, T, D>>,
+{
+    let node = nodes.next().ok_or(ErrorKind::InvalidFormat)?;
+    let mut tree = OwnedKdTree::with_capacity(capacity);
+    tree.size = node.size as usize;
+    tree.min_bounds = node.min_bounds;
+    tree.max_bounds = node.max_bounds;
+    match (node.split, node.points, node.bucket) {
+        (Some((dim, value)), None, None) if (dim as usize) < D => {
+            let left = link(nodes, capacity)?;
+            let right = link(nodes, capacity)?;
+            if left.size + right.size != tree.size {
+                return Err(ErrorKind::InvalidFormat);
+            }
+            tree.node = TreeNode::Stem {
+                split_dimension: dim as usize,
+                split_value: value,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+        (None, Some(points), Some(bucket))
+            if points.len() == bucket.len() && points.len() == tree.size =>
+        {
+            tree.node = TreeNode::Leaf {
+                points: points.into_iter().map(|PointBuf(p)| p).collect(),
+                bucket: bucket.into(),
+            };
+        }
+        _ => return Err(ErrorKind::InvalidFormat),
+    }
+    Ok(tree)
+}
+
+impl<'de, A, T, const D: usize> Deserialize<'de> for OwnedKdTree<A, T, D>
+where
+    A: Float + Deserialize<'de>,
+    T: PartialEq + Deserialize<'de>,
+{
+    fn deserialize<De: Deserializer<'de>>(deserializer: De) -> Result<Self, De::Error> {
+        struct TreeVisitor<A, T, const D: usize>(PhantomData<(A, T)>);
+
+        impl<'de, A, T, const D: usize> Visitor<'de> for TreeVisitor<A, T, D>
+        where
+            A: Float + Deserialize<'de>,
+            T: PartialEq + Deserialize<'de>,
+        {
+            type Value = OwnedKdTree<A, T, D>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a serialized OwnedKdTree")
+            }
+
+            fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<Self::Value, S::Error> {
+                let invalid = || de::Error::custom(ErrorKind::InvalidFormat);
+                let version: u32 = seq.next_element()?.ok_or_else(invalid)?;
+                if version != FORMAT_VERSION {
+                    return Err(de::Error::custom(ErrorKind::FormatVersionMismatch));
+                }
+                let byte_order: u32 = seq.next_element()?.ok_or_else(invalid)?;
+                if byte_order != BYTE_ORDER {
+                    return Err(invalid());
+                }
+                let scalar: u8 = seq.next_element()?.ok_or_else(invalid)?;
+                if scalar != scalar_tag::<A>() {
+                    return Err(de::Error::custom(ErrorKind::UnsupportedType));
+                }
+                let dimensions: u64 = seq.next_element()?.ok_or_else(invalid)?;
+                if dimensions != D as u64 {
+                    return Err(de::Error::custom(ErrorKind::DimensionMismatch {
+                        expected: D,
+                        got: dimensions as usize,
+                    }));
+                }
+                let count: u64 = seq.next_element()?.ok_or_else(invalid)?;
+                let capacity: u64 = seq.next_element()?.ok_or_else(invalid)?;
+                let nodes: Vec<NodeBuf<A, T, D>> = seq.next_element()?.ok_or_else(invalid)?;
+                if nodes.len() as u64 != count || capacity == 0 {
+                    return Err(invalid());
+                }
+                let mut nodes = nodes.into_iter();
+                let tree = link(&mut nodes, capacity as usize).map_err(de::Error::custom)?;
+                if nodes.next().is_some() {
+                    return Err(invalid());
+                }
+                // the searches would silently miss points outside the bounds or on the wrong
+                // side of a split
+                tree.validate().map_err(|_| invalid())?;
+                Ok(tree)
+            }
+        }
+
+        deserializer.deserialize_tuple(7, TreeVisitor(PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate bincode;
+
+    use super::{OwnedKdTree, TreeNode};
+    use crate::distance::squared_euclidean;
+    use crate::error::ErrorKind;
+
+    fn tree() -> OwnedKdTree<f64, u32, 3> {
+        let items = (0..500u32)
+            .map(|i| {
+                (
+                    [(i % 10) as f64, (i * 7 % 23) as f64, (i * 31 % 101) as f64],
+                    i,
+                )
+            })
+            .collect();
+        OwnedKdTree::from_points(items, 8).unwrap()
+    }
+
+    #[test]
+    fn it_round_trips() {
+        let tree = tree();
+        let bytes = bincode::serialize(&tree).unwrap();
+        let read: OwnedKdTree<f64, u32, 3> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(read.stats(), tree.stats());
+        let dist = |a: &[f64; 3], b: &[f64; 3]| squared_euclidean(a, b);
+        let query = [4.5, 11.0, 50.0];
+        assert_eq!(
+            read.nearest(&query, 10, &dist).unwrap(),
+            tree.nearest(&query, 10, &dist).unwrap()
+        );
+
+        let empty: OwnedKdTree<f64, u32, 3> = OwnedKdTree::new();
+        let bytes = bincode::serialize(&empty).unwrap();
+        let read: OwnedKdTree<f64, u32, 3> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(read.size(), 0);
+    }
+
+    #[test]
+    fn it_rejects_incompatible_headers() {
+        let bytes = bincode::serialize(&tree()).unwrap();
+        let message = |bytes: &[u8]| {
+            bincode::deserialize::<OwnedKdTree<f64, u32, 3>>(bytes)
+                .unwrap_err()
+                .to_string()
+        };
+
+        let mut bumped = bytes.clone();
+        bumped[0] += 1;
+        assert_eq!(
+            message(&bumped),
+            ErrorKind::FormatVersionMismatch.to_string()
+        );
+
+        // the dimensions follow the version, the byte order and the scalar tag
+        let mut altered = bytes.clone();
+        altered[9] = 2;
+        let mismatch = |expected, got| ErrorKind::DimensionMismatch { expected, got }.to_string();
+        assert_eq!(message(&altered), mismatch(3, 2));
+        let as_2d = bincode::deserialize::<OwnedKdTree<f64, u32, 2>>(&bytes).unwrap_err();
+        assert_eq!(as_2d.to_string(), mismatch(2, 3));
+        let as_f32 = bincode::deserialize::<OwnedKdTree<f32, u32, 3>>(&bytes).unwrap_err();
+        assert_eq!(as_f32.to_string(), ErrorKind::UnsupportedType.to_string());
+
+        let mut swapped = bytes.clone();
+        swapped[4..8].reverse();
+        assert_eq!(message(&swapped), ErrorKind::InvalidFormat.to_string());
+    }
+
+    #[test]
+    fn it_rejects_inconsistent_nodes() {
+        let tree = tree();
+        let mut bytes = bincode::serialize(&tree).unwrap();
+        // the size of the root follows the header and the length of the node list
+        let size = 4 + 4 + 1 + 8 + 8 + 8 + 8;
+        bytes[size] += 1;
+        let error = bincode::deserialize::<OwnedKdTree<f64, u32, 3>>(&bytes).unwrap_err();
+        assert_eq!(error.to_string(), ErrorKind::InvalidFormat.to_string());
+    }
+
+    #[test]
+    fn it_keeps_points_at_split_values_in_place() {
+        let items: Vec<([f64; 2], u32)> = (0..64u32)
+            .map(|i| ([(i % 4) as f64, (i / 4 % 4) as f64], i))
+            .collect();
+        let tree = OwnedKdTree::from_points(items, 2).unwrap();
+        let bytes = bincode::serialize(&tree).unwrap();
+        let mut read: OwnedKdTree<f64, u32, 2> = bincode::deserialize(&bytes).unwrap();
+        read.debug_validate();
+        let split_value = match &read.node {
+            TreeNode::Stem { split_value, .. } => *split_value,
+            TreeNode::Leaf { .. } => unreachable!(),
+        };
+        assert_eq!(split_value, 2.0);
+        let dist = |a: &[f64; 2], b: &[f64; 2]| squared_euclidean(a, b);
+        for y in 0..4 {
+            let point = [split_value, y as f64];
+            assert_eq!(read.within(&point, 0.0, &dist).unwrap().len(), 4);
+            assert_eq!(read.remove_at(&point).unwrap().len(), 4);
+            read.add(point, 100 + y).unwrap();
+            read.add(point, 200 + y).unwrap();
+            read.debug_validate();
+            assert_eq!(read.within(&point, 0.0, &dist).unwrap().len(), 2);
+        }
+
+        // a leaf point moved onto the split from below would be looked for on the other side
+        let mut moved = tree.clone();
+        if let TreeNode::Stem { left, .. } = &mut moved.node {
+            let mut node = &mut **left;
+            while !node.is_leaf() {
+                node = match &mut node.node {
+                    TreeNode::Stem { right, .. } => right,
+                    TreeNode::Leaf { .. } => unreachable!(),
+                };
+            }
+            if let TreeNode::Leaf { points, .. } = &mut node.node {
+                points[0][0] = split_value;
+            }
+        }
+        let bytes = bincode::serialize(&moved).unwrap();
+        let error = bincode::deserialize::<OwnedKdTree<f64, u32, 2>>(&bytes).unwrap_err();
+        assert_eq!(error.to_string(), ErrorKind::InvalidFormat.to_string());
+    }
+
+    #[test]
+    fn it_never_panics_on_corrupted_bytes() {
+        use self::bincode::Options;
+
+        let items = (0..40u32)
+            .map(|i| ([(i % 7) as f64, (i / 7) as f64], i))
+            .collect();
+        let tree: OwnedKdTree<f64, u32, 2> = OwnedKdTree::from_points(items, 4).unwrap();
+        let bytes = bincode::serialize(&tree).unwrap();
+        // the options of `bincode::deserialize`, with a limit so corrupted lengths fail rather
+        // than allocate
+        let options = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .with_limit(4 * bytes.len() as u64);
+        let dist = |a: &[f64; 2], b: &[f64; 2]| squared_euclidean(a, b);
+        let mut accepted = 0;
+        for i in 0..bytes.len() {
+            for &flip in &[0x01, 0x40, 0xff] {
+                let mut corrupted = bytes.clone();
+                corrupted[i] ^= flip;
+                let mut read: OwnedKdTree<f64, u32, 2> = match options.deserialize(&corrupted) {
+                    Ok(read) => read,
+                    Err(_) => continue,
+                };
+                // whatever was accepted is a well formed tree, though its items may have moved
+                accepted += 1;
+                read.debug_validate();
+                assert_eq!(read.iter().count(), read.size());
+                read.nearest(&[3.0, 2.0], 5, &dist).unwrap();
+                read.within(&[3.0, 2.0], 4.0, &dist).unwrap();
+                read.iter_farthest(&[0.0, 0.0], &dist).unwrap().count();
+                read.remove(&[1.0, 1.0], &8).unwrap();
+                read.add([2.5, 2.5], 100).unwrap();
+                read.stats();
+            }
+        }
+        assert!(accepted > 0);
+    }
+}
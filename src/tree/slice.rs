@@ -0,0 +1,114 @@
+//! Variants of the point based methods of [`OwnedKdTree`] taking points as slices, for callers
+//! such as language bindings which only know the number of dimensions at runtime. A slice of
+//! another length than `D` returns `ErrorKind::DimensionMismatch`.
+
+use std::convert::TryFrom;
+
+use num_traits::{Float, One, Zero};
+
+use super::{ErrorKind, OwnedKdTree};
+
+fn to_array<A: Copy, const D: usize>(point: &[A]) -> Result<[A; D], ErrorKind> {
+    <[A; D]>::try_from(point).map_err(|_| ErrorKind::DimensionMismatch {
+        expected: D,
+        got: point.len(),
+    })
+}
+
+impl<A: Float + Zero + One, T: PartialEq, const D: usize> OwnedKdTree<A, T, D> {
+    /// [`add`](#method.add) with the point as a slice
+    pub fn add_slice(&mut self, point: &[A], data: T) -> Result<(), ErrorKind> {
+        self.add(to_array(point)?, data)
+    }
+
+    /// [`nearest`](#method.nearest) with the point as a slice
+    pub fn nearest_slice<F>(
+        &self,
+        point: &[A],
+        num: usize,
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        self.nearest(&to_array(point)?, num, distance)
+    }
+
+    /// [`within`](#method.within) with the point as a slice
+    pub fn within_slice<F>(
+        &self,
+        point: &[A],
+        radius: A,
+        distance: &F,
+    ) -> Result<Vec<(A, &T)>, ErrorKind>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        self.within(&to_array(point)?, radius, distance)
+    }
+
+    /// [`remove`](#method.remove) with the point as a slice
+    pub fn remove_slice(&mut self, point: &[A], data: &T) -> Result<usize, ErrorKind> {
+        self.remove(&to_array(point)?, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::distance::squared_euclidean;
+    use crate::error::ErrorKind;
+    use crate::tree::OwnedKdTree;
+
+    fn dist(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+        squared_euclidean(a, b)
+    }
+
+    #[test]
+    fn it_matches_the_array_methods() {
+        let mut by_slice = OwnedKdTree::with_capacity(2);
+        let mut by_array = OwnedKdTree::with_capacity(2);
+        for i in 0..50 {
+            let point = [(i % 7) as f64, (i % 5) as f64, (i % 3) as f64];
+            by_slice.add_slice(&point[..], i).unwrap();
+            by_array.add(point, i).unwrap();
+        }
+        let query: Vec<f64> = vec![2.5, 1.0, 0.5];
+        let array = [2.5, 1.0, 0.5];
+        assert_eq!(
+            by_slice.nearest_slice(&query, 6, &dist).unwrap(),
+            by_array.nearest(&array, 6, &dist).unwrap()
+        );
+        assert_eq!(
+            by_slice.within_slice(&query, 2.0, &dist).unwrap(),
+            by_array.within(&array, 2.0, &dist).unwrap()
+        );
+        assert_eq!(by_slice.remove_slice(&[0.0, 0.0, 0.0], &0), Ok(1));
+        assert_eq!(by_slice.remove_slice(&[0.0, 0.0, 0.0], &0), Ok(0));
+        assert_eq!(by_slice.size(), 49);
+    }
+
+    #[test]
+    fn it_rejects_slices_of_other_lengths() {
+        let mut tree = OwnedKdTree::<f64, usize, 3>::new();
+        let mismatch = |got| ErrorKind::DimensionMismatch { expected: 3, got };
+        assert_eq!(tree.add_slice(&[1.0, 2.0], 0), Err(mismatch(2)));
+        assert_eq!(tree.add_slice(&[1.0, 2.0, 3.0, 4.0], 0), Err(mismatch(4)));
+        assert_eq!(tree.size(), 0);
+        tree.add_slice(&[1.0, 2.0, 3.0], 0).unwrap();
+        assert_eq!(tree.nearest_slice(&[], 1, &dist), Err(mismatch(0)));
+        assert_eq!(tree.within_slice(&[1.0], 1.0, &dist), Err(mismatch(1)));
+        assert_eq!(tree.remove_slice(&[1.0, 2.0], &0), Err(mismatch(2)));
+        assert_eq!(
+            tree.add_slice(&[f64::NAN, 2.0, 3.0], 1),
+            Err(ErrorKind::NonFiniteCoordinate)
+        );
+        assert_eq!(
+            ErrorKind::DimensionMismatch {
+                expected: 3,
+                got: 2
+            }
+            .to_string(),
+            "KdTree error: dimension mismatch, expected 3 but got 2"
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! The minimum spanning tree of the items of a tree, built by Borůvka's algorithm: every round,
+//! each component of the edges found so far takes the shortest edge leaving it. Those edges are
+//! found by a nearest search from every item for the nearest item in another component, which
+//! skips the nodes whose items are all in the component of the item searched from and the
+//! nodes farther than the shortest edge its component has found.
+
+use num_traits::{Float, One, Zero};
+
+use super::{Node, OwnedKdTree};
+use crate::geometry::distance_to_space_const;
+
+/// A node of the tree in preorder, with the component all its items are in
+struct Component<'a, A, T: PartialEq, const D: usize> {
+    node: &'a OwnedKdTree<A, T, D>,
+    /// The position of the first item of the node in iteration order
+    start: usize,
+    /// The index of the right child of a stem, 0 for a leaf
+    right: usize,
+    /// `None` if its items are in several components
+    component: Option<usize>,
+}
+
+fn flatten<'a, A: Float + Zero + One, T: PartialEq, const D: usize>(
+    node: &'a OwnedKdTree<A, T, D>,
+    start: usize,
+    nodes: &mut Vec<Component<'a, A, T, D>>,
+) {
+    let i = nodes.len();
+    nodes.push(Component {
+        node,
+        start,
+        right: 0,
+        component: None,
+    });
+    if let Some([left, right]) = node.children() {
+        flatten(left, start, nodes);
+        nodes[i].right = nodes.len();
+        flatten(right, start + left.size, nodes);
+    }
+}
+
+fn leaf_points<A, T: PartialEq, const D: usize>(node: &OwnedKdTree<A, T, D>) -> &[[A; D]] {
+    match &node.node {
+        Node::Stem { .. } => &[],
+        Node::Leaf { points, .. } => points,
+    }
+}
+
+/// Labels `nodes[i]` and the nodes below it with the component of their items, returning the
+/// label of `nodes[i]`
+fn label<A, T: PartialEq, const D: usize>(
+    nodes: &mut [Component<A, T, D>],
+    i: usize,
+    components: &[usize],
+) -> Option<usize> {
+    let component = if nodes[i].right == 0 {
+        let start = nodes[i].start;
+        let mut components = components[start..start + nodes[i].node.size].iter();
+        let first = components.next().copied();
+        first.filter(|&c| components.all(|&d| d == c))
+    } else {
+        let right = nodes[i].right;
+        let ours = label(nodes, i + 1, components);
+        let theirs = label(nodes, right, components);
+        match (nodes[i + 1].node.size, nodes[right].node.size) {
+            (0, _) => theirs,
+            (_, 0) => ours,
+            _ => ours.filter(|&c| theirs == Some(c)),
+        }
+    };
+    nodes[i].component = component;
+    component
+}
+
+/// Whether the edge `(distance, i, j)`, with `i < j`, is shorter than `edge`. Equally long edges
+/// are ordered by their items, so that the edges the components take never form a cycle.
+fn shorter<A: Float>(candidate: (A, usize, usize), edge: Option<(A, usize, usize)>) -> bool {
+    match edge {
+        None => !candidate.0.is_nan(),
+        Some(edge) => {
+            candidate.0 < edge.0
+                || (candidate.0 == edge.0 && (candidate.1, candidate.2) < (edge.1, edge.2))
+        }
+    }
+}
+
+/// Brings `edge` down to the shortest edge from the item at `item` to an item below `nodes[i]`
+/// outside of `component`
+#[allow(clippy::too_many_arguments)]
+fn nearest_outside<A, T, F, const D: usize>(
+    nodes: &[Component<A, T, D>],
+    i: usize,
+    point: &[A; D],
+    item: usize,
+    component: usize,
+    components: &[usize],
+    edge: &mut Option<(A, usize, usize)>,
+    distance: &F,
+) where
+    A: Float + Zero + One,
+    T: PartialEq,
+    F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+{
+    let node = nodes[i].node;
+    if node.size == 0 || nodes[i].component == Some(component) {
+        return;
+    }
+    if let Some((longest, _, _)) = *edge {
+        // an equally long edge between earlier items may still win the tie
+        if distance_to_space_const(point, &node.min_bounds, &node.max_bounds, distance) > longest {
+            return;
+        }
+    }
+    if nodes[i].right == 0 {
+        let start = nodes[i].start;
+        for (k, q) in leaf_points(node).iter().enumerate() {
+            let other = start + k;
+            if components[other] != component {
+                let candidate = (distance(point, q), item.min(other), item.max(other));
+                if shorter(candidate, *edge) {
+                    *edge = Some(candidate);
+                }
+            }
+        }
+        return;
+    }
+    let (left, right) = (i + 1, nodes[i].right);
+    let to = |j: usize| {
+        let node = nodes[j].node;
+        distance_to_space_const(point, &node.min_bounds, &node.max_bounds, distance)
+    };
+    let (near, far) = if to(right) < to(left) {
+        (right, left)
+    } else {
+        (left, right)
+    };
+    for &j in &[near, far] {
+        nearest_outside(nodes, j, point, item, component, components, edge, distance);
+    }
+}
+
+fn find(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+impl<A: Float + Zero + One, T: PartialEq, const D: usize> OwnedKdTree<A, T, D> {
+    /// The edges of a minimum spanning tree of the stored items, each the positions `(i, j)`,
+    /// `i < j`, of two items in [`iter`](#method.iter) order and their distance, sorted by
+    /// distance. The positions stay valid until the tree is next changed. A tree of fewer than
+    /// two items has no edges.
+    ///
+    /// Like every search, this relies on `distance` being symmetric and on the distance from a
+    /// point to the nearest corner or face of a box never being more than to any point in it,
+    /// which holds for [`squared_euclidean`](../distance/fn.squared_euclidean.html) and the
+    /// other distances in [`distance`](../distance/index.html). A minimum spanning tree only
+    /// depends on the order of the distances, so the squared Euclidean distance gives the same
+    /// tree as the Euclidean one. Of equally long edges the one between earlier items is taken.
+    /// Items at a NaN distance from all others are left unconnected, so the edges are then a
+    /// spanning forest.
+    pub fn minimum_spanning_tree<F>(&self, distance: &F) -> Vec<(usize, usize, A)>
+    where
+        F: Fn(&[A; D], &[A; D]) -> A + ?Sized,
+    {
+        let mut nodes = vec![];
+        flatten(self, 0, &mut nodes);
+        let points: Vec<&[A; D]> = self.iter().map(|(p, _)| p).collect();
+        let mut parents: Vec<usize> = (0..self.size).collect();
+        let mut components = parents.clone();
+        let mut edges = vec![];
+        loop {
+            label(&mut nodes, 0, &components);
+            let mut shortest: Vec<Option<(A, usize, usize)>> = vec![None; self.size];
+            for (item, point) in points.iter().enumerate() {
+                let component = components[item];
+                let edge = &mut shortest[component];
+                nearest_outside(
+                    &nodes,
+                    0,
+                    point,
+                    item,
+                    component,
+                    &components,
+                    edge,
+                    distance,
+                );
+            }
+            let found = edges.len();
+            for &(d, i, j) in shortest.iter().flatten() {
+                let (a, b) = (find(&mut parents, i), find(&mut parents, j));
+                // both components may have taken the same edge
+                if a != b {
+                    parents[a.max(b)] = a.min(b);
+                    edges.push((i, j, d));
+                }
+            }
+            if edges.len() == found {
+                break;
+            }
+            for (item, component) in components.iter_mut().enumerate() {
+                *component = find(&mut parents, item);
+            }
+        }
+        edges.sort_by(|a, b| (a.2, a.0, a.1).partial_cmp(&(b.2, b.0, b.1)).unwrap());
+        edges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use self::rand::{Rng, SeedableRng, StdRng};
+    use crate::distance::squared_euclidean;
+    use crate::tree::OwnedKdTree;
+
+    fn dist(a: &[f64; 2], b: &[f64; 2]) -> f64 {
+        squared_euclidean(a, b)
+    }
+
+    /// The distances of the edges of a minimum spanning tree by Prim's algorithm, sorted
+    fn prim(points: &[[f64; 2]]) -> Vec<f64> {
+        let mut nearest = vec![f64::INFINITY; points.len()];
+        let mut taken = vec![false; points.len()];
+        let mut edges = vec![];
+        let mut next = 0;
+        for _ in 1..points.len() {
+            taken[next] = true;
+            for (i, p) in points.iter().enumerate() {
+                nearest[i] = nearest[i].min(dist(p, &points[next]));
+            }
+            next = (0..points.len())
+                .filter(|&i| !taken[i])
+                .fold(None, |best: Option<usize>, i| match best {
+                    Some(b) if nearest[b] <= nearest[i] => Some(b),
+                    _ => Some(i),
+                })
+                .unwrap();
+            edges.push(nearest[next]);
+        }
+        edges.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        edges
+    }
+
+    /// Whether the edges connect all `n` items without a cycle
+    fn spans(n: usize, edges: &[(usize, usize, f64)]) -> bool {
+        let mut components: Vec<usize> = (0..n).collect();
+        for &(i, j, _) in edges {
+            let (a, b) = (components[i], components[j]);
+            if a == b {
+                return false;
+            }
+            for c in components.iter_mut() {
+                if *c == b {
+                    *c = a;
+                }
+            }
+        }
+        edges.len() + 1 == n
+    }
+
+    #[test]
+    fn it_spans_the_items_like_prims_algorithm() {
+        let mut rng: StdRng = SeedableRng::from_seed(&[19, 4, 1][..]);
+        for &(n, capacity) in &[(2, 1), (3, 16), (40, 1), (300, 4), (500, 16)] {
+            let mut tree = OwnedKdTree::with_capacity(capacity);
+            for i in 0..n {
+                let centre = (i % 3) as f64 * 50.0;
+                let point = [centre + rng.gen_range(-5.0, 5.0), rng.gen_range(-5.0, 5.0)];
+                tree.add(point, i).unwrap();
+            }
+            let points: Vec<[f64; 2]> = tree.iter().map(|(p, _)| *p).collect();
+            let edges = tree.minimum_spanning_tree(&dist);
+            assert!(spans(n, &edges));
+            for &(i, j, d) in &edges {
+                assert!(i < j);
+                assert_eq!(d, dist(&points[i], &points[j]));
+            }
+            let distances: Vec<f64> = edges.iter().map(|e| e.2).collect();
+            assert_eq!(distances, prim(&points));
+        }
+    }
+
+    #[test]
+    fn it_spans_equally_distant_and_duplicate_items() {
+        let grid: Vec<([f64; 2], usize)> = (0..300)
+            .map(|i| ([(i % 15) as f64, ((i / 15) % 10) as f64], i))
+            .collect();
+        let tree = OwnedKdTree::from_points(grid, 4).unwrap();
+        let points: Vec<[f64; 2]> = tree.iter().map(|(p, _)| *p).collect();
+        let edges = tree.minimum_spanning_tree(&dist);
+        assert!(spans(300, &edges));
+        // the 150 grid points are a point apart, and each has a twin
+        assert_eq!(edges.iter().filter(|e| e.2 == 0.0).count(), 150);
+        assert_eq!(edges.iter().filter(|e| e.2 == 1.0).count(), 149);
+        let distances: Vec<f64> = edges.iter().map(|e| e.2).collect();
+        assert_eq!(distances, prim(&points));
+
+        assert!(OwnedKdTree::<f64, usize, 2>::new()
+            .minimum_spanning_tree(&dist)
+            .is_empty());
+        let one = OwnedKdTree::from_points(vec![([1.0, 2.0], 0)], 4).unwrap();
+        assert!(one.minimum_spanning_tree(&dist).is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+//! JavaScript bindings to a tree of 2 dimensional points with `u32` ids, enabled by the `wasm`
+//! feature and built with `wasm-pack` or `wasm-bindgen` for `wasm32-unknown-unknown`.
+//!
+//! Each call from JavaScript into WebAssembly has a fixed cost, so the API moves many points per
+//! call as typed arrays: [`WasmKdTree2::add_batch`] takes the coordinates interleaved as
+//! `x0, y0, x1, y1, ...` and the queries return `dist0, id0, dist1, id1, ...`.
+//!
+//! ```js
+//! const tree = new WasmKdTree2();
+//! tree.addBatch(new Float64Array([0, 0, 3, 4]), new Uint32Array([7, 8]));
+//! tree.nearest(0, 1, 1); // Float64Array [1, 7]
+//! tree.withinRadius(0, 0, 5); // Float64Array [0, 7, 5, 8]
+//! ```
+//!
+//! Distances and radii are euclidean, unlike the squared distances of the other bindings, as a
+//! map expects a radius in its own units. Errors are thrown as `Error`s.
+
+use wasm_bindgen::prelude::*;
+
+use crate::distance::squared_euclidean;
+use crate::error::ErrorKind;
+use crate::geometry::KdTree2;
+use crate::tree::DEFAULT_LEAF_CAPACITY;
+
+fn squared(a: &[f64; 2], b: &[f64; 2]) -> f64 {
+    squared_euclidean(a, b)
+}
+
+fn interleaved(found: Vec<(f64, &u32)>) -> Vec<f64> {
+    let mut out = Vec::with_capacity(2 * found.len());
+    for (d, &id) in found {
+        out.push(d.sqrt());
+        out.push(f64::from(id));
+    }
+    out
+}
+
+/// A tree of 2 dimensional points with `u32` ids
+#[wasm_bindgen]
+pub struct WasmKdTree2 {
+    tree: KdTree2<f64, u32>,
+}
+
+#[wasm_bindgen]
+impl WasmKdTree2 {
+    /// An empty tree with leaves of [`DEFAULT_LEAF_CAPACITY`] points
+    #[wasm_bindgen(constructor)]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> WasmKdTree2 {
+        WasmKdTree2 {
+            tree: KdTree2::with_capacity(DEFAULT_LEAF_CAPACITY),
+        }
+    }
+
+    /// Adds the point `points[2 * i], points[2 * i + 1]` with the id `ids[i]` for each `i`. If
+    /// `points` does not hold two coordinates per id, nothing is added; otherwise the points are
+    /// added in order until one has a non finite coordinate.
+    #[wasm_bindgen(js_name = addBatch)]
+    pub fn add_batch(&mut self, points: &[f64], ids: &[u32]) -> Result<(), JsError> {
+        if points.len() != 2 * ids.len() {
+            return Err(ErrorKind::DimensionMismatch {
+                expected: 2 * ids.len(),
+                got: points.len(),
+            }
+            .into());
+        }
+        let items = points
+            .chunks_exact(2)
+            .zip(ids)
+            .map(|(p, &id)| ([p[0], p[1]], id))
+            .collect();
+        self.tree.add_all(items)?;
+        Ok(())
+    }
+
+    /// The `k` points nearest to `x, y`, as their distances and ids interleaved from the nearest
+    pub fn nearest(&self, x: f64, y: f64, k: usize) -> Result<Vec<f64>, JsError> {
+        Ok(interleaved(self.tree.nearest(&[x, y], k, &squared)?))
+    }
+
+    /// The points within `radius` of `x, y`, as their distances and ids interleaved from the
+    /// nearest
+    #[wasm_bindgen(js_name = withinRadius)]
+    pub fn within_radius(&self, x: f64, y: f64, radius: f64) -> Result<Vec<f64>, JsError> {
+        if radius.is_nan() || radius < 0.0 {
+            return Err(ErrorKind::InvalidRadius.into());
+        }
+        Ok(interleaved(self.tree.within(
+            &[x, y],
+            radius * radius,
+            &squared,
+        )?))
+    }
+
+    /// The number of points in the tree
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> usize {
+        self.tree.size()
+    }
+}
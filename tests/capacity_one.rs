@@ -0,0 +1,137 @@
+extern crate kdtree;
+extern crate rand;
+
+use kdtree::distance::squared_euclidean;
+use kdtree::OwnedKdTree;
+use rand::{Rng, SeedableRng, StdRng};
+
+fn dist(a: &[f64; 2], b: &[f64; 2]) -> f64 {
+    squared_euclidean(a, b)
+}
+
+/// Checks every query of `tree` around `query` against a brute force search of `model`
+fn check(tree: &mut OwnedKdTree<f64, usize, 2>, model: &[([f64; 2], usize)], query: &[f64; 2]) {
+    let mut expected: Vec<(f64, usize)> =
+        model.iter().map(|&(p, i)| (dist(query, &p), i)).collect();
+    expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let distances = |found: &[(f64, usize)]| found.iter().map(|&(d, _)| d).collect::<Vec<_>>();
+    let sorted_ids = |found: &[(f64, usize)]| {
+        let mut ids: Vec<usize> = found.iter().map(|&(_, i)| i).collect();
+        ids.sort_unstable();
+        ids
+    };
+    let owned =
+        |found: Vec<(f64, &usize)>| found.into_iter().map(|(d, &i)| (d, i)).collect::<Vec<_>>();
+    assert_eq!(tree.size(), model.len());
+
+    let k = std::cmp::min(5, expected.len());
+    let nearest = owned(tree.nearest(query, 5, &dist).unwrap());
+    assert_eq!(distances(&nearest), distances(&expected[..k]));
+
+    let radius = expected.get(expected.len() / 2).map_or(1.0, |&(d, _)| d);
+    let within = owned(tree.within(query, radius, &dist).unwrap());
+    let in_radius: Vec<(f64, usize)> = expected
+        .iter()
+        .cloned()
+        .filter(|&(d, _)| d <= radius)
+        .collect();
+    assert_eq!(distances(&within), distances(&in_radius));
+    assert_eq!(sorted_ids(&within), sorted_ids(&in_radius));
+
+    let iterated = owned(tree.iter_nearest(query, &dist).unwrap().collect());
+    assert_eq!(distances(&iterated), distances(&expected));
+    assert_eq!(sorted_ids(&iterated), sorted_ids(&expected));
+    let mut farthest = owned(tree.iter_farthest(query, &dist).unwrap().collect());
+    farthest.reverse();
+    assert_eq!(distances(&farthest), distances(&expected));
+    let mutated: Vec<usize> = tree
+        .iter_nearest_mut(query, &dist)
+        .unwrap()
+        .map(|(_, &mut i)| i)
+        .collect();
+    assert_eq!(mutated.len(), model.len());
+
+    let mut all: Vec<usize> = tree.iter().map(|(_, &i)| i).collect();
+    all.sort_unstable();
+    assert_eq!(all, sorted_ids(&expected));
+}
+
+#[test]
+fn it_matches_brute_force_at_capacity_one() {
+    let mut rng: StdRng = SeedableRng::from_seed(&[1, 1, 1][..]);
+    let mut tree = OwnedKdTree::with_capacity(1);
+    let mut model: Vec<([f64; 2], usize)> = vec![];
+    for i in 0..1500 {
+        match rng.gen_range(0, 10) {
+            0..=5 => {
+                // a coarse grid, so that many points coincide on one or both axes
+                let point = [rng.gen_range(0, 8) as f64, rng.gen_range(0, 8) as f64 * 0.5];
+                tree.add(point, i).unwrap();
+                model.push((point, i));
+            }
+            6 | 7 if !model.is_empty() => {
+                let (point, id) = model.swap_remove(rng.gen_range(0, model.len()));
+                assert_eq!(tree.remove(&point, &id).unwrap(), 1);
+            }
+            _ => {
+                let query = [rng.gen_range(-1.0, 9.0), rng.gen_range(-1.0, 5.0)];
+                check(&mut tree, &model, &query);
+            }
+        }
+    }
+    while let Some((point, id)) = model.pop() {
+        assert_eq!(tree.remove(&point, &id).unwrap(), 1);
+    }
+    check(&mut tree, &model, &[0.0, 0.0]);
+}
+
+#[test]
+fn it_builds_from_points_at_capacity_one() {
+    let mut rng: StdRng = SeedableRng::from_seed(&[1, 2, 1][..]);
+    let model: Vec<([f64; 2], usize)> = (0..500)
+        .map(|i| ([rng.gen_range(0, 6) as f64, rng.gen_range(-1.0, 1.0)], i))
+        .collect();
+    let mut tree = OwnedKdTree::from_points(model.clone(), 1).unwrap();
+    assert!(tree.stats().max_leaf_size <= 1);
+    for query in [[0.0, 0.0], [2.5, -0.3], [7.0, 3.0]] {
+        check(&mut tree, &model, &query);
+    }
+}
+
+#[test]
+fn it_splits_adjacent_floats() {
+    let a = 1.0f64;
+    let b = f64::from_bits(a.to_bits() + 1);
+    let mut tree = OwnedKdTree::with_capacity(1);
+    let model = vec![([a, 0.0], 0), ([b, 0.0], 1), ([a, 0.0], 2), ([b, 0.0], 3)];
+    for &(point, i) in &model {
+        tree.add(point, i).unwrap();
+    }
+    check(&mut tree, &model, &[a, 0.0]);
+    check(&mut tree, &model, &[b, 1.0]);
+}
+
+#[test]
+fn it_splits_extreme_ranges() {
+    let mut tree = OwnedKdTree::with_capacity(1);
+    let model = vec![
+        ([f64::MAX, 0.0], 0),
+        ([-f64::MAX, 0.0], 1),
+        ([f64::MAX, 1.0], 2),
+        ([0.0, f64::MAX], 3),
+    ];
+    for &(point, i) in &model {
+        tree.add(point, i).unwrap();
+    }
+    assert_eq!(tree.size(), 4);
+    assert_eq!(tree.stats().max_leaf_size, 1);
+    let nearest: Vec<usize> = tree
+        .nearest(&[f64::MAX, 0.5], 2, &|a: &[f64; 2], b: &[f64; 2]| {
+            (a[0] - b[0]).abs().max((a[1] - b[1]).abs())
+        })
+        .unwrap()
+        .into_iter()
+        .map(|(_, &i)| i)
+        .collect();
+    assert_eq!(nearest, vec![0, 2]);
+}
@@ -1,7 +1,8 @@
 extern crate kdtree;
+extern crate rand;
 
 use kdtree::distance::squared_euclidean;
-use kdtree::KdTree;
+use kdtree::{KdTree, OwnedKdTree};
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 static POINT_A: ([f64; 2], usize) = ([0f64, 0f64], 0);
@@ -71,3 +72,79 @@ fn it_works() {
     iter.next().unwrap();
     assert_eq!(count.swap(0, Ordering::SeqCst), 0);
 }
+
+#[test]
+fn nearest_multi_saves_work_on_coherent_queries() {
+    let size = 10_000;
+    use rand::{Rng, SeedableRng, StdRng};
+
+    let mut rng: StdRng = SeedableRng::from_seed(&[2, 7, 1, 8][..]);
+    let items: Vec<([f64; 2], usize)> = (0..size)
+        .map(|i| ([rng.gen_range(0.0, 100.0), rng.gen_range(0.0, 100.0)], i))
+        .collect();
+    let tree = OwnedKdTree::from_points(items, 16).unwrap();
+
+    let count = AtomicUsize::new(0);
+    let new_dist = |a: &[f64; 2], b: &[f64; 2]| {
+        count.fetch_add(1, Ordering::SeqCst);
+        squared_euclidean(a, b)
+    };
+    // a scanline of queries
+    let queries: Vec<[f64; 2]> = (0..200).map(|i| [i as f64 * 0.5, 50.0]).collect();
+
+    let individual: Vec<_> = queries
+        .iter()
+        .map(|q| tree.nearest(q, 4, &new_dist).unwrap())
+        .collect();
+    let independent_count = count.swap(0, Ordering::SeqCst);
+
+    let multi = tree.nearest_multi(&queries, 4, &new_dist).unwrap();
+    let multi_count = count.swap(0, Ordering::SeqCst);
+
+    assert_eq!(multi, individual);
+    assert!(
+        multi_count < independent_count,
+        "{} >= {}",
+        multi_count,
+        independent_count
+    );
+}
+
+#[test]
+fn within_capped_prunes_once_full() {
+    use rand::{Rng, SeedableRng, StdRng};
+
+    let mut rng: StdRng = SeedableRng::from_seed(&[8, 1, 8][..]);
+    let mut tree = OwnedKdTree::with_capacity(8);
+    while tree.size() < 20_000 {
+        let point = [rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0)];
+        if squared_euclidean(&point, &[0.0, 0.0]) <= 1.0 {
+            let id = tree.size();
+            tree.add(point, id).unwrap();
+        }
+    }
+
+    let count = AtomicUsize::new(0);
+    let counted = |a: &[f64; 2], b: &[f64; 2]| {
+        count.fetch_add(1, Ordering::SeqCst);
+        squared_euclidean(a, b)
+    };
+    let query = [0.1, -0.2];
+    let within = tree.within(&query, 0.5, &counted).unwrap();
+    let uncapped = count.swap(0, Ordering::SeqCst);
+    let capped = tree.within_capped(&query, 0.5, 10, &counted).unwrap();
+    let with_cap = count.swap(0, Ordering::SeqCst);
+
+    assert_eq!(capped, within[..10]);
+    assert!(within.len() > 5000);
+    assert!(with_cap * 50 < uncapped, "{} vs {}", with_cap, uncapped);
+    assert_eq!(
+        tree.within_capped(&query, 0.5, within.len() + 5, &counted)
+            .unwrap(),
+        within
+    );
+    assert!(tree
+        .within_capped(&query, 0.5, 0, &counted)
+        .unwrap()
+        .is_empty());
+}
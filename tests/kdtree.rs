@@ -158,10 +158,42 @@ fn handles_wrong_dimension() {
     );
 }
 
+#[test]
+fn handles_invalid_radius() {
+    let mut kdtree = KdTree::with_capacity(2, 2);
+    for i in 0..20 {
+        kdtree.add([(i % 5) as f64, 0.0], i).unwrap();
+    }
+
+    let mut at_origin: Vec<usize> = kdtree
+        .within(&POINT_A.0, 0.0, &squared_euclidean)
+        .unwrap()
+        .into_iter()
+        .map(|(_, &i)| i)
+        .collect();
+    at_origin.sort_unstable();
+    assert_eq!(at_origin, vec![0, 5, 10, 15]);
+    assert_eq!(
+        kdtree
+            .within(&POINT_A.0, f64::INFINITY, &squared_euclidean)
+            .unwrap()
+            .len(),
+        20
+    );
+    assert_eq!(
+        kdtree.within(&POINT_A.0, -1.0, &squared_euclidean),
+        Err(ErrorKind::InvalidRadius)
+    );
+    assert_eq!(
+        kdtree.within(&POINT_A.0, f64::NAN, &squared_euclidean),
+        Err(ErrorKind::InvalidRadius)
+    );
+}
+
 #[test]
 fn handles_non_finite_coordinate() {
-    let point_a = ([std::f64::NAN, std::f64::NAN], 0f64);
-    let point_b = ([std::f64::INFINITY, std::f64::INFINITY], 0f64);
+    let point_a = ([f64::NAN, f64::NAN], 0f64);
+    let point_b = ([f64::INFINITY, f64::INFINITY], 0f64);
     let mut kdtree = KdTree::with_capacity(2, 1);
 
     assert_eq!(
@@ -392,3 +424,32 @@ fn handles_remove_no_match() {
         vec![(16.0, &4), (36.0, &3)]
     );
 }
+
+#[test]
+fn handles_points_at_the_split_value() {
+    let next = f64::from_bits(1f64.to_bits() + 1);
+    let mut kdtree = KdTree::with_capacity(1, 1);
+    // the midpoint of adjacent floats rounds down to the smaller of them
+    kdtree.add([1f64], 0).unwrap();
+    kdtree.add([next], 1).unwrap();
+    kdtree.add([next], 2).unwrap();
+    // and the midpoint of 0 and 1 is a split value too
+    kdtree.add([0f64], 3).unwrap();
+    kdtree.add([0.5f64], 4).unwrap();
+
+    assert_eq!(kdtree.size(), 5);
+    assert_eq!(
+        kdtree.nearest(&[0.5f64], 1, &squared_euclidean).unwrap(),
+        vec![(0.0, &4)]
+    );
+    assert_eq!(
+        kdtree.within(&[1f64], 0.0, &squared_euclidean).unwrap(),
+        vec![(0.0, &0)]
+    );
+    assert_eq!(kdtree.remove(&[next], &1).unwrap(), 1);
+    assert_eq!(kdtree.remove(&[0.5f64], &4).unwrap(), 1);
+    assert_eq!(
+        kdtree.nearest(&[next], 3, &squared_euclidean).unwrap(),
+        vec![(0.0, &2), ((next - 1.0).powi(2), &0), (next.powi(2), &3)]
+    );
+}
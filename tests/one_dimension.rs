@@ -0,0 +1,187 @@
+extern crate kdtree;
+extern crate rand;
+
+use kdtree::OwnedKdTree;
+use rand::{Rng, SeedableRng, StdRng};
+
+fn dist(a: &[f64; 1], b: &[f64; 1]) -> f64 {
+    (a[0] - b[0]).abs()
+}
+
+/// The items of the tree as a sorted `Vec`, answering the queries by bisection
+struct Oracle {
+    items: Vec<(f64, usize)>,
+}
+
+impl Oracle {
+    fn add(&mut self, x: f64, id: usize) {
+        let i = self.items.partition_point(|&item| item < (x, id));
+        self.items.insert(i, (x, id));
+    }
+
+    fn remove(&mut self, x: f64, id: usize) -> usize {
+        match self
+            .items
+            .binary_search_by(|item| item.partial_cmp(&(x, id)).unwrap())
+        {
+            Ok(i) => {
+                self.items.remove(i);
+                1
+            }
+            Err(_) => 0,
+        }
+    }
+
+    /// The items within `radius` of `x`, a contiguous range of the sorted items
+    fn within(&self, x: f64, radius: f64) -> &[(f64, usize)] {
+        let start = self.items.partition_point(|&(y, _)| y < x - radius);
+        let end = self.items.partition_point(|&(y, _)| y <= x + radius);
+        &self.items[start..end]
+    }
+
+    /// The distances and coordinates of the `num` items nearest to `x`, merging outwards from
+    /// where `x` would be inserted
+    fn nearest(&self, x: f64, num: usize) -> Vec<(f64, f64)> {
+        let split = self.items.partition_point(|&(y, _)| y < x);
+        let (mut below, mut above) = (split, split);
+        let mut nearest = vec![];
+        while nearest.len() < num && (below > 0 || above < self.items.len()) {
+            let low = below.checked_sub(1).map(|i| self.items[i].0);
+            let high = self.items.get(above).map(|&(y, _)| y);
+            // equal distances come lowest point first, as in the tree
+            let take_low = match (low, high) {
+                (Some(low), Some(high)) => x - low <= high - x,
+                (low, _) => low.is_some(),
+            };
+            if take_low {
+                below -= 1;
+                nearest.push((x - self.items[below].0, self.items[below].0));
+            } else {
+                nearest.push((self.items[above].0 - x, self.items[above].0));
+                above += 1;
+            }
+        }
+        nearest
+    }
+}
+
+fn check(tree: &OwnedKdTree<f64, usize, 1>, oracle: &Oracle, x: f64, radius: f64) {
+    assert_eq!(tree.size(), oracle.items.len());
+    for &num in &[0, 1, 3, 10] {
+        let nearest: Vec<(f64, f64)> = tree
+            .nearest(&[x], num, &dist)
+            .unwrap()
+            .into_iter()
+            .map(|(d, _)| d)
+            .zip(tree.iter_nearest(&[x], &dist).unwrap().map(|(d, _)| d))
+            .map(|(d, e)| {
+                assert_eq!(d, e);
+                d
+            })
+            .zip(oracle.nearest(x, num))
+            .map(|(d, (expected, y))| {
+                assert_eq!(d, expected);
+                (d, y)
+            })
+            .collect();
+        assert_eq!(nearest.len(), num.min(oracle.items.len()));
+    }
+
+    let mut within: Vec<usize> = tree
+        .within(&[x], radius, &dist)
+        .unwrap()
+        .into_iter()
+        .map(|(_, &id)| id)
+        .collect();
+    within.sort_unstable();
+    let mut expected: Vec<usize> = oracle.within(x, radius).iter().map(|&(_, id)| id).collect();
+    expected.sort_unstable();
+    assert_eq!(within, expected);
+
+    let mut in_box: Vec<usize> = tree
+        .within_box(&[x - radius], &[x + radius])
+        .unwrap()
+        .into_iter()
+        .copied()
+        .collect();
+    in_box.sort_unstable();
+    assert_eq!(in_box, expected);
+}
+
+fn run(seed: &[usize], capacity: usize, coordinate: &dyn Fn(&mut StdRng) -> f64) {
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let mut tree = OwnedKdTree::with_capacity(capacity);
+    let mut oracle = Oracle { items: vec![] };
+    for id in 0..3000 {
+        match rng.gen_range(0, 10) {
+            0..=4 => {
+                let x = coordinate(&mut rng);
+                tree.add([x], id).unwrap();
+                oracle.add(x, id);
+            }
+            5 | 6 if !oracle.items.is_empty() => {
+                let (x, old) = oracle.items[rng.gen_range(0, oracle.items.len())];
+                assert_eq!(tree.remove(&[x], &old).unwrap(), oracle.remove(x, old));
+            }
+            5 | 6 => assert_eq!(tree.remove(&[1.0], &id).unwrap(), 0),
+            _ => {
+                let x = coordinate(&mut rng);
+                let radius = rng.gen_range(0, 12) as f64 * 0.25;
+                check(&tree, &oracle, x, radius);
+            }
+        }
+    }
+    let mut all: Vec<(f64, usize)> = tree.iter().map(|(p, &id)| (p[0], id)).collect();
+    all.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(all, oracle.items);
+}
+
+#[test]
+fn it_matches_a_sorted_vec() {
+    // multiples of a quarter, so that the distances and the radii are exact
+    let coordinate = |rng: &mut StdRng| rng.gen_range(-40, 40) as f64 * 0.25;
+    for &capacity in &[1, 2, 16] {
+        run(&[1, capacity], capacity, &coordinate);
+    }
+}
+
+#[test]
+fn it_matches_a_sorted_vec_with_many_duplicates() {
+    // a handful of values, most of them repeated well beyond the capacity of a leaf
+    let coordinate = |rng: &mut StdRng| [0.0, 0.0, 0.0, 0.25, 5.0, 5.0][rng.gen_range(0, 6)];
+    for &capacity in &[1, 2, 16] {
+        run(&[2, capacity], capacity, &coordinate);
+    }
+}
+
+#[test]
+fn it_splits_adjacent_and_extreme_values() {
+    let mut tree = OwnedKdTree::with_capacity(1);
+    let next = f64::from_bits(1.0f64.to_bits() + 1);
+    let points = [1.0, next, 1.0, next, f64::MAX, -f64::MAX, f64::MAX, 0.0];
+    for (id, &x) in points.iter().enumerate() {
+        tree.add([x], id).unwrap();
+    }
+    assert_eq!(tree.size(), points.len());
+    let nearest: Vec<usize> = tree
+        .nearest(&[next], 4, &dist)
+        .unwrap()
+        .into_iter()
+        .map(|(_, &id)| id)
+        .collect();
+    assert_eq!(nearest.len(), 4);
+    assert!(nearest[..2].iter().all(|&id| points[id] == next));
+    assert!(nearest[2..].iter().all(|&id| points[id] == 1.0));
+    let mut at_max = tree.remove_at(&[f64::MAX]).unwrap();
+    at_max.sort_unstable();
+    assert_eq!(at_max, vec![4, 6]);
+    assert_eq!(tree.size(), points.len() - 2);
+    assert_eq!(
+        tree.within_box(&[1e300], &[f64::MAX]).unwrap(),
+        Vec::<&usize>::new()
+    );
+    assert_eq!(
+        tree.nearest(&[-f64::MAX], 1, &dist).unwrap(),
+        vec![(0.0, &5)]
+    );
+}
@@ -0,0 +1,1363 @@
+extern crate kdtree;
+extern crate rand;
+
+use kdtree::distance::squared_euclidean;
+use kdtree::tree::{BuildOrder, Builder, NodeRef};
+use kdtree::OwnedErrorKind as ErrorKind;
+use kdtree::OwnedKdTree;
+use std::f64::consts::PI;
+use std::ops::ControlFlow;
+
+fn dist<const D: usize>(a: &[f64; D], b: &[f64; D]) -> f64 {
+    squared_euclidean(a, b)
+}
+
+#[test]
+fn it_builds_from_points() {
+    let items: Vec<([f64; 2], usize)> = (0..100)
+        .map(|i| ([(i % 10) as f64, (i / 10) as f64], i))
+        .collect();
+    let tree = OwnedKdTree::from_points(items.clone(), 4).unwrap();
+    assert_eq!(tree.size(), 100);
+    assert!(tree.stats().max_leaf_size <= 4);
+    assert!(tree.stats().max_depth <= 6);
+
+    let mut incremental = OwnedKdTree::with_capacity(4);
+    for (p, d) in items {
+        incremental.add(p, d).unwrap();
+    }
+    for query in [[0.0, 0.0], [4.5, 4.5], [9.2, 3.7]].iter() {
+        let distances = |tree: &OwnedKdTree<f64, usize, 2>| {
+            let nearest = tree.nearest(query, 5, &dist).unwrap();
+            nearest.into_iter().map(|(d, _)| d).collect::<Vec<_>>()
+        };
+        assert_eq!(distances(&tree), distances(&incremental));
+    }
+
+    assert_eq!(
+        OwnedKdTree::from_points(vec![([0.0, f64::NAN], 0)], 4).err(),
+        Some(ErrorKind::NonFiniteCoordinate)
+    );
+    assert_eq!(
+        OwnedKdTree::<f64, usize, 2>::from_points(vec![], 0).err(),
+        Some(ErrorKind::ZeroCapacity)
+    );
+}
+
+#[test]
+fn it_builds_from_duplicate_points() {
+    let mut items = vec![([0.0], 0); 20];
+    items.push(([1.0], 1));
+    let tree = OwnedKdTree::from_points(items, 2).unwrap();
+    assert_eq!(tree.size(), 21);
+    assert_eq!(
+        tree.nearest(&[0.9], 1, &dist).unwrap(),
+        vec![((1.0f64 - 0.9).powi(2), &1)]
+    );
+}
+
+#[test]
+fn it_transforms_points() {
+    // lon/lat in degrees, 60 degrees north where a degree of longitude is half as long as a
+    // degree of latitude
+    let origin = [10.0, 60.0];
+    let east = ([10.8, 60.0], "east");
+    let north = ([10.0, 60.5], "north");
+    let tree = OwnedKdTree::from_points(vec![east, north], 16).unwrap();
+    assert_eq!(tree.nearest(&origin, 1, &dist).unwrap()[0].1, &"north");
+
+    let to_plane = |[lon, lat]: [f64; 2]| {
+        let meters_per_degree = 111_320.0;
+        [
+            (lon - origin[0]) * meters_per_degree * origin[1].to_radians().cos(),
+            (lat - origin[1]) * meters_per_degree,
+        ]
+    };
+    let projected = tree.transform_points(to_plane).unwrap();
+    assert_eq!(projected.size(), 2);
+    let nearest = projected.nearest(&to_plane(origin), 1, &dist).unwrap();
+    assert_eq!(nearest[0].1, &"east");
+
+    let broken = projected.transform_points(|p| [p[0] / 0.0, p[1]]);
+    assert_eq!(broken.err(), Some(ErrorKind::NonFiniteCoordinate));
+}
+
+#[test]
+fn it_builds_in_morton_order() {
+    let items: Vec<([f64; 3], usize)> = (0..2000)
+        .map(|i| {
+            let i = i as f64;
+            (
+                [(i * 0.37).sin(), (i * 1.13).cos(), (i * 0.071).sin()],
+                i as usize,
+            )
+        })
+        .collect();
+    let unsorted = OwnedKdTree::from_points(items.clone(), 8).unwrap();
+    let morton = Builder::new()
+        .capacity(8)
+        .order(BuildOrder::Morton)
+        .from_points(items)
+        .unwrap();
+    assert_eq!(morton.size(), 2000);
+    for query in [[0.0, 0.0, 0.0], [0.5, -0.5, 0.25], [1.0, 1.0, 1.0]].iter() {
+        assert_eq!(
+            morton.nearest(query, 10, &dist).unwrap(),
+            unsorted.nearest(query, 10, &dist).unwrap()
+        );
+    }
+
+    // the splits are the same, only the items inside the leaves are put in Morton order
+    fn leaves_of(node: NodeRef<f64, usize, 3>, leaves: &mut Vec<Vec<usize>>) {
+        match (node.left(), node.right()) {
+            (Some(left), Some(right)) => {
+                leaves_of(left, leaves);
+                leaves_of(right, leaves);
+            }
+            _ => {
+                let mut data: Vec<usize> = node.iter().map(|(_, &d)| d).collect();
+                data.sort_unstable();
+                leaves.push(data);
+            }
+        }
+    }
+    let (mut sorted, mut given) = (vec![], vec![]);
+    leaves_of(morton.root(), &mut sorted);
+    leaves_of(unsorted.root(), &mut given);
+    assert_eq!(sorted, given);
+    assert!(morton.iter().eq(morton.iter_morton()));
+    assert!(!unsorted.iter().eq(unsorted.iter_morton()));
+    let mut data: Vec<usize> = unsorted.iter_morton().map(|(_, &d)| d).collect();
+    data.sort_unstable();
+    assert!(data.into_iter().eq(0..2000));
+
+    // consecutive items of the Morton order are closer together than those of the input order
+    let steps = |items: Vec<(&[f64; 3], &usize)>| -> f64 {
+        items.windows(2).map(|w| dist(w[0].0, w[1].0).sqrt()).sum()
+    };
+    let within_leaves = steps(unsorted.iter().collect());
+    let along_the_curve = steps(unsorted.iter_morton().collect());
+    assert!(along_the_curve < within_leaves);
+}
+
+#[test]
+fn it_builds_in_hilbert_order() {
+    let items: Vec<([f64; 2], usize)> = (0..3000)
+        .map(|i| {
+            let i = i as f64;
+            ([(i * 0.37).sin() * 100.0, (i * 1.13).cos()], i as usize)
+        })
+        .collect();
+    let unsorted = OwnedKdTree::from_points(items.clone(), 8).unwrap();
+    let hilbert = Builder::new()
+        .capacity(8)
+        .order(BuildOrder::Hilbert)
+        .from_points(items.clone())
+        .unwrap();
+    let mut stored: Vec<([f64; 2], usize)> = hilbert.iter().map(|(p, &d)| (*p, d)).collect();
+    stored.sort_by_key(|&(_, d)| d);
+    assert_eq!(stored, items);
+    for query in [[0.0, 0.0], [50.0, -0.5], [-100.0, 1.0]].iter() {
+        assert_eq!(
+            hilbert.nearest(query, 10, &dist).unwrap(),
+            unsorted.nearest(query, 10, &dist).unwrap()
+        );
+    }
+    let items: Vec<([f64; 3], usize)> = (0..500)
+        .map(|i| ([(i % 8) as f64, (i / 8 % 8) as f64, (i / 64) as f64], i))
+        .collect();
+    let hilbert = Builder::new()
+        .order(BuildOrder::Hilbert)
+        .from_points(items)
+        .unwrap();
+    let mut stored: Vec<usize> = hilbert.iter().map(|(_, &d)| d).collect();
+    stored.sort_unstable();
+    assert!(stored.into_iter().eq(0..500));
+}
+
+#[test]
+fn it_iterates_in_morton_order() {
+    let items: Vec<([f64; 2], usize)> = (0..16)
+        .map(|i| ([(i % 4) as f64, (i / 4) as f64], i))
+        .collect();
+    let tree = OwnedKdTree::from_points(items, 1).unwrap();
+    assert_eq!(tree.iter().count(), 16);
+
+    let visited: Vec<_> = tree.iter_morton().collect();
+    let mut data: Vec<_> = visited.iter().map(|(_, &d)| d).collect();
+    data.sort();
+    assert_eq!(data, (0..16).collect::<Vec<_>>());
+    for quadrant in visited.chunks(4) {
+        let (p, _) = quadrant[0];
+        let cell = |p: &[f64; 2]| ((p[0] / 2.0) as usize, (p[1] / 2.0) as usize);
+        assert!(quadrant.iter().all(|(q, _)| cell(q) == cell(p)));
+    }
+}
+
+#[test]
+fn it_iterates_lower_sides_first() {
+    let items: Vec<([f64; 1], usize)> = [5, 2, 7, 0, 3, 6, 1, 4]
+        .iter()
+        .map(|&i| ([i as f64], i))
+        .collect();
+    let tree = OwnedKdTree::from_points(items, 1).unwrap();
+    assert!(tree.iter().map(|(_, &d)| d).eq(0..8));
+}
+
+#[test]
+fn it_reports_build_progress() {
+    let items = || -> Vec<([f64; 2], usize)> {
+        (0..20_000)
+            .map(|i| ([(i % 200) as f64, (i / 200) as f64], i))
+            .collect()
+    };
+
+    let mut reported = vec![];
+    let tree = Builder::new()
+        .from_points_with_progress(items(), |done, total| {
+            reported.push((done, total));
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+    assert_eq!(tree.size(), 20_000);
+    assert!(reported.len() > 1);
+    assert!(reported.windows(2).all(|w| w[0].0 < w[1].0));
+    assert!(reported.iter().all(|&(_, total)| total == 20_000));
+    assert_eq!(reported.last(), Some(&(20_000, 20_000)));
+
+    let mut calls = 0;
+    let cancelled = Builder::new().from_points_with_progress(items(), |done, total| {
+        calls += 1;
+        assert!(done < total);
+        ControlFlow::Break(())
+    });
+    assert_eq!(cancelled.err(), Some(ErrorKind::Cancelled));
+    assert_eq!(calls, 1);
+}
+
+#[test]
+fn it_reports_add_all_progress() {
+    let items: Vec<([f64; 1], usize)> = (0..10_000)
+        .map(|i| ([(i * 7919 % 10_000) as f64], i))
+        .collect();
+
+    let mut tree = OwnedKdTree::new();
+    let mut reported = vec![];
+    tree.add_all_with_progress(items.clone(), |done, total| {
+        reported.push((done, total));
+        ControlFlow::Continue(())
+    })
+    .unwrap();
+    assert_eq!(tree.size(), 10_000);
+    assert_eq!(
+        reported,
+        vec![(4096, 10_000), (8192, 10_000), (10_000, 10_000)]
+    );
+
+    let mut tree = OwnedKdTree::new();
+    let cancelled = tree.add_all_with_progress(items, |done, _| {
+        if done > 5000 {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    });
+    assert_eq!(cancelled, Err(ErrorKind::Cancelled));
+    assert_eq!(tree.size(), 8192);
+}
+
+#[test]
+fn it_rejects_items_beyond_max_items() {
+    let items: Vec<([f64; 2], usize)> = (0..10).map(|i| ([i as f64, 0.0], i)).collect();
+    let mut tree = Builder::new().capacity(2).max_items(Some(6)).build();
+    assert_eq!(tree.max_items(), Some(6));
+    assert_eq!(tree.add_all(items[..4].to_vec()), Ok(4));
+    assert_eq!(tree.add_all(items[4..].to_vec()), Ok(2));
+    assert_eq!(tree.size(), 6);
+    assert_eq!(tree.add([1.5, 0.0], 100), Err(ErrorKind::Full));
+    assert_eq!(tree.size(), 6);
+
+    tree.remove(&[2.0, 0.0], &2).unwrap();
+    assert_eq!(tree.add([1.5, 0.0], 100), Ok(()));
+    assert_eq!(tree.add([2.5, 0.0], 101), Err(ErrorKind::Full));
+    assert_eq!(tree.nearest(&[1.6, 0.0], 1, &dist).unwrap()[0].1, &100);
+
+    let built = Builder::new()
+        .max_items(Some(10))
+        .from_points(items.clone());
+    assert_eq!(built.unwrap().add([0.0, 1.0], 10), Err(ErrorKind::Full));
+    let too_many = Builder::new().max_items(Some(9)).from_points(items.clone());
+    assert_eq!(too_many.err(), Some(ErrorKind::Full));
+    // emptying the tree keeps the limit
+    for (point, data) in tree.iter().map(|(p, &d)| (*p, d)).collect::<Vec<_>>() {
+        tree.remove(&point, &data).unwrap();
+    }
+    assert_eq!(tree.max_items(), Some(6));
+    assert_eq!(tree.add_all(items.clone()), Ok(6));
+
+    let mut unbounded = OwnedKdTree::new();
+    assert_eq!(unbounded.max_items(), None);
+    assert_eq!(unbounded.add_all(items), Ok(10));
+}
+
+#[test]
+fn it_finds_approximate_neighbours_with_bbf() {
+    use rand::distributions::{IndependentSample, Normal};
+    use rand::{SeedableRng, StdRng};
+
+    let mut rng: StdRng = SeedableRng::from_seed(&[7, 11, 13][..]);
+    let normal = Normal::new(0.0, 1.0);
+    let mut gaussian = |scale: f64| {
+        let mut p = [0.0; 32];
+        for x in p.iter_mut() {
+            *x = normal.ind_sample(&mut rng) * scale;
+        }
+        p
+    };
+    let items: Vec<_> = (0..20_000).map(|i| (gaussian(1.0), i)).collect();
+    let queries: Vec<_> = items
+        .iter()
+        .step_by(200)
+        .map(|(p, _)| {
+            let noise = gaussian(0.1);
+            let mut q = *p;
+            for (x, n) in q.iter_mut().zip(noise.iter()) {
+                *x += n;
+            }
+            q
+        })
+        .collect();
+    let tree = OwnedKdTree::from_points(items, 16).unwrap();
+    let leaves = tree.stats().leaves;
+    let max_leaf_checks = leaves / 50;
+
+    let mut found = 0;
+    for query in &queries {
+        let exact = tree.nearest(query, 1, &dist).unwrap();
+        let approximate = tree.nearest_bbf(query, 1, max_leaf_checks, &dist).unwrap();
+        if exact == approximate {
+            found += 1;
+        }
+    }
+    let recall = found as f64 / queries.len() as f64;
+    assert!(
+        recall > 0.9,
+        "recall@1 {} with {} of {} leaves",
+        recall,
+        max_leaf_checks,
+        leaves
+    );
+}
+
+#[test]
+fn it_ranks_by_cosine_similarity() {
+    let cosine = |a: &[f64; 3], b: &[f64; 3]| {
+        let dot: f64 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+        let norm = |v: &[f64; 3]| v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        dot / (norm(a) * norm(b))
+    };
+    let items: Vec<([f64; 3], usize)> = (0..500)
+        .map(|i| {
+            let i = i as f64;
+            let scale = 1.0 + (i * 0.3).sin().abs() * 10.0;
+            (
+                [
+                    (i * 0.7).cos() * scale,
+                    (i * 1.9).sin() * scale,
+                    (i * 0.2).cos(),
+                ],
+                i as usize,
+            )
+        })
+        .collect();
+    let mut tree = OwnedKdTree::new();
+    for &(p, d) in &items {
+        tree.add_normalized(p, d).unwrap();
+    }
+
+    let query = [1.0, 2.0, -0.5];
+    let mut brute_force: Vec<_> = items.iter().map(|(p, d)| (cosine(p, &query), d)).collect();
+    brute_force.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+    let nearest = tree.nearest_cosine(&query, 10).unwrap();
+    assert_eq!(nearest.len(), 10);
+    for ((similarity, item), (expected, expected_item)) in nearest.iter().zip(brute_force) {
+        assert_eq!(*item, expected_item);
+        assert!((similarity - expected).abs() < 1e-9);
+    }
+
+    assert_eq!(tree.add_normalized([0.0; 3], 0), Err(ErrorKind::ZeroVector));
+    assert_eq!(
+        tree.nearest_cosine(&[0.0; 3], 1),
+        Err(ErrorKind::ZeroVector)
+    );
+    assert_eq!(tree.size(), 500);
+}
+
+#[test]
+fn it_takes_removed_payloads() {
+    let mut tree = OwnedKdTree::with_capacity(2);
+    tree.add([0.0, 0.0], "a".to_string()).unwrap();
+    tree.add([0.0, 0.0], "b".to_string()).unwrap();
+    tree.add([0.0, 0.0], "a".to_string()).unwrap();
+    tree.add([1.0, 1.0], "a".to_string()).unwrap();
+    tree.add([2.0, 2.0], "c".to_string()).unwrap();
+
+    let taken = tree.take(&[0.0, 0.0], &"a".to_string()).unwrap();
+    assert_eq!(taken, vec!["a".to_string(), "a".to_string()]);
+    assert_eq!(tree.size(), 3);
+
+    assert_eq!(
+        tree.take(&[0.0, 0.0], &"c".to_string()).unwrap(),
+        Vec::<String>::new()
+    );
+    assert_eq!(tree.size(), 3);
+
+    assert_eq!(
+        tree.take(&[0.0, 0.0], &"b".to_string()).unwrap(),
+        vec!["b".to_string()]
+    );
+    assert_eq!(tree.remove(&[1.0, 1.0], &"a".to_string()), Ok(1));
+    assert_eq!(tree.size(), 1);
+    assert_eq!(
+        tree.nearest(&[0.0, 0.0], 5, &dist).unwrap(),
+        vec![(8.0, &"c".to_string())]
+    );
+}
+
+#[test]
+fn it_removes_in_batches() {
+    use rand::{Rng, SeedableRng, StdRng};
+
+    let mut rng: StdRng = SeedableRng::from_seed(&[3, 1, 4, 1, 5][..]);
+    let mut items = vec![];
+    for i in 0..2000 {
+        // coarse coordinates so some items share a point
+        let p = [rng.gen_range(0, 30) as f64, rng.gen_range(0, 30) as f64];
+        items.push((p, i % 50));
+    }
+    let mut batched = OwnedKdTree::with_capacity(4);
+    batched.add_all(items.clone()).unwrap();
+    let mut single = batched.clone();
+
+    let mut removals: Vec<_> = items.iter().step_by(3).cloned().collect();
+    removals.push(([100.0, 100.0], 0));
+    removals.push(([1.0, 1.0], 1000));
+    removals.push(removals[0]);
+
+    let expected: usize = removals
+        .iter()
+        .map(|(p, d)| single.remove(p, d).unwrap())
+        .sum();
+    assert_eq!(batched.remove_all(&removals), Ok(expected));
+    assert_eq!(batched.size(), single.size());
+    assert_eq!(batched.size(), 2000 - expected);
+    assert_eq!(batched.iter().count(), batched.size());
+
+    let mut remaining: Vec<_> = batched.iter().map(|(p, &d)| (*p, d)).collect();
+    let mut expected_remaining: Vec<_> = single.iter().map(|(p, &d)| (*p, d)).collect();
+    remaining.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    expected_remaining.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(remaining, expected_remaining);
+
+    assert_eq!(
+        batched.remove_all(&[([f64::NAN, 0.0], 0)]),
+        Err(ErrorKind::NonFiniteCoordinate)
+    );
+}
+
+#[test]
+fn it_summarizes_contents() {
+    let mut tree = OwnedKdTree::with_capacity(4);
+    assert_eq!(tree.centroid(), None);
+    assert_eq!(tree.variance(), None);
+    assert_eq!(tree.extent(), None);
+
+    for i in 0..100 {
+        let i = i as f64;
+        tree.add([i.sin() * 3.0, i * 0.5 - 10.0, 7.0], i).unwrap();
+    }
+    let points: Vec<[f64; 3]> = tree.iter().map(|(p, _)| *p).collect();
+    let n = points.len() as f64;
+    let mut mean = [0.0; 3];
+    let mut min = [f64::INFINITY; 3];
+    let mut max = [f64::NEG_INFINITY; 3];
+    for p in &points {
+        for dim in 0..3 {
+            mean[dim] += p[dim] / n;
+            min[dim] = min[dim].min(p[dim]);
+            max[dim] = max[dim].max(p[dim]);
+        }
+    }
+    let mut variance = [0.0; 3];
+    for p in &points {
+        for dim in 0..3 {
+            variance[dim] += (p[dim] - mean[dim]).powi(2) / n;
+        }
+    }
+
+    let centroid = tree.centroid().unwrap();
+    let tree_variance = tree.variance().unwrap();
+    for dim in 0..3 {
+        assert!((centroid[dim] - mean[dim]).abs() < 1e-9);
+        assert!((tree_variance[dim] - variance[dim]).abs() < 1e-9);
+    }
+    assert_eq!(tree_variance[2], 0.0);
+    assert_eq!(tree.extent(), Some((min, max)));
+}
+
+#[test]
+fn it_behaves_as_new_after_removing_everything() {
+    let items: Vec<([f64; 2], usize)> = (0..50).map(|i| ([i as f64, -(i as f64)], i)).collect();
+    let mut tree = OwnedKdTree::with_capacity(4);
+    tree.add_all(items.clone()).unwrap();
+    assert!(!tree.root().is_leaf());
+
+    assert_eq!(tree.remove_all(&items), Ok(50));
+    assert_eq!(tree.size(), 0);
+    assert_eq!(tree.extent(), None);
+    assert_eq!(tree.root().bounds(), None);
+    assert!(tree.root().is_leaf());
+    assert_eq!(
+        tree.stats(),
+        OwnedKdTree::<f64, usize, 2>::with_capacity(4).stats()
+    );
+    assert_eq!(tree.nearest(&[0.0, 0.0], 3, &dist).unwrap(), vec![]);
+
+    tree.add([100.0, 100.0], 100).unwrap();
+    tree.add([101.0, 100.0], 101).unwrap();
+    assert_eq!(tree.extent(), Some(([100.0, 100.0], [101.0, 100.0])));
+    assert_eq!(
+        tree.nearest(&[0.0, 0.0], 3, &dist).unwrap(),
+        vec![(20000.0, &100), (20201.0, &101)]
+    );
+}
+
+#[test]
+fn it_prunes_emptied_subtrees() {
+    let mut tree = OwnedKdTree::with_capacity(2);
+    for i in 0..8 {
+        tree.add([i as f64], i).unwrap();
+    }
+    for i in 0..4 {
+        tree.remove(&[i as f64], &i).unwrap();
+    }
+    let left = tree.root().left().unwrap();
+    assert_eq!(left.size(), 0);
+    assert_eq!(left.bounds(), None);
+    assert!(left.is_leaf());
+    assert_eq!(
+        tree.iter_nearest(&[0.0], &dist)
+            .unwrap()
+            .collect::<Vec<_>>(),
+        vec![(16.0, &4), (25.0, &5), (36.0, &6), (49.0, &7)]
+    );
+    assert_eq!(tree.nearest(&[0.0], 1, &dist).unwrap(), vec![(16.0, &4)]);
+}
+
+#[test]
+fn it_queries_several_radii_at_once() {
+    let items: Vec<([f64; 2], usize)> = (0..400)
+        .map(|i| ([(i % 20) as f64 * 0.37, (i / 20) as f64 * 0.53], i))
+        .collect();
+    let tree = OwnedKdTree::from_points(items, 8).unwrap();
+    let query = [3.1, 4.2];
+    let radii = [0.5, 2.0, 2.0, 9.0];
+    let multi = tree.within_multi(&query, &radii, &dist).unwrap();
+    assert_eq!(multi.len(), radii.len());
+    for (within, &r) in multi.iter().zip(radii.iter()) {
+        let mut expected = tree.within(&query, r, &dist).unwrap();
+        let mut within = within.clone();
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        within.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(within, expected);
+    }
+    assert!(multi[0].len() < multi[1].len() && multi[1].len() < multi[3].len());
+
+    assert_eq!(tree.within_multi(&query, &[], &dist), Ok(vec![]));
+    assert_eq!(
+        tree.within_multi(&query, &[2.0, 1.0], &dist),
+        Err(ErrorKind::InvalidRadius)
+    );
+    assert_eq!(
+        tree.within_multi(&query, &[-1.0, 1.0], &dist),
+        Err(ErrorKind::InvalidRadius)
+    );
+    assert_eq!(
+        tree.within_multi(&query, &[f64::NAN], &dist),
+        Err(ErrorKind::InvalidRadius)
+    );
+}
+
+#[test]
+fn it_validates_the_radius_of_within_queries() {
+    let mut items: Vec<([f64; 2], usize)> = (0..400)
+        .map(|i| ([(i % 20) as f64, (i / 20) as f64], i))
+        .collect();
+    // a hotspot of items at one grid point
+    items.extend((400..450).map(|i| ([2.0, 3.0], i)));
+    let tree = OwnedKdTree::from_points(items.clone(), 4).unwrap();
+    let hotspot = [2.0, 3.0];
+
+    let mut at_hotspot: Vec<usize> = tree
+        .within(&hotspot, 0.0, &dist)
+        .unwrap()
+        .into_iter()
+        .map(|(d, &i)| {
+            assert_eq!(d, 0.0);
+            i
+        })
+        .collect();
+    at_hotspot.sort_unstable();
+    let mut expected: Vec<usize> = (400..450).collect();
+    expected.insert(0, 62);
+    assert_eq!(at_hotspot, expected);
+    let grouped = tree.within_grouped(&hotspot, 0.0, &dist).unwrap();
+    assert_eq!(grouped.len(), 1);
+    assert_eq!(grouped[0].2.len(), 51);
+    assert!(tree.any_within(&hotspot, 0.0, &dist).unwrap());
+    assert!(!tree.any_within(&[2.5, 3.0], 0.0, &dist).unwrap());
+    assert!(tree.within(&[2.5, 3.0], 0.0, &dist).unwrap().is_empty());
+
+    let everything = tree.within(&hotspot, f64::INFINITY, &dist).unwrap();
+    assert_eq!(everything.len(), 450);
+    assert!(everything.windows(2).all(|w| w[0].0 <= w[1].0));
+    assert_eq!(
+        tree.fold_within(&hotspot, f64::INFINITY, &dist, 0, |n, _, _| n + 1),
+        Ok(450)
+    );
+    assert!(!tree
+        .within_limited(&hotspot, f64::INFINITY, &dist, 500)
+        .unwrap()
+        .is_truncated());
+    let flat = tree.clone().freeze();
+    assert_eq!(
+        flat.within(&hotspot, f64::INFINITY, &dist).unwrap().len(),
+        450
+    );
+    assert_eq!(flat.within(&hotspot, 0.0, &dist).unwrap().len(), 51);
+
+    for &radius in &[-1.0, -f64::MIN_POSITIVE, f64::NEG_INFINITY, f64::NAN] {
+        let invalid = Some(ErrorKind::InvalidRadius);
+        assert_eq!(tree.within(&hotspot, radius, &dist).err(), invalid);
+        assert_eq!(
+            tree.within_capped(&hotspot, radius, 5, &dist).err(),
+            invalid
+        );
+        assert_eq!(tree.within_keys(&hotspot, radius, &dist).err(), invalid);
+        assert_eq!(tree.within_grouped(&hotspot, radius, &dist).err(), invalid);
+        assert_eq!(tree.within_owned(&hotspot, radius, &dist).err(), invalid);
+        assert_eq!(tree.any_within(&hotspot, radius, &dist).err(), invalid);
+        assert_eq!(
+            tree.fold_within(&hotspot, radius, &dist, 0, |n, _, _| n + 1)
+                .err(),
+            invalid
+        );
+        assert_eq!(
+            tree.within_limited(&hotspot, radius, &dist, 5).err(),
+            invalid
+        );
+        assert_eq!(flat.within(&hotspot, radius, &dist).err(), invalid);
+    }
+}
+
+#[test]
+fn it_traverses_with_custom_pruning() {
+    let items = (0..1000)
+        .map(|i| ([(i * 7919 % 1000) as f64, (i % 37) as f64], i))
+        .collect();
+    let tree: OwnedKdTree<f64, usize, 2> = OwnedKdTree::from_points(items, 8).unwrap();
+
+    // a half-plane x + y <= 100
+    let mut inside = vec![];
+    let flow = tree.traverse(
+        |min, _| min[0] + min[1] <= 100.0,
+        |p, &d| {
+            if p[0] + p[1] <= 100.0 {
+                inside.push(d);
+            }
+            ControlFlow::Continue(())
+        },
+    );
+    assert_eq!(flow, ControlFlow::Continue(()));
+    let mut expected: Vec<usize> = tree
+        .iter()
+        .filter(|(p, _)| p[0] + p[1] <= 100.0)
+        .map(|(_, &d)| d)
+        .collect();
+    inside.sort_unstable();
+    expected.sort_unstable();
+    assert_eq!(inside, expected);
+
+    let mut seen = 0;
+    let flow = tree.traverse(
+        |_, _| true,
+        |_, _| {
+            seen += 1;
+            if seen == 10 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        },
+    );
+    assert_eq!(flow, ControlFlow::Break(()));
+    assert_eq!(seen, 10);
+}
+
+#[test]
+fn it_finds_points_inside_convex_polytopes() {
+    use rand::{Rng, SeedableRng, StdRng};
+
+    let mut rng: StdRng = SeedableRng::from_seed(&[9, 8, 7][..]);
+    let items: Vec<([f64; 3], usize)> = (0..5000)
+        .map(|i| {
+            let p = [
+                rng.gen_range(-10.0, 10.0),
+                rng.gen_range(-10.0, 10.0),
+                rng.gen_range(-10.0, 10.0),
+            ];
+            (p, i)
+        })
+        .collect();
+    let tree = OwnedKdTree::from_points(items.clone(), 16).unwrap();
+
+    let dot = |a: &[f64; 3], b: &[f64; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+    for _ in 0..50 {
+        let center = [
+            rng.gen_range(-5.0, 5.0),
+            rng.gen_range(-5.0, 5.0),
+            rng.gen_range(-5.0, 5.0),
+        ];
+        let planes: Vec<([f64; 3], f64)> = (0..6)
+            .map(|_| {
+                let n = [
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                ];
+                (n, dot(&n, &center) + rng.gen_range(0.0, 4.0))
+            })
+            .collect();
+
+        let mut within: Vec<usize> = tree.within_convex(&planes).into_iter().copied().collect();
+        let mut expected: Vec<usize> = items
+            .iter()
+            .filter(|(p, _)| planes.iter().all(|(n, d)| dot(n, p) <= *d))
+            .map(|&(_, i)| i)
+            .collect();
+        within.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(within, expected);
+    }
+
+    assert_eq!(tree.within_convex(&[]).len(), 5000);
+    let empty = [([1.0, 0.0, 0.0], 0.0), ([-1.0, 0.0, 0.0], -1.0)];
+    assert!(tree.within_convex(&empty).is_empty());
+}
+
+#[test]
+fn it_finds_points_inside_cones() {
+    use rand::{Rng, SeedableRng, StdRng};
+
+    let mut rng: StdRng = SeedableRng::from_seed(&[1, 2, 3, 5][..]);
+    let mut point = || {
+        [
+            rng.gen_range(-10.0, 10.0),
+            rng.gen_range(-10.0, 10.0),
+            rng.gen_range(-10.0, 10.0),
+        ]
+    };
+    let items: Vec<([f64; 3], usize)> = (0..5000).map(|i| (point(), i)).collect();
+    let tree = OwnedKdTree::from_points(items.clone(), 16).unwrap();
+
+    let norm = |a: &[f64; 3]| (a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt();
+    for (i, &angle) in [0.1, 0.4, 1.0, 2.0, 3.0, 4.0]
+        .iter()
+        .cycle()
+        .take(60)
+        .enumerate()
+    {
+        let apex = point();
+        let dir = point();
+        let radius = 2.0 + (i % 7) as f64;
+
+        let within: Vec<(f64, usize)> = tree
+            .within_cone(&apex, &dir, angle, radius)
+            .unwrap()
+            .into_iter()
+            .map(|(d, &i)| (d, i))
+            .collect();
+        let mut expected: Vec<(f64, usize)> = items
+            .iter()
+            .filter_map(|&(p, i)| {
+                let offset = [p[0] - apex[0], p[1] - apex[1], p[2] - apex[2]];
+                let distance = norm(&offset);
+                let cos = (offset[0] * dir[0] + offset[1] * dir[1] + offset[2] * dir[2])
+                    / (distance * norm(&dir));
+                let inside = distance <= radius && (angle >= PI || cos.acos() <= angle);
+                if inside {
+                    Some((distance, i))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let mut sorted = within.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(sorted, expected);
+        assert!(within.windows(2).all(|w| w[0].0 <= w[1].0));
+    }
+
+    assert_eq!(
+        tree.within_cone(&[0.0; 3], &[0.0; 3], 1.0, 1.0),
+        Err(ErrorKind::ZeroVector)
+    );
+    for &angle in &[-0.1, f64::NAN] {
+        assert_eq!(
+            tree.within_cone(&[0.0; 3], &[1.0, 0.0, 0.0], angle, 1.0),
+            Err(ErrorKind::InvalidAngle)
+        );
+    }
+    for &radius in &[-1.0, f64::NAN] {
+        assert_eq!(
+            tree.within_cone(&[0.0; 3], &[1.0, 0.0, 0.0], 1.0, radius),
+            Err(ErrorKind::InvalidRadius)
+        );
+    }
+    assert_eq!(
+        tree.within_cone(&[0.0; 3], &[1.0, 0.0, 0.0], 0.0, 0.0),
+        Ok(vec![])
+    );
+}
+
+#[test]
+fn it_folds_items_within_a_radius() {
+    let items = (0..2000)
+        .map(|i| ([(i * 7919 % 2000) as f64 / 20.0, (i % 50) as f64], i))
+        .collect();
+    let tree: OwnedKdTree<f64, usize, 2> = OwnedKdTree::from_points(items, 16).unwrap();
+
+    for &(point, radius) in &[
+        ([50.0, 25.0], 36.0),
+        ([0.0, 0.0], 100.0),
+        ([-50.0, 0.0], 1.0),
+    ] {
+        let within = tree.within(&point, radius, &dist).unwrap();
+        let (count, sum) = tree
+            .fold_within(&point, radius, &dist, (0, 0), |(count, sum), d, &i| {
+                assert!(d <= radius);
+                (count + 1, sum + i)
+            })
+            .unwrap();
+        assert_eq!(count, within.len());
+        assert_eq!(sum, within.iter().map(|&(_, &i)| i).sum::<usize>());
+    }
+    assert_eq!(
+        tree.fold_within(&[f64::NAN, 0.0], 1.0, &dist, 0, |acc, _, _| acc),
+        Err(ErrorKind::NonFiniteCoordinate)
+    );
+}
+
+#[test]
+fn it_round_trips_through_columns() {
+    let points: Vec<[f64; 3]> = (0..500)
+        .map(|i| [(i % 10) as f64, (i % 7) as f64, (i * 31 % 100) as f64])
+        .collect();
+    let ids: Vec<u32> = (0..500).collect();
+    let tree = OwnedKdTree::from_columns(points.clone(), ids.clone(), 8).unwrap();
+    assert_eq!(tree.size(), 500);
+
+    let (out_points, out_ids) = tree.into_columns();
+    let mut pairs: Vec<(u32, [f64; 3])> = out_ids.into_iter().zip(out_points).collect();
+    pairs.sort_by_key(|&(id, _)| id);
+    let expected: Vec<(u32, [f64; 3])> = ids.into_iter().zip(points).collect();
+    assert_eq!(pairs, expected);
+
+    let mismatched = OwnedKdTree::<f64, u32, 2>::from_columns(vec![[0.0; 2]; 3], vec![1, 2], 8);
+    assert_eq!(mismatched.unwrap_err(), ErrorKind::LengthMismatch);
+}
+
+#[test]
+fn it_breaks_distance_ties_by_point() {
+    use rand::{Rng, SeedableRng, StdRng};
+
+    // twelve points at distance 5 from the origin, and a few farther away
+    let mut points: Vec<[f64; 2]> = vec![
+        [3.0, 4.0],
+        [-3.0, 4.0],
+        [3.0, -4.0],
+        [-3.0, -4.0],
+        [4.0, 3.0],
+        [-4.0, 3.0],
+        [4.0, -3.0],
+        [-4.0, -3.0],
+        [5.0, 0.0],
+        [-5.0, 0.0],
+        [0.0, 5.0],
+        [0.0, -5.0],
+    ];
+    points.extend((0..20).map(|i| [6.0 + i as f64, -(i as f64)]));
+    let items: Vec<([f64; 2], usize)> = points.into_iter().zip(0..).collect();
+
+    let mut rng: StdRng = SeedableRng::from_seed(&[2, 7, 1, 8][..]);
+    let mut results = vec![];
+    for _ in 0..10 {
+        let mut shuffled = items.clone();
+        rng.shuffle(&mut shuffled);
+        let mut tree = OwnedKdTree::with_capacity(2);
+        for (point, i) in shuffled {
+            tree.add(point, i).unwrap();
+        }
+        let ids =
+            |found: Vec<(f64, &usize)>| found.into_iter().map(|(_, &i)| i).collect::<Vec<_>>();
+        results.push((
+            ids(tree.nearest(&[0.0, 0.0], 5, &dist).unwrap()),
+            ids(tree.nearest(&[0.0, 0.0], 14, &dist).unwrap()),
+            ids(tree.within(&[0.0, 0.0], 25.0, &dist).unwrap()),
+            ids(tree
+                .iter_nearest(&[0.0, 0.0], &dist)
+                .unwrap()
+                .take(14)
+                .collect()),
+        ));
+    }
+    // ties come out in lexicographic order of their points
+    assert_eq!(results[0].0, vec![9, 7, 5, 3, 1]);
+    assert_eq!(results[0].1[..12], results[0].2[..]);
+    assert_eq!(results[0].1, results[0].3);
+    assert!(results.iter().all(|r| *r == results[0]));
+}
+
+#[test]
+fn it_skips_items_at_nan_distances() {
+    use rand::{Rng, SeedableRng, StdRng};
+
+    let mut rng: StdRng = SeedableRng::from_seed(&[6, 0, 2][..]);
+    let points: Vec<[f64; 2]> = (0..1000)
+        .map(|_| [rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0)])
+        .collect();
+    let mut tree = OwnedKdTree::from_points(points.iter().cloned().zip(0..).collect(), 8).unwrap();
+    // undefined on the left half of the plane, including for the corners of boxes reaching it
+    let partial = |a: &[f64; 2], b: &[f64; 2]| {
+        if b[0] < 0.0 {
+            f64::NAN
+        } else {
+            squared_euclidean(a, b)
+        }
+    };
+    let query = [0.05, 0.0];
+    let mut expected: Vec<(f64, usize)> = (0..points.len())
+        .filter(|&i| points[i][0] >= 0.0)
+        .map(|i| (partial(&query, &points[i]), i))
+        .collect();
+    expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let owned = |found: Vec<(f64, &usize)>| found.into_iter().map(|(d, &i)| (d, i)).collect();
+    let nearest: Vec<(f64, usize)> = owned(tree.nearest(&query, 10, &partial).unwrap());
+    assert_eq!(nearest, expected[..10]);
+    let all: Vec<(f64, usize)> = owned(tree.nearest(&query, points.len(), &partial).unwrap());
+    assert_eq!(all, expected);
+    let within: Vec<(f64, usize)> = owned(tree.within(&query, 0.1, &partial).unwrap());
+    let in_radius = expected.iter().take_while(|&&(d, _)| d <= 0.1).count();
+    assert_eq!(within, expected[..in_radius]);
+    let iterated: Vec<(f64, usize)> = owned(tree.iter_nearest(&query, &partial).unwrap().collect());
+    assert_eq!(iterated, expected);
+    let iterated_mut: Vec<(f64, usize)> = tree
+        .iter_nearest_mut(&query, &partial)
+        .unwrap()
+        .map(|(d, &mut i)| (d, i))
+        .collect();
+    assert_eq!(iterated_mut, expected);
+    let multi = tree
+        .nearest_multi(&[query, [0.5, 0.5]], 10, &partial)
+        .unwrap();
+    assert_eq!(owned(multi[0].clone()), expected[..10]);
+}
+
+#[test]
+fn it_queries_with_distances_selected_at_runtime() {
+    use kdtree::query::DynDistance;
+
+    let manhattan = |a: &[f64; 2], b: &[f64; 2]| (a[0] - b[0]).abs() + (a[1] - b[1]).abs();
+    let metrics: Vec<Box<DynDistance<f64, 2>>> = vec![
+        Box::new(dist),
+        Box::new(manhattan),
+        Box::new(|a, b| dist(a, b).sqrt()),
+    ];
+    let points: Vec<([f64; 2], usize)> = (0..200)
+        .map(|i| ([(i % 20) as f64 * 0.37, (i / 20) as f64 * 0.53], i))
+        .collect();
+    let mut tree = OwnedKdTree::from_points(points.clone(), 4).unwrap();
+    let query = [2.1, 1.7];
+    for metric in &metrics {
+        let distance: &DynDistance<f64, 2> = &**metric;
+        let mut expected: Vec<(f64, usize)> = points
+            .iter()
+            .map(|&(p, i)| (distance(&query, &p), i))
+            .collect();
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let in_radius = expected.iter().take_while(|&&(d, _)| d <= 1.0).count();
+        let distances =
+            |found: Vec<(f64, &usize)>| found.iter().map(|&(d, _)| d).collect::<Vec<_>>();
+        let expected_distances: Vec<f64> = expected.iter().map(|&(d, _)| d).collect();
+
+        let nearest = distances(tree.nearest(&query, 5, distance).unwrap());
+        assert_eq!(nearest, expected_distances[..5]);
+        assert_eq!(
+            distances(tree.nearest_dyn(&query, 5, distance).unwrap()),
+            nearest
+        );
+        let (budgeted, _) = tree
+            .nearest_budgeted(&query, 5, distance, usize::MAX)
+            .unwrap();
+        assert_eq!(distances(budgeted), nearest);
+        assert_eq!(
+            distances(tree.nearest_bbf(&query, 5, usize::MAX, distance).unwrap()),
+            nearest
+        );
+        let multi = tree.nearest_multi(&[query], 5, distance).unwrap();
+        assert_eq!(distances(multi[0].clone()), nearest);
+
+        let within = distances(tree.within(&query, 1.0, distance).unwrap());
+        assert_eq!(within, expected_distances[..in_radius]);
+        assert_eq!(
+            distances(tree.within_dyn(&query, 1.0, distance).unwrap()),
+            within
+        );
+        let radii = tree.within_multi(&query, &[1.0], distance).unwrap();
+        assert_eq!(distances(radii[0].clone()), within);
+        let count = tree
+            .fold_within(&query, 1.0, distance, 0, |n, _, _| n + 1)
+            .unwrap();
+        assert_eq!(count, in_radius);
+
+        let iterated: Vec<f64> = tree
+            .iter_nearest(&query, distance)
+            .unwrap()
+            .map(|(d, _)| d)
+            .collect();
+        assert_eq!(iterated, expected_distances);
+        let iterated_dyn: Vec<f64> = tree
+            .iter_nearest_dyn(&query, distance)
+            .unwrap()
+            .map(|(d, _)| d)
+            .collect();
+        assert_eq!(iterated_dyn, expected_distances);
+        let iterated_mut: Vec<f64> = tree
+            .iter_nearest_mut(&query, distance)
+            .unwrap()
+            .map(|(d, _)| d)
+            .collect();
+        assert_eq!(iterated_mut, expected_distances);
+
+        let flat = tree.clone().freeze();
+        assert_eq!(
+            distances(flat.nearest(&query, 5, distance).unwrap()),
+            nearest
+        );
+        assert_eq!(
+            distances(flat.within(&query, 1.0, distance).unwrap()),
+            within
+        );
+    }
+}
+
+#[test]
+fn it_iterates_farthest_first() {
+    use rand::{Rng, SeedableRng, StdRng};
+
+    let mut rng: StdRng = SeedableRng::from_seed(&[4, 4, 2][..]);
+    let random: Vec<[f64; 2]> = (0..2000)
+        .map(|_| [rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0)])
+        .collect();
+    // a lattice around the query, with many items at equal distances
+    let lattice: Vec<[f64; 2]> = (0..400)
+        .map(|i| [(i % 20) as f64 - 10.0, (i / 20) as f64 - 10.0])
+        .collect();
+    for (points, query) in [(random, [0.3, -0.2]), (lattice, [0.0, 0.0])] {
+        let mut tree = OwnedKdTree::with_capacity(4);
+        for (i, &point) in points.iter().enumerate() {
+            tree.add(point, i).unwrap();
+        }
+        let mut nearest: Vec<(f64, &usize)> = tree.iter_nearest(&query, &dist).unwrap().collect();
+        let farthest: Vec<(f64, &usize)> = tree.iter_farthest(&query, &dist).unwrap().collect();
+        nearest.reverse();
+        assert_eq!(farthest, nearest);
+
+        let mut expected: Vec<f64> = points.iter().map(|p| dist(&query, p)).collect();
+        expected.sort_by(|a, b| b.partial_cmp(a).unwrap());
+        let first: Vec<f64> = tree
+            .iter_farthest(&query, &dist)
+            .unwrap()
+            .take(5)
+            .map(|(d, _)| d)
+            .collect();
+        assert_eq!(first, expected[..5]);
+    }
+    let empty = OwnedKdTree::<f64, usize, 2>::new();
+    assert_eq!(empty.iter_farthest(&[0.0, 0.0], &dist).unwrap().count(), 0);
+    assert!(empty.iter_farthest(&[f64::NAN, 0.0], &dist).is_err());
+}
+
+#[test]
+fn it_summarizes_leaves() {
+    use rand::{Rng, SeedableRng, StdRng};
+
+    let mut rng: StdRng = SeedableRng::from_seed(&[5, 3, 5][..]);
+    let items: Vec<([f64; 3], usize)> = (0..3000)
+        .map(|i| {
+            let point = [
+                rng.gen_range(-5.0, 5.0),
+                rng.gen_range(0.0, 1.0),
+                rng.gen_range(-1.0, 1.0),
+            ];
+            (point, i)
+        })
+        .collect();
+    let mut tree = OwnedKdTree::from_points(items.clone(), 16).unwrap();
+    for (point, i) in items.iter().take(500) {
+        tree.remove(point, i).unwrap();
+    }
+
+    let summaries = tree.leaf_summaries();
+    assert_eq!(
+        summaries.iter().map(|s| s.count).sum::<usize>(),
+        tree.size()
+    );
+    for summary in &summaries {
+        let (min, max) = summary.bounds;
+        assert!(summary.count > 0);
+        assert!((0..3).all(|d| min[d] <= summary.centroid[d] && summary.centroid[d] <= max[d]));
+    }
+
+    let knn = tree.leaf_knn(4);
+    assert_eq!(knn.len(), summaries.len());
+    for (i, neighbours) in knn.iter().enumerate() {
+        let centroid = &summaries[i].centroid;
+        let mut expected: Vec<f64> = (0..summaries.len())
+            .filter(|&j| j != i)
+            .map(|j| dist(centroid, &summaries[j].centroid))
+            .collect();
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let found: Vec<f64> = neighbours.iter().map(|&(d, _)| d).collect();
+        assert_eq!(found, expected[..4]);
+        assert!(neighbours
+            .iter()
+            .all(|&(d, j)| j != i && d == dist(centroid, &summaries[j].centroid)));
+    }
+
+    let empty = OwnedKdTree::<f64, usize, 3>::new();
+    assert!(empty.leaf_summaries().is_empty());
+    assert!(empty.leaf_knn(3).is_empty());
+}
+
+#[test]
+fn it_resumes_nearest_iteration_from_clones() {
+    use rand::{Rng, SeedableRng, StdRng};
+
+    let mut rng: StdRng = SeedableRng::from_seed(&[1, 4, 1][..]);
+    let mut tree = OwnedKdTree::with_capacity(4);
+    for i in 0..1000 {
+        let point = [rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0)];
+        tree.add(point, i).unwrap();
+    }
+    let query = [0.1, 0.2];
+    let all: Vec<(f64, &usize)> = tree.iter_nearest(&query, &dist).unwrap().collect();
+
+    let mut iter = tree.iter_nearest(&query, &dist).unwrap();
+    assert_eq!(iter.yielded_count(), 0);
+    for expected in &all[..10] {
+        assert_eq!(iter.peek(), Some(*expected));
+        assert_eq!(iter.peek(), Some(*expected));
+        assert_eq!(iter.next(), Some(*expected));
+    }
+    assert_eq!(iter.yielded_count(), 10);
+
+    let mut cloned = iter.clone();
+    let first: Vec<(f64, &usize)> = iter.by_ref().take(50).collect();
+    assert_eq!(first, all[10..60]);
+    assert_eq!(iter.yielded_count(), 60);
+    assert_eq!(cloned.yielded_count(), 10);
+    let second: Vec<(f64, &usize)> = cloned.by_ref().collect();
+    assert_eq!(second, all[10..]);
+    assert_eq!(cloned.yielded_count(), all.len());
+    assert_eq!(cloned.peek(), None);
+    let rest: Vec<(f64, &usize)> = iter.collect();
+    assert_eq!(rest, all[60..]);
+}
+
+#[test]
+fn it_excludes_the_query_item_once() {
+    let mut tree = OwnedKdTree::with_capacity(2);
+    // three co-located items, two of them with equal data
+    for data in ["self", "other", "self"] {
+        tree.add([1.0, 1.0], data).unwrap();
+    }
+    tree.add([1.0, 2.0], "above").unwrap();
+    tree.add([3.0, 1.0], "right").unwrap();
+    fn sorted<'a>(mut found: Vec<(f64, &'a &'static str)>) -> Vec<(f64, &'a &'static str)> {
+        found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        found
+    }
+
+    let found = tree
+        .nearest_excluding_self(&[1.0, 1.0], 2, &dist, &"self")
+        .unwrap();
+    assert_eq!(sorted(found), vec![(0.0, &"other"), (0.0, &"self")]);
+    let found = tree
+        .nearest_excluding_self(&[1.0, 1.0], 10, &dist, &"self")
+        .unwrap();
+    assert_eq!(found[2..], [(1.0, &"above"), (4.0, &"right")]);
+    assert_eq!(
+        sorted(found[..2].to_vec()),
+        vec![(0.0, &"other"), (0.0, &"self")]
+    );
+    // only an item stored at the query point is skipped
+    let found = tree
+        .nearest_excluding_self(&[1.0, 1.0], 4, &dist, &"above")
+        .unwrap();
+    assert_eq!(found.len(), 4);
+    assert_eq!(found[3], (1.0, &"above"));
+    let found = tree
+        .nearest_excluding_self(&[1.0, 1.0], 1, &dist, &"missing")
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    assert!(tree
+        .nearest_excluding_self(&[1.0, 1.0], 0, &dist, &"self")
+        .unwrap()
+        .is_empty());
+
+    // "right" is not stored at the query point, so nothing is skipped for it
+    for excluded in ["self", "other", "right"] {
+        let expected = tree
+            .nearest_excluding_self(&[1.0, 1.0], 10, &dist, &excluded)
+            .unwrap();
+        let mut iter = tree
+            .iter_nearest_excluding_self(&[1.0, 1.0], &dist, &excluded)
+            .unwrap();
+        let first = iter.peek();
+        let iterated: Vec<(f64, &&str)> = iter.by_ref().collect();
+        assert_eq!(first, iterated.first().cloned());
+        assert_eq!(iter.yielded_count(), expected.len());
+        assert_eq!(sorted(iterated), sorted(expected));
+    }
+    assert!(tree
+        .iter_nearest_excluding_self(&[f64::NAN, 1.0], &dist, &"self")
+        .is_err());
+}
+
+#[test]
+fn it_modifies_items_found_within_a_radius() {
+    let mut tree = OwnedKdTree::with_capacity(4);
+    for x in 0..20 {
+        for y in 0..20 {
+            tree.add([x as f64, y as f64], 0).unwrap();
+        }
+    }
+    tree.add([10.0, 10.0], 1).unwrap();
+
+    let keys = tree.within_keys(&[10.0, 10.0], 4.0, &dist).unwrap();
+    let within: Vec<f64> = tree
+        .within(&[10.0, 10.0], 4.0, &dist)
+        .unwrap()
+        .iter()
+        .map(|&(d, _)| d)
+        .collect();
+    assert_eq!(keys.iter().map(|&(d, _)| d).collect::<Vec<_>>(), within);
+    assert_eq!(keys.iter().filter(|&&(_, p)| p == [10.0, 10.0]).count(), 2);
+    for (_, key) in &keys {
+        if key[0] > 10.0 {
+            *tree.get_mut(key).unwrap().unwrap() += 5;
+        }
+    }
+    let mut removed = vec![];
+    for (_, key) in &keys {
+        if key[0] <= 10.0 {
+            removed.extend(tree.remove_at(key).unwrap());
+        }
+    }
+    removed.sort_unstable();
+    // the second key of the point holding two items finds nothing left to remove
+    assert_eq!(removed, vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    assert_eq!(tree.size(), 401 - removed.len());
+    let left: Vec<(f64, &i32)> = tree.within(&[10.0, 10.0], 4.0, &dist).unwrap();
+    assert_eq!(left.len(), keys.len() - removed.len());
+    assert!(left.iter().all(|&(_, &data)| data == 5));
+
+    assert_eq!(tree.get_mut(&[0.5, 0.0]), Ok(None));
+    assert_eq!(tree.remove_at(&[0.5, 0.0]), Ok(vec![]));
+    assert!(tree.remove_at(&[f64::NAN, 0.0]).is_err());
+}
+
+#[test]
+fn it_finds_correspondences_of_a_translated_copy() {
+    let targets: Vec<([f64; 2], usize)> = (0..400)
+        .map(|i| ([(i % 20) as f64, (i / 20) as f64], i))
+        .collect();
+    let tree = OwnedKdTree::from_points(targets.clone(), 8).unwrap();
+    let mut sources: Vec<[f64; 2]> = targets
+        .iter()
+        .map(|(p, _)| [p[0] + 0.1, p[1] - 0.2])
+        .collect();
+    // outliers with no target nearby, interleaved with the matched sources
+    sources.insert(7, [50.0, 50.0]);
+    sources.insert(100, [-3.0, 4.0]);
+
+    let found = tree.correspondences(&sources, 0.3 * 0.3, &dist).unwrap();
+    assert_eq!(found.len(), sources.len());
+    for (i, matched) in found.iter().enumerate() {
+        if i == 7 || i == 100 {
+            assert_eq!(*matched, None);
+            continue;
+        }
+        let target = if i < 7 {
+            i
+        } else if i < 100 {
+            i - 1
+        } else {
+            i - 2
+        };
+        let expected_dist = dist(&sources[i], &targets[target].0);
+        assert_eq!(*matched, Some((i, expected_dist, &target)));
+    }
+
+    assert!(tree
+        .correspondences(&sources, 0.01, &dist)
+        .unwrap()
+        .iter()
+        .all(Option::is_none));
+    assert_eq!(tree.correspondences(&[], 1.0, &dist), Ok(vec![]));
+    assert_eq!(
+        tree.correspondences(&[[0.0, f64::NAN]], 1.0, &dist),
+        Err(ErrorKind::NonFiniteCoordinate)
+    );
+}
+
+#[test]
+#[allow(deprecated)]
+fn it_still_compiles_the_paths_below_owned_kdtree() {
+    use kdtree::owned_kdtree;
+
+    let mut tree: owned_kdtree::OwnedKdTree<f64, usize, 2> = owned_kdtree::Builder::new()
+        .capacity(owned_kdtree::DEFAULT_LEAF_CAPACITY)
+        .split_strategy(owned_kdtree::SplitStrategy::Widest)
+        .build();
+    assert_eq!(
+        tree.add([f64::NAN, 0.0], 0),
+        Err(owned_kdtree::ErrorKind::NonFiniteCoordinate)
+    );
+    for i in 0..10 {
+        tree.add([i as f64, 0.0], i).unwrap();
+    }
+    let (found, stats): (_, owned_kdtree::QueryStats) =
+        tree.nearest_with_stats(&[2.2, 0.0], 1, &dist).unwrap();
+    assert_eq!(found[0].1, &2);
+    assert!(stats.points_visited > 0);
+    let nearest: owned_kdtree::NearestIter<f64, usize, _, 2> =
+        tree.iter_nearest(&[2.2, 0.0], &dist).unwrap();
+    assert_eq!(nearest.count(), 10);
+    // the aliases name the types at the new paths
+    let moved: kdtree::tree::OwnedKdTree<f64, usize, 2> = tree;
+    assert_eq!(moved.size(), 10);
+}
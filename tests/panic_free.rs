@@ -0,0 +1,40 @@
+//! Links the checked queries of the `panic_free` module, which fails if any of them can panic.
+//! Run with `cargo test --profile no-panic --features no-panic --test panic_free`.
+#![cfg(feature = "no-panic")]
+
+extern crate kdtree;
+
+use kdtree::panic_free::{self, Tree};
+use kdtree::OwnedErrorKind as ErrorKind;
+
+#[test]
+fn it_answers_queries_without_panicking() {
+    let items = (0..100).map(|i| ([i as f64, 0.0, 0.0], i)).collect();
+    let tree: Tree = Tree::from_points(items, 4).unwrap();
+    let point = [10.2, 0.0, 0.0];
+
+    let nearest = panic_free::nearest(&tree, &point, 2).unwrap();
+    assert_eq!(
+        nearest.iter().map(|&(_, &i)| i).collect::<Vec<_>>(),
+        [10, 11]
+    );
+    assert_eq!(panic_free::within(&tree, &point, 4.5).unwrap().len(), 4);
+    let nearest: Vec<usize> = panic_free::iter_nearest(&tree, &point)
+        .unwrap()
+        .map(|(_, &i)| i)
+        .take(3)
+        .collect();
+    assert_eq!(nearest, [10, 11, 9]);
+    let farthest = panic_free::iter_farthest(&tree, &point).unwrap().next();
+    assert_eq!(farthest.map(|(_, &i)| i), Some(99));
+
+    let nan = [f64::NAN, 0.0, 0.0];
+    assert_eq!(
+        panic_free::nearest(&tree, &nan, 1),
+        Err(ErrorKind::NonFiniteCoordinate)
+    );
+    assert_eq!(
+        panic_free::within(&tree, &point, -1.0),
+        Err(ErrorKind::InvalidRadius)
+    );
+}
@@ -0,0 +1,51 @@
+//! Run with `wasm-pack test --node -- --features wasm`, or `cargo test --target
+//! wasm32-unknown-unknown --features wasm` with `wasm-bindgen-test-runner` as the runner.
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+extern crate kdtree;
+extern crate wasm_bindgen_test;
+
+use kdtree::wasm::WasmKdTree2;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn it_answers_batch_queries() {
+    let mut tree = WasmKdTree2::new();
+    let mut points = vec![];
+    let mut ids = vec![];
+    for i in 0..100u32 {
+        points.push(f64::from(i % 10));
+        points.push(f64::from(i / 10));
+        ids.push(i);
+    }
+    tree.add_batch(&points, &ids).unwrap();
+    tree.add_batch(&[3.0, 4.0], &[1000]).unwrap();
+    assert_eq!(tree.size(), 101);
+
+    assert_eq!(
+        tree.nearest(-1.0, 0.0, 2).unwrap(),
+        vec![1.0, 0.0, 2f64.sqrt(), 10.0]
+    );
+    let found = tree.nearest(3.0, 4.0, 2).unwrap();
+    assert_eq!(found[0], 0.0);
+    assert_eq!(found[2], 0.0);
+    let mut at_point = vec![found[1], found[3]];
+    at_point.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(at_point, vec![43.0, 1000.0]);
+
+    let within = tree.within_radius(9.0, 9.0, 1.0).unwrap();
+    assert_eq!(within, vec![0.0, 99.0, 1.0, 98.0, 1.0, 89.0]);
+    assert!(tree.within_radius(50.0, 50.0, 1.0).unwrap().is_empty());
+}
+
+#[wasm_bindgen_test]
+fn it_rejects_invalid_batches_and_queries() {
+    let mut tree = WasmKdTree2::new();
+    assert!(tree.add_batch(&[1.0, 2.0, 3.0], &[0, 1]).is_err());
+    assert_eq!(tree.size(), 0);
+    assert!(tree.add_batch(&[1.0, 2.0, f64::NAN, 0.0], &[0, 1]).is_err());
+    assert_eq!(tree.size(), 1);
+    assert!(tree.nearest(f64::INFINITY, 0.0, 1).is_err());
+    assert!(tree.within_radius(0.0, 0.0, -1.0).is_err());
+    assert!(tree.within_radius(0.0, 0.0, f64::NAN).is_err());
+}